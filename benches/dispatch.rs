@@ -0,0 +1,118 @@
+//! Cost of driving a transport through `&mut dyn Transport`, as the run
+//! loops do, against statically dispatched calls on the concrete type, next
+//! to the framing every message pays anyway. Runs over the in-memory
+//! transport so no network time hides the difference:
+//!
+//!     cargo bench --bench dispatch
+
+use bytes::Bytes;
+use iroh_gossip_metrics::frame::{self, Compression};
+use iroh_gossip_metrics::transport::{
+    BroadcastScope, Discovery, GossipTuning, MemTransport, Transport, TransportOpts,
+};
+use rand::RngCore;
+use std::hint::black_box;
+use std::time::{Duration, Instant};
+
+const ROUNDS: u32 = 200_000;
+const PAYLOAD: usize = 256;
+
+fn opts(topic: &str) -> TransportOpts {
+    TransportOpts {
+        topic_hex: vec![topic.to_string()],
+        topic_name: Vec::new(),
+        topic_derivation: Default::default(),
+        send_topic: None,
+        secret_hex: None,
+        bootstrap: Vec::new(),
+        udp_bind: None,
+        discovery: Discovery::Direct,
+        run_time: 0,
+        rejoin_on_isolation_ms: None,
+        broadcast_scope: BroadcastScope::Swarm,
+        unicast: false,
+        rx_buffer: None,
+        gossip: GossipTuning::default(),
+        max_recv_bytes: None,
+        join_in_background: false,
+        force_no_join: false,
+    }
+}
+
+/// One broadcast and its delivery, statically dispatched.
+async fn round_trip_static<T: Transport>(sender: &T, receiver: &mut T, payload: &Bytes) {
+    sender.broadcast(payload.clone()).await.unwrap();
+    black_box(receiver.next().await);
+}
+
+/// The same over trait objects.
+async fn round_trip_dyn(sender: &dyn Transport, receiver: &mut dyn Transport, payload: &Bytes) {
+    sender.broadcast(payload.clone()).await.unwrap();
+    black_box(receiver.next().await);
+}
+
+fn per_round(elapsed: Duration) -> f64 {
+    elapsed.as_nanos() as f64 / ROUNDS as f64
+}
+
+fn main() {
+    let rt = tokio::runtime::Builder::new_current_thread()
+        .enable_all()
+        .build()
+        .unwrap();
+    // One sender/receiver pair per variant, each on its own topic.
+    let pair = || {
+        let mut topic = [0u8; 32];
+        rand::rng().fill_bytes(&mut topic);
+        let topic = hex::encode(topic);
+        let sender = MemTransport::connect(opts(&topic)).unwrap();
+        let receiver = MemTransport::connect(opts(&topic)).unwrap();
+        (sender, receiver)
+    };
+    let (sender, mut receiver) = pair();
+    let (dyn_sender, dyn_receiver) = pair();
+    let (dyn_sender, mut dyn_receiver): (Box<dyn Transport>, Box<dyn Transport>) =
+        (Box::new(dyn_sender), Box::new(dyn_receiver));
+    rt.block_on(async {
+        // Both receivers start with the sender's NeighborUp.
+        black_box(receiver.next().await);
+        black_box(dyn_receiver.next().await);
+    });
+
+    let body = vec![0u8; PAYLOAD];
+    let framed = Bytes::from(frame::encode(&body, Compression::None, None, None).unwrap());
+
+    let start = Instant::now();
+    for _ in 0..ROUNDS {
+        let f = frame::encode(black_box(&body), Compression::None, None, None).unwrap();
+        black_box(frame::decode(&f, None).unwrap());
+    }
+    let framing = per_round(start.elapsed());
+
+    let (static_ns, dyn_ns) = rt.block_on(async {
+        for _ in 0..ROUNDS / 10 {
+            round_trip_static(&sender, &mut receiver, &framed).await;
+            round_trip_dyn(dyn_sender.as_ref(), dyn_receiver.as_mut(), &framed).await;
+        }
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            round_trip_static(&sender, &mut receiver, &framed).await;
+        }
+        let static_ns = per_round(start.elapsed());
+        let start = Instant::now();
+        for _ in 0..ROUNDS {
+            round_trip_dyn(dyn_sender.as_ref(), dyn_receiver.as_mut(), &framed).await;
+        }
+        (static_ns, per_round(start.elapsed()))
+    });
+
+    println!("{ROUNDS} rounds, {PAYLOAD}-byte payload, mem transport");
+    println!("  frame encode+decode     {framing:8.1} ns/msg");
+    println!("  broadcast+next static   {static_ns:8.1} ns/msg");
+    println!("  broadcast+next dyn      {dyn_ns:8.1} ns/msg");
+    println!(
+        "  dispatch overhead       {:8.1} ns/msg ({:+.1}% of a round trip)",
+        dyn_ns - static_ns,
+        (dyn_ns - static_ns) / static_ns * 100.0
+    );
+}
@@ -0,0 +1,50 @@
+//! Build-time metadata for `util::build_info`: dependency versions from
+//! Cargo.lock, git hash, target triple and rustc version.
+
+use std::process::Command;
+
+fn main() {
+    println!("cargo:rerun-if-changed=Cargo.lock");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+
+    let lock = std::fs::read_to_string("Cargo.lock").unwrap_or_default();
+    for (name, var) in [
+        ("iroh", "BUILD_IROH_VERSION"),
+        ("iroh-gossip", "BUILD_IROH_GOSSIP_VERSION"),
+    ] {
+        let version = locked_version(&lock, name).unwrap_or_else(|| "unknown".to_string());
+        println!("cargo:rustc-env={var}={version}");
+    }
+
+    let git_hash = command_output("git", &["rev-parse", "--short=12", "HEAD"]).unwrap_or_default();
+    println!("cargo:rustc-env=BUILD_GIT_HASH={git_hash}");
+
+    let target = std::env::var("TARGET").unwrap_or_default();
+    println!("cargo:rustc-env=BUILD_TARGET={target}");
+
+    let rustc = std::env::var("RUSTC").unwrap_or_else(|_| "rustc".to_string());
+    let rustc_version = command_output(&rustc, &["--version"]).unwrap_or_default();
+    println!("cargo:rustc-env=BUILD_RUSTC_VERSION={rustc_version}");
+}
+
+/// Version of package `name` in a Cargo.lock (the first entry if several are locked).
+fn locked_version(lock: &str, name: &str) -> Option<String> {
+    let wanted = format!("name = \"{name}\"");
+    let mut lines = lock.lines();
+    while let Some(line) = lines.next() {
+        if line.trim() == wanted {
+            let version = lines.next()?.trim().strip_prefix("version = ")?;
+            return Some(version.trim_matches('"').to_string());
+        }
+    }
+    None
+}
+
+fn command_output(program: &str, args: &[&str]) -> Option<String> {
+    let out = Command::new(program).args(args).output().ok()?;
+    if !out.status.success() {
+        return None;
+    }
+    Some(String::from_utf8(out.stdout).ok()?.trim().to_string())
+}
@@ -5,6 +5,8 @@ Post-processing script for iroh-gossip-metrics benchmark summaries.
 - Loads multiple use-cases (UCs), each with many runs and peers.
 - Aggregates metrics per run (mean across peers).
 - Produces CSV tables and a set of comparison plots across UCs.
+- For runs with sender fault injection, compares each receiver's duplicates
+  and out_of_order against the injected ones (injection_discrepancy.csv).
 
 Usage examples:
     python scripts/analyze.py --uc uc1 --peers 10 20
@@ -73,6 +75,34 @@ def sender_joined(run_dir: Path) -> bool:
     return False
 
 
+def sender_injection(run_dir: Path) -> tuple[int, int] | None:
+    """
+    Count the duplicates and reorders the sender injected (--inject-*), from
+    the inject_dup / inject_reorder events of its log.
+
+    Returns None if the run has no sender log or the sender injected nothing,
+    so runs without injection get no ground-truth columns.
+    """
+    send_log = run_dir / "send.jsonl"
+    if not send_log.exists():
+        return None
+
+    dups = reorders = 0
+    try:
+        with send_log.open() as fh:
+            for line in fh:
+                if '"event":"inject_dup"' in line:
+                    dups += 1
+                elif '"event":"inject_reorder"' in line:
+                    reorders += 1
+    except OSError:
+        return None
+
+    if dups == 0 and reorders == 0:
+        return None
+    return dups, reorders
+
+
 # ---------------------------------------------------------------------------
 # JSON helper
 # ---------------------------------------------------------------------------
@@ -102,6 +132,16 @@ def load_summary_file(path: Path):
         return None
 
 
+def build_label(build) -> str:
+    """Short version label of a summary's build block ("unknown" if absent)."""
+    if not isinstance(build, dict):
+        return "unknown"
+    label = f"{build.get('version')} (iroh-gossip {build.get('iroh_gossip')}"
+    if build.get("git_hash"):
+        label += f", {build['git_hash']}"
+    return label + ")"
+
+
 # ---------------------------------------------------------------------------
 # Load runs, attach sender_joined
 # ---------------------------------------------------------------------------
@@ -123,6 +163,7 @@ def load_runs(
     def _consume_run_dir(run_dir: Path, effective_churn: int | None):
         run_id = run_dir.name
         sender_ok = sender_joined(run_dir)
+        injected = sender_injection(run_dir)
 
         files = sorted(run_dir.glob(SUMMARY_GLOB))
         if not files:
@@ -135,8 +176,15 @@ def load_runs(
 
             s["peer"] = f.stem.replace("-summary", "")
             s["uc"] = uc_label
+            # Group by the run directory, so receivers with and without a
+            # heartbeat stay in one run; the sender's run_id (if learned)
+            # is kept as its own column.
             s["run"] = run_id
+            s["sender_run_id"] = s.get("sender_run_id")
             s["sender_joined"] = sender_ok
+            if injected is not None:
+                s["dups_injected"], s["reorders_injected"] = injected
+            s["build"] = build_label(s.get("build"))
 
             if effective_churn is not None:
                 s["churn_pct"] = effective_churn
@@ -625,6 +673,13 @@ def main():
         all_peers.append(df)
     peer_df = pd.concat(all_peers, ignore_index=True)
 
+    # Runs built from different versions are not directly comparable
+    builds = peer_df.groupby("build")["uc"].unique()
+    if len(builds) > 1:
+        print("[warn] Summaries come from different builds:")
+        for label, ucs in builds.items():
+            print(f"[warn]   {label}: {', '.join(sorted(ucs))}")
+
     # Sender-fail rates per UC: fraction of runs where sender_joined==False
     fail_series = peer_df.groupby(["uc", "run"])["sender_joined"].first().eq(False)
     fail_rates = fail_series.groupby("uc").mean()
@@ -681,6 +736,30 @@ def main():
     peer_df = ensure_numeric(peer_df, metrics)
     peer_df.to_csv(out_dir / "per_peer_raw.csv", index=False)
 
+    # Observed duplicates / reorders against the sender's injection log, for
+    # runs that used --inject-dup-prob / --inject-reorder.
+    if {"dups_injected", "duplicates", "out_of_order"}.issubset(peer_df.columns):
+        inj_cols = [
+            "uc",
+            "run",
+            "peer",
+            "duplicates",
+            "dups_injected",
+            "out_of_order",
+            "reorders_injected",
+        ]
+        inj = peer_df[peer_df["dups_injected"].notna()][inj_cols].copy()
+        inj["dup_discrepancy"] = inj["duplicates"] - inj["dups_injected"]
+        inj["reorder_discrepancy"] = inj["out_of_order"] - inj["reorders_injected"]
+        inj.to_csv(out_dir / "injection_discrepancy.csv", index=False)
+        print("[info] Observed - injected, mean per UC:")
+        means = inj.groupby("uc")[["dup_discrepancy", "reorder_discrepancy"]].mean()
+        for uc, row in means.iterrows():
+            print(
+                f"[info]   {uc}: duplicates {row['dup_discrepancy']:+.2f}, "
+                f"out_of_order {row['reorder_discrepancy']:+.2f}"
+            )
+
     # Compute per-run means across peers
     run_df = per_run_means(peer_df, metrics)
 
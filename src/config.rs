@@ -0,0 +1,144 @@
+//! `--config` files: experiment presets in TOML, keyed like the `Args` fields.
+//!
+//! ```toml
+//! rate = 100
+//! topic_name = ["lab"]
+//! idle_report_ms = "5s"
+//!
+//! [preset.lan-direct]     # selected with --preset lan-direct
+//! discovery = "direct"
+//! heartbeat_ms = "1s"
+//! ```
+//!
+//! Top-level keys apply to every run, the selected preset overrides them.
+//! File values are fed through the same clap parsers as flags, so they are
+//! validated identically. Flags given on the command line override the file,
+//! which overrides `IGM_*` environment variables. Unknown keys are an error.
+
+use anyhow::{Context, anyhow, bail};
+use clap::parser::ValueSource;
+use clap::{CommandFactory, FromArgMatches};
+use std::ffi::OsString;
+use std::fs;
+use std::path::Path;
+
+/// Arguments that only make sense on the command line.
+const CLI_ONLY: &[&str] = &["config", "preset", "print_config", "version_verbose"];
+
+/// Parse the process arguments into `T`, merging in `--config` (and `--preset`).
+///
+/// Clap errors (including `--help`) exit the process as `Parser::parse` does.
+pub fn parse_with_config<T: CommandFactory + FromArgMatches>() -> anyhow::Result<T> {
+    let cli: Vec<OsString> = std::env::args_os().collect();
+    let matches = T::command().get_matches_from(&cli);
+    let config = matches.get_one::<std::path::PathBuf>("config");
+    let preset = matches.get_one::<String>("preset");
+
+    let argv = match (config, preset) {
+        (None, Some(_)) => bail!("--preset requires --config"),
+        (None, None) => cli,
+        (Some(path), preset) => {
+            let table = load(path, preset.map(String::as_str))?;
+            let mut argv = cli[..1].to_vec();
+            argv.extend(file_args::<T>(&table, &matches, path)?);
+            argv.extend(cli[1..].iter().cloned());
+            argv
+        }
+    };
+    let matches = T::command().get_matches_from(argv);
+    T::from_arg_matches(&matches).map_err(|e| e.exit())
+}
+
+/// Read a config file and flatten the selected preset over its top-level keys.
+fn load(path: &Path, preset: Option<&str>) -> anyhow::Result<toml::Table> {
+    let text =
+        fs::read_to_string(path).with_context(|| format!("reading config {}", path.display()))?;
+    let mut table: toml::Table =
+        toml::from_str(&text).with_context(|| format!("parsing config {}", path.display()))?;
+    let presets = match table.remove("preset") {
+        Some(toml::Value::Table(presets)) => presets,
+        Some(_) => bail!("{}: `preset` must be a table of presets", path.display()),
+        None => toml::Table::new(),
+    };
+    if let Some(name) = preset {
+        let Some(toml::Value::Table(values)) = presets.get(name) else {
+            let known: Vec<&str> = presets.keys().map(String::as_str).collect();
+            bail!(
+                "{}: no preset `{name}` (available: {})",
+                path.display(),
+                if known.is_empty() {
+                    "none".to_string()
+                } else {
+                    known.join(", ")
+                }
+            );
+        };
+        table.extend(values.clone());
+    }
+    Ok(table)
+}
+
+/// Turn file values into flag tokens, skipping arguments given on the command line.
+fn file_args<T: CommandFactory>(
+    table: &toml::Table,
+    matches: &clap::ArgMatches,
+    path: &Path,
+) -> anyhow::Result<Vec<OsString>> {
+    let cmd = T::command();
+    let unknown: Vec<&str> = table
+        .keys()
+        .map(String::as_str)
+        .filter(|key| {
+            CLI_ONLY.contains(key)
+                || !cmd
+                    .get_arguments()
+                    .any(|a| a.get_id() == *key && a.get_long().is_some())
+        })
+        .collect();
+    if !unknown.is_empty() {
+        bail!(
+            "{}: unknown config keys: {}",
+            path.display(),
+            unknown.join(", ")
+        );
+    }
+
+    let mut argv = Vec::new();
+    for arg in cmd.get_arguments() {
+        let id = arg.get_id().as_str();
+        let Some(value) = table.get(id) else {
+            continue;
+        };
+        if matches.value_source(id) == Some(ValueSource::CommandLine) {
+            continue;
+        }
+        let flag = format!("--{}", arg.get_long().expect("checked above"));
+        let values = match value {
+            toml::Value::Array(items) => items.iter().collect(),
+            other => vec![other],
+        };
+        for value in values {
+            let text = match value {
+                toml::Value::String(s) => s.clone(),
+                toml::Value::Integer(i) => i.to_string(),
+                toml::Value::Float(f) => f.to_string(),
+                toml::Value::Boolean(b) if !arg.get_action().takes_values() => {
+                    // Switches can only be turned on; `false` keeps the default.
+                    if *b {
+                        argv.push(OsString::from(&flag));
+                    }
+                    continue;
+                }
+                toml::Value::Boolean(b) => b.to_string(),
+                other => {
+                    return Err(anyhow!(
+                        "{}: `{id}` has unsupported value {other}",
+                        path.display()
+                    ));
+                }
+            };
+            argv.push(OsString::from(format!("{flag}={text}")));
+        }
+    }
+    Ok(argv)
+}
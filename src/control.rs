@@ -0,0 +1,131 @@
+//! Commands for a running node: stdin lines (`--interactive`) and the
+//! pause/resume signals (`--pause-signals`).
+//!
+//! Both sources push a `Control` into one process-wide queue, which the
+//! sender and receiver loops drain between their steps; each command is
+//! logged as a `control` event together with its effect.
+
+use crate::util::diag;
+use std::collections::VecDeque;
+use std::io::BufRead;
+use std::sync::Mutex;
+use tokio::sync::Notify;
+
+/// One command to a run loop.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Control {
+    /// Log (and print) the current summary without ending the test.
+    Snapshot,
+    /// Send at this many messages per second from now on (sender).
+    Rate(u64),
+    /// Pause sending until `Resume` (sender).
+    Pause,
+    /// End a `Pause` (sender).
+    Resume,
+    /// Re-join the bootstrap peers on the send topic.
+    Rejoin,
+    /// Stop and summarize, like Ctrl-C.
+    Quit,
+}
+
+/// Printed to stderr for a line that is not a command.
+pub const USAGE: &str = "commands: snapshot | rate <msg/s> | pause | resume | rejoin | quit";
+
+impl Control {
+    /// Parse one command line such as `rate 200`.
+    pub fn parse(line: &str) -> Result<Self, String> {
+        let mut words = line.split_whitespace();
+        let cmd = match (words.next(), words.next()) {
+            (Some("snapshot"), None) => Control::Snapshot,
+            (Some("rate"), Some(rate)) => match rate.parse::<u64>() {
+                Ok(rate) if rate > 0 => Control::Rate(rate),
+                _ => {
+                    return Err(format!(
+                        "invalid rate `{rate}`, expected messages per second"
+                    ));
+                }
+            },
+            (Some("pause"), None) => Control::Pause,
+            (Some("resume"), None) => Control::Resume,
+            (Some("rejoin"), None) => Control::Rejoin,
+            (Some("quit"), None) => Control::Quit,
+            _ => return Err(format!("unknown command `{}`", line.trim())),
+        };
+        match words.next() {
+            Some(extra) => Err(format!("unexpected `{extra}` after {}", cmd.as_str())),
+            None => Ok(cmd),
+        }
+    }
+
+    /// Command name as logged in `control` events.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Control::Snapshot => "snapshot",
+            Control::Rate(_) => "rate",
+            Control::Pause => "pause",
+            Control::Resume => "resume",
+            Control::Rejoin => "rejoin",
+            Control::Quit => "quit",
+        }
+    }
+}
+
+static QUEUE: Mutex<VecDeque<(Control, &'static str)>> = Mutex::new(VecDeque::new());
+static QUEUE_NOTIFY: Notify = Notify::const_new();
+
+/// Queue `cmd` from `source` ("stdin" or "signal") for the run loop.
+pub fn push(cmd: Control, source: &'static str) {
+    QUEUE.lock().unwrap().push_back((cmd, source));
+    QUEUE_NOTIFY.notify_one();
+}
+
+/// The oldest queued command and its source.
+pub fn next() -> Option<(Control, &'static str)> {
+    QUEUE.lock().unwrap().pop_front()
+}
+
+/// Resolve once a command was queued since the last call.
+pub async fn wait() {
+    QUEUE_NOTIFY.notified().await;
+}
+
+/// Read commands from stdin until it closes (`--interactive`). Lines that
+/// are not commands print the usage to stderr.
+pub fn spawn_stdin() {
+    std::thread::spawn(|| {
+        for line in std::io::stdin().lock().lines() {
+            let Ok(line) = line else {
+                break;
+            };
+            if line.trim().is_empty() {
+                continue;
+            }
+            match Control::parse(&line) {
+                Ok(cmd) => push(cmd, "stdin"),
+                Err(e) => diag!("{e}; {USAGE}"),
+            }
+        }
+    });
+}
+
+/// Queue `Pause` on SIGUSR1 and `Resume` on SIGUSR2 (`--pause-signals`).
+#[cfg(unix)]
+pub fn spawn_signals() -> anyhow::Result<tokio::task::JoinHandle<()>> {
+    use tokio::signal::unix::{SignalKind, signal};
+    let mut pause = signal(SignalKind::user_defined1())?;
+    let mut resume = signal(SignalKind::user_defined2())?;
+    Ok(tokio::spawn(async move {
+        loop {
+            tokio::select! {
+                Some(()) = pause.recv() => push(Control::Pause, "signal"),
+                Some(()) = resume.recv() => push(Control::Resume, "signal"),
+                else => break,
+            }
+        }
+    }))
+}
+
+#[cfg(not(unix))]
+pub fn spawn_signals() -> anyhow::Result<tokio::task::JoinHandle<()>> {
+    anyhow::bail!("--pause-signals needs SIGUSR1/SIGUSR2 (Unix only)")
+}
@@ -0,0 +1,89 @@
+//! UDP forwarding of received payloads (`--forward-udp`), to feed the gossip
+//! stream into a local consumer.
+//!
+//! The receiver queues each payload without blocking; a background task sends
+//! it as one datagram. A full queue drops the payload and a failed send counts
+//! as a forward error, neither ever stops the run.
+
+use crate::metrics::ForwardCounters;
+use bytes::Bytes;
+use std::net::SocketAddr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::net::UdpSocket;
+use tokio::sync::mpsc;
+use tracing::warn;
+
+/// Payloads waiting for the socket before new ones are dropped.
+const QUEUE: usize = 1024;
+
+#[derive(Debug, Default)]
+struct Counters {
+    forwarded: AtomicU64,
+    dropped: AtomicU64,
+    errors: AtomicU64,
+}
+
+/// The queueing half, held by the receiver loop.
+#[derive(Debug, Clone)]
+pub struct Forwarder {
+    tx: mpsc::Sender<Bytes>,
+    counters: Arc<Counters>,
+    /// Forward every non-control payload before decoding (`--forward-raw`),
+    /// not just valid data messages.
+    pub raw: bool,
+}
+
+/// Bind a local socket connected to `target` and start the sending task.
+pub async fn spawn(target: SocketAddr, raw: bool) -> anyhow::Result<Forwarder> {
+    let local: SocketAddr = match target {
+        SocketAddr::V4(_) => ([0, 0, 0, 0], 0).into(),
+        SocketAddr::V6(_) => ([0u16; 8], 0).into(),
+    };
+    let socket = UdpSocket::bind(local).await?;
+    socket
+        .connect(target)
+        .await
+        .map_err(|e| anyhow::anyhow!("invalid --forward-udp {target}: {e}"))?;
+
+    let (tx, mut rx) = mpsc::channel::<Bytes>(QUEUE);
+    let counters = Arc::new(Counters::default());
+    let task_counters = counters.clone();
+    tokio::spawn(async move {
+        let mut warned = false;
+        while let Some(payload) = rx.recv().await {
+            match socket.send(&payload).await {
+                Ok(_) => {
+                    task_counters.forwarded.fetch_add(1, Ordering::Relaxed);
+                }
+                Err(e) => {
+                    // One warning per run; the rest shows in the summary.
+                    if !warned {
+                        warn!("udp forward failed: {e}");
+                        warned = true;
+                    }
+                    task_counters.errors.fetch_add(1, Ordering::Relaxed);
+                }
+            }
+        }
+    });
+    Ok(Forwarder { tx, counters, raw })
+}
+
+impl Forwarder {
+    /// Queue one payload, dropping it if the queue is full.
+    pub fn forward(&self, payload: Bytes) {
+        if self.tx.try_send(payload).is_err() {
+            self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+        }
+    }
+
+    /// Counters so far, for the summary.
+    pub fn counters(&self) -> ForwardCounters {
+        ForwardCounters {
+            forwarded: self.counters.forwarded.load(Ordering::Relaxed),
+            forward_dropped: self.counters.dropped.load(Ordering::Relaxed),
+            forward_errors: self.counters.errors.load(Ordering::Relaxed),
+        }
+    }
+}
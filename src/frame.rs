@@ -0,0 +1,988 @@
+//! Payload framing for benchmark messages.
+//!
+//! Every payload starts with one flag byte and an 8-byte wire timestamp slot,
+//! followed by the body (the encoded, padded `DataMsg`). Plain runs pay these
+//! nine bytes, which are taken out of the padding budget.
+//!
+//! The slot is zero when the frame is built; the transport's outgoing task
+//! fills in its clock right before handing the frame to gossip
+//! (`stamp_wire_ts`), so receivers can tell local queueing from propagation.
+//! It is not covered by encryption or signatures. Frames of older builds lack
+//! it (no `FLAG_WIRE_TS`) and decode with `wire_ms: None`.
+//!
+//! Encrypted frames carry a 12-byte nonce after the flag byte; the body is
+//! compressed first (if requested), then sealed with ChaCha20-Poly1305 using
+//! the flag byte as associated data.
+//!
+//! Signed frames carry the sender's ed25519 public key and a signature over
+//! `(test_id, seq, sent_ms)` directly after the flag byte (before any nonce).
+//!
+//! Frames larger than the gossip message limit are split into chunks. A chunk
+//! starts with its own flag byte, followed by `test_id`, `seq`, `chunk_idx` and
+//! `chunk_count` and a slice of the complete frame; receivers reassemble the
+//! frame before decoding it.
+//!
+//! Heartbeats are standalone control payloads (own flag byte plus a postcard
+//! `Heartbeat`); they are never compressed, encrypted, signed or chunked.
+//! Receiver announcements (`ReceiverAlive`) and acks (`AckUpTo`) are framed
+//! the same way.
+//!
+//! A batch (`--batch`) carries several complete frames in one gossip message:
+//! its own flag byte, then each frame prefixed with its length (u32, little
+//! endian). Batches are never chunked; a single frame goes out as is.
+
+use crate::metrics::{AckUpTo, Heartbeat, ReceiverAlive};
+use anyhow::Result;
+use bytes::Bytes;
+use chacha20poly1305::aead::{Aead, KeyInit, Payload};
+use chacha20poly1305::{ChaCha20Poly1305, Key, Nonce};
+use iroh::{PublicKey, SecretKey};
+use iroh_base::Signature;
+use rand::RngCore;
+use serde::Serialize;
+use std::collections::HashMap;
+use std::collections::hash_map::Entry;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+
+/// Size of the frame header in bytes: flag byte and wire timestamp slot.
+pub const HEADER_LEN: usize = 1 + WIRE_TS_LEN;
+
+/// Length of the wire timestamp slot (little-endian ms since epoch).
+const WIRE_TS_LEN: usize = 8;
+
+/// Frame carries the wire timestamp slot after the flag byte.
+const FLAG_WIRE_TS: u8 = 0b0000_1000;
+
+/// Body is zstd-compressed.
+const FLAG_COMPRESSED: u8 = 0b0000_0001;
+
+/// Body is encrypted; a nonce follows the flag byte.
+const FLAG_ENCRYPTED: u8 = 0b0000_0010;
+
+/// Frame carries a signature block after the flag byte.
+const FLAG_SIGNED: u8 = 0b0000_0100;
+
+/// Length of the signature block: public key plus ed25519 signature.
+const SIGNATURE_LEN: usize = 32 + 64;
+
+/// Payload is a chunk of a larger frame (no other flag may be set).
+const FLAG_CHUNK: u8 = 0b1000_0000;
+
+/// Payload is a heartbeat control message (no other flag may be set).
+const FLAG_HEARTBEAT: u8 = 0b0100_0000;
+
+/// Payload is a receiver announcement (no other flag may be set).
+const FLAG_ANNOUNCE: u8 = 0b0010_0000;
+
+/// Payload is a receiver ack (no other flag may be set).
+const FLAG_ACK: u8 = 0b0001_0000;
+
+/// Payload is a batch of frames (the whole byte; not a combination of the
+/// chunk and heartbeat flags).
+const FLAG_BATCH: u8 = 0b1100_0000;
+
+/// Length prefix of each frame in a batch.
+const BATCH_LEN_PREFIX: usize = 4;
+
+/// Chunk header: flag byte, test_id, seq, chunk_idx, chunk_count.
+pub const CHUNK_HEADER_LEN: usize = 1 + 16 + 8 + 4 + 4;
+
+/// Bytes iroh-gossip adds around a broadcast payload (topic, message id, round,
+/// enum tags), reserved when deriving the chunk size from the gossip limit.
+pub const GOSSIP_ENVELOPE_OVERHEAD: usize = 128;
+
+/// Length of the ChaCha20-Poly1305 nonce.
+const NONCE_LEN: usize = 12;
+
+/// Bytes added by encryption: nonce plus Poly1305 tag.
+const ENCRYPTION_OVERHEAD: usize = NONCE_LEN + 16;
+
+/// Context string for deriving the payload key from the topic.
+const KEY_CONTEXT: &str = "iroh-gossip-metrics 2025 payload encryption v1";
+
+/// Upper bound for a decompressed body.
+pub const MAX_BODY: usize = 16 * 1024 * 1024;
+
+/// Total framing overhead in bytes (header, plus nonce and tag when encrypted,
+/// plus the signature block when signed).
+pub fn overhead(encrypted: bool, signed: bool) -> usize {
+    HEADER_LEN
+        + if encrypted { ENCRYPTION_OVERHEAD } else { 0 }
+        + if signed { SIGNATURE_LEN } else { 0 }
+}
+
+/// Largest unchunked frame a sender with `--size size` produces: every framing
+/// option, plus slack for zstd growth and for messages too small to pad.
+pub fn max_frame_len(size: usize) -> usize {
+    size + overhead(true, true) + 64
+}
+
+/// Signer public key and signature attached to a frame.
+#[derive(Debug, Clone)]
+pub struct SignatureBlock {
+    public_key: [u8; 32],
+    signature: [u8; 64],
+}
+
+/// Why a signed-message check rejected a message.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SigReject {
+    /// The frame carries no signature.
+    Unsigned,
+    /// The signature does not verify for the attached key and message fields.
+    BadSignature,
+    /// The signature is valid but the signer is not on the allowlist.
+    NotAllowed,
+}
+
+impl SigReject {
+    /// Name used in logs.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            SigReject::Unsigned => "unsigned",
+            SigReject::BadSignature => "bad_sig",
+            SigReject::NotAllowed => "not_allowed",
+        }
+    }
+}
+
+/// The bytes a sender signs: `test_id || seq || sent_ms` (little endian).
+fn signed_fields(test_id: &[u8; 16], seq: u64, sent_ms: u64) -> [u8; 32] {
+    let mut msg = [0u8; 32];
+    msg[..16].copy_from_slice(test_id);
+    msg[16..24].copy_from_slice(&seq.to_le_bytes());
+    msg[24..].copy_from_slice(&sent_ms.to_le_bytes());
+    msg
+}
+
+/// Sign the identifying fields of a `DataMsg`.
+pub fn sign(secret: &SecretKey, test_id: &[u8; 16], seq: u64, sent_ms: u64) -> SignatureBlock {
+    let signature = secret.sign(&signed_fields(test_id, seq, sent_ms));
+    SignatureBlock {
+        public_key: *secret.public().as_bytes(),
+        signature: signature.to_bytes(),
+    }
+}
+
+impl SignatureBlock {
+    /// Check the signature over the given fields and, if `allow` is non-empty,
+    /// that the signer is one of the allowed keys.
+    pub fn check(
+        &self,
+        test_id: &[u8; 16],
+        seq: u64,
+        sent_ms: u64,
+        allow: &[PublicKey],
+    ) -> Result<PublicKey, SigReject> {
+        let signer =
+            PublicKey::from_bytes(&self.public_key).map_err(|_| SigReject::BadSignature)?;
+        let signature = Signature::from_bytes(&self.signature);
+        signer
+            .verify(&signed_fields(test_id, seq, sent_ms), &signature)
+            .map_err(|_| SigReject::BadSignature)?;
+        if !allow.is_empty() && !allow.contains(&signer) {
+            return Err(SigReject::NotAllowed);
+        }
+        Ok(signer)
+    }
+}
+
+/// Payload compression applied by the sender.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum Compression {
+    /// Send the body uncompressed.
+    #[default]
+    None,
+    /// zstd with the given level.
+    Zstd(i32),
+}
+
+impl Compression {
+    /// Name used in logs and summaries.
+    pub fn label(&self) -> String {
+        match self {
+            Compression::None => "none".to_string(),
+            Compression::Zstd(level) => format!("zstd:{level}"),
+        }
+    }
+}
+
+impl Serialize for Compression {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&self.label())
+    }
+}
+
+/// Parse `none`, `zstd` or `zstd:<level>`, used as clap value parser.
+pub fn parse_compression(s: &str) -> Result<Compression, String> {
+    match s.trim().split_once(':') {
+        None if s.trim() == "none" => Ok(Compression::None),
+        None if s.trim() == "zstd" => Ok(Compression::Zstd(zstd::DEFAULT_COMPRESSION_LEVEL)),
+        Some(("zstd", level)) => {
+            let level: i32 = level
+                .parse()
+                .map_err(|_| format!("'{level}' is not a zstd level"))?;
+            if !zstd::compression_level_range().contains(&level) {
+                return Err(format!("zstd level {level} is out of range"));
+            }
+            Ok(Compression::Zstd(level))
+        }
+        _ => Err(format!("'{s}' is not 'none', 'zstd' or 'zstd:<level>'")),
+    }
+}
+
+/// Symmetric payload encryption with a key derived from the topic.
+///
+/// Nonces are a random per-run prefix followed by a counter of sealed frames
+/// (shared by clones), so they never repeat within a run, not even for
+/// redundant copies or injected duplicates of one seq, and differ between
+/// runs on the same topic.
+#[derive(Clone)]
+pub struct Encryption {
+    key: [u8; 32],
+    nonce_prefix: [u8; 4],
+    sealed: Arc<AtomicU64>,
+}
+
+impl std::fmt::Debug for Encryption {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Encryption").finish_non_exhaustive()
+    }
+}
+
+impl Encryption {
+    /// Derive the payload key from the 32-byte topic ID via blake3 key derivation.
+    pub fn from_topic(topic: &[u8; 32]) -> Self {
+        let mut nonce_prefix = [0u8; 4];
+        rand::rng().fill_bytes(&mut nonce_prefix);
+        Self {
+            key: blake3::derive_key(KEY_CONTEXT, topic),
+            nonce_prefix,
+            sealed: Arc::new(AtomicU64::new(0)),
+        }
+    }
+
+    fn cipher(&self) -> ChaCha20Poly1305 {
+        ChaCha20Poly1305::new(Key::from_slice(&self.key))
+    }
+
+    /// Nonce for the next frame to seal.
+    fn next_nonce(&self) -> [u8; NONCE_LEN] {
+        let counter = self.sealed.fetch_add(1, Ordering::Relaxed);
+        let mut nonce = [0u8; NONCE_LEN];
+        nonce[..4].copy_from_slice(&self.nonce_prefix);
+        nonce[4..].copy_from_slice(&counter.to_le_bytes());
+        nonce
+    }
+}
+
+/// Why a frame could not be decoded.
+#[derive(Debug, Clone)]
+pub enum FrameError {
+    /// Not a frame this build understands (empty, unknown flags, bad compression).
+    Malformed(String),
+    /// The frame is encrypted but the receiver has no key (`--encrypt` missing).
+    MissingKey,
+    /// The receiver expects encryption but the frame is plaintext.
+    Unencrypted,
+    /// Decryption failed: wrong key (topic) or tampered payload.
+    AuthFailed,
+}
+
+impl FrameError {
+    /// Whether this is a sender/receiver encryption setting mismatch.
+    pub fn is_mismatch(&self) -> bool {
+        matches!(self, FrameError::MissingKey | FrameError::Unencrypted)
+    }
+}
+
+impl std::fmt::Display for FrameError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            FrameError::Malformed(e) => write!(f, "malformed frame: {e}"),
+            FrameError::MissingKey => {
+                write!(f, "encrypted payload but receiver runs without --encrypt")
+            }
+            FrameError::Unencrypted => {
+                write!(f, "plaintext payload but receiver runs with --encrypt")
+            }
+            FrameError::AuthFailed => write!(f, "payload authentication failed"),
+        }
+    }
+}
+
+impl std::error::Error for FrameError {}
+
+/// A decoded frame: the body plus how it travelled.
+#[derive(Debug)]
+pub struct Decoded {
+    /// Body after decryption and decompression.
+    pub body: Vec<u8>,
+    /// Whether the body was compressed on the wire.
+    pub compressed: bool,
+    /// Size of the (possibly compressed) body before encryption.
+    pub sealed_len: usize,
+    /// Signature block, if the frame is signed.
+    pub signature: Option<SignatureBlock>,
+    /// Sender clock when the transport handed the frame to gossip (None for
+    /// frames without the slot, or never stamped).
+    pub wire_ms: Option<u64>,
+}
+
+/// Wrap a body into a frame using the given compression, optional encryption
+/// and optional signature block.
+pub fn encode(
+    body: &[u8],
+    compression: Compression,
+    encryption: Option<&Encryption>,
+    signature: Option<&SignatureBlock>,
+) -> Result<Vec<u8>> {
+    let (mut flags, body) = match compression {
+        Compression::None => (FLAG_WIRE_TS, body.to_vec()),
+        Compression::Zstd(level) => (
+            FLAG_WIRE_TS | FLAG_COMPRESSED,
+            zstd::bulk::compress(body, level)?,
+        ),
+    };
+    let mut frame =
+        Vec::with_capacity(overhead(encryption.is_some(), signature.is_some()) + body.len());
+    frame.push(0);
+    frame.extend_from_slice(&[0; WIRE_TS_LEN]);
+    if let Some(sig) = signature {
+        flags |= FLAG_SIGNED;
+        frame.extend_from_slice(&sig.public_key);
+        frame.extend_from_slice(&sig.signature);
+    }
+    let Some(enc) = encryption else {
+        frame[0] = flags;
+        frame.extend_from_slice(&body);
+        return Ok(frame);
+    };
+
+    flags |= FLAG_ENCRYPTED;
+    frame[0] = flags;
+    let nonce = enc.next_nonce();
+    let sealed = enc
+        .cipher()
+        .encrypt(
+            Nonce::from_slice(&nonce),
+            Payload {
+                msg: &body,
+                aad: &[flags],
+            },
+        )
+        .map_err(|_| anyhow::anyhow!("payload encryption failed"))?;
+    frame.extend_from_slice(&nonce);
+    frame.extend_from_slice(&sealed);
+    Ok(frame)
+}
+
+/// Unwrap a frame, decrypting and decompressing the body if needed.
+pub fn decode(frame: &[u8], encryption: Option<&Encryption>) -> Result<Decoded, FrameError> {
+    let (&flags, mut rest) = frame
+        .split_first()
+        .ok_or_else(|| FrameError::Malformed("empty frame".into()))?;
+    if flags & !(FLAG_WIRE_TS | FLAG_COMPRESSED | FLAG_ENCRYPTED | FLAG_SIGNED) != 0 {
+        return Err(FrameError::Malformed(format!(
+            "unknown frame flags {flags:#04x}"
+        )));
+    }
+
+    let wire_ms = if flags & FLAG_WIRE_TS != 0 {
+        if rest.len() < WIRE_TS_LEN {
+            return Err(FrameError::Malformed("truncated wire timestamp".into()));
+        }
+        let (slot, tail) = rest.split_at(WIRE_TS_LEN);
+        rest = tail;
+        Some(u64::from_le_bytes(slot.try_into().expect("8-byte slot"))).filter(|&ms| ms > 0)
+    } else {
+        None
+    };
+
+    let signature = if flags & FLAG_SIGNED != 0 {
+        if rest.len() < SIGNATURE_LEN {
+            return Err(FrameError::Malformed("truncated signature".into()));
+        }
+        let (block, tail) = rest.split_at(SIGNATURE_LEN);
+        rest = tail;
+        Some(SignatureBlock {
+            public_key: block[..32].try_into().expect("32-byte key"),
+            signature: block[32..].try_into().expect("64-byte signature"),
+        })
+    } else {
+        None
+    };
+
+    let encrypted = flags & FLAG_ENCRYPTED != 0;
+    let body = match (encrypted, encryption) {
+        (false, None) => rest.to_vec(),
+        (true, None) => return Err(FrameError::MissingKey),
+        (false, Some(_)) => return Err(FrameError::Unencrypted),
+        (true, Some(enc)) => {
+            if rest.len() < NONCE_LEN {
+                return Err(FrameError::Malformed("truncated nonce".into()));
+            }
+            let (nonce, sealed) = rest.split_at(NONCE_LEN);
+            enc.cipher()
+                .decrypt(
+                    Nonce::from_slice(nonce),
+                    Payload {
+                        msg: sealed,
+                        aad: &[flags],
+                    },
+                )
+                .map_err(|_| FrameError::AuthFailed)?
+        }
+    };
+
+    let sealed_len = body.len();
+    let compressed = flags & FLAG_COMPRESSED != 0;
+    let body = if compressed {
+        zstd::bulk::decompress(&body, MAX_BODY)
+            .map_err(|e| FrameError::Malformed(format!("decompression failed: {e}")))?
+    } else {
+        body
+    };
+    Ok(Decoded {
+        body,
+        compressed,
+        sealed_len,
+        signature,
+        wire_ms,
+    })
+}
+
+/// Fill the wire timestamp slot of a data frame, or of every frame in a
+/// batch, with `now_ms`. Control payloads and chunks have no slot and are
+/// returned unchanged.
+pub fn stamp_wire_ts(payload: Bytes, now_ms: u64) -> Bytes {
+    fn stamp(frame: &mut [u8], now_ms: u64) {
+        if frame.len() >= HEADER_LEN && frame[0] & FLAG_WIRE_TS != 0 {
+            frame[1..HEADER_LEN].copy_from_slice(&now_ms.to_le_bytes());
+        }
+    }
+
+    if is_batch(&payload) {
+        let mut batch = payload.to_vec();
+        let mut at = 1;
+        while let Some(prefix) = batch.get(at..at + BATCH_LEN_PREFIX) {
+            let len = u32::from_le_bytes(prefix.try_into().expect("4-byte prefix")) as usize;
+            let start = at + BATCH_LEN_PREFIX;
+            let Some(frame) = batch.get_mut(start..start + len) else {
+                break;
+            };
+            stamp(frame, now_ms);
+            at = start + len;
+        }
+        return Bytes::from(batch);
+    }
+    let stampable = payload
+        .first()
+        .is_some_and(|&flags| flags & FLAG_WIRE_TS != 0);
+    if !stampable || payload.len() < HEADER_LEN {
+        return payload;
+    }
+    let mut frame = payload.to_vec();
+    stamp(&mut frame, now_ms);
+    Bytes::from(frame)
+}
+
+/// Bytes a frame of `frame_len` adds to a batch.
+pub fn batch_entry_len(frame_len: usize) -> usize {
+    BATCH_LEN_PREFIX + frame_len
+}
+
+/// Pack complete frames into one batch payload; a single frame is returned
+/// unchanged, so a batch of one is the plain frame on the wire.
+pub fn encode_batch(frames: &[Bytes]) -> Bytes {
+    if let [frame] = frames {
+        return frame.clone();
+    }
+    let len = 1 + frames
+        .iter()
+        .map(|f| batch_entry_len(f.len()))
+        .sum::<usize>();
+    let mut batch = Vec::with_capacity(len);
+    batch.push(FLAG_BATCH);
+    for frame in frames {
+        batch.extend_from_slice(&(frame.len() as u32).to_le_bytes());
+        batch.extend_from_slice(frame);
+    }
+    Bytes::from(batch)
+}
+
+/// Whether a payload is a batch of frames.
+pub fn is_batch(payload: &[u8]) -> bool {
+    payload.first() == Some(&FLAG_BATCH)
+}
+
+/// The frames of a batch; None if the payload is not a well-formed one.
+pub fn decode_batch(payload: &Bytes) -> Option<Vec<Bytes>> {
+    if !is_batch(payload) {
+        return None;
+    }
+    let mut frames = Vec::new();
+    let mut at = 1;
+    while at < payload.len() {
+        let prefix = payload.get(at..at + BATCH_LEN_PREFIX)?;
+        let len = u32::from_le_bytes(prefix.try_into().expect("4-byte prefix")) as usize;
+        let start = at + BATCH_LEN_PREFIX;
+        if payload.len() < start + len {
+            return None;
+        }
+        frames.push(payload.slice(start..start + len));
+        at = start + len;
+    }
+    Some(frames)
+}
+
+/// Chunk data size that keeps every chunk within a gossip message limit of `max_message_size`.
+pub fn chunk_size_for(max_message_size: usize) -> usize {
+    max_message_size
+        .saturating_sub(GOSSIP_ENVELOPE_OVERHEAD + CHUNK_HEADER_LEN)
+        .max(1)
+}
+
+/// Split a frame into chunks carrying at most `chunk_bytes` of frame data each.
+///
+/// Frames that already fit are returned unchanged as a single payload.
+pub fn split(frame: Vec<u8>, test_id: &[u8; 16], seq: u64, chunk_bytes: usize) -> Vec<Vec<u8>> {
+    if frame.len() <= chunk_bytes + CHUNK_HEADER_LEN {
+        return vec![frame];
+    }
+    let count = frame.len().div_ceil(chunk_bytes) as u32;
+    frame
+        .chunks(chunk_bytes)
+        .enumerate()
+        .map(|(idx, data)| {
+            let mut chunk = Vec::with_capacity(CHUNK_HEADER_LEN + data.len());
+            chunk.push(FLAG_CHUNK);
+            chunk.extend_from_slice(test_id);
+            chunk.extend_from_slice(&seq.to_le_bytes());
+            chunk.extend_from_slice(&(idx as u32).to_le_bytes());
+            chunk.extend_from_slice(&count.to_le_bytes());
+            chunk.extend_from_slice(data);
+            chunk
+        })
+        .collect()
+}
+
+/// Whether a payload is a chunk rather than a complete frame.
+pub fn is_chunk(payload: &[u8]) -> bool {
+    payload.first() == Some(&FLAG_CHUNK)
+}
+
+/// Most chunks a frame may be split into; chunk headers claiming more are
+/// dropped before anything is allocated for them.
+pub const MAX_CHUNK_COUNT: usize = 1 << 16;
+
+/// Partially received frame.
+#[derive(Debug)]
+struct Partial {
+    chunks: Vec<Option<Vec<u8>>>,
+    received: usize,
+    /// Chunk data plus the slot table, as charged against `max_bytes`.
+    bytes: usize,
+    started_ms: u64,
+}
+
+/// Receiver-side reassembly of chunked frames.
+///
+/// Partials older than `timeout_ms` are dropped by `expire`; when buffered
+/// chunk data (and the chunk slots of each partial) exceeds `max_bytes`, the
+/// oldest partials are evicted. Both count as incomplete reassemblies. Chunk
+/// headers are untrusted: a count above `MAX_CHUNK_COUNT`, or one that makes
+/// the frame larger than `max_bytes`, drops the chunk.
+#[derive(Debug)]
+pub struct Reassembler {
+    timeout_ms: u64,
+    max_bytes: usize,
+    buffered: usize,
+    partials: HashMap<([u8; 16], u64), Partial>,
+    /// Frames already completed, so late duplicate chunks don't start a new partial.
+    completed: HashMap<([u8; 16], u64), u64>,
+}
+
+impl Reassembler {
+    pub fn new(timeout_ms: u64, max_bytes: usize) -> Self {
+        Self {
+            timeout_ms,
+            max_bytes,
+            buffered: 0,
+            partials: HashMap::new(),
+            completed: HashMap::new(),
+        }
+    }
+
+    /// Add a chunk; returns the complete frame once all chunks of it arrived.
+    ///
+    /// Malformed chunks are ignored. `incomplete` is increased for every partial
+    /// evicted to stay within the memory cap.
+    pub fn push(&mut self, chunk: &[u8], now_ms: u64, incomplete: &mut u64) -> Option<Vec<u8>> {
+        if chunk.len() < CHUNK_HEADER_LEN || !is_chunk(chunk) {
+            return None;
+        }
+        let test_id: [u8; 16] = chunk[1..17].try_into().ok()?;
+        let seq = u64::from_le_bytes(chunk[17..25].try_into().ok()?);
+        let idx = u32::from_le_bytes(chunk[25..29].try_into().ok()?) as usize;
+        let count = u32::from_le_bytes(chunk[29..33].try_into().ok()?) as usize;
+        let data = &chunk[CHUNK_HEADER_LEN..];
+        let key = (test_id, seq);
+        if count == 0
+            || count > MAX_CHUNK_COUNT
+            || idx >= count
+            || (count - 1).saturating_mul(data.len()) > self.max_bytes
+            || self.completed.contains_key(&key)
+        {
+            return None;
+        }
+
+        let partial = match self.partials.entry(key) {
+            Entry::Occupied(e) => e.into_mut(),
+            Entry::Vacant(e) => {
+                let slots = count * std::mem::size_of::<Option<Vec<u8>>>();
+                self.buffered += slots;
+                e.insert(Partial {
+                    chunks: vec![None; count],
+                    received: 0,
+                    bytes: slots,
+                    started_ms: now_ms,
+                })
+            }
+        };
+        if partial.chunks.len() != count || partial.chunks[idx].is_some() {
+            return None;
+        }
+        partial.chunks[idx] = Some(data.to_vec());
+        partial.received += 1;
+        partial.bytes += data.len();
+        self.buffered += data.len();
+
+        if partial.received == count {
+            let partial = self.partials.remove(&key)?;
+            self.buffered -= partial.bytes;
+            self.completed.insert(key, now_ms);
+            return Some(partial.chunks.into_iter().flatten().flatten().collect());
+        }
+
+        while self.buffered > self.max_bytes {
+            let Some(oldest) = self
+                .partials
+                .iter()
+                .min_by_key(|(_, p)| p.started_ms)
+                .map(|(k, _)| *k)
+            else {
+                break;
+            };
+            self.drop_partial(&oldest, now_ms);
+            *incomplete += 1;
+        }
+        None
+    }
+
+    /// Drop partials older than the timeout; returns how many were dropped.
+    pub fn expire(&mut self, now_ms: u64) -> u64 {
+        let cutoff = now_ms.saturating_sub(self.timeout_ms);
+        let stale: Vec<_> = self
+            .partials
+            .iter()
+            .filter(|(_, p)| p.started_ms <= cutoff)
+            .map(|(k, _)| *k)
+            .collect();
+        for key in &stale {
+            self.drop_partial(key, now_ms);
+        }
+        self.completed.retain(|_, &mut done| done > cutoff);
+        stale.len() as u64
+    }
+
+    /// Number of partials still pending (incomplete if the run ends now).
+    pub fn pending(&self) -> u64 {
+        self.partials.len() as u64
+    }
+
+    /// Time (ms) at which the oldest partial times out, if any is pending.
+    pub fn next_expiry(&self) -> Option<u64> {
+        self.partials
+            .values()
+            .map(|p| p.started_ms + self.timeout_ms)
+            .min()
+    }
+
+    fn drop_partial(&mut self, key: &([u8; 16], u64), now_ms: u64) {
+        if let Some(p) = self.partials.remove(key) {
+            self.buffered -= p.bytes;
+            self.completed.insert(*key, now_ms);
+        }
+    }
+}
+
+/// Encode a heartbeat control payload.
+pub fn encode_heartbeat(heartbeat: &Heartbeat) -> Result<Vec<u8>> {
+    let mut payload = vec![FLAG_HEARTBEAT];
+    payload.extend(postcard::to_allocvec(heartbeat)?);
+    Ok(payload)
+}
+
+/// Whether a payload is a heartbeat rather than a data frame.
+pub fn is_heartbeat(payload: &[u8]) -> bool {
+    payload.first() == Some(&FLAG_HEARTBEAT)
+}
+
+/// Decode a heartbeat; `None` if the payload is not one.
+pub fn decode_heartbeat(payload: &[u8]) -> Option<Heartbeat> {
+    match payload.split_first() {
+        Some((&FLAG_HEARTBEAT, body)) => postcard::from_bytes(body).ok(),
+        _ => None,
+    }
+}
+
+/// Encode a receiver announcement control payload.
+pub fn encode_announce(alive: &ReceiverAlive) -> Result<Vec<u8>> {
+    let mut payload = vec![FLAG_ANNOUNCE];
+    payload.extend(postcard::to_allocvec(alive)?);
+    Ok(payload)
+}
+
+/// Whether a payload is a receiver announcement rather than a data frame.
+pub fn is_announce(payload: &[u8]) -> bool {
+    payload.first() == Some(&FLAG_ANNOUNCE)
+}
+
+/// Decode a receiver announcement; `None` if the payload is not one.
+pub fn decode_announce(payload: &[u8]) -> Option<ReceiverAlive> {
+    match payload.split_first() {
+        Some((&FLAG_ANNOUNCE, body)) => postcard::from_bytes(body).ok(),
+        _ => None,
+    }
+}
+
+/// Encode a receiver ack control payload.
+pub fn encode_ack(ack: &AckUpTo) -> Result<Vec<u8>> {
+    let mut payload = vec![FLAG_ACK];
+    payload.extend(postcard::to_allocvec(ack)?);
+    Ok(payload)
+}
+
+/// Whether a payload is a receiver ack rather than a data frame.
+pub fn is_ack(payload: &[u8]) -> bool {
+    payload.first() == Some(&FLAG_ACK)
+}
+
+/// Decode a receiver ack; `None` if the payload is not one.
+pub fn decode_ack(payload: &[u8]) -> Option<AckUpTo> {
+    match payload.split_first() {
+        Some((&FLAG_ACK, body)) => postcard::from_bytes(body).ok(),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const TEST_ID: [u8; 16] = [9; 16];
+
+    /// A chunk header for `seq` claiming `idx` of `count`, followed by `data`.
+    fn chunk(seq: u64, idx: u32, count: u32, data: &[u8]) -> Vec<u8> {
+        let mut chunk = vec![FLAG_CHUNK];
+        chunk.extend_from_slice(&TEST_ID);
+        chunk.extend_from_slice(&seq.to_le_bytes());
+        chunk.extend_from_slice(&idx.to_le_bytes());
+        chunk.extend_from_slice(&count.to_le_bytes());
+        chunk.extend_from_slice(data);
+        chunk
+    }
+
+    #[test]
+    fn split_and_reassemble_out_of_order() {
+        let frame: Vec<u8> = (0..=255u8).cycle().take(1000).collect();
+        let mut chunks = split(frame.clone(), &TEST_ID, 3, 300);
+        assert_eq!(chunks.len(), 4);
+        assert!(
+            chunks
+                .iter()
+                .all(|c| is_chunk(c) && c.len() <= 300 + CHUNK_HEADER_LEN)
+        );
+        chunks.reverse();
+
+        let mut r = Reassembler::new(1000, 1 << 20);
+        let mut incomplete = 0;
+        assert_eq!(r.push(&chunks[0], 0, &mut incomplete), None);
+        // A repeated chunk does not count twice.
+        assert_eq!(r.push(&chunks[0], 0, &mut incomplete), None);
+        assert_eq!(r.push(&chunks[1], 1, &mut incomplete), None);
+        assert_eq!(r.push(&chunks[2], 2, &mut incomplete), None);
+        assert_eq!(r.push(&chunks[3], 3, &mut incomplete), Some(frame));
+        assert_eq!((r.pending(), r.buffered, incomplete), (0, 0, 0));
+        // A late copy of a completed frame's chunk starts no new partial.
+        assert_eq!(r.push(&chunks[1], 4, &mut incomplete), None);
+        assert_eq!(r.pending(), 0);
+    }
+
+    #[test]
+    fn frames_that_fit_are_not_split() {
+        let frame = vec![1u8; 300 + CHUNK_HEADER_LEN];
+        assert_eq!(split(frame.clone(), &TEST_ID, 0, 300), vec![frame]);
+        assert_eq!(
+            chunk_size_for(1024),
+            1024 - GOSSIP_ENVELOPE_OVERHEAD - CHUNK_HEADER_LEN
+        );
+        assert_eq!(chunk_size_for(10), 1);
+    }
+
+    #[test]
+    fn implausible_chunk_headers_are_dropped() {
+        let mut r = Reassembler::new(1000, 4096);
+        let mut incomplete = 0;
+        let bad = [
+            chunk(1, 0, 0, b"x"),
+            chunk(1, 2, 2, b"x"),
+            chunk(1, 0, MAX_CHUNK_COUNT as u32 + 1, b"x"),
+            // 100 chunks of 100 bytes would exceed the 4 KiB cap.
+            chunk(1, 0, 100, &[0; 100]),
+            chunk(1, 0, 2, b"")[..CHUNK_HEADER_LEN - 1].to_vec(),
+        ];
+        for c in &bad {
+            assert_eq!(r.push(c, 0, &mut incomplete), None);
+        }
+        assert_eq!((r.pending(), r.buffered, incomplete), (0, 0, 0));
+        // A chunk disagreeing with its partial's count is ignored.
+        assert_eq!(r.push(&chunk(2, 0, 2, b"a"), 0, &mut incomplete), None);
+        assert_eq!(r.push(&chunk(2, 1, 3, b"b"), 0, &mut incomplete), None);
+        assert_eq!(
+            r.push(&chunk(2, 1, 2, b"b"), 0, &mut incomplete),
+            Some(b"ab".to_vec())
+        );
+    }
+
+    #[test]
+    fn memory_cap_evicts_the_oldest_partial() {
+        let slots = 2 * std::mem::size_of::<Option<Vec<u8>>>();
+        let mut r = Reassembler::new(1000, 2 * (slots + 100) + 50);
+        let mut incomplete = 0;
+        r.push(&chunk(1, 0, 2, &[0; 100]), 10, &mut incomplete);
+        r.push(&chunk(2, 0, 2, &[0; 100]), 20, &mut incomplete);
+        assert_eq!((r.pending(), incomplete), (2, 0));
+        r.push(&chunk(3, 0, 2, &[0; 100]), 30, &mut incomplete);
+        assert_eq!((r.pending(), incomplete), (2, 1));
+        assert!(r.buffered <= r.max_bytes);
+        // seq 1 was evicted: its second chunk no longer completes anything.
+        assert_eq!(
+            r.push(&chunk(1, 1, 2, &[0; 100]), 40, &mut incomplete),
+            None
+        );
+        assert!(
+            r.push(&chunk(2, 1, 2, &[0; 100]), 40, &mut incomplete)
+                .is_some()
+        );
+    }
+
+    #[test]
+    fn partials_time_out() {
+        let mut r = Reassembler::new(500, 1 << 20);
+        let mut incomplete = 0;
+        r.push(&chunk(1, 0, 2, b"a"), 100, &mut incomplete);
+        r.push(&chunk(2, 0, 2, b"b"), 300, &mut incomplete);
+        assert_eq!(r.next_expiry(), Some(600));
+        assert_eq!(r.expire(599), 0);
+        assert_eq!(r.expire(600), 1);
+        assert_eq!((r.pending(), r.next_expiry()), (1, Some(800)));
+        assert_eq!(r.push(&chunk(1, 1, 2, b"a"), 601, &mut incomplete), None);
+        assert_eq!(r.expire(800), 1);
+        assert_eq!((r.pending(), r.buffered), (0, 0));
+    }
+    fn frame(body: &[u8]) -> Bytes {
+        Bytes::from(encode(body, Compression::None, None, None).unwrap())
+    }
+
+    #[test]
+    fn batch_of_one_is_the_plain_frame() {
+        let f = frame(b"only");
+        assert_eq!(encode_batch(std::slice::from_ref(&f)), f);
+        assert!(!is_batch(&f));
+        assert_eq!(decode_batch(&f), None);
+    }
+
+    #[test]
+    fn batch_roundtrip_and_wire_stamps() {
+        let frames = [frame(b"a"), frame(b""), frame(&[7; 300])];
+        let batch = encode_batch(&frames);
+        assert!(is_batch(&batch) && !is_chunk(&batch));
+        let len = 1 + frames
+            .iter()
+            .map(|f| batch_entry_len(f.len()))
+            .sum::<usize>();
+        assert_eq!(batch.len(), len);
+        assert_eq!(decode_batch(&batch).unwrap(), frames);
+
+        let stamped = decode_batch(&stamp_wire_ts(batch, 1234)).unwrap();
+        for (inner, orig) in stamped.iter().zip(&frames) {
+            let decoded = decode(inner, None).unwrap();
+            assert_eq!(decoded.wire_ms, Some(1234));
+            assert_eq!(decoded.body, decode(orig, None).unwrap().body);
+        }
+    }
+
+    #[test]
+    fn malformed_batches_are_rejected() {
+        let batch = encode_batch(&[frame(b"a"), frame(b"b")]);
+        // Cut inside the last frame, and inside its length prefix.
+        assert_eq!(decode_batch(&batch.slice(..batch.len() - 1)), None);
+        let second = 1 + batch_entry_len(frame(b"a").len());
+        assert_eq!(decode_batch(&batch.slice(..second + 2)), None);
+        // A length prefix claiming more than the payload holds.
+        let mut lying = vec![FLAG_BATCH];
+        lying.extend_from_slice(&u32::MAX.to_le_bytes());
+        lying.extend_from_slice(b"short");
+        assert_eq!(decode_batch(&Bytes::from(lying)), None);
+        // An empty batch has no frames.
+        assert_eq!(
+            decode_batch(&Bytes::from_static(&[FLAG_BATCH])),
+            Some(vec![])
+        );
+    }
+
+    #[test]
+    fn signature_covers_test_id_seq_and_sent_ms() {
+        let secret = SecretKey::from_bytes(&rand::random());
+        let sig = sign(&secret, &TEST_ID, 5, 1000);
+        let frame = encode(b"body", Compression::None, None, Some(&sig)).unwrap();
+        let decoded = decode(&frame, None).unwrap();
+        assert_eq!(decoded.body, b"body");
+        let sig = decoded.signature.expect("signed frame");
+        assert_eq!(sig.check(&TEST_ID, 5, 1000, &[]), Ok(secret.public()));
+
+        let bad = Err(SigReject::BadSignature);
+        assert_eq!(sig.check(&[8; 16], 5, 1000, &[]), bad);
+        assert_eq!(sig.check(&TEST_ID, 6, 1000, &[]), bad);
+        assert_eq!(sig.check(&TEST_ID, 5, 1001, &[]), bad);
+    }
+
+    #[test]
+    fn signer_must_be_on_the_allow_list() {
+        let secret = SecretKey::from_bytes(&rand::random());
+        let other = SecretKey::from_bytes(&rand::random()).public();
+        let sig = sign(&secret, &TEST_ID, 0, 0);
+        assert_eq!(
+            sig.check(&TEST_ID, 0, 0, &[other]),
+            Err(SigReject::NotAllowed)
+        );
+        assert_eq!(
+            sig.check(&TEST_ID, 0, 0, &[other, secret.public()]),
+            Ok(secret.public())
+        );
+    }
+
+    #[test]
+    fn unsigned_and_truncated_signed_frames() {
+        let frame = encode(b"body", Compression::None, None, None).unwrap();
+        assert!(decode(&frame, None).unwrap().signature.is_none());
+
+        let sig = sign(&SecretKey::from_bytes(&rand::random()), &TEST_ID, 0, 0);
+        let signed = encode(b"", Compression::None, None, Some(&sig)).unwrap();
+        let err = decode(&signed[..HEADER_LEN + SIGNATURE_LEN - 1], None).unwrap_err();
+        assert!(matches!(err, FrameError::Malformed(_)), "{err}");
+    }
+}
@@ -0,0 +1,230 @@
+//! InfluxDB sink (`--influx-url`): every log event as one line-protocol point,
+//! pushed in batches to `<url>/write?db=<db>&precision=ms`.
+//!
+//! The measurement is the event kind, tags are role, peer ID and run ID, and
+//! fields are `elapsed_ms`, `seq`, `lat_ms`, `ldh` and the numeric top-level
+//! keys of `extra`. Points are queued without blocking and posted from a
+//! background task; a full queue or a batch that still fails after the retries
+//! is dropped and counted, never reported as an error to the benchmark.
+
+use crate::metrics::{EventSink, InfluxCounters, LogEvent};
+use serde_json::Value;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, sleep, sleep_until};
+use tracing::warn;
+
+/// Longest a partial batch waits before it is posted.
+const FLUSH_MS: u64 = 1000;
+
+/// Attempts per batch, with exponential backoff starting at `RETRY_BASE_MS`.
+const ATTEMPTS: u32 = 3;
+const RETRY_BASE_MS: u64 = 250;
+
+/// Per-request timeout, so a dead server only delays the queue.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// Sink settings from the CLI.
+#[derive(Debug, Clone)]
+pub struct InfluxOptions {
+    /// Server base URL, e.g. `http://host:8086`.
+    pub url: String,
+    pub db: String,
+    /// Points per POST.
+    pub batch: usize,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    sent: AtomicU64,
+    dropped: AtomicU64,
+    failures: AtomicU64,
+    /// Points queued but not yet posted or dropped.
+    pending: AtomicU64,
+}
+
+/// The writing half, one of the run's `MultiSink` sinks.
+pub struct InfluxSink {
+    tx: mpsc::Sender<String>,
+    counters: Arc<Counters>,
+}
+
+/// The owning half: counter snapshots and the final flush.
+pub struct InfluxHandle {
+    counters: Arc<Counters>,
+    task: JoinHandle<()>,
+}
+
+/// Start the background poster. Fails only on an invalid URL.
+pub fn spawn(opts: InfluxOptions) -> anyhow::Result<(InfluxSink, InfluxHandle)> {
+    let mut url = reqwest::Url::parse(&opts.url)
+        .map_err(|e| anyhow::anyhow!("invalid --influx-url {}: {e}", opts.url))?;
+    url.set_path(&format!("{}/write", url.path().trim_end_matches('/')));
+    url.query_pairs_mut()
+        .append_pair("db", &opts.db)
+        .append_pair("precision", "ms");
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()?;
+
+    let batch = opts.batch.max(1);
+    let (tx, rx) = mpsc::channel(batch * 10);
+    let counters = Arc::new(Counters::default());
+    let task = tokio::spawn(post_loop(client, url, rx, batch, counters.clone()));
+    Ok((
+        InfluxSink {
+            tx,
+            counters: counters.clone(),
+        },
+        InfluxHandle { counters, task },
+    ))
+}
+
+impl EventSink for InfluxSink {
+    fn name(&self) -> &'static str {
+        "influx"
+    }
+
+    fn write(&mut self, ev: &LogEvent) -> anyhow::Result<()> {
+        match self.tx.try_send(line_protocol(ev)) {
+            Ok(()) => {
+                self.counters.pending.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(_) => {
+                self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl InfluxHandle {
+    /// Counters so far, for the summary.
+    pub fn counters(&self) -> InfluxCounters {
+        InfluxCounters {
+            points_sent: self.counters.sent.load(Ordering::Relaxed),
+            points_dropped: self.counters.dropped.load(Ordering::Relaxed),
+            post_failures: self.counters.failures.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Give queued points up to `wait` to go out, then stop the poster.
+    pub async fn finish(self, wait: Duration) {
+        let deadline = Instant::now() + wait;
+        while self.counters.pending.load(Ordering::Relaxed) > 0 && Instant::now() < deadline {
+            sleep(Duration::from_millis(50)).await;
+        }
+        self.task.abort();
+    }
+}
+
+/// Collect lines into batches of `batch` (or whatever arrived within
+/// `FLUSH_MS`) and post them until the queue closes.
+async fn post_loop(
+    client: reqwest::Client,
+    url: reqwest::Url,
+    mut rx: mpsc::Receiver<String>,
+    batch: usize,
+    counters: Arc<Counters>,
+) {
+    while let Some(first) = rx.recv().await {
+        let mut lines = vec![first];
+        let deadline = Instant::now() + Duration::from_millis(FLUSH_MS);
+        while lines.len() < batch {
+            tokio::select! {
+                line = rx.recv() => match line {
+                    Some(line) => lines.push(line),
+                    None => break,
+                },
+                _ = sleep_until(deadline) => break,
+            }
+        }
+
+        let n = lines.len() as u64;
+        let body = lines.join("\n");
+        let mut posted = false;
+        for attempt in 0..ATTEMPTS {
+            if attempt > 0 {
+                sleep(Duration::from_millis(RETRY_BASE_MS << (attempt - 1))).await;
+            }
+            let res = client.post(url.clone()).body(body.clone()).send().await;
+            match res.and_then(|r| r.error_for_status()) {
+                Ok(_) => {
+                    posted = true;
+                    break;
+                }
+                Err(e) => {
+                    counters.failures.fetch_add(1, Ordering::Relaxed);
+                    warn!("influx post failed (attempt {}): {e}", attempt + 1);
+                }
+            }
+        }
+        let counter = if posted {
+            &counters.sent
+        } else {
+            &counters.dropped
+        };
+        counter.fetch_add(n, Ordering::Relaxed);
+        counters.pending.fetch_sub(n, Ordering::Relaxed);
+    }
+}
+
+/// Encode one event as a line-protocol point (ms timestamp).
+fn line_protocol(ev: &LogEvent) -> String {
+    let mut line = escape(ev.event.as_str(), ", ");
+    for (key, value) in [
+        ("role", Some(ev.role)),
+        ("peer_id", Some(ev.peer_id)),
+        ("run_id", ev.run_id),
+    ] {
+        if let Some(value) = value.filter(|v| !v.is_empty()) {
+            line.push_str(&format!(",{key}={}", escape(value, ", =")));
+        }
+    }
+
+    let mut fields = Vec::new();
+    for (key, value) in [
+        ("elapsed_ms", ev.elapsed_ms),
+        ("seq", ev.seq),
+        ("lat_ms", ev.lat_ms),
+        ("ldh", ev.ldh.map(u64::from)),
+    ] {
+        if let Some(v) = value {
+            fields.push(format!("{key}={v}i"));
+        }
+    }
+    if let Value::Object(extra) = &ev.extra {
+        for (key, value) in extra {
+            let Value::Number(n) = value else {
+                continue;
+            };
+            let value = match (n.as_u64(), n.as_i64(), n.as_f64()) {
+                (Some(u), _, _) => format!("{u}i"),
+                (None, Some(i), _) => format!("{i}i"),
+                (None, None, Some(f)) if f.is_finite() => f.to_string(),
+                _ => continue,
+            };
+            fields.push(format!("extra_{}={value}", escape(key, ", =")));
+        }
+    }
+    // `elapsed_ms` is stamped by `EventSink::emit`, so there is always a field.
+    line.push(' ');
+    line.push_str(&fields.join(","));
+    line.push_str(&format!(" {}", ev.ts_ms));
+    line
+}
+
+/// Backslash-escape every character of `special` in `s`.
+fn escape(s: &str, special: &str) -> String {
+    let mut out = String::with_capacity(s.len());
+    for c in s.chars() {
+        if special.contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+    }
+    out
+}
@@ -0,0 +1,22 @@
+//! iroh-gossip reliability experiments as a library: the transports, the
+//! sender/receiver run loops and the offline reports behind the
+//! `iroh-gossip-metrics` binary.
+//!
+//! `create` builds a transport by kind; `runner` drives it.
+
+pub mod config;
+pub mod control;
+pub mod forward;
+pub mod frame;
+pub mod influx;
+pub mod metrics;
+pub mod otlp;
+pub mod replay;
+pub mod report;
+pub mod runner;
+pub mod scenario;
+pub mod selftest;
+pub mod transport;
+pub mod util;
+
+pub use transport::{Transport, TransportKind, TransportOpts, create};
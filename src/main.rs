@@ -1,73 +1,1006 @@
-mod metrics;
-mod transport;
-mod util;
-
 use anyhow::Result;
-use clap::Parser;
+use clap::{Parser, Subcommand};
+use iroh_gossip::proto::TopicId;
+use serde::Serialize;
+use std::io::IsTerminal;
+use std::path::PathBuf;
+use std::str::FromStr;
 use tracing_subscriber::EnvFilter;
 
-use crate::transport::{Discovery, IrohGossip, Transport};
+use iroh_gossip_metrics::metrics::{EventSink, QuantileMethod, StatsConfig};
+use iroh_gossip_metrics::runner::{ReceiverOptions, RepairOptions, SenderOptions};
+use iroh_gossip_metrics::transport::{
+    BroadcastScope, Discovery, Transport, TransportKind, TransportOpts,
+};
+use iroh_gossip_metrics::util::diag;
+use iroh_gossip_metrics::{
+    config, control, forward, frame, influx, metrics, otlp, replay, report, runner, scenario,
+    selftest, transport, util,
+};
+
+/// Payload size unless `--size` is given.
+const DEFAULT_SIZE: usize = 256;
 
 /// Command-line interface for iroh-gossip reliability experiments
-#[derive(Parser, Debug)]
-#[command(version, about = "Reliability lab for UDP vs iroh-gossip")]
+///
+/// Every flag can also be set through an `IGM_<FLAG>` environment variable
+/// (e.g. `IGM_TOPIC_HEX`), or a `.env` file in the working directory; flags
+/// given on the command line take precedence.
+#[derive(Parser, Debug, Serialize)]
+#[command(
+    version,
+    about = "Reliability lab for UDP vs iroh-gossip",
+    after_help = "Durations accept 500ms, 3s, 2m or 1h; bare numbers are milliseconds.\n\
+                  Every flag can also be set as IGM_<FLAG> in the environment or a .env file."
+)]
 struct Args {
-    /// Role: sender or receiver
-    #[arg(long, value_parser = ["sender","receiver"])]
-    role: String,
+    /// Role: sender or receiver (required except for offline subcommands)
+    #[arg(long, env = "IGM_ROLE", value_parser = ["sender","receiver"])]
+    role: Option<String>,
+
+    /// Path to JSONL log file (default lab.log.jsonl, or log.jsonl in the --out-dir run directory)
+    #[arg(long, env = "IGM_LOG")]
+    log: Option<String>,
+
+    /// Collect this run's artifacts in <dir>/<run_id>/: log.jsonl, summary.json,
+    /// config.json and stderr.txt (--log and --summary-out still take precedence)
+    #[arg(long, env = "IGM_OUT_DIR")]
+    out_dir: Option<PathBuf>,
+
+    /// Also write the final summary JSON to this file
+    #[arg(long, env = "IGM_SUMMARY_OUT")]
+    summary_out: Option<PathBuf>,
+
+    /// Also write the CSV series of `export` (latency, sends, peers, events)
+    /// into this directory while the run goes
+    #[arg(long, env = "IGM_CSV_DIR")]
+    csv_dir: Option<PathBuf>,
+
+    /// Also push every log event to InfluxDB at this base URL (e.g. http://host:8086)
+    #[arg(long, env = "IGM_INFLUX_URL")]
+    influx_url: Option<String>,
+
+    /// InfluxDB database for --influx-url
+    #[arg(long, env = "IGM_INFLUX_DB", default_value = "gossip")]
+    influx_db: String,
+
+    /// Points per InfluxDB write request
+    #[arg(long, env = "IGM_INFLUX_BATCH", default_value_t = 100)]
+    influx_batch: usize,
 
-    /// Path to JSONL log file
-    #[arg(long, default_value = "lab.log.jsonl")]
-    log: String,
+    /// Export a trace span per sampled message to this OTLP/HTTP collector
+    /// (e.g. http://jaeger:4318)
+    #[arg(long, env = "IGM_OTLP_ENDPOINT")]
+    otlp_endpoint: Option<String>,
+
+    /// Fraction of messages traced with --otlp-endpoint; sender and receivers
+    /// pick the same ones
+    #[arg(long, env = "IGM_OTLP_SAMPLE", default_value_t = 0.01, value_parser = util::parse_probability)]
+    otlp_sample: f64,
 
     /// Total number of messages to send
-    #[arg(long, default_value_t = 2000)]
+    #[arg(long, env = "IGM_NUM", default_value_t = 2000)]
     num: u64,
 
     /// Send rate (messages per second)
-    #[arg(long, default_value_t = 50)]
+    #[arg(long, env = "IGM_RATE", default_value_t = 50)]
     rate: u64,
 
-    /// Payload size in bytes
-    #[arg(long, default_value_t = 256)]
-    size: usize,
-
-    /// Percentage of peers churned in the scenario (0-100), for logging/analysis
-    #[arg(long, default_value_t = 0)]
-    churn_pct: u64,
-
-    /// Milliseconds to wait after receive before summarizing
-    #[arg(long, default_value_t = 3000)]
-    idle_report_ms: u64,
-
-    /// Topic hex ID (optional)
-    #[arg(long)]
-    topic_hex: Option<String>,
-
-    /// Topic name (used if no hex is given)
-    #[arg(long)]
-    topic_name: Option<String>,
-    #[arg(long)]
+    /// Payload size in bytes (default 256); on a receiver it bounds --max-recv-bytes
+    /// and is the size arrivals are checked against until a heartbeat announces one
+    #[arg(long, env = "IGM_SIZE")]
+    size: Option<usize>,
+
+    /// Receiver: drop gossip messages above this many bytes before decoding and
+    /// count them as oversized_msgs (default: --size plus framing if given,
+    /// else the gossip maximum message size)
+    #[arg(long, env = "IGM_MAX_RECV_BYTES", value_name = "BYTES")]
+    max_recv_bytes: Option<usize>,
+
+    /// Percentage of peers churned in the scenario (0-100), for logging/analysis
+    #[arg(long, env = "IGM_CHURN_PCT", default_value_t = 0)]
+    churn_pct: u64,
+
+    /// How long to wait after receive before summarizing
+    #[arg(long = "idle-report", env = "IGM_IDLE_REPORT", alias = "idle-report-ms", default_value_t = 3000, value_name = "DURATION", value_parser = util::parse_duration)]
+    idle_report_ms: u64,
+
+    /// Receiver: never exit after a test; summarize each test as it completes
+    /// or goes idle (into --out-dir/<test_id>.json and a summary log event)
+    /// and keep listening until Ctrl-C
+    #[arg(long, env = "IGM_CONTINUOUS", default_value_t = false)]
+    continuous: bool,
+
+    /// Receiver: once all expected messages arrived, summarize after this long
+    /// (trailing duplicates are still counted)
+    #[arg(long = "complete-grace", env = "IGM_COMPLETE_GRACE", alias = "complete-grace-ms", default_value_t = 250, value_name = "DURATION", value_parser = util::parse_duration)]
+    complete_grace_ms: u64,
+
+    /// Receiver: also wake the loop at least this often (default: only on
+    /// events and the next idle/sampling deadline)
+    #[arg(long = "poll-floor", env = "IGM_POLL_FLOOR", alias = "poll-floor-ms", value_name = "DURATION", value_parser = util::parse_duration)]
+    poll_floor_ms: Option<u64>,
+
+    /// Receiver: log only every Nth recv event (plus the first, last and anomalous ones);
+    /// statistics still count every message
+    #[arg(long, env = "IGM_LOG_SAMPLE_RECV", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    log_sample_recv: u64,
 
+    /// Abort the run on the first failed log write (default: count failures in
+    /// log_write_errors and keep going, so the summary is still produced)
+    #[arg(long, env = "IGM_STRICT_LOGGING")]
+    strict_logging: bool,
+
+    /// Receiver: broadcast a liveness announcement (peer ID + unique count) at this
+    /// interval, so the sender can count live receivers
+    #[arg(long = "announce", env = "IGM_ANNOUNCE", alias = "announce-ms", value_name = "DURATION", value_parser = util::parse_duration)]
+    announce_ms: Option<u64>,
+
+    /// Receiver: broadcast a cumulative ack every N new messages (and on completion);
+    /// sender: collect the acks and report the ack lag per receiver
+    #[arg(long, env = "IGM_ACK_EVERY", value_name = "N", value_parser = clap::value_parser!(u64).range(1..))]
+    ack_every: Option<u64>,
+
+    /// Receiver: start the run as soon as the endpoint is bound and join the topic
+    /// in the background; the idle timeout then counts from join completion
+    #[arg(long, env = "IGM_JOIN_IN_BACKGROUND")]
+    join_in_background: bool,
+
+    /// Debug: never subscribe to the topic, so the run goes down the never-joined
+    /// paths (no_join event, joined: false, diagnosis join_skipped)
+    #[arg(long, env = "IGM_FORCE_NO_JOIN")]
+    force_no_join: bool,
+
+    /// Topic hex ID (optional; repeatable or comma-separated to subscribe to several topics)
+    #[arg(long, env = "IGM_TOPIC_HEX", value_delimiter = ',', value_parser = util::parse_topic_hex)]
+    topic_hex: Vec<String>,
+
+    /// Topic name (repeatable or comma-separated; not together with --topic-hex)
+    #[arg(
+        long,
+        env = "IGM_TOPIC_NAME",
+        value_delimiter = ',',
+        conflicts_with = "topic_hex"
+    )]
+    topic_name: Vec<String>,
+
+    /// How --topic-name is hashed into a topic ID: v1 (domain-separated) or
+    /// legacy (plain blake3 of the name, for older deployments)
+    #[arg(long, env = "IGM_TOPIC_DERIVATION", default_value = "v1", value_parser = ["v1", "legacy"])]
+    topic_derivation: String,
+
+    /// Sender: subscribed topic (hex ID or name) that carries the data stream (default: the first)
+    #[arg(long, env = "IGM_SEND_TOPIC")]
+    send_topic: Option<String>,
     /// Optional secret key (32-byte hex)
+    #[arg(long, env = "IGM_SECRET_HEX", value_parser = util::parse_secret_hex)]
     secret_hex: Option<String>,
 
-    /// Comma-separated list of bootstrap node IDs (for relay discovery)
-    #[arg(long, default_value = "")]
+    /// Comma-separated list of bootstrap node IDs (for relay discovery), or
+    /// socket addresses (ip:port) with --transport udp
+    #[arg(long, env = "IGM_BOOTSTRAP", default_value = "")]
     bootstrap: String,
 
-    #[arg(long, default_value = "direct", value_parser = ["direct", "relay"])]
+    /// Skip --bootstrap entries that are not valid node IDs (addresses) instead of failing
+    #[arg(long, env = "IGM_IGNORE_BAD_BOOTSTRAP")]
+    ignore_bad_bootstrap: bool,
+
+    #[arg(long, env = "IGM_DISCOVERY", default_value = "direct", value_parser = ["direct", "relay"])]
     discovery: String,
+
+    /// Transport implementation: iroh (gossip), udp (a datagram to every
+    /// known peer) or mem (in-process only, for tests)
+    #[arg(long, env = "IGM_TRANSPORT", default_value = "iroh", value_parser = ["iroh", "udp", "mem"])]
+    transport: String,
+
+    /// Local address of the udp transport's socket (default: an ephemeral port);
+    /// receivers pass it to --bootstrap
+    #[arg(long, env = "IGM_UDP_BIND")]
+    udp_bind: Option<std::net::SocketAddr>,
+
+    /// Comma-separated percentiles (0-100) reported in the summary percentile maps
+    #[arg(long, env = "IGM_PERCENTILES", value_delimiter = ',', default_value = "50,90,99", value_parser = util::parse_percentile)]
+    percentiles: Vec<f64>,
+
+    /// Quantile estimator for the percentile maps: nearest-rank or linear interpolation
+    #[arg(long, env = "IGM_QUANTILE_METHOD", default_value = "nearest", value_parser = ["nearest", "linear"])]
+    quantile_method: String,
+
+    /// Number of recent sequences whose first-arrival time is kept for duplicate spacing
+    #[arg(long, env = "IGM_DUP_WINDOW", default_value_t = 10_000)]
+    dup_window: u64,
+
+    /// Furthest a seq may run ahead of the highest one seen (and a test's total
+    /// ahead of the expected one) before the message is rejected as implausible
+    #[arg(long, env = "IGM_SEQ_WINDOW", default_value_t = 1_000_000)]
+    seq_window: u64,
+
+    /// Also report latency percentiles without the slowest X % of samples
+    /// (lat_p99_trimmed, ...), e.g. to keep one cold-start delivery out of p99
+    #[arg(long, env = "IGM_TRIM_LATENCY_PCT", default_value_t = 0.0, value_parser = util::parse_percentile)]
+    trim_latency_pct: f64,
+
+    /// Receiver: flag latency_degrading when latency rises faster than this many
+    /// ms per minute over the run (least-squares trend)
+    #[arg(
+        long,
+        env = "IGM_LAT_TREND_THRESHOLD",
+        default_value_t = 10.0,
+        value_name = "MS_PER_MIN"
+    )]
+    lat_trend_threshold: f64,
+
+    /// Receiver: window of the sender's send time for worst_bucket_delivery_rate and
+    /// the zero-delivery streak; keep it several send intervals wide
+    #[arg(long = "bucket", env = "IGM_BUCKET", alias = "bucket-ms", default_value_t = 1000, value_name = "DURATION", value_parser = util::parse_duration)]
+    bucket_ms: u64,
+
+    /// Receiver: sender rate (msg/s) the delivery buckets expect (default: estimated
+    /// from the received seqs and send times)
+    #[arg(long, env = "IGM_EXPECTED_RATE")]
+    expected_rate: Option<f64>,
+
+    /// Receiver: once the statistics use about this much memory, keep latency and
+    /// LDH as histograms and drop gap opening times instead of growing further
+    #[arg(long, env = "IGM_MAX_STATS_MEMORY_MB", value_name = "MB", value_parser = util::parse_megabytes)]
+    max_stats_memory_mb: Option<f64>,
+
+    /// Receiver: list at most this many neighbors (longest-connected first) in the summary
+    #[arg(long, env = "IGM_SUMMARY_NEIGHBORS", default_value_t = 32)]
+    summary_neighbors: usize,
+
+    /// Interval between neighbor-set snapshots in the receiver log (0 disables)
+    #[arg(long = "neighbor-sample", env = "IGM_NEIGHBOR_SAMPLE", alias = "neighbor-sample-ms", default_value_t = 5000, value_name = "DURATION", value_parser = util::parse_duration)]
+    neighbor_sample_ms: u64,
+
+    /// Interval between connection-level samples (net_stats) in the receiver log (0 disables)
+    #[arg(long = "conn-sample", env = "IGM_CONN_SAMPLE", alias = "conn-sample-ms", default_value_t = 1000, value_name = "DURATION", value_parser = util::parse_duration)]
+    conn_sample_ms: u64,
+
+    /// Interval between RSS/CPU samples of the receiver process (resource) in the
+    /// log; Linux only (0 disables)
+    #[arg(long = "resource-sample", env = "IGM_RESOURCE_SAMPLE", alias = "resource-sample-ms", default_value_t = 0, value_name = "DURATION", value_parser = util::parse_duration)]
+    resource_sample_ms: u64,
+
+    /// Re-join the bootstrap peers after this long without any neighbor
+    #[arg(long = "rejoin-on-isolation", env = "IGM_REJOIN_ON_ISOLATION", alias = "rejoin-on-isolation-ms", value_name = "DURATION", value_parser = util::parse_duration)]
+    rejoin_on_isolation_ms: Option<u64>,
+
+    /// Events buffered per gossip subscription and in the transport's event channel
+    /// (default: 2048 and 1024); raise it if the receiver logs `lagged` events
+    #[arg(long, env = "IGM_RX_BUFFER", value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..))]
+    rx_buffer: Option<usize>,
+
+    /// Broadcast to the whole swarm or only to direct neighbors (1-hop baseline;
+    /// forwarders do not relay neighbor-scope messages)
+    #[arg(long, env = "IGM_BROADCAST_SCOPE", default_value = "swarm", value_parser = ["swarm", "neighbors"])]
+    broadcast_scope: String,
+
+    /// Gossip: maximum message size in bytes (all peers should agree; default 4096)
+    #[arg(long, env = "IGM_GOSSIP_MAX_MESSAGE_SIZE")]
+    gossip_max_message_size: Option<usize>,
+
+    /// Gossip: active view capacity, i.e. mesh degree (default 5)
+    #[arg(long, env = "IGM_GOSSIP_ACTIVE_VIEW")]
+    gossip_active_view: Option<usize>,
+
+    /// Gossip: passive view capacity (default 30)
+    #[arg(long, env = "IGM_GOSSIP_PASSIVE_VIEW")]
+    gossip_passive_view: Option<usize>,
+
+    /// Gossip: ForwardJoin hops until a joining peer enters the active view (default 6)
+    #[arg(long, env = "IGM_GOSSIP_ACTIVE_WALK")]
+    gossip_active_walk: Option<u16>,
+
+    /// Gossip: ForwardJoin hops until a joining peer enters the passive view (default 3)
+    #[arg(long, env = "IGM_GOSSIP_PASSIVE_WALK")]
+    gossip_passive_walk: Option<u16>,
+
+    /// Gossip: Shuffle hops until a peer replies (default 6)
+    #[arg(long, env = "IGM_GOSSIP_SHUFFLE_WALK")]
+    gossip_shuffle_walk: Option<u16>,
+
+    /// Gossip: active peers included in a Shuffle request (default 3)
+    #[arg(long, env = "IGM_GOSSIP_SHUFFLE_ACTIVE")]
+    gossip_shuffle_active: Option<usize>,
+
+    /// Gossip: passive peers included in a Shuffle request (default 4)
+    #[arg(long, env = "IGM_GOSSIP_SHUFFLE_PASSIVE")]
+    gossip_shuffle_passive: Option<usize>,
+
+    /// Gossip: interval between Shuffle requests (default 60s)
+    #[arg(long = "gossip-shuffle-interval", env = "IGM_GOSSIP_SHUFFLE_INTERVAL", alias = "gossip-shuffle-interval-ms", value_name = "DURATION", value_parser = util::parse_duration)]
+    gossip_shuffle_interval_ms: Option<u64>,
+
+    /// Gossip: timeout for a Neighbor request (default 500ms)
+    #[arg(long = "gossip-neighbor-timeout", env = "IGM_GOSSIP_NEIGHBOR_TIMEOUT", alias = "gossip-neighbor-timeout-ms", value_name = "DURATION", value_parser = util::parse_duration)]
+    gossip_neighbor_timeout_ms: Option<u64>,
+
+    /// Gossip: wait this long after an IHave before sending a Graft (default 80ms)
+    #[arg(long = "gossip-graft-timeout-1", env = "IGM_GOSSIP_GRAFT_TIMEOUT_1", alias = "gossip-graft-timeout-1-ms", value_name = "DURATION", value_parser = util::parse_duration)]
+    gossip_graft_timeout_1_ms: Option<u64>,
+
+    /// Gossip: wait this long for a Graft reply before asking the next peer (default 40ms)
+    #[arg(long = "gossip-graft-timeout-2", env = "IGM_GOSSIP_GRAFT_TIMEOUT_2", alias = "gossip-graft-timeout-2-ms", value_name = "DURATION", value_parser = util::parse_duration)]
+    gossip_graft_timeout_2_ms: Option<u64>,
+
+    /// Gossip: delay before IHave messages are pushed to lazy peers (default 5ms)
+    #[arg(long = "gossip-dispatch-timeout", env = "IGM_GOSSIP_DISPATCH_TIMEOUT", alias = "gossip-dispatch-timeout-ms", value_name = "DURATION", value_parser = util::parse_duration)]
+    gossip_dispatch_timeout_ms: Option<u64>,
+
+    /// Gossip: hops a lazy peer must be closer than eager ones to be promoted (default 7)
+    #[arg(long, env = "IGM_GOSSIP_OPTIMIZATION_THRESHOLD")]
+    gossip_optimization_threshold: Option<u16>,
+
+    /// Gossip: how long messages stay in the cache for Graft replies (default 30s)
+    #[arg(long = "gossip-cache-retention", env = "IGM_GOSSIP_CACHE_RETENTION", alias = "gossip-cache-retention-ms", value_name = "DURATION", value_parser = util::parse_duration)]
+    gossip_cache_retention_ms: Option<u64>,
+
+    /// Gossip: how long received message IDs are remembered (default 90s)
+    #[arg(long = "gossip-id-retention", env = "IGM_GOSSIP_ID_RETENTION", alias = "gossip-id-retention-ms", value_name = "DURATION", value_parser = util::parse_duration)]
+    gossip_id_retention_ms: Option<u64>,
+
+    /// Gossip: interval between cache eviction passes (default 1s)
+    #[arg(long = "gossip-cache-evict", env = "IGM_GOSSIP_CACHE_EVICT", alias = "gossip-cache-evict-ms", value_name = "DURATION", value_parser = util::parse_duration)]
+    gossip_cache_evict_ms: Option<u64>,
+
+    /// Sender: compress payloads before broadcast: none, zstd or zstd:<level>
+    #[arg(long, env = "IGM_COMPRESS", default_value = "none", value_parser = frame::parse_compression)]
+    compress: frame::Compression,
+
+    /// Padding fill: zero, random (incompressible) or compressible (four letters),
+    /// streams keyed by test id, seq and --seed (sender, default zero); on a
+    /// receiver, check every pad against it and count mismatches as corruption
+    #[arg(long = "pad-pattern", env = "IGM_PAD", alias = "pad", value_name = "PATTERN", value_parser = util::parse_pad_pattern)]
+    pad: Option<util::PadPattern>,
+
+    /// Seed of the --pad-pattern streams; sender and receiver must agree
+    #[arg(long, env = "IGM_SEED", default_value_t = 0)]
+    seed: u64,
+
+    /// Receiver: summary output, json (stdout), pretty (table on stderr) or both
+    #[arg(long, env = "IGM_SUMMARY_FORMAT", default_value = "both", value_parser = ["json", "pretty", "both"])]
+    summary_format: String,
+
+    /// Encrypt payloads (ChaCha20-Poly1305, key derived from the topic); all peers must agree
+    #[arg(long, env = "IGM_ENCRYPT", default_value_t = false)]
+    encrypt: bool,
+
+    /// Sender: sign (test_id, seq, sent_ms) of every message with the node key
+    #[arg(long, env = "IGM_SIGN", default_value_t = false)]
+    sign: bool,
+
+    /// Receiver: only count messages with a valid signature
+    #[arg(long, env = "IGM_REQUIRE_SIGNED", default_value_t = false)]
+    require_signed: bool,
+
+    /// Receiver: with --require-signed, only accept these signer node IDs (repeatable)
+    #[arg(long, env = "IGM_ALLOW_SENDER", requires = "require_signed")]
+    allow_sender: Vec<String>,
+
+    /// Receiver: only count messages from these sender node IDs (repeatable);
+    /// others are logged as recv_foreign
+    #[arg(long, env = "IGM_EXPECT_SENDER", value_delimiter = ',')]
+    expect_sender: Vec<String>,
+
+    /// Receiver: leave out messages sent longer than this before they arrived
+    /// (by the sender's clock), e.g. leftovers of a previous run; logged as recv_stale
+    #[arg(long = "max-msg-age", env = "IGM_MAX_MSG_AGE", alias = "max-msg-age-ms", value_name = "DURATION", value_parser = util::parse_duration)]
+    max_msg_age_ms: Option<u64>,
+
+    /// Receiver: skip the echo RTT probes to every neighbor at run start and end
+    #[arg(long, env = "IGM_NO_RTT_PROBE")]
+    no_rtt_probe: bool,
+
+    /// Receiver: echo probes per neighbor and RTT probe round
+    #[arg(long, env = "IGM_RTT_PROBE_COUNT", default_value_t = 5)]
+    rtt_probe_count: u32,
+
+    /// Receiver: longest one RTT probe round may delay the run
+    #[arg(long = "rtt-probe-budget", env = "IGM_RTT_PROBE_BUDGET", default_value_t = 1000, value_name = "DURATION", value_parser = util::parse_duration)]
+    rtt_probe_budget_ms: u64,
+
+    /// Receiver: re-send every valid data payload (the raw gossip bytes) to this
+    /// UDP address, e.g. 127.0.0.1:9000
+    #[arg(long, env = "IGM_FORWARD_UDP", value_name = "ADDR")]
+    forward_udp: Option<std::net::SocketAddr>,
+
+    /// Receiver: with --forward-udp, forward every data payload as received, even
+    /// ones that do not decode
+    #[arg(long, env = "IGM_FORWARD_RAW", requires = "forward_udp")]
+    forward_raw: bool,
+
+    /// Sender: chunk data size for payloads above the gossip message limit
+    /// (default: derived from the limit minus header overhead)
+    #[arg(long, env = "IGM_CHUNK_BYTES")]
+    chunk_bytes: Option<usize>,
+
+    /// Sender: broadcast a heartbeat (sender clock + current seq) at this interval
+    #[arg(long = "heartbeat", env = "IGM_HEARTBEAT", alias = "heartbeat-ms", value_name = "DURATION", value_parser = util::parse_duration)]
+    heartbeat_ms: Option<u64>,
+
+    /// Sender: re-broadcast each message right after it with this probability (0-1),
+    /// as ground truth for the duplicate metrics
+    #[arg(long, env = "IGM_INJECT_DUP_PROB", default_value_t = 0.0, value_parser = util::parse_probability)]
+    inject_dup_prob: f64,
+
+    /// Sender: broadcast every message this many times, the copies spaced by
+    /// --redundancy-gap; receivers deduplicate them by seq
+    #[arg(long, env = "IGM_REDUNDANCY", default_value_t = 1, value_parser = clap::value_parser!(u32).range(1..))]
+    redundancy: u32,
+
+    /// Sender: time between two copies of a message with --redundancy
+    #[arg(long = "redundancy-gap", env = "IGM_REDUNDANCY_GAP", alias = "redundancy-gap-ms", default_value_t = 50, value_name = "DURATION", value_parser = util::parse_duration)]
+    redundancy_gap_ms: u64,
+
+    /// Sender: pack up to this many messages into one gossip message (1 = one
+    /// message each, the plain format); receivers unbatch them
+    #[arg(long, env = "IGM_BATCH", default_value_t = 1, value_parser = clap::value_parser!(u64).range(1..))]
+    batch: u64,
+
+    /// Sender: send an incomplete --batch once its oldest message waited this long
+    #[arg(long = "batch-max-wait", env = "IGM_BATCH_MAX_WAIT", alias = "batch-max-wait-ms", default_value_t = 10, value_name = "DURATION", value_parser = util::parse_duration)]
+    batch_max_wait_ms: u64,
+
+    /// Sender: hold back every k-th message and send it d messages later ("k:d"),
+    /// as ground truth for the reordering metrics
+    #[arg(long, env = "IGM_INJECT_REORDER", value_parser = runner::parse_reorder)]
+    inject_reorder: Option<runner::Reorder>,
+
+    /// Sender: pause sending at an offset from the start of the run for a duration
+    /// ("at:duration", e.g. "60s:20s"; repeatable or comma-separated)
+    #[arg(long, env = "IGM_PAUSE_SCHEDULE", value_name = "AT:DURATION", value_delimiter = ',', value_parser = runner::parse_pause)]
+    pause_schedule: Vec<runner::PauseWindow>,
+
+    /// Sender: pause sending on SIGUSR1 and resume on SIGUSR2 (Unix only)
+    #[arg(long, env = "IGM_PAUSE_SIGNALS")]
+    pause_signals: bool,
+
+    /// Read commands from stdin while running: snapshot, rate <msg/s>, pause,
+    /// resume, rejoin, quit (each logged as a control event)
+    #[arg(long, env = "IGM_INTERACTIVE")]
+    interactive: bool,
+
+    /// Sender: after a pause, send the held-back messages back to back instead of
+    /// resuming at the normal cadence
+    #[arg(long, env = "IGM_CATCH_UP")]
+    catch_up: bool,
+
+    /// Sender: before sending, listen this long for another sender on the topic
+    /// (e.g. one left running) and warn if one is heard (0 disables)
+    #[arg(long = "probe", env = "IGM_PROBE", alias = "probe-ms", default_value_t = 1000, value_name = "DURATION", value_parser = util::parse_duration)]
+    probe_ms: u64,
+
+    /// Sender: abort instead of warning when the --probe hears another sender
+    #[arg(long, env = "IGM_EXCLUSIVE")]
+    exclusive: bool,
+
+    /// Sender: return as soon as the last message is handed to the transport,
+    /// without pacing out the last interval or waiting for NACKs and acks
+    #[arg(long, env = "IGM_NO_WAIT")]
+    no_wait: bool,
+
+    /// Sender: keep draining transport events (neighbor churn, errors) this
+    /// long after the last send before summarizing (0 disables)
+    #[arg(long = "linger", env = "IGM_LINGER", alias = "linger-ms", default_value_t = 0, value_name = "DURATION", value_parser = util::parse_duration)]
+    linger_ms: u64,
+
+    /// Sender: replay a JSONL trace of {"offset_ms", "size"} or {"offset_ms",
+    /// "payload" (base64)} lines instead of --num/--rate/--size ("-" = stdin)
+    #[arg(long, env = "IGM_REPLAY", value_name = "PATH")]
+    replay: Option<String>,
+
+    /// Sender: adapt the rate (AIMD, starting at --rate): halve it on broadcast
+    /// errors or a deep broadcast queue, raise it after every clean interval
+    #[arg(long, env = "IGM_ADAPTIVE")]
+    adaptive: bool,
+
+    /// Sender: broadcast queue depth above which --adaptive cuts the rate
+    #[arg(long, env = "IGM_ADAPTIVE_QUEUE_DEPTH", default_value_t = 32)]
+    adaptive_queue_depth: usize,
+
+    /// Sender: messages per second --adaptive adds after each clean interval
+    #[arg(long, env = "IGM_ADAPTIVE_INCREASE", default_value_t = 10)]
+    adaptive_increase: u64,
+
+    /// Sender: time without errors or a deep queue before --adaptive raises the rate
+    #[arg(long = "adaptive-interval", env = "IGM_ADAPTIVE_INTERVAL", alias = "adaptive-interval-ms", default_value_t = 1000, value_name = "DURATION", value_parser = util::parse_duration)]
+    adaptive_interval_ms: u64,
+
+    /// Receiver: drop partially reassembled payloads after this long
+    #[arg(long = "reassembly-timeout", env = "IGM_REASSEMBLY_TIMEOUT", alias = "reassembly-timeout-ms", default_value_t = 5000, value_name = "DURATION", value_parser = util::parse_duration)]
+    reassembly_timeout_ms: u64,
+
+    /// Receiver: cap on buffered chunk data in bytes (also the largest frame
+    /// a chunk header may announce)
+    #[arg(long, env = "IGM_REASSEMBLY_MAX_BYTES", default_value_t = 64 * 1024 * 1024)]
+    reassembly_max_bytes: usize,
+
+    /// NACK-based repair over a unicast side-channel (sender and receivers).
+    /// Leave off for baseline runs; repaired deliveries are reported separately.
+    #[arg(long, env = "IGM_REPAIR", default_value_t = false)]
+    repair: bool,
+
+    /// Receiver: node ID of the sender to request retransmissions from (required with --repair)
+    #[arg(long, env = "IGM_REPAIR_PEER")]
+    repair_peer: Option<String>,
+
+    /// Receiver: NACK sequences that have been missing for this long
+    #[arg(long = "repair-after", env = "IGM_REPAIR_AFTER", alias = "repair-after-ms", default_value_t = 500, value_name = "DURATION", value_parser = util::parse_duration)]
+    repair_after_ms: u64,
+
+    /// Sender: keep serving NACKs until none arrived for this long after the last send
+    #[arg(long = "repair-linger", env = "IGM_REPAIR_LINGER", alias = "repair-linger-ms", default_value_t = 3000, value_name = "DURATION", value_parser = util::parse_duration)]
+    repair_linger_ms: u64,
+
+    /// Write the full iroh metrics registry as a final `iroh_metrics` log event
+    #[arg(long, env = "IGM_DUMP_IROH_METRICS", default_value_t = false)]
+    dump_iroh_metrics: bool,
+
+    /// Stop the sender or receiver loop after this long
+    #[arg(long = "max-runtime", env = "IGM_MAX_RUNTIME", alias = "max-runtime-ms", value_name = "DURATION", value_parser = util::parse_duration)]
+    max_runtime_ms: Option<u64>,
+
+    /// Log a watchdog_stall event after this long without progress (sends or transport events)
+    #[arg(long = "watchdog", env = "IGM_WATCHDOG", alias = "watchdog-ms", value_name = "DURATION", value_parser = util::parse_duration)]
+    watchdog_ms: Option<u64>,
+
+    /// Receiver: exit with code 3 unless delivery_rate is at least this (0-1)
+    #[arg(long, env = "IGM_ASSERT_DELIVERY_MIN")]
+    assert_delivery_min: Option<f64>,
+
+    /// Receiver: exit with code 3 unless lat_p99 is at most this many ms
+    #[arg(long, env = "IGM_ASSERT_LAT_P99_MAX")]
+    assert_lat_p99_max: Option<u64>,
+
+    /// Receiver: exit with code 3 unless duplicates are at most this many
+    #[arg(long, env = "IGM_ASSERT_DUPLICATES_MAX")]
+    assert_duplicates_max: Option<u64>,
+
+    /// Receiver: exit with code 3 unless worst_bucket_delivery_rate is at least this (0-1)
+    #[arg(long, env = "IGM_ASSERT_WORST_BUCKET_MIN")]
+    assert_worst_bucket_min: Option<f64>,
+
+    /// Print crate, iroh and iroh-gossip versions, git hash, target and rustc, then exit
+    #[arg(long, env = "IGM_VERSION_VERBOSE", default_value_t = false)]
+    version_verbose: bool,
+
+    /// Read defaults for any of these flags from a TOML file (keys are the flag
+    /// names with underscores); flags on the command line override it
+    #[arg(long, env = "IGM_CONFIG")]
+    config: Option<PathBuf>,
+
+    /// Config file preset (`[preset.<name>]`) applied over the file's top-level keys
+    #[arg(long, env = "IGM_PRESET")]
+    preset: Option<String>,
+
+    /// Print the resolved configuration (flags merged with --config and IGM_* variables)
+    /// as JSON, then exit
+    #[arg(long, default_value_t = false)]
+    #[serde(skip)]
+    print_config: bool,
+
+    #[command(subcommand)]
+    #[serde(skip)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug)]
+enum Command {
+    /// Run a matrix of sender parameters from a TOML file; receivers write one summary per test
+    Scenario {
+        /// Scenario TOML file (rates, sizes, repeats, duration_s, cooldown_ms)
+        #[arg(long)]
+        file: PathBuf,
+
+        /// Directory for per-run logs and summaries and the run manifest
+        #[arg(long, default_value = "scenario-out")]
+        out_dir: PathBuf,
+    },
+
+    /// Print mean/stddev/min/max per numeric field over receiver summary files
+    Aggregate {
+        /// Summary JSON files, directories (all *.json inside) or glob patterns
+        #[arg(required = true)]
+        inputs: Vec<String>,
+    },
+
+    /// Compare two receiver summaries side by side with absolute and relative deltas
+    Compare {
+        /// Baseline summary JSON
+        #[arg(long)]
+        a: PathBuf,
+
+        /// Candidate summary JSON (deltas are b - a)
+        #[arg(long)]
+        b: PathBuf,
+
+        /// Print machine-readable JSON instead of a table
+        #[arg(long, default_value_t = false)]
+        json: bool,
+
+        /// Exit with code 3 if this holds, e.g. "delivery_rate_delta < -0.02" (repeatable)
+        #[arg(long, value_parser = report::parse_threshold)]
+        fail_if: Vec<report::Threshold>,
+    },
+
+    /// Join recv events of several receiver logs into a per-message coverage matrix
+    Merge {
+        /// Receiver logs, plus optionally the sender log to define the sent messages
+        #[arg(long, num_args = 1.., required = true)]
+        logs: Vec<PathBuf>,
+
+        /// Path of the JSON report
+        #[arg(long, default_value = "merge-report.json")]
+        out: PathBuf,
+    },
+
+    /// Check a JSONL log for parse errors and inconsistencies (exit code 1 if not clean)
+    Validate {
+        /// Log file to check
+        log: PathBuf,
+
+        /// Rewrite the file without a trailing partial line
+        #[arg(long, default_value_t = false)]
+        fix_truncated: bool,
+    },
+
+    /// Write CSV series (latency, sends, peers, events) from a sender or receiver log
+    Export {
+        /// Log file to export
+        log: PathBuf,
+
+        /// Directory for the CSV files
+        #[arg(long, default_value = "export")]
+        out_dir: PathBuf,
+
+        /// Use seconds since the first event instead of epoch milliseconds
+        #[arg(long, default_value_t = false)]
+        relative_time: bool,
+
+        /// Emit every Nth latency/send row
+        #[arg(long, default_value_t = 1)]
+        downsample: u64,
+    },
+
+    /// Render a one-page Markdown or HTML report from a receiver summary
+    Report {
+        /// Receiver summary JSON
+        summary: PathBuf,
+
+        /// JSONL log of the same run (adds setup metadata and the gap list)
+        #[arg(long)]
+        log_file: Option<PathBuf>,
+
+        /// Output format: md or html
+        #[arg(long, default_value = "md", value_parser = ["md", "html"])]
+        format: String,
+
+        /// Output file (default: stdout)
+        #[arg(long)]
+        out: Option<PathBuf>,
+
+        /// Verdict threshold: minimum delivery_rate (0-1)
+        #[arg(long, default_value_t = 0.99)]
+        delivery_min: f64,
+
+        /// Verdict threshold: maximum lat_p99 in ms
+        #[arg(long)]
+        lat_p99_max: Option<u64>,
+
+        /// Verdict threshold: maximum duplicates
+        #[arg(long)]
+        duplicates_max: Option<u64>,
+
+        /// Verdict threshold: minimum worst_bucket_delivery_rate (0-1)
+        #[arg(long)]
+        worst_bucket_min: Option<f64>,
+    },
+
+    /// Print the topic ID of each name under both derivations (v1 and legacy)
+    Topic {
+        /// Topic names
+        #[arg(required = true)]
+        names: Vec<String>,
+    },
+
+    /// Follow a growing receiver log and print rolling stats every second
+    Tail {
+        /// Receiver log to follow
+        log: PathBuf,
+
+        /// Window for the rolling latency percentiles
+        #[arg(long = "window", alias = "window-ms", default_value_t = 60_000, value_name = "DURATION", value_parser = util::parse_duration)]
+        window_ms: u64,
+    },
+
+    /// Run a sender and a receiver in this process on a random topic and print
+    /// a PASS/FAIL verdict (exit code 1 on FAIL)
+    Selftest {
+        /// Messages to send
+        #[arg(long, default_value_t = 200)]
+        num: u64,
+
+        /// Send rate (messages per second)
+        #[arg(long, default_value_t = 100, value_parser = clap::value_parser!(u64).range(1..))]
+        rate: u64,
+
+        /// Payload size in bytes
+        #[arg(long, default_value_t = 256)]
+        size: usize,
+
+        /// Directory for the sender and receiver logs
+        #[arg(long, default_value = "selftest-out")]
+        out_dir: PathBuf,
+
+        /// Transport of both ends: iroh, or mem to leave the network out
+        #[arg(long = "transport", default_value = "iroh", value_parser = ["iroh", "mem"])]
+        selftest_transport: String,
+    },
+}
+
+fn main() -> Result<()> {
+    // Before the runtime starts any threads, as it sets environment variables.
+    util::load_dotenv(".env")?;
+    run()
 }
 
 #[tokio::main]
-async fn main() -> Result<()> {
+async fn run() -> Result<()> {
+    util::process_start_ms();
     tracing_subscriber::fmt()
         .with_env_filter(EnvFilter::from_default_env())
         .init();
-    let args = Args::parse();
+    let args: Args = config::parse_with_config()?;
+
+    if args.version_verbose {
+        let build = util::build_info();
+        println!("iroh-gossip-metrics {}", build.version);
+        println!("iroh {}", build.iroh);
+        println!("iroh-gossip {}", build.iroh_gossip);
+        println!("git {}", build.git_hash.as_deref().unwrap_or("unknown"));
+        println!("target {}", build.target);
+        println!("{}", build.rustc);
+        return Ok(());
+    }
 
-    let mut logger = util::JsonWriter::new(&args.log)?;
+    if args.print_config {
+        println!(
+            "{}",
+            serde_json::to_string_pretty(&resolved_config(&args)?)?
+        );
+        return Ok(());
+    }
+
+    // Offline subcommands need no transport or log file
+    match &args.command {
+        Some(Command::Aggregate { inputs }) => {
+            let files = report::expand_inputs(inputs)?;
+            let aggregate = report::aggregate(&files)?;
+            println!("{}", serde_json::to_string_pretty(&aggregate)?);
+            return Ok(());
+        }
+        Some(Command::Compare {
+            a,
+            b,
+            json,
+            fail_if,
+        }) => {
+            let comparison = report::compare(a, b, fail_if)?;
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&comparison)?);
+            } else {
+                print!("{}", comparison.table());
+            }
+            if !comparison.failed.is_empty() {
+                std::process::exit(report::EXIT_THRESHOLD_FAILED);
+            }
+            return Ok(());
+        }
+        Some(Command::Merge { logs, out }) => {
+            let report = report::merge(logs)?;
+            print!("{}", report.table());
+            std::fs::write(out, serde_json::to_string_pretty(&report)?)?;
+            return Ok(());
+        }
+        Some(Command::Validate { log, fix_truncated }) => {
+            let mut validation = report::validate(log)?;
+            print!("{}", validation.report(log));
+            if *fix_truncated && report::fix_truncated(log, &validation)? {
+                println!("dropped truncated last line");
+                validation.truncated_at = None;
+            }
+            if !validation.is_clean() {
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Some(Command::Export {
+            log,
+            out_dir,
+            relative_time,
+            downsample,
+        }) => {
+            let opts = report::ExportOptions {
+                relative_time: *relative_time,
+                downsample: *downsample,
+            };
+            for (path, rows) in report::export(log, out_dir, &opts)? {
+                println!("{} ({rows} rows)", path.display());
+            }
+            return Ok(());
+        }
+        Some(Command::Report {
+            summary,
+            log_file,
+            format,
+            out,
+            delivery_min,
+            lat_p99_max,
+            duplicates_max,
+            worst_bucket_min,
+        }) => {
+            let format = match format.as_str() {
+                "md" => report::ReportFormat::Markdown,
+                "html" => report::ReportFormat::Html,
+                _ => anyhow::bail!("Invalid report format, use 'md' or 'html'."),
+            };
+            let verdict = report::Assertions {
+                delivery_min: Some(*delivery_min),
+                lat_p99_max: *lat_p99_max,
+                duplicates_max: *duplicates_max,
+                worst_bucket_min: *worst_bucket_min,
+            };
+            let text = report::render_report(summary, log_file.as_deref(), &verdict, format)?;
+            match out {
+                Some(path) => std::fs::write(path, text)?,
+                None => print!("{text}"),
+            }
+            return Ok(());
+        }
+        Some(Command::Tail { log, window_ms }) => {
+            return report::tail(log, *window_ms).await;
+        }
+        Some(Command::Topic { names }) => {
+            for name in names {
+                println!("{name}");
+                for derivation in [util::TopicDerivation::V1, util::TopicDerivation::Legacy] {
+                    println!(
+                        "  {:<7} {}",
+                        derivation.as_str(),
+                        hex::encode(util::topic_from_name(name, derivation))
+                    );
+                }
+            }
+            return Ok(());
+        }
+        _ => {}
+    }
+
+    // The self-test brings its own transports and needs no --role
+    if let Some(Command::Selftest {
+        num,
+        rate,
+        size,
+        out_dir,
+        selftest_transport,
+    }) = &args.command
+    {
+        util::install_interrupt_handler();
+        diag!("run_id={}", util::run_id());
+        let opts = selftest::SelftestOptions {
+            total: *num,
+            rate: *rate,
+            payload_size: *size,
+            transport: match selftest_transport.as_str() {
+                "mem" => TransportKind::Mem,
+                _ => TransportKind::Iroh,
+            },
+        };
+        let outcome = selftest::run(&opts, out_dir).await?;
+        if let Some(summary) = &outcome.summary {
+            println!("{}", serde_json::to_string_pretty(summary)?);
+        }
+        println!("{}", outcome.verdict());
+        if outcome.failure.is_some() {
+            std::process::exit(1);
+        }
+        return Ok(());
+    }
+    let Some(role) = args.role.as_deref() else {
+        anyhow::bail!("--role is required (sender or receiver).");
+    };
+    util::install_interrupt_handler();
+    if args.interactive {
+        if args.replay.as_deref() == Some("-") {
+            anyhow::bail!("--interactive reads stdin, which --replay - already does.");
+        }
+        control::spawn_stdin();
+        diag!("interactive: {}", control::USAGE);
+    }
+
+    // Per-run artifact directory; explicit --log/--summary-out paths win.
+    let run_dir = match &args.out_dir {
+        Some(dir) => {
+            let run_dir = util::create_run_dir(dir)?;
+            util::set_diag_copy(&run_dir.join("stderr.txt"))?;
+            std::fs::write(
+                run_dir.join("config.json"),
+                serde_json::to_string_pretty(&resolved_config(&args)?)?,
+            )?;
+            Some(run_dir)
+        }
+        None => None,
+    };
+    let log_path = match (&args.log, &run_dir) {
+        (Some(log), _) => PathBuf::from(log),
+        (None, Some(run_dir)) => run_dir.join("log.jsonl"),
+        (None, None) => PathBuf::from("lab.log.jsonl"),
+    };
+    let summary_path = args
+        .summary_out
+        .clone()
+        .or_else(|| run_dir.as_ref().map(|d| d.join("summary.json")));
+    diag!("run_id={}", util::run_id());
+
+    // Determine transport implementation
+    let transport_kind = match args.transport.as_str() {
+        "iroh" => TransportKind::Iroh,
+        "udp" => TransportKind::Udp,
+        "mem" => TransportKind::Mem,
+        _ => anyhow::bail!("Invalid transport, use 'iroh', 'udp' or 'mem'."),
+    };
+
+    // The peer ID is known up front only with a fixed key; every event carries it anyway.
+    let peer_id = args.secret_hex.as_deref().and_then(|h| {
+        let key: [u8; 32] = hex::decode(h).ok()?.try_into().ok()?;
+        Some(format!(
+            "{}:{}",
+            transport_kind.id_prefix(),
+            iroh::SecretKey::from_bytes(&key).public()
+        ))
+    });
+    let header = metrics::LogHeader::new(peer_id, resolved_config(&args)?);
+    // Every event goes to the JSONL log and to each sink configured next to it.
+    let mut jsonl = util::JsonWriter::with_header(&log_path, &header)?;
+    jsonl.set_strict(args.strict_logging)?;
+    let mut logger = metrics::MultiSink::new();
+    logger.push(Box::new(jsonl));
+    if let Some(dir) = &args.csv_dir {
+        let opts = report::ExportOptions {
+            relative_time: false,
+            downsample: 1,
+        };
+        logger.push(Box::new(report::CsvSink::new(dir, opts)?));
+    }
+    let influx = match &args.influx_url {
+        Some(url) => {
+            let (sink, handle) = influx::spawn(influx::InfluxOptions {
+                url: url.clone(),
+                db: args.influx_db.clone(),
+                batch: args.influx_batch,
+            })?;
+            logger.push(Box::new(sink));
+            Some(handle)
+        }
+        None => None,
+    };
+    let otlp = match &args.otlp_endpoint {
+        Some(endpoint) => {
+            let (sink, handle) = otlp::spawn(
+                otlp::OtlpOptions {
+                    endpoint: endpoint.clone(),
+                    sample: args.otlp_sample,
+                    payload_size: args.size.unwrap_or(DEFAULT_SIZE),
+                },
+                role,
+                header.peer_id.as_deref(),
+            )?;
+            logger.push(Box::new(sink));
+            Some(handle)
+        }
+        None => None,
+    };
 
     // Determine discovery mode
     let discovery = match args.discovery.as_str() {
@@ -76,49 +1009,241 @@ async fn main() -> Result<()> {
         _ => anyhow::bail!("Invalid discovery mode, use 'direct' or 'relay'."),
     };
 
-    let run_time = args.num.clone() / args.rate.clone();
+    // Determine quantile estimator
+    let quantile_method = match args.quantile_method.as_str() {
+        "nearest" => QuantileMethod::Nearest,
+        "linear" => QuantileMethod::Linear,
+        _ => anyhow::bail!("Invalid quantile method, use 'nearest' or 'linear'."),
+    };
+
+    // Determine broadcast scope
+    let broadcast_scope = match args.broadcast_scope.as_str() {
+        "swarm" => BroadcastScope::Swarm,
+        "neighbors" => BroadcastScope::Neighbors,
+        _ => anyhow::bail!("Invalid broadcast scope, use 'swarm' or 'neighbors'."),
+    };
+
+    // Receiver summary output: JSON on stdout and/or a table on stderr
+    let (summary_json, summary_pretty) = match args.summary_format.as_str() {
+        "json" => (true, false),
+        "pretty" => (false, true),
+        "both" => (true, true),
+        _ => anyhow::bail!("Invalid summary format, use 'json', 'pretty' or 'both'."),
+    };
+
+    let topic_derivation =
+        util::TopicDerivation::parse(&args.topic_derivation).map_err(anyhow::Error::msg)?;
+
+    // Payload key derived from the topic (needs a shared topic)
+    let encryption = if args.encrypt {
+        let topic = match (args.topic_hex.as_slice(), args.topic_name.as_slice()) {
+            ([h], []) => *TopicId::from_str(h)?.as_bytes(),
+            ([], [n]) => util::topic_from_name(n, topic_derivation),
+            ([], []) => anyhow::bail!("--encrypt requires --topic-hex or --topic-name."),
+            _ => anyhow::bail!("--encrypt supports a single topic."),
+        };
+        Some(frame::Encryption::from_topic(&topic))
+    } else {
+        None
+    };
+
+    // Signature policy with optional signer allowlist
+    let require_signed = if args.require_signed {
+        let allow = args
+            .allow_sender
+            .iter()
+            .map(|s| s.parse::<iroh::PublicKey>())
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| anyhow::anyhow!("Invalid --allow-sender node ID: {e}"))?;
+        Some(runner::SignaturePolicy { allow })
+    } else {
+        None
+    };
+
+    // Origin filter; exact only for signed messages
+    let expect_sender = args
+        .expect_sender
+        .iter()
+        .map(|s| s.trim().parse::<iroh::PublicKey>())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| anyhow::anyhow!("Invalid --expect-sender node ID: {e}"))?;
+    if !expect_sender.is_empty() && role == "receiver" {
+        diag!(
+            "--expect-sender: unsigned messages are matched by the neighbor that delivered \
+             them, which is approximate on multi-hop overlays; run the sender with --sign \
+             for exact filtering"
+        );
+    }
 
-    match args.role.as_str() {
+    // Receivers need to know whom to NACK
+    let repair_opts = match (args.repair, &args.repair_peer) {
+        (false, _) => None,
+        (true, Some(peer)) => Some(RepairOptions {
+            peer: peer.clone(),
+            after_ms: args.repair_after_ms,
+        }),
+        (true, None) if role == "receiver" => {
+            anyhow::bail!("--repair on a receiver requires --repair-peer <sender node id>.")
+        }
+        (true, None) => None,
+    };
+
+    // Validate the scenario before connecting
+    let scenario = match &args.command {
+        Some(Command::Scenario { file, out_dir }) => Some((
+            scenario::Scenario::load(file)?,
+            file.as_path(),
+            out_dir.as_path(),
+        )),
+        _ => None,
+    };
+
+    // Likewise the replay trace
+    let replay = match &args.replay {
+        Some(_) if role != "sender" => anyhow::bail!("--replay only applies to the sender."),
+        Some(_) if scenario.is_some() => {
+            anyhow::bail!("--replay cannot be combined with a scenario.")
+        }
+        Some(path) => Some(replay::load(path)?),
+        None => None,
+    };
+
+    let assertions = report::Assertions {
+        delivery_min: args.assert_delivery_min,
+        lat_p99_max: args.assert_lat_p99_max,
+        duplicates_max: args.assert_duplicates_max,
+        worst_bucket_min: args.assert_worst_bucket_min,
+    };
+    let mut assertions_failed = false;
+    if args.continuous {
+        if role != "receiver" {
+            anyhow::bail!("--continuous only applies to the receiver.");
+        }
+        if scenario.is_some() {
+            anyhow::bail!("--continuous cannot be combined with a scenario.");
+        }
+        if !assertions.is_empty() {
+            anyhow::bail!("--continuous cannot be combined with --assert-* checks.");
+        }
+    }
+
+    let run_time = match (&scenario, &replay) {
+        (Some((s, _, _)), _) => s.duration_s,
+        (None, Some(entries)) => entries.last().map_or(0, |e| e.offset_ms / 1000),
+        (None, None) => args.num / args.rate,
+    };
+
+    let transport_opts = TransportOpts {
+        topic_hex: args.topic_hex.clone(),
+        topic_name: args.topic_name.clone(),
+        topic_derivation,
+        send_topic: args.send_topic.clone(),
+        secret_hex: args.secret_hex.clone(),
+        bootstrap: match transport_kind {
+            TransportKind::Udp => {
+                util::parse_bootstrap_addrs(&args.bootstrap, args.ignore_bad_bootstrap)?
+            }
+            _ => util::parse_bootstrap(&args.bootstrap, args.ignore_bad_bootstrap)?,
+        },
+        udp_bind: args.udp_bind,
+        discovery,
+        run_time,
+        rejoin_on_isolation_ms: args.rejoin_on_isolation_ms,
+        broadcast_scope,
+        unicast: args.repair,
+        rx_buffer: args.rx_buffer,
+        max_recv_bytes: match role {
+            "receiver" => args.max_recv_bytes.or(args.size.map(frame::max_frame_len)),
+            _ => None,
+        },
+        join_in_background: role == "receiver" && args.join_in_background,
+        force_no_join: args.force_no_join,
+        gossip: transport::GossipTuning {
+            max_message_size: args.gossip_max_message_size,
+            active_view: args.gossip_active_view,
+            passive_view: args.gossip_passive_view,
+            active_walk: args.gossip_active_walk,
+            passive_walk: args.gossip_passive_walk,
+            shuffle_walk: args.gossip_shuffle_walk,
+            shuffle_active: args.gossip_shuffle_active,
+            shuffle_passive: args.gossip_shuffle_passive,
+            shuffle_interval_ms: args.gossip_shuffle_interval_ms,
+            neighbor_timeout_ms: args.gossip_neighbor_timeout_ms,
+            graft_timeout_1_ms: args.gossip_graft_timeout_1_ms,
+            graft_timeout_2_ms: args.gossip_graft_timeout_2_ms,
+            dispatch_timeout_ms: args.gossip_dispatch_timeout_ms,
+            optimization_threshold: args.gossip_optimization_threshold,
+            cache_retention_ms: args.gossip_cache_retention_ms,
+            id_retention_ms: args.gossip_id_retention_ms,
+            cache_evict_ms: args.gossip_cache_evict_ms,
+        },
+    };
+
+    match role {
         "sender" => {
-            let gossip = IrohGossip::connect(
-                args.topic_hex.clone(),
-                args.topic_name.clone(),
-                args.secret_hex.clone(),
-                args.bootstrap
-                    .split(',')
-                    .filter(|s| !s.is_empty())
-                    .map(|s| s.to_string())
-                    .collect(),
-                discovery,
-                run_time,
-            )
-            .await?;
-
-            logger.write(&metrics::LogEvent {
+            let mut gossip = transport::create(transport_kind, transport_opts.clone()).await?;
+
+            logger.emit(metrics::LogEvent {
                 ts_ms: util::now_ms(),
+                elapsed_ms: None,
                 role: "sender",
                 peer_id: &gossip.id(),
-                event: "setup",
+                run_id: None,
+                event: metrics::EventKind::Setup,
                 seq: None,
                 lat_ms: None,
                 ldh: None,
                 extra: serde_json::json!({
+                    "topic": gossip.topic(),
+                    "topics": gossip.topics(),
+                    "topic_derivation": gossip.topic_derivation().map(|d| d.as_str()),
+                    "transport": transport_kind.as_str(),
                     "discovery": args.discovery,
-                    "num": args.num,
-                    "rate": args.rate,
-                    "size": args.size,
-                    "churn_pct": args.churn_pct,
-                    "joined": gossip.joined(),
-                    "join_wait_ms": gossip.join_wait_ms(),
-                }),
-            })?;
+                    "num": args.num,
+                    "rate": args.rate,
+                    "size": args.size.unwrap_or(DEFAULT_SIZE),
+                    "churn_pct": args.churn_pct,
+                    "broadcast_scope": args.broadcast_scope,
+                    "compress": args.compress.label(),
+                    "encrypt": args.encrypt,
+                    "sign": args.sign,
+                    "pad_pattern": args.pad.unwrap_or_default(),
+                    "seed": args.seed,
+                    "inject_dup_prob": args.inject_dup_prob,
+                    "redundancy": args.redundancy,
+                    "redundancy_gap_ms": args.redundancy_gap_ms,
+                    "batch": args.batch,
+                    "batch_max_wait_ms": args.batch_max_wait_ms,
+                    "inject_reorder": args.inject_reorder.map(|r| format!("{}:{}", r.every, r.delay)),
+                    "adaptive": args.adaptive,
+                    "ack_every": args.ack_every,
+                    "pause_schedule": args.pause_schedule,
+                    "interactive": args.interactive,
+                    "catch_up": args.catch_up,
+                    "probe_ms": args.probe_ms,
+                    "exclusive": args.exclusive,
+                    "no_wait": args.no_wait,
+                    "linger_ms": args.linger_ms,
+                    "observed_addrs": gossip.observed_addrs(),
+                    "replay": args.replay,
+                    "replay_messages": replay.as_ref().map(Vec::len),
+                    "joined": gossip.joined(),
+                    "join_wait_ms": gossip.join_wait_ms(),
+                    "build": util::build_info(),
+                    "config": resolved_config(&args)?,
+                    "gossip": gossip.gossip_config(),
+                }),
+            })?;
+            write_connect_report(&mut logger, "sender", gossip.as_ref())?;
 
             if !gossip.joined() {
-                logger.write(&metrics::LogEvent {
+                logger.emit(metrics::LogEvent {
                     ts_ms: util::now_ms(),
+                    elapsed_ms: None,
                     role: "sender",
                     peer_id: &gossip.id(),
-                    event: "no_join",
+                    run_id: None,
+                    event: metrics::EventKind::NoJoin,
                     seq: None,
                     lat_ms: None,
                     ldh: None,
@@ -126,48 +1251,387 @@ async fn main() -> Result<()> {
                         "join_wait_ms": gossip.join_wait_ms(),
                     }),
                 })?;
-                return Ok(());
+            } else {
+                let opts = SenderOptions {
+                    total: args.num,
+                    rate: args.rate,
+                    payload_size: args.size.unwrap_or(DEFAULT_SIZE),
+                    pad: args.pad.unwrap_or_default(),
+                    seed: args.seed,
+                    compression: args.compress,
+                    encryption: encryption.clone(),
+                    sign: args.sign,
+                    chunk_bytes: args.chunk_bytes,
+                    heartbeat_ms: args.heartbeat_ms.filter(|&ms| ms > 0),
+                    repair_linger_ms: args.repair.then_some(args.repair_linger_ms),
+                    max_runtime_ms: args.max_runtime_ms,
+                    watchdog_ms: args.watchdog_ms,
+                    inject: runner::Injection {
+                        dup_prob: args.inject_dup_prob,
+                        reorder: args.inject_reorder,
+                    },
+                    adaptive: args.adaptive.then_some(runner::Adaptive {
+                        queue_depth: args.adaptive_queue_depth,
+                        increase: args.adaptive_increase,
+                        interval_ms: args.adaptive_interval_ms,
+                    }),
+                    collect_acks: args.ack_every.is_some(),
+                    pause_schedule: args.pause_schedule.clone(),
+                    pause_signals: args.pause_signals,
+                    catch_up: args.catch_up,
+                    replay,
+                    probe_ms: args.probe_ms,
+                    exclusive: args.exclusive,
+                    no_wait: args.no_wait,
+                    linger_ms: args.linger_ms,
+                    redundancy: args.redundancy,
+                    redundancy_gap_ms: args.redundancy_gap_ms,
+                    batch: args.batch as usize,
+                    batch_max_wait_ms: args.batch_max_wait_ms,
+                };
+                if let Some((s, file, out_dir)) = &scenario {
+                    scenario::run_sender_scenario(gossip.as_mut(), s, file, opts, out_dir).await?;
+                    if args.dump_iroh_metrics {
+                        write_metrics_dump(&mut logger, "sender", gossip.as_ref())?;
+                    }
+                } else {
+                    let mut summary =
+                        runner::run_sender(gossip.as_mut(), &mut logger, opts).await?;
+                    summary.influx = influx.as_ref().map(influx::InfluxHandle::counters);
+                    if args.dump_iroh_metrics {
+                        write_metrics_dump(&mut logger, "sender", gossip.as_ref())?;
+                    }
+                    let json = serde_json::to_string_pretty(&summary)?;
+                    if let Some(path) = &summary_path {
+                        std::fs::write(path, &json)?;
+                    }
+                    println!("{json}");
+                }
             }
 
-            transport::run_sender(gossip, &mut logger, args.num, args.rate, args.size).await?;
+            shutdown_transport(&mut logger, "sender", gossip.as_mut()).await?;
         }
 
         "receiver" => {
-            let gossip = IrohGossip::connect(
-                args.topic_hex.clone(),
-                args.topic_name.clone(),
-                args.secret_hex.clone(),
-                args.bootstrap
-                    .split(',')
-                    .filter(|s| !s.is_empty())
-                    .map(|s| s.to_string())
-                    .collect(),
-                discovery,
-                run_time,
-            )
-            .await?;
-
-            logger.write(&metrics::LogEvent {
+            let mut gossip = transport::create(transport_kind, transport_opts.clone()).await?;
+
+            logger.emit(metrics::LogEvent {
                 ts_ms: util::now_ms(),
+                elapsed_ms: None,
                 role: "receiver",
                 peer_id: &gossip.id(),
-                event: "setup",
+                run_id: None,
+                event: metrics::EventKind::Setup,
                 seq: None,
                 lat_ms: None,
                 ldh: None,
                 extra: serde_json::json!({
-                    "discovery": args.discovery,
-                    "churn_pct": args.churn_pct,
-                    "joined": gossip.joined(),
-                    "join_wait_ms": gossip.join_wait_ms(),
-                }),
-            })?;
+                    "topic": gossip.topic(),
+                    "topics": gossip.topics(),
+                    "topic_derivation": gossip.topic_derivation().map(|d| d.as_str()),
+                    "transport": transport_kind.as_str(),
+                    "discovery": args.discovery,
+                    "churn_pct": args.churn_pct,
+                    "percentiles": args.percentiles,
+                    "quantile_method": args.quantile_method,
+                    "encrypt": args.encrypt,
+                    "require_signed": args.require_signed,
+                    "allow_sender": args.allow_sender,
+                    "expect_sender": args.expect_sender,
+                    "observed_addrs": gossip.observed_addrs(),
+                    "pad_pattern": args.pad,
+                    "seed": args.pad.map(|_| args.seed),
+                    "joined": gossip.joined(),
+                    "join_pending": gossip.join_pending(),
+                    "join_wait_ms": gossip.join_wait_ms(),
+                    "log_sample_recv": args.log_sample_recv,
+                    "announce_ms": args.announce_ms,
+                    "ack_every": args.ack_every,
+                    "continuous": args.continuous,
+                    "interactive": args.interactive,
+                    "build": util::build_info(),
+                    "config": resolved_config(&args)?,
+                    "gossip": gossip.gossip_config(),
+                }),
+            })?;
+            write_connect_report(&mut logger, "receiver", gossip.as_ref())?;
+
+            let opts = ReceiverOptions {
+                idle_report_ms: args.idle_report_ms,
+                neighbor_sample_ms: args.neighbor_sample_ms,
+                conn_sample_ms: args.conn_sample_ms,
+                resource_sample_ms: args.resource_sample_ms,
+                stats: StatsConfig {
+                    percentiles: args.percentiles.clone(),
+                    quantile_method,
+                    dup_window: args.dup_window,
+                    seq_window: args.seq_window,
+                    expected_size: args.size.map(|s| s as u64),
+                    neighbor_cap: args.summary_neighbors,
+                    trim_latency_pct: args.trim_latency_pct,
+                    lat_trend_threshold: args.lat_trend_threshold,
+                    bucket_ms: args.bucket_ms,
+                    expected_rate: args.expected_rate,
+                    max_memory_bytes: args
+                        .max_stats_memory_mb
+                        .map(|mb| (mb * 1024.0 * 1024.0) as u64),
+                },
+                repair: repair_opts.clone(),
+                encryption: encryption.clone(),
+                require_signed: require_signed.clone(),
+                expect_sender: expect_sender.clone(),
+                max_msg_age_ms: args.max_msg_age_ms,
+                rtt_probe: (!args.no_rtt_probe).then_some(runner::RttProbeOptions {
+                    count: args.rtt_probe_count,
+                    budget_ms: args.rtt_probe_budget_ms,
+                }),
+                verify_pad: args.pad.map(|p| (p, args.seed)),
+                forward: match args.forward_udp {
+                    Some(addr) => Some(forward::spawn(addr, args.forward_raw).await?),
+                    None => None,
+                },
+                reassembly_timeout_ms: args.reassembly_timeout_ms,
+                reassembly_max_bytes: args.reassembly_max_bytes,
+                max_runtime_ms: args.max_runtime_ms,
+                watchdog_ms: args.watchdog_ms,
+                complete_grace_ms: args.complete_grace_ms,
+                poll_floor_ms: args.poll_floor_ms,
+                log_sample_recv: args.log_sample_recv,
+                announce_ms: args.announce_ms.filter(|&ms| ms > 0),
+                ack_every: args.ack_every,
+            };
+            if let Some((s, _, out_dir)) = &scenario {
+                let written =
+                    scenario::run_receiver_scenario(gossip.as_mut(), &mut logger, s, opts, out_dir)
+                        .await?;
+                println!("wrote {written} run summaries to {}", out_dir.display());
+            } else if args.continuous {
+                let influx = influx.as_ref();
+                let out_dir = args.out_dir.as_deref();
+                runner::run_receiver_continuous(
+                    gossip.as_mut(),
+                    &mut logger,
+                    opts,
+                    |mut summary| {
+                        summary.influx = influx.map(influx::InfluxHandle::counters);
+                        let json = serde_json::to_string_pretty(&summary)?;
+                        if let (Some(dir), Some(test_id)) = (out_dir, &summary.test_id) {
+                            std::fs::write(dir.join(format!("{test_id}.json")), &json)?;
+                        }
+                        if let Some(path) = &summary_path {
+                            std::fs::write(path, &json)?;
+                        }
+                        if summary_json {
+                            println!("{json}");
+                        }
+                        if summary_pretty {
+                            let color = std::io::stderr().is_terminal();
+                            util::diag_str(&summary.pretty(color).to_string());
+                        }
+                        Ok(())
+                    },
+                )
+                .await?;
+            } else {
+                let mut summary = runner::run_receiver(gossip.as_mut(), &mut logger, opts).await?;
+                summary.influx = influx.as_ref().map(influx::InfluxHandle::counters);
+                let json = serde_json::to_string_pretty(&summary)?;
+                if let Some(path) = &summary_path {
+                    std::fs::write(path, &json)?;
+                }
+                if summary_json {
+                    println!("{json}");
+                }
+                if summary_pretty {
+                    let color = std::io::stderr().is_terminal();
+                    util::diag_str(&summary.pretty(color).to_string());
+                }
+                if !assertions.is_empty() {
+                    assertions_failed =
+                        check_assertions(&mut logger, gossip.as_ref(), &assertions, &summary)?;
+                }
+            }
+            if args.dump_iroh_metrics {
+                write_metrics_dump(&mut logger, "receiver", gossip.as_ref())?;
+            }
 
-            let summary = transport::run_receiver(gossip, &mut logger, args.idle_report_ms).await?;
-            println!("{}", serde_json::to_string_pretty(&summary)?);
+            shutdown_transport(&mut logger, "receiver", gossip.as_mut()).await?;
         }
 
         _ => anyhow::bail!("invalid role, must be 'sender' or 'receiver'"),
     }
+
+    logger.flush();
+    let lost = logger.errors_by_sink();
+    if !lost.is_empty() {
+        diag!(
+            "events lost per sink: {}",
+            lost.iter()
+                .map(|(sink, n)| format!("{sink}={n}"))
+                .collect::<Vec<_>>()
+                .join(" ")
+        );
+    }
+    if let Some(influx) = influx {
+        influx.finish(std::time::Duration::from_secs(3)).await;
+    }
+    if let Some(otlp) = otlp {
+        otlp.finish(std::time::Duration::from_secs(3)).await;
+    }
+
+    if let Some(run_dir) = &run_dir {
+        diag!("run_dir={}", run_dir.display());
+    }
+
+    if assertions_failed {
+        std::process::exit(report::EXIT_THRESHOLD_FAILED);
+    }
+    Ok(())
+}
+
+/// The effective configuration for `--print-config` and setup events, secrets redacted.
+fn resolved_config(args: &Args) -> Result<serde_json::Value> {
+    let mut config = serde_json::to_value(args)?;
+    if args.secret_hex.is_some() {
+        config["secret_hex"] = "<redacted>".into();
+    }
+    Ok(config)
+}
+
+/// Evaluate `--assert-*` limits, logging one `assert` event per check and
+/// printing failures. Returns true if any assertion failed.
+fn check_assertions(
+    logger: &mut dyn EventSink,
+    transport: &dyn Transport,
+    assertions: &report::Assertions,
+    summary: &metrics::Summary,
+) -> Result<bool> {
+    let mut failed = false;
+    for outcome in assertions.evaluate(summary) {
+        logger.emit(metrics::LogEvent {
+            ts_ms: util::now_ms(),
+            elapsed_ms: None,
+            role: "receiver",
+            peer_id: &transport.id(),
+            run_id: None,
+            event: metrics::EventKind::Assert,
+            seq: None,
+            lat_ms: None,
+            ldh: None,
+            extra: serde_json::to_value(&outcome)?,
+        })?;
+        if !outcome.passed {
+            failed = true;
+            diag!(
+                "assertion failed: {} (limit {}, actual {})",
+                outcome.name,
+                outcome.limit.map_or("-".to_string(), |v| v.to_string()),
+                outcome.actual.map_or("n/a".to_string(), |v| v.to_string())
+            );
+        }
+    }
+    Ok(failed)
+}
+
+/// Write the transport's full metrics registry dump as an `iroh_metrics` log event.
+fn write_metrics_dump(
+    logger: &mut dyn EventSink,
+    role: &str,
+    transport: &dyn Transport,
+) -> Result<()> {
+    if let Some(dump) = transport.metrics_dump() {
+        logger.emit(metrics::LogEvent {
+            ts_ms: util::now_ms(),
+            elapsed_ms: None,
+            role,
+            peer_id: &transport.id(),
+            run_id: None,
+            event: metrics::EventKind::IrohMetrics,
+            seq: None,
+            lat_ms: None,
+            ldh: None,
+            extra: serde_json::json!({ "openmetrics": dump }),
+        })?;
+    }
+    Ok(())
+}
+
+/// Log the transport's `connect` report (node ID, bootstraps, join outcome).
+fn write_connect_report(
+    logger: &mut dyn EventSink,
+    role: &str,
+    transport: &dyn Transport,
+) -> Result<()> {
+    if let Some(report) = transport.connect_report() {
+        logger.emit(metrics::LogEvent {
+            ts_ms: util::now_ms(),
+            elapsed_ms: None,
+            role,
+            peer_id: &transport.id(),
+            run_id: None,
+            event: metrics::EventKind::Connect,
+            seq: None,
+            lat_ms: None,
+            ldh: None,
+            extra: serde_json::to_value(report)?,
+        })?;
+    }
+    Ok(())
+}
+
+/// Close the transport and log a `shutdown` event with the time it took.
+async fn shutdown_transport(
+    logger: &mut dyn EventSink,
+    role: &str,
+    transport: &mut dyn Transport,
+) -> Result<()> {
+    let start = util::now_ms();
+    transport.close().await?;
+    let shutdown_ms = util::now_ms().saturating_sub(start);
+
+    logger.emit(metrics::LogEvent {
+        ts_ms: util::now_ms(),
+        elapsed_ms: None,
+        role,
+        peer_id: &transport.id(),
+        run_id: None,
+        event: metrics::EventKind::Shutdown,
+        seq: None,
+        lat_ms: None,
+        ldh: None,
+        extra: serde_json::json!({ "shutdown_ms": shutdown_ms }),
+    })?;
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn env_fills_flags_and_the_command_line_wins() {
+        let secret = "ab".repeat(32);
+        // SAFETY: no other test of this binary reads or writes the environment.
+        unsafe {
+            std::env::set_var("IGM_ROLE", "receiver");
+            std::env::set_var("IGM_RATE", "7");
+            std::env::set_var("IGM_SECRET_HEX", &secret);
+        }
+        let args = Args::try_parse_from(["igm", "--role", "sender"]);
+        unsafe {
+            std::env::remove_var("IGM_ROLE");
+            std::env::remove_var("IGM_RATE");
+            std::env::remove_var("IGM_SECRET_HEX");
+        }
+        let args = args.unwrap();
+        assert_eq!(args.role.as_deref(), Some("sender"));
+        assert_eq!(args.rate, 7);
+        assert_eq!(args.num, 2000);
+        assert_eq!(args.secret_hex.as_deref(), Some(secret.as_str()));
+
+        let config = resolved_config(&args).unwrap();
+        assert_eq!(config["secret_hex"], "<redacted>");
+        assert_eq!(config["rate"], 7);
+        assert_eq!(config["role"], "sender");
+    }
+}
@@ -1,6 +1,8 @@
-use crate::util::now_ms;
+use crate::frame::SigReject;
+use crate::transport::{ConnectReport, GossipConfig};
+use crate::util::{BuildInfo, elapsed_ms};
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{BTreeMap, BTreeSet, HashMap, HashSet};
 
 /// Application-level payload sent during benchmarks.
 ///
@@ -9,6 +11,8 @@ use std::collections::HashSet;
 /// - a monotonically increasing sequence number (`seq`),
 /// - the sender timestamp (`sent_ms`) for end-to-end latency,
 /// - the expected total number of messages in this test (`total`),
+/// - the sender's intended send time as an offset into its schedule
+///   (`sched_offset_ms`), so receivers see the sender's pacing error,
 /// - optional padding (`pad`) to reach a fixed payload size.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct DataMsg {
@@ -20,24 +24,828 @@ pub struct DataMsg {
     pub sent_ms: u64,
     /// Total messages expected in this test run.
     pub total: u64,
+    /// Copy index of a redundantly sent message (0 = the original, see
+    /// `--redundancy`); copies keep the original's `sent_ms`.
+    pub copy: u32,
+    /// Intended send time in ms after the schedule start (seq / rate plus
+    /// pauses, or the `--replay` offset); None in messages rebuilt from logs
+    /// that lack it. Copies keep the original's offset.
+    pub sched_offset_ms: Option<u64>,
     /// Padding bytes (used to normalize payload size).
     pub pad: Vec<u8>,
 }
 
+/// Low-rate control message broadcast by the sender alongside data (`--heartbeat-ms`).
+///
+/// Lets receivers tell "sender sent nothing" apart from "overlay delivered nothing".
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Heartbeat {
+    /// Test the sender is running.
+    pub test_id: [u8; 16],
+    /// Run ID of the sender process (`util::run_id_bytes`).
+    pub run_id: [u8; 8],
+    /// Sender clock (ms since epoch).
+    pub sent_ms: u64,
+    /// Number of data messages sent so far (i.e. the next seq).
+    pub seq: u64,
+    /// Configured heartbeat interval (ms).
+    pub interval_ms: u64,
+    /// Size every data payload is padded to (None when sizes vary, as with
+    /// `--replay`).
+    pub payload_size: Option<u64>,
+}
+
+/// Liveness announcement broadcast by receivers (`--announce-ms`), so the
+/// sender can count who is actually listening.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ReceiverAlive {
+    /// Transport ID of the announcing receiver.
+    pub peer_id: String,
+    /// Unique data messages it has received so far.
+    pub received_unique: u64,
+}
+
+/// Seqs past `highest_contiguous_seq` an `AckUpTo` can report individually.
+pub const ACK_BITMAP_BITS: u64 = 256;
+
+/// Cumulative acknowledgment broadcast by receivers (`--ack-every`).
+///
+/// Every seq up to `highest_contiguous_seq` (None: not even seq 0) arrived;
+/// bit `i` of `bitmap` (LSB first) reports seq `highest_contiguous_seq + 2 + i`
+/// (`1 + i` without a contiguous prefix), the one right after the prefix being
+/// missing by definition.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AckUpTo {
+    pub test_id: [u8; 16],
+    /// Transport ID of the acknowledging receiver.
+    pub receiver_id: String,
+    pub highest_contiguous_seq: Option<u64>,
+    pub bitmap: Vec<u8>,
+}
+
+impl AckUpTo {
+    /// Number of seqs in the contiguous prefix (0..n all arrived).
+    pub fn contiguous(&self) -> u64 {
+        self.highest_contiguous_seq.map_or(0, |h| h + 1)
+    }
+
+    /// The seqs past the prefix reported by `bitmap`.
+    pub fn bitmap_seqs(&self) -> impl Iterator<Item = u64> + '_ {
+        let first_missing = self.contiguous();
+        self.bitmap.iter().enumerate().flat_map(move |(byte, &b)| {
+            (0..8)
+                .filter(move |bit| b & (1 << bit) != 0)
+                .map(move |bit| first_missing + 1 + (byte * 8 + bit) as u64)
+        })
+    }
+}
+
+/// Acknowledgments of one receiver as seen by the sender (`--ack-every`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AckStats {
+    pub acks_received: u64,
+    /// Sent messages covered by its acks, and their share of all sent.
+    pub acked_msgs: u64,
+    pub acked_fraction: f64,
+    // time from sending a message to the first ack covering it (sender clock)
+    pub ack_lag_p50_ms: Option<u64>,
+    pub ack_lag_p90_ms: Option<u64>,
+    pub ack_lag_p99_ms: Option<u64>,
+    pub ack_lag_max_ms: Option<u64>,
+}
+
+impl AckStats {
+    /// Summarize `acks_received` acks that covered messages after `lags` ms,
+    /// out of `messages_sent`.
+    pub fn new(acks_received: u64, mut lags: Vec<u64>, messages_sent: u64) -> Self {
+        lags.sort_unstable();
+        let acked_msgs = lags.len() as u64;
+        Self {
+            acks_received,
+            acked_msgs,
+            acked_fraction: if messages_sent == 0 {
+                0.0
+            } else {
+                acked_msgs as f64 / messages_sent as f64
+            },
+            ack_lag_p50_ms: Stats::quantil(&lags, 0.50),
+            ack_lag_p90_ms: Stats::quantil(&lags, 0.90),
+            ack_lag_p99_ms: Stats::quantil(&lags, 0.99),
+            ack_lag_max_ms: lags.last().copied(),
+        }
+    }
+}
+
+/// One pause of the send loop (`--pause-schedule`, SIGUSR1/SIGUSR2).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PauseRecord {
+    /// "schedule" or "signal".
+    pub source: String,
+    /// Start as an offset from the start of the run, and actual length.
+    pub start_ms: u64,
+    pub duration_ms: u64,
+    /// Last seq sent before the pause (None if it came first) and the seq
+    /// sent after it.
+    pub last_seq_before: Option<u64>,
+    pub next_seq: u64,
+    /// Messages sent unpaced afterwards to make up for the pause (`--catch-up`).
+    pub catch_up_msgs: u64,
+}
+
+/// Transport events the sender drained after its last send (`--linger`).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LingerEvents {
+    /// Actual length of the window; shorter than asked if the stream ended.
+    pub linger_ms: u64,
+    pub neighbor_up: u64,
+    pub neighbor_down: u64,
+    /// Gossip messages heard (receiver control messages, other senders).
+    pub messages: u64,
+    pub rejoin_attempts: u64,
+    /// Transport errors, also counted in `errors_by_kind`.
+    pub errors: u64,
+    /// Any other event (unicasts, oversized messages, ...).
+    pub other: u64,
+}
+
+/// Dedup window sizes (in first-seen sequences) whose hit rate the summary
+/// reports (`DedupWindow::hit_rate`).
+const DEDUP_WINDOWS: [u64; 3] = [128, 1024, 8192];
+
+/// What an application deduplicating by seq would need to remember: for each
+/// duplicate, the number of first copies that arrived since (and including)
+/// its own first copy, i.e. the smallest window of recently first-seen
+/// sequences that still recognizes it.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DedupWindow {
+    /// Duplicates measured.
+    pub duplicates: u64,
+    /// Window that recognizes every one of them (0 without duplicates).
+    pub required_window: u64,
+    /// Fraction of the duplicates a window of N sequences recognizes, per N.
+    pub hit_rate: BTreeMap<String, f64>,
+}
+
+/// How closely a `--replay` run kept to its trace: actual minus intended send
+/// time per message (sender clock).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReplayStats {
+    pub messages: u64,
+    pub sched_err_p50_ms: Option<u64>,
+    pub sched_err_p90_ms: Option<u64>,
+    pub sched_err_p99_ms: Option<u64>,
+    pub sched_err_max_ms: Option<u64>,
+}
+
+impl ReplayStats {
+    /// Summarize the scheduling errors of the sent messages.
+    pub fn new(mut errors: Vec<u64>) -> Self {
+        errors.sort_unstable();
+        Self {
+            messages: errors.len() as u64,
+            sched_err_p50_ms: Stats::quantil(&errors, 0.50),
+            sched_err_p90_ms: Stats::quantil(&errors, 0.90),
+            sched_err_p99_ms: Stats::quantil(&errors, 0.99),
+            sched_err_max_ms: errors.last().copied(),
+        }
+    }
+}
+
+/// Raw path RTT to one neighbor, probed before and after the run (None = not
+/// a neighbor then, or no probe answered).
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct NeighborRtt {
+    pub start_min_ms: Option<f64>,
+    pub start_median_ms: Option<f64>,
+    pub end_min_ms: Option<f64>,
+    pub end_median_ms: Option<f64>,
+}
+
+/// Announcement intervals a receiver may stay silent before it counts as stale.
+pub const STALE_INTERVALS: u64 = 3;
+
+/// Sender-side view of the receivers that announced themselves.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ReceiversSeen {
+    pub receivers_seen: u64,
+    /// Last reported unique count per receiver.
+    pub last_unique: BTreeMap<String, u64>,
+    /// Best minus worst last-reported count (None before the first announcement).
+    pub unique_spread: Option<u64>,
+    /// Receivers silent for `STALE_INTERVALS` of their own announcement
+    /// interval; their `last_unique` is where they stopped reporting.
+    #[serde(default)]
+    pub stale: BTreeSet<String>,
+    /// Worst last-reported count over the messages sent (None before the
+    /// first announcement).
+    #[serde(default)]
+    pub min_delivery_rate: Option<f64>,
+    /// Time of the last announcement and the gap before it, per receiver.
+    #[serde(skip)]
+    heard: BTreeMap<String, (u64, Option<u64>)>,
+}
+
+impl ReceiversSeen {
+    /// Record an announcement at `now_ms`, replacing the receiver's previous count.
+    pub fn note(&mut self, alive: ReceiverAlive, now_ms: u64) {
+        let gap = self
+            .heard
+            .get(&alive.peer_id)
+            .map(|&(last, _)| now_ms.saturating_sub(last));
+        self.heard.insert(alive.peer_id.clone(), (now_ms, gap));
+        self.stale.remove(&alive.peer_id);
+        self.last_unique
+            .insert(alive.peer_id, alive.received_unique);
+        self.receivers_seen = self.last_unique.len() as u64;
+        let best = self.last_unique.values().max();
+        let worst = self.last_unique.values().min();
+        self.unique_spread = best.zip(worst).map(|(b, w)| b - w);
+    }
+
+    /// Mark receivers whose announcements stopped as stale, returning the
+    /// newly stale ones. A receiver heard only once has no interval yet.
+    pub fn mark_stale(&mut self, now_ms: u64) -> Vec<String> {
+        let mut newly = Vec::new();
+        for (peer, &(last, gap)) in &self.heard {
+            if let Some(gap) = gap.filter(|&g| g > 0)
+                && now_ms.saturating_sub(last) > STALE_INTERVALS * gap
+                && self.stale.insert(peer.clone())
+            {
+                newly.push(peer.clone());
+            }
+        }
+        newly
+    }
+
+    /// Final staleness and worst-receiver delivery rate after `messages_sent`.
+    pub fn finish(&mut self, now_ms: u64, messages_sent: u64) {
+        self.mark_stale(now_ms);
+        self.min_delivery_rate = self
+            .last_unique
+            .values()
+            .min()
+            .filter(|_| messages_sent > 0)
+            .map(|&worst| worst as f64 / messages_sent as f64);
+    }
+}
+
+/// Message on the unicast repair side-channel (only used with `--repair`).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub enum RepairMsg {
+    /// Receiver → sender: request retransmission of the listed sequences.
+    Nack { test_id: [u8; 16], seqs: Vec<u64> },
+    /// Sender → receiver: an encoded `DataMsg` sent again over unicast.
+    Retransmit { data: Vec<u8> },
+}
+
+/// Repair-layer traffic and deliveries.
+///
+/// Receivers fill the NACK/delivery side, senders the retransmission side.
+/// Only present in summaries of `--repair` runs.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct RepairCounters {
+    pub nacks_sent: u64,
+    pub nacks_received: u64,
+    pub seqs_requested: u64,
+    pub retransmissions: u64,
+    pub repair_bytes: u64,
+    pub received_via_repair: u64,
+    pub repair_lat_p50_ms: Option<u64>,
+    pub repair_lat_max_ms: Option<u64>,
+}
+
+/// Ground-truth duplicates and reorders injected by the sender (`--inject-*`),
+/// with the settings that produced them. Only present if injection was on.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct InjectionCounters {
+    pub dup_prob: f64,
+    pub reorder_every: Option<u64>,
+    pub reorder_delay: Option<u64>,
+    pub dups_injected: u64,
+    pub reorders_injected: u64,
+}
+
+/// Send rate chosen by the `--adaptive` controller over the run.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct AdaptiveRate {
+    pub final_rate: u64,
+    pub min_rate: u64,
+    pub max_rate: u64,
+    /// Every rate change (the first entry is the starting rate).
+    pub series: Vec<RateSample>,
+}
+
+/// Rate in effect from `elapsed_ms` (since the send loop started) on.
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
+pub struct RateSample {
+    pub elapsed_ms: u64,
+    pub rate: u64,
+}
+
+/// Connection history of one neighbor over the run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct NeighborLifetime {
+    pub peer: String,
+    pub sessions: u64,
+    pub connected_ms: u64,
+    pub longest_session_ms: u64,
+}
+
+/// Sessions of one neighbor as accumulated by `Stats`.
+#[derive(Debug, Clone, Default)]
+struct PeerSessions {
+    open_since_ms: Option<u64>,
+    sessions: u64,
+    ended: u64,
+    connected_ms: u64,
+    longest_ms: u64,
+}
+
+impl PeerSessions {
+    fn open(&mut self, ts_ms: u64) {
+        if self.open_since_ms.is_none() {
+            self.open_since_ms = Some(ts_ms);
+            self.sessions += 1;
+        }
+    }
+
+    fn close(&mut self, ts_ms: u64) {
+        if let Some(since) = self.open_since_ms.take() {
+            let len = ts_ms.saturating_sub(since);
+            self.connected_ms += len;
+            self.longest_ms = self.longest_ms.max(len);
+        }
+    }
+}
+
+/// Compressed vs uncompressed payload bytes.
+///
+/// `avg_ratio` is the mean of per-message uncompressed/compressed ratios.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct CompressionStats {
+    pub frames: u64,
+    pub uncompressed_bytes: u64,
+    pub compressed_bytes: u64,
+    pub avg_ratio: Option<f64>,
+}
+
+impl CompressionStats {
+    /// Add one message of `uncompressed` body bytes sent as `compressed` bytes.
+    pub fn note(&mut self, uncompressed: usize, compressed: usize) {
+        self.frames += 1;
+        self.uncompressed_bytes += uncompressed as u64;
+        self.compressed_bytes += compressed as u64;
+        let ratio = uncompressed as f64 / compressed.max(1) as f64;
+        let avg = self.avg_ratio.unwrap_or(0.0);
+        self.avg_ratio = Some(avg + (ratio - avg) / self.frames as f64);
+    }
+}
+
+/// Payload encryption cost.
+///
+/// `overhead_bytes` is nonce plus tag over all messages, `avg_us` the mean
+/// time to frame (sender) or unframe (receiver) one message, including
+/// compression when both are enabled.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct CryptoStats {
+    pub messages: u64,
+    pub overhead_bytes: u64,
+    pub avg_us: Option<f64>,
+}
+
+impl CryptoStats {
+    /// Add one message with `overhead` extra bytes that took `us` microseconds.
+    pub fn note(&mut self, overhead: usize, us: u64) {
+        self.messages += 1;
+        self.overhead_bytes += overhead as u64;
+        let avg = self.avg_us.unwrap_or(0.0);
+        self.avg_us = Some(avg + (us as f64 - avg) / self.messages as f64);
+    }
+}
+
+/// Kind of a `LogEvent`, serialized as the snake_case `event` string.
+///
+/// Readers match on this exhaustively, so adding a kind forces every
+/// consumer to decide how to treat it. Kinds this build does not know (logs
+/// of a newer build) read as `Unknown`, which the offline tools skip.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum EventKind {
+    /// Run configuration, written once after connecting.
+    Setup,
+    /// Node ID, bootstrap parsing and per-topic join outcome of the transport.
+    Connect,
+    /// Start of one scenario run (sender).
+    ScenarioRun,
+    /// The topic join did not complete.
+    NoJoin,
+    /// Join confirmed (receiver startup milestone).
+    JoinConfirmed,
+    /// A join milestone of the transport (`join_stages`).
+    JoinStage,
+    /// First transport event (receiver startup milestone).
+    FirstEvent,
+    /// First data message (receiver startup milestone).
+    FirstData,
+    /// Data message broadcast.
+    Send,
+    /// Data message received.
+    Recv,
+    /// Heartbeat received.
+    Heartbeat,
+    /// The subscriber lagged and missed events.
+    Lagged,
+    /// Direct neighbor added.
+    NeighborUp,
+    /// Direct neighbor removed.
+    NeighborDown,
+    /// Periodic snapshot of the active view.
+    NeighborSnapshot,
+    /// Rejoin attempt after isolation.
+    RejoinAttempt,
+    /// Periodic wire byte counters.
+    NetStats,
+    /// Connection liveness of one neighbor (`pr_avg_ratio`).
+    ReachProbe,
+    /// A receiver's announced unique count, or its going stale (sender).
+    Coverage,
+    /// Echo round trips to one neighbor at run start or end (`--no-rtt-probe`
+    /// skips them).
+    RttProbe,
+    /// The endpoint's direct addresses or home relay changed (NAT rebinding,
+    /// network switch).
+    AddrChange,
+    /// Periodic RSS/CPU sample of the receiver process (`--resource-sample`).
+    Resource,
+    /// Transport error other than lagging.
+    TransportError,
+    /// A background task of the transport ended before the run did (a dead
+    /// receive task ends the run as `transport_closed`).
+    TaskExited,
+    /// Frame failed authentication.
+    AuthFailure,
+    /// Frame encryption setting differs from ours.
+    EncryptionMismatch,
+    /// Message rejected by the signature policy.
+    SigReject,
+    /// Message from a sender outside `--expect-sender`, left out of the statistics
+    /// (receiver).
+    RecvForeign,
+    /// Another sender (a different test id) on the topic: seen by the sender
+    /// during its `--probe` window, or by the receiver during the active test.
+    ConcurrentSenderDetected,
+    /// Message older than `--max-msg-age` on arrival, left out of the statistics
+    /// (receiver).
+    RecvStale,
+    /// Message with an impossible seq or total, left out of the statistics
+    /// (receiver).
+    RecvImplausibleSeq,
+    /// Padding that differs from the `--pad-pattern` derivation, a corrupted
+    /// message (receiver).
+    PadMismatch,
+    /// Payload of another size than the sender announced, one of the first
+    /// `SIZE_MISMATCH_DETAIL` (receiver).
+    SizeMismatch,
+    /// A message above `--max-recv-bytes`, dropped before decoding (receiver;
+    /// at most one per second, `unlogged_before` counts the drops in between).
+    RecvOversized,
+    /// Repair request sent (receiver).
+    Nack,
+    /// Retransmission received (receiver).
+    RepairRecv,
+    /// Retransmissions served (sender).
+    RepairServed,
+    /// The outgoing broadcast queue stayed near capacity (sender).
+    Backpressure,
+    /// The `--adaptive` controller changed the send rate (sender).
+    RateAdjust,
+    /// Byte breakdown of the first data message (sender and receiver).
+    PayloadBreakdown,
+    /// `--max-runtime-ms` was exceeded.
+    MaxRuntimeReached,
+    /// No progress for the watchdog period.
+    WatchdogStall,
+    /// Outcome of one `--assert-*` check.
+    Assert,
+    /// Full iroh metrics dump.
+    IrohMetrics,
+    /// Message re-sent as an injected duplicate (sender, `--inject-dup-prob`).
+    InjectDup,
+    /// Message held back for injected reordering (sender, `--inject-reorder`).
+    InjectReorder,
+    /// Sending paused (sender, `--pause-schedule` or SIGUSR1).
+    PauseStart,
+    /// Sending resumed after a pause (sender).
+    PauseEnd,
+    /// The memory guard switched the statistics to histograms (receiver,
+    /// `--max-stats-memory-mb`).
+    StatsDegraded,
+    /// Summary of one finished test, the whole `Summary` (receiver) or
+    /// `SenderSummary` as `extra`; the `influx` counters are only known to the
+    /// caller and stay unset.
+    Summary,
+    /// A run-time command (`--interactive`, `--pause-signals`) and its effect.
+    Control,
+    /// Transport shut down.
+    Shutdown,
+    /// Any kind this build does not know.
+    #[serde(other)]
+    Unknown,
+}
+
+impl EventKind {
+    /// The serialized `event` string.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::Setup => "setup",
+            EventKind::Connect => "connect",
+            EventKind::ScenarioRun => "scenario_run",
+            EventKind::NoJoin => "no_join",
+            EventKind::JoinConfirmed => "join_confirmed",
+            EventKind::JoinStage => "join_stage",
+            EventKind::FirstEvent => "first_event",
+            EventKind::FirstData => "first_data",
+            EventKind::Send => "send",
+            EventKind::Recv => "recv",
+            EventKind::Heartbeat => "heartbeat",
+            EventKind::Lagged => "lagged",
+            EventKind::NeighborUp => "neighbor_up",
+            EventKind::NeighborDown => "neighbor_down",
+            EventKind::NeighborSnapshot => "neighbor_snapshot",
+            EventKind::RejoinAttempt => "rejoin_attempt",
+            EventKind::NetStats => "net_stats",
+            EventKind::ReachProbe => "reach_probe",
+            EventKind::Coverage => "coverage",
+            EventKind::RttProbe => "rtt_probe",
+            EventKind::AddrChange => "addr_change",
+            EventKind::Resource => "resource",
+            EventKind::TransportError => "transport_error",
+            EventKind::TaskExited => "task_exited",
+            EventKind::AuthFailure => "auth_failure",
+            EventKind::EncryptionMismatch => "encryption_mismatch",
+            EventKind::SigReject => "sig_reject",
+            EventKind::RecvForeign => "recv_foreign",
+            EventKind::ConcurrentSenderDetected => "concurrent_sender_detected",
+            EventKind::RecvStale => "recv_stale",
+            EventKind::RecvImplausibleSeq => "recv_implausible_seq",
+            EventKind::PadMismatch => "pad_mismatch",
+            EventKind::SizeMismatch => "size_mismatch",
+            EventKind::RecvOversized => "recv_oversized",
+            EventKind::Nack => "nack",
+            EventKind::RepairRecv => "repair_recv",
+            EventKind::Backpressure => "backpressure",
+            EventKind::RateAdjust => "rate_adjust",
+            EventKind::PayloadBreakdown => "payload_breakdown",
+            EventKind::RepairServed => "repair_served",
+            EventKind::MaxRuntimeReached => "max_runtime_reached",
+            EventKind::WatchdogStall => "watchdog_stall",
+            EventKind::Assert => "assert",
+            EventKind::IrohMetrics => "iroh_metrics",
+            EventKind::InjectDup => "inject_dup",
+            EventKind::InjectReorder => "inject_reorder",
+            EventKind::PauseStart => "pause_start",
+            EventKind::PauseEnd => "pause_end",
+            EventKind::StatsDegraded => "stats_degraded",
+            EventKind::Summary => "summary",
+            EventKind::Control => "control",
+            EventKind::Shutdown => "shutdown",
+            EventKind::Unknown => "unknown",
+        }
+    }
+}
+
+/// A message `Stats::check_seq` kept out of the statistics, with the values
+/// that gave it away.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ImplausibleSeq {
+    /// seq_beyond_total, total_beyond_window or seq_beyond_window.
+    pub reason: &'static str,
+    pub seq: u64,
+    pub total: u64,
+    /// Highest seq and expected total recorded before it.
+    pub max_seq_seen: Option<u64>,
+    pub total_expected: u64,
+}
+
+/// How a recorded message relates to the ones before it (see `Stats::record`).
+#[derive(Debug, Clone, Copy)]
+pub struct Arrival {
+    /// Another copy of this sequence arrived earlier.
+    pub duplicate: bool,
+    /// A higher sequence arrived earlier.
+    pub out_of_order: bool,
+}
+
+/// Destination for log events: the JSONL file, a CSV directory, a network
+/// exporter, or several of them at once (`MultiSink`).
+pub trait EventSink: Send {
+    /// Short name used when reporting the sink's failures.
+    fn name(&self) -> &'static str;
+
+    /// Take one event, already stamped with run ID and `elapsed_ms`.
+    fn write(&mut self, ev: &LogEvent) -> anyhow::Result<()>;
+
+    /// Push out anything buffered.
+    fn flush(&mut self) {}
+
+    /// Whether a failed write should fail the run, rather than be counted
+    /// (`--strict-logging` on the JSONL file).
+    fn strict(&self) -> bool {
+        false
+    }
+
+    /// Events this sink lost so far.
+    fn write_errors(&self) -> u64 {
+        0
+    }
+
+    /// Stamp `ev` with this process's run ID and `elapsed_ms` where unset,
+    /// then write it.
+    fn emit(&mut self, mut ev: LogEvent) -> anyhow::Result<()> {
+        ev.run_id.get_or_insert(crate::util::run_id());
+        ev.elapsed_ms.get_or_insert_with(crate::util::elapsed_ms);
+        self.write(&ev)
+    }
+}
+
+/// Fan-out to several sinks. A failing sink has its failures counted (the
+/// first one is printed) and the others still get the event; only a strict
+/// sink's failure is passed on.
+#[derive(Default)]
+pub struct MultiSink {
+    sinks: Vec<(Box<dyn EventSink>, u64)>,
+}
+
+impl MultiSink {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Pass every event written from now on to `sink` as well.
+    pub fn push(&mut self, sink: Box<dyn EventSink>) {
+        self.sinks.push((sink, 0));
+    }
+
+    /// Lost events per sink name, for sinks that lost any.
+    pub fn errors_by_sink(&self) -> BTreeMap<String, u64> {
+        self.sinks
+            .iter()
+            .map(|(sink, failed)| (sink.name().to_string(), failed + sink.write_errors()))
+            .filter(|(_, n)| *n > 0)
+            .collect()
+    }
+}
+
+impl EventSink for MultiSink {
+    fn name(&self) -> &'static str {
+        "multi"
+    }
+
+    fn write(&mut self, ev: &LogEvent) -> anyhow::Result<()> {
+        for (sink, failed) in &mut self.sinks {
+            let Err(e) = sink.write(ev) else {
+                continue;
+            };
+            if sink.strict() {
+                return Err(e);
+            }
+            if *failed == 0 {
+                crate::util::diag!(
+                    "{} sink failed, further failures are only counted: {e}",
+                    sink.name()
+                );
+            }
+            *failed += 1;
+        }
+        Ok(())
+    }
+
+    fn flush(&mut self) {
+        for (sink, _) in &mut self.sinks {
+            sink.flush();
+        }
+    }
+
+    fn write_errors(&self) -> u64 {
+        self.sinks
+            .iter()
+            .map(|(sink, failed)| failed + sink.write_errors())
+            .sum()
+    }
+}
+
+/// Points of the InfluxDB sink: posted, dropped (queue full or out of retries),
+/// and failed POST attempts.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct InfluxCounters {
+    pub points_sent: u64,
+    pub points_dropped: u64,
+    pub post_failures: u64,
+}
+
+/// Payloads re-sent to the local UDP consumer (`--forward-udp`): sent, dropped
+/// on a full queue, and failed sends.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ForwardCounters {
+    pub forwarded: u64,
+    pub forward_dropped: u64,
+    pub forward_errors: u64,
+}
+
+/// Byte breakdown of one data message: the encoded `DataMsg` fields, padding
+/// up to `--size`, the body as sealed into the frame, and the frame's header,
+/// nonce, tag and signature.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PayloadBreakdown {
+    pub header_bytes: usize,
+    pub pad_bytes: usize,
+    /// Header plus padding after compression (equal to their sum without it).
+    pub sealed_bytes: usize,
+    pub framing_bytes: usize,
+    pub wire_bytes: usize,
+}
+
+impl PayloadBreakdown {
+    /// Share of the wire bytes that carry message fields rather than padding
+    /// or framing.
+    pub fn efficiency(&self) -> f64 {
+        if self.wire_bytes == 0 {
+            return 0.0;
+        }
+        self.header_bytes as f64 / self.wire_bytes as f64
+    }
+
+    /// `payload_breakdown` event payload: the fields plus `efficiency`.
+    pub fn to_extra(self) -> serde_json::Value {
+        let mut extra = serde_json::to_value(self).unwrap_or_default();
+        extra["efficiency"] = self.efficiency().into();
+        extra
+    }
+}
+
+/// Receiver process usage over the `resource` samples of a run.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ResourceUsage {
+    pub samples: u64,
+    pub peak_rss_bytes: u64,
+    /// Mean CPU use in percent of one core.
+    pub mean_cpu_pct: Option<f64>,
+}
+
+/// `schema` of the header line that opens logs written by `JsonWriter::with_header`.
+pub const LOG_SCHEMA: &str = "iroh-gossip-metrics/1";
+
+/// First line of a JSONL log: what produced it. Logs written before headers
+/// existed start directly with a `LogEvent`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LogHeader {
+    pub schema: String,
+    pub run_id: String,
+    pub started_ms: u64,
+    /// Local peer, if known before the transport was up.
+    pub peer_id: Option<String>,
+    /// Resolved run configuration, secrets redacted.
+    pub config: serde_json::Value,
+}
+
+impl LogHeader {
+    /// Header for this process (`LOG_SCHEMA`, own run ID, now).
+    pub fn new(peer_id: Option<String>, config: serde_json::Value) -> Self {
+        Self {
+            schema: LOG_SCHEMA.to_string(),
+            run_id: crate::util::run_id().to_string(),
+            started_ms: crate::util::now_ms(),
+            peer_id,
+            config,
+        }
+    }
+
+    /// Parse a log line as a header; None for event lines.
+    pub fn parse(line: &str) -> Option<Self> {
+        serde_json::from_str(line).ok()
+    }
+}
+
 /// One structured log line written as JSONL.
 ///
 /// Lifetimes are used so we can reference static role/event strings
 /// without allocating new `String`s for every log entry.
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct LogEvent<'a> {
-    /// Timestamp of the log event (ms since epoch).
+    /// Timestamp of the log event (ms since epoch), for cross-host correlation.
     pub ts_ms: u64,
+    /// Monotonic ms since process start (`util::elapsed_ms`); `EventSink::emit` stamps it if `None`.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub elapsed_ms: Option<u64>,
     /// Role of the logging process ("sender" or "receiver").
     pub role: &'a str,
     /// Identifier of the local peer/transport instance.
     pub peer_id: &'a str,
-    /// Event type, e.g. "send", "recv", "neighbor_up".
-    pub event: &'a str,
+    /// Run ID of the writing process; `EventSink::emit` stamps its own if `None`.
+    #[serde(default, borrow, skip_serializing_if = "Option::is_none")]
+    pub run_id: Option<&'a str>,
+    /// Event type, serialized as e.g. "send", "recv", "neighbor_up".
+    pub event: EventKind,
     /// Optional sequence number (present for send/recv events).
     pub seq: Option<u64>,
     /// Optional end-to-end latency in milliseconds (for recv events).
@@ -48,38 +856,316 @@ pub struct LogEvent<'a> {
     pub extra: serde_json::Value,
 }
 
+/// Snapshot (or delta) of iroh-gossip's protocol-layer counters.
+///
+/// Data messages are application payloads as seen by the gossip layer,
+/// control messages are membership/broadcast-tree traffic.
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct ProtocolCounters {
+    pub data_msgs_sent: u64,
+    pub data_msgs_recv: u64,
+    pub data_bytes_sent: u64,
+    pub data_bytes_recv: u64,
+    pub ctrl_msgs_sent: u64,
+    pub ctrl_msgs_recv: u64,
+    pub ctrl_bytes_sent: u64,
+    pub ctrl_bytes_recv: u64,
+    pub neighbor_up: u64,
+    pub neighbor_down: u64,
+}
+
+impl ProtocolCounters {
+    /// Counter increase since an earlier snapshot.
+    pub fn since(&self, start: &ProtocolCounters) -> ProtocolCounters {
+        ProtocolCounters {
+            data_msgs_sent: self.data_msgs_sent.saturating_sub(start.data_msgs_sent),
+            data_msgs_recv: self.data_msgs_recv.saturating_sub(start.data_msgs_recv),
+            data_bytes_sent: self.data_bytes_sent.saturating_sub(start.data_bytes_sent),
+            data_bytes_recv: self.data_bytes_recv.saturating_sub(start.data_bytes_recv),
+            ctrl_msgs_sent: self.ctrl_msgs_sent.saturating_sub(start.ctrl_msgs_sent),
+            ctrl_msgs_recv: self.ctrl_msgs_recv.saturating_sub(start.ctrl_msgs_recv),
+            ctrl_bytes_sent: self.ctrl_bytes_sent.saturating_sub(start.ctrl_bytes_sent),
+            ctrl_bytes_recv: self.ctrl_bytes_recv.saturating_sub(start.ctrl_bytes_recv),
+            neighbor_up: self.neighbor_up.saturating_sub(start.neighbor_up),
+            neighbor_down: self.neighbor_down.saturating_sub(start.neighbor_down),
+        }
+    }
+}
+
+/// Cumulative bytes sent/received by the endpoint's socket (all peers, all protocols).
+#[derive(Debug, Clone, Copy, Default, Serialize, Deserialize)]
+pub struct WireBytes {
+    pub tx: u64,
+    pub rx: u64,
+}
+
+/// Estimator used to derive quantiles from sorted samples.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum QuantileMethod {
+    /// Nearest-rank rounding (the historic estimator, comparable with old runs).
+    #[default]
+    Nearest,
+    /// Linear interpolation between the two closest ranks.
+    Linear,
+}
+
+/// Receiver-side configuration for how statistics are summarized.
+#[derive(Debug, Clone)]
+pub struct StatsConfig {
+    /// Percentiles (0-100) reported in the percentile maps of the summary.
+    pub percentiles: Vec<f64>,
+    /// Estimator used for the percentile maps.
+    pub quantile_method: QuantileMethod,
+    /// Number of sequences (below the max seen) for which first-arrival times
+    /// are kept to measure duplicate spacing.
+    pub dup_window: u64,
+    /// Peers listed in `Summary::neighbors` (the longest-connected ones).
+    pub neighbor_cap: usize,
+    /// Share (0-100) of the slowest latency samples dropped for the `*_trimmed`
+    /// percentiles (0 disables them).
+    pub trim_latency_pct: f64,
+    /// Approximate memory (bytes) the statistics may use before latency and LDH
+    /// samples switch to histograms (None = unbounded).
+    pub max_memory_bytes: Option<u64>,
+    /// Latency slope (ms per minute of receive time) above which the summary
+    /// flags `latency_degrading`.
+    pub lat_trend_threshold: f64,
+    /// Width of the send-time windows of the per-bucket delivery figures.
+    pub bucket_ms: u64,
+    /// Sender rate (msg/s) the buckets expect; inferred from the received
+    /// seqs and send times if None.
+    pub expected_rate: Option<f64>,
+    /// Furthest a seq may lie beyond the highest one seen, and a test's `total`
+    /// beyond the expected one, before the message counts as implausible
+    /// (see `Stats::check_seq`); also how far past the previous max skipped
+    /// seqs are tracked as gaps one by one.
+    pub seq_window: u64,
+    /// Payload size to check arrivals against until a heartbeat announces the
+    /// sender's (receiver `--size`).
+    pub expected_size: Option<u64>,
+}
+
+impl Default for StatsConfig {
+    fn default() -> Self {
+        Self {
+            percentiles: vec![50.0, 90.0, 99.0],
+            quantile_method: QuantileMethod::Nearest,
+            dup_window: 10_000,
+            neighbor_cap: 32,
+            trim_latency_pct: 0.0,
+            max_memory_bytes: None,
+            lat_trend_threshold: 10.0,
+            bucket_ms: 1000,
+            expected_rate: None,
+            seq_window: 1_000_000,
+            expected_size: None,
+        }
+    }
+}
+
+/// Size mismatches logged as `size_mismatch` events; later ones are only counted.
+pub const SIZE_MISMATCH_DETAIL: u64 = 5;
+
+/// Send-span buckets beyond which the per-bucket delivery is not computed
+/// (a nonsensical span from a tiny inferred rate).
+const MAX_DELIVERY_BUCKETS: u64 = 1_000_000;
+
+/// Per-bucket delivery figures (see `Stats::bucket_delivery`).
+#[derive(Debug, Default)]
+struct BucketDelivery {
+    rate: Option<f64>,
+    estimated: bool,
+    worst: Option<f64>,
+    below_90: u64,
+    zero_streak_ms: u64,
+}
+
 /// Accumulates per-run receiver statistics.
 ///
 /// This struct is intentionally stateful and updated incrementally
 /// as messages and transport events arrive.
 #[derive(Default, Clone)]
 pub struct Stats {
+    // summary configuration
+    config: StatsConfig,
+
     // delivery/duplicates/order
     seen: HashSet<u64>,
+    // every seq below this arrived (the contiguous prefix `AckUpTo` reports)
+    contiguous: u64,
     max_seq_seen: i64,
     recv_total: u64,
     pub duplicates: u64,
     pub out_of_order: u64,
 
+    // duplicate spacing: seq -> (first arrival ms, duplicate count), bounded by dup_window
+    first_arrivals: BTreeMap<u64, (u64, u64)>,
+    dup_delays: Vec<u64>,
+    max_dups_for_single_seq: u64,
+    // dedup window: seq -> number of first copies before its own (dropped by
+    // the memory guard), and per duplicate the largest distance and the hits
+    // of each `DEDUP_WINDOWS` size
+    first_copy_index: HashMap<u64, u64>,
+    dedup_measured: u64,
+    dedup_required: u64,
+    dedup_hits: [u64; DEDUP_WINDOWS.len()],
+
+    // reordering depth (seq displacement) and RFC 4737 extent (arrival positions)
+    arrivals: u64,
+    reorder_depths: Vec<u64>,
+    reorder_extent: u64,
+    // (new max seq, arrival index) for every arrival that raised max_seq_seen
+    max_seq_arrivals: Vec<(u64, u64)>,
+
+    // gaps (missing seqs below max_seq_seen): seq -> time the gap was first observed
+    gap_opened_ms: BTreeMap<u64, u64>,
+    // skipped seqs more than `seq_window` past the previous max, kept as
+    // untimed ranges (start -> end, exclusive) instead of one entry each
+    gap_ranges: BTreeMap<u64, u64>,
+    gaps_filled: u64,
+    late_arrival_delays: Vec<u64>,
+
+    // payload bytes of first copies (for wire overhead)
+    unique_payload_bytes: u64,
+
     // lag/end-to-end-delay (E2E)
     pub lagged_events: u64,
+    // max seq seen (-1 before any) when the open lag episode began, and the
+    // seqs still missing across each closed episode
+    lag_open: Option<i64>,
+    lag_gaps: Vec<u64>,
+    // latencies of first copies, and of duplicate copies
     lats: Vec<u64>,
+    dup_lats: Vec<u64>,
+    // first-copy latencies with their monotonic receive time, for the thirds of
+    // the run (dropped by the memory guard), and the running latency-vs-time fit
+    lat_timeline: Vec<(u64, u64)>,
+    lat_trend: LatencyTrend,
+
+    // first copies by sender-clock window (`StatsConfig::bucket_ms`), and the
+    // (sent_ms, seq) of the earliest and latest sent one, to place the send span
+    sent_buckets: BTreeMap<u64, u64>,
+    sent_first: Option<(u64, u64)>,
+    sent_last: Option<(u64, u64)>,
+
+    // (from enqueue, from wire) latencies of first copies carrying a wire
+    // timestamp (dropped by the memory guard)
+    wire_split: Vec<(u64, u64)>,
+
+    // first copies by the path type of the delivering neighbor, and the
+    // latencies of the direct and relay ones (dropped by the memory guard)
+    direct_path_msgs: u64,
+    relay_path_msgs: u64,
+    unknown_path_msgs: u64,
+    direct_path_lats: Vec<u64>,
+    relay_path_lats: Vec<u64>,
+
+    // schedule start implied by each first copy (`sent_ms - sched_offset_ms`;
+    // dropped by the memory guard) and the earliest one, which the pacing
+    // error of a message is measured against
+    sched_starts: Vec<u64>,
+    sched_base: Option<u64>,
 
     // LDH (Last Delivery Hop)
     ldhs: Vec<u64>,
+    // first copies delivered with neighbor scope (no LDH, 1 hop)
+    direct_deliveries: u64,
 
     // expected total messages
     pub total_expected: u64,
 
-    // peer reachability (PR)
+    // peer reachability (PR); the ratio is None until the first reachability probe
     pr_last_ts: Option<u64>,
-    pr_last_ratio: f64,
+    pr_last_ratio: Option<f64>,
     pr_acc_ms: f64,
     pr_total_ms: f64,
 
     // count neighbours in active view
     neighbour_down: u64,
     neighbour_up: u64,
+    // connection sessions per neighbor (monotonic clock)
+    neighbor_sessions: BTreeMap<String, PeerSessions>,
+
+    // rejoin attempts after isolation
+    rejoin_attempts: u64,
+    rejoin_successes: u64,
+
+    // changes of our observed direct addresses / home relay
+    addr_changes: u64,
+    relay_changes: u64,
+
+    // transport errors per kind
+    errors_by_kind: BTreeMap<String, u64>,
+    // transport tasks that ended early (`task_exited`)
+    task_exits: u64,
+
+    // compressed frames of the active test (all copies)
+    compression: CompressionStats,
+
+    // encryption: decrypted frames, auth failures, sender/receiver setting mismatches
+    crypto: CryptoStats,
+    auth_failures: u64,
+    encryption_mismatches: u64,
+    // payloads that were not a frame this build understands
+    decode_errors: u64,
+
+    // heartbeats: count, last arrival, largest gap, and seq ranges sent while none arrived
+    heartbeats_received: u64,
+    last_heartbeat: Option<(u64, u64)>,
+    max_heartbeat_gap_ms: Option<u64>,
+    heartbeat_outages: Vec<(u64, u64)>,
+    // run ID of the sender, learned from its heartbeats
+    sender_run_id: Option<[u8; 8]>,
+
+    // receiver announcements: sent, and other receivers heard (never data)
+    announces_sent: u64,
+    // cumulative acks broadcast (`--ack-every`)
+    acks_sent: u64,
+    peers_heard: HashSet<String>,
+
+    // signature policy rejects
+    rejected_unsigned: u64,
+    rejected_bad_sig: u64,
+    rejected_not_allowed: u64,
+
+    // messages from senders outside `--expect-sender`
+    foreign_msgs: u64,
+
+    // tests other than the active one seen meanwhile (concurrent senders)
+    other_tests: BTreeSet<[u8; 16]>,
+
+    // messages older than `--max-msg-age` on arrival
+    stale_msgs: u64,
+
+    // messages whose seq or total failed `check_seq`
+    implausible_seq: u64,
+
+    // redundant copies (`--redundancy`): highest copy index seen, and seqs a
+    // later copy delivered first whose original has not arrived (yet)
+    max_copy: u32,
+    rescued_by_copy: HashSet<u64>,
+
+    // messages whose padding failed the `--pad-pattern` check
+    pad_mismatches: u64,
+    // frames per received `--batch` gossip message
+    batch_sizes: Option<LatencyHistogram>,
+
+    // payload size announced by the sender's heartbeats, and the arrivals of
+    // another size with the smallest and largest such size
+    announced_size: Option<u64>,
+    size_mismatch_msgs: u64,
+    size_mismatch_min: Option<u64>,
+    size_mismatch_max: Option<u64>,
+    // messages dropped unread for exceeding --max-recv-bytes
+    oversized_msgs: u64,
+
+    // byte breakdown of the first message of the test
+    payload: Option<PayloadBreakdown>,
+
+    // repair layer: counters, latencies of repaired first copies, last arrival
+    repair: RepairCounters,
+    repair_lats: Vec<u64>,
+    last_arrival_ms: u64,
 
     // connectivity-level (active neighbors)
     conn_last_connected: u64,
@@ -90,126 +1176,1800 @@ pub struct Stats {
     downtime_started_at: Option<u64>,
     downtime_periods: u64,
     downtime_duration_ms: Vec<u64>,
+
+    // origin of the peer view (join or run start) and the first time a neighbor was seen
+    view_origin_ms: Option<u64>,
+    first_neighbor_ms: Option<u64>,
+
+    // memory guard (`--max-stats-memory-mb`): once degraded, latency and LDH samples
+    // go to histograms (with running sums for mean/stddev) and new gaps are kept
+    // without their opening time
+    degradation: Option<StatsDegradation>,
+    degradation_reported: bool,
+    lat_hist: Option<LatencyHistogram>,
+    dup_lat_hist: Option<LatencyHistogram>,
+    ldh_hist: Option<LatencyHistogram>,
+    lat_sum: f64,
+    lat_sq_sum: f64,
+    gaps_untimed: BTreeSet<u64>,
+}
+
+/// Least-squares fit of latency against receive time, kept as running sums
+/// so it survives the memory guard.
+#[derive(Debug, Clone, Default)]
+struct LatencyTrend {
+    n: f64,
+    t0_ms: Option<u64>,
+    sum_t: f64,
+    sum_lat: f64,
+    sum_tt: f64,
+    sum_t_lat: f64,
+}
+
+impl LatencyTrend {
+    fn add(&mut self, recv_ms: u64, lat: u64) {
+        let t0 = *self.t0_ms.get_or_insert(recv_ms);
+        // Minutes since the first sample keep the sums small.
+        let t = recv_ms.saturating_sub(t0) as f64 / 60_000.0;
+        let lat = lat as f64;
+        self.n += 1.0;
+        self.sum_t += t;
+        self.sum_lat += lat;
+        self.sum_tt += t * t;
+        self.sum_t_lat += t * lat;
+    }
+
+    /// Slope in ms of latency per minute (None without two distinct receive times).
+    fn slope_ms_per_min(&self) -> Option<f64> {
+        let denom = self.n * self.sum_tt - self.sum_t * self.sum_t;
+        (denom > 0.0).then(|| (self.n * self.sum_t_lat - self.sum_t * self.sum_lat) / denom)
+    }
+}
+
+/// What the memory guard dropped when it degraded the statistics (the
+/// `stats_degraded` event).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct StatsDegradation {
+    /// Monotonic time of the switch.
+    pub at_ms: u64,
+    /// Approximate usage at the switch, and the configured cap.
+    pub approx_bytes: u64,
+    pub cap_bytes: u64,
+    /// Samples folded into histograms.
+    pub lat_samples: u64,
+    pub dup_lat_samples: u64,
+    pub ldh_samples: u64,
+    /// Open gaps whose opening time was dropped.
+    pub gap_timestamps: u64,
+}
+
+/// Share of `StatsConfig::max_memory_bytes` at which the guard degrades.
+const DEGRADE_AT: f64 = 0.9;
+
+/// Why a sender or receiver run ended.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Termination {
+    /// Sender sent everything; receiver test closed by the next test.
+    Complete,
+    /// Receiver went idle for `--idle-report-ms`.
+    Idle,
+    /// `--max-runtime-ms` was exceeded.
+    MaxRuntime,
+    /// Ctrl-C.
+    Interrupt,
+    /// The transport's event stream ended or failed fatally.
+    TransportClosed,
+}
+
+impl Termination {
+    /// Name used in logs and console output (same as the serialized form).
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Termination::Complete => "complete",
+            Termination::Idle => "idle",
+            Termination::MaxRuntime => "max_runtime",
+            Termination::Interrupt => "interrupt",
+            Termination::TransportClosed => "transport_closed",
+        }
+    }
+}
+
+/// Why a receiver saw no data, derived from its summary counters.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Diagnosis {
+    /// The join was skipped on purpose (`--force-no-join`).
+    JoinSkipped,
+    /// Subscribing to a topic failed outright.
+    SubscribeFailed,
+    /// The join never completed, with no bootstrap peer to join through.
+    NoBootstrap,
+    /// The join never completed.
+    JoinTimeout,
+    /// A transport task died (see `task_exited`) before any data arrived.
+    TaskExited,
+    /// Joined, but no neighbor ever came up.
+    JoinedNoNeighbors,
+    /// Neighbors were up, yet no data message arrived.
+    NeighborsNoData,
+    /// Only messages of other senders or earlier runs arrived (`--expect-sender`,
+    /// `--max-msg-age`).
+    ForeignTestOnly,
+    /// Only payloads that failed to decode, authenticate or verify arrived.
+    DecodeErrorsOnly,
+    /// Only messages above `--max-recv-bytes` arrived.
+    OversizedOnly,
+    /// Data arrived.
+    Ok,
+}
+
+impl Diagnosis {
+    /// Pick the first explanation that fits, from the join onwards.
+    pub fn of(s: &Summary) -> Self {
+        let undecodable = s.decode_errors
+            + s.auth_failures
+            + s.encryption_mismatches
+            + s.rejected_unsigned
+            + s.rejected_bad_sig
+            + s.rejected_not_allowed;
+        if s.received_unique > 0 {
+            Diagnosis::Ok
+        } else if !s.joined {
+            match &s.connect {
+                Some(c) if c.join_skipped() => Diagnosis::JoinSkipped,
+                Some(c) if c.subscribe_error().is_some() => Diagnosis::SubscribeFailed,
+                Some(c) if c.bootstraps_parsed == 0 => Diagnosis::NoBootstrap,
+                _ => Diagnosis::JoinTimeout,
+            }
+        } else if s.task_exits > 0 {
+            Diagnosis::TaskExited
+        } else if s.neighbour_up == 0 && s.neighbors.is_empty() {
+            Diagnosis::JoinedNoNeighbors
+        } else if s.foreign_msgs + s.stale_msgs > 0 {
+            Diagnosis::ForeignTestOnly
+        } else if undecodable > 0 {
+            Diagnosis::DecodeErrorsOnly
+        } else if s.oversized_msgs > 0 {
+            Diagnosis::OversizedOnly
+        } else {
+            Diagnosis::NeighborsNoData
+        }
+    }
+
+    /// Name used in logs and console output (same as the serialized form).
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Diagnosis::JoinSkipped => "join_skipped",
+            Diagnosis::SubscribeFailed => "subscribe_failed",
+            Diagnosis::NoBootstrap => "no_bootstrap",
+            Diagnosis::JoinTimeout => "join_timeout",
+            Diagnosis::TaskExited => "task_exited",
+            Diagnosis::JoinedNoNeighbors => "joined_no_neighbors",
+            Diagnosis::NeighborsNoData => "neighbors_no_data",
+            Diagnosis::ForeignTestOnly => "foreign_test_only",
+            Diagnosis::DecodeErrorsOnly => "decode_errors_only",
+            Diagnosis::OversizedOnly => "oversized_only",
+            Diagnosis::Ok => "ok",
+        }
+    }
+
+    /// One-line hint on what to check next.
+    pub fn hint(&self) -> &'static str {
+        match self {
+            Diagnosis::JoinSkipped => "--force-no-join is set; drop it to join the topic",
+            Diagnosis::SubscribeFailed => {
+                "iroh-gossip rejected the subscription; see connect.topics for the error"
+            }
+            Diagnosis::NoBootstrap => {
+                "no peer was found without --bootstrap; pass the node_id= of a running peer, or use --discovery direct on one LAN"
+            }
+            Diagnosis::JoinTimeout => {
+                "no bootstrap peer answered; check --bootstrap, --discovery and the network"
+            }
+            Diagnosis::TaskExited => {
+                "a transport task ended early; the task_exited event has its reason (a panic is a bug)"
+            }
+            Diagnosis::JoinedNoNeighbors => {
+                "the topic has no other members; check that both sides use the same --topic-*"
+            }
+            Diagnosis::NeighborsNoData => {
+                "peers are up but sent nothing; is the sender running on this topic, with --send-topic?"
+            }
+            Diagnosis::ForeignTestOnly => {
+                "only messages of other senders or earlier runs arrived; check --expect-sender and --max-msg-age"
+            }
+            Diagnosis::DecodeErrorsOnly => {
+                "every payload failed to decode; check --encrypt, --require-signed and that both sides run the same build"
+            }
+            Diagnosis::OversizedOnly => {
+                "every message exceeded the receive cap; raise --max-recv-bytes or match --size on both sides"
+            }
+            Diagnosis::Ok => "data arrived",
+        }
+    }
+}
+
+/// Version of the `Summary` JSON layout; bump when fields are renamed or
+/// change meaning (adding fields is compatible).
+///
+/// 2: `lat_*` cover first copies only (duplicates moved to `dup_lat_*`).
+pub const SUMMARY_SCHEMA_VERSION: u32 = 2;
+
+/// Sparse latency histogram with log-linear buckets (4 significant bits, so
+/// bucket width is at most 1/8 of its lower bound). Keys are bucket lower
+/// bounds in ms; histograms of separate runs can be merged exactly.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct LatencyHistogram {
+    pub buckets: BTreeMap<u64, u64>,
 }
 
-/// Final summarized metrics for one receiver run.
-#[derive(Debug, Clone, Serialize)]
-pub struct Summary {
-    // delivery
-    pub received_unique: u64,
-    pub recv_total: u64,
-    pub total_expected: u64,
-    pub delivery_rate: f64,
+impl LatencyHistogram {
+    fn bucket(v: u64) -> u64 {
+        let bits = 64 - v.leading_zeros();
+        if bits <= 4 {
+            return v;
+        }
+        let shift = bits - 4;
+        (v >> shift) << shift
+    }
+
+    /// Count one sample.
+    pub fn add(&mut self, v: u64) {
+        *self.buckets.entry(Self::bucket(v)).or_default() += 1;
+    }
+
+    /// Build a histogram from latency samples (None if there are none).
+    pub fn from_samples(samples: &[u64]) -> Option<Self> {
+        if samples.is_empty() {
+            return None;
+        }
+        let mut hist = Self::default();
+        for &v in samples {
+            *hist.buckets.entry(Self::bucket(v)).or_default() += 1;
+        }
+        Some(hist)
+    }
+
+    /// Add all counts of `other`.
+    pub fn merge(&mut self, other: &Self) {
+        for (&b, &n) in &other.buckets {
+            *self.buckets.entry(b).or_default() += n;
+        }
+    }
+
+    pub fn count(&self) -> u64 {
+        self.buckets.values().sum()
+    }
+
+    /// Nearest-rank quantile (0.0-1.0), reported as the bucket lower bound.
+    pub fn quantile(&self, q: f64) -> Option<u64> {
+        let total = self.count();
+        if total == 0 {
+            return None;
+        }
+        let rank = ((total - 1) as f64 * q).round() as u64;
+        let mut seen = 0;
+        for (&b, &n) in &self.buckets {
+            seen += n;
+            if seen > rank {
+                return Some(b);
+            }
+        }
+        self.buckets.keys().next_back().copied()
+    }
+}
+
+/// `join_wait_ms` taken apart by the join milestones (`join_stages`), each
+/// the time since the stage it waits on; None if either stage never happened.
+/// The relay connects alongside the gossip join, so `discovery_ms` overlaps
+/// the later stages.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct JoinBreakdown {
+    /// Process start to endpoint_bound.
+    pub bind_ms: Option<u64>,
+    /// endpoint_bound to relay_connected (`--discovery relay` only).
+    pub discovery_ms: Option<u64>,
+    /// endpoint_bound to subscribe_sent.
+    pub subscribe_ms: Option<u64>,
+    /// subscribe_sent to first_neighbor.
+    pub first_neighbor_ms: Option<u64>,
+    /// first_neighbor to joined (every topic joined).
+    pub joined_ms: Option<u64>,
+}
+
+impl JoinBreakdown {
+    /// Deltas between the milestones of `Transport::join_stages`.
+    pub fn from_stages(stages: &BTreeMap<String, u64>) -> Self {
+        let between =
+            |from: &str, to: &str| Some(stages.get(to)?.saturating_sub(*stages.get(from)?));
+        Self {
+            bind_ms: stages.get("endpoint_bound").copied(),
+            discovery_ms: between("endpoint_bound", "relay_connected"),
+            subscribe_ms: between("endpoint_bound", "subscribe_sent"),
+            first_neighbor_ms: between("subscribe_sent", "first_neighbor"),
+            joined_ms: between("first_neighbor", "joined"),
+        }
+    }
+}
+
+/// Final summarized metrics for one receiver run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Summary {
+    // layout version (`SUMMARY_SCHEMA_VERSION`; 0 for files written before versioning)
+    #[serde(default)]
+    pub schema_version: u32,
+
+    // hex test_id of the summarized test (None if no data arrived)
+    pub test_id: Option<String>,
+    // run ID of this receiver process, and of the sender once a heartbeat arrived
+    pub run_id: Option<String>,
+    pub sender_run_id: Option<String>,
+    // versions of the receiver binary (None in files written before it was recorded)
+    #[serde(default)]
+    pub build: Option<BuildInfo>,
+    // effective iroh-gossip parameters of the receiver (None in older files)
+    #[serde(default)]
+    pub gossip: Option<GossipConfig>,
+    // joined topic ID (hex; the send topic with several)
+    #[serde(default)]
+    pub topic: Option<String>,
+    // topic name derivation ("v1" or "legacy"; None for hex topics and older files)
+    #[serde(default)]
+    pub topic_derivation: Option<String>,
+
+    // delivery
+    pub received_unique: u64,
+    pub recv_total: u64,
+    pub total_expected: u64,
+    pub delivery_rate: f64,
+    // delivery per `bucket_ms` window of the sender's send time, against
+    // `bucket_rate` (msg/s; estimated from the received seqs and send times
+    // unless --expected-rate was given)
+    #[serde(default)]
+    pub worst_bucket_delivery_rate: Option<f64>,
+    #[serde(default)]
+    pub buckets_below_90pct: u64,
+    #[serde(default)]
+    pub longest_zero_delivery_streak_ms: u64,
+    #[serde(default)]
+    pub bucket_ms: u64,
+    #[serde(default)]
+    pub bucket_rate: Option<f64>,
+    #[serde(default)]
+    pub bucket_rate_estimated: bool,
+
+    // duplicates
+    pub duplicate_rate: f64,
+    pub duplicates: u64,
+    pub out_of_order: u64,
+
+    // duplicate spacing (time since the first copy of the same seq; monotonic clock)
+    pub dup_delay_p50_ms: Option<u64>,
+    pub dup_delay_p90_ms: Option<u64>,
+    pub dup_delay_max_ms: Option<u64>,
+    pub max_dups_for_single_seq: u64,
+    // duplicate suppression window an application would need (None if the
+    // memory guard dropped the first-copy index)
+    #[serde(default)]
+    pub dedup_window: Option<DedupWindow>,
+
+    // reordering depth (max_seq_seen - seq for late messages)
+    pub reorder_depth_p50: Option<u64>,
+    pub reorder_depth_p90: Option<u64>,
+    pub reorder_depth_max: Option<u64>,
+    // RFC 4737 reordering extent (in arrival positions)
+    pub reorder_extent: u64,
+    // fraction of received messages a reorder buffer of N slots would drop
+    pub reorder_buffer_droppable: BTreeMap<String, f64>,
+
+    // late arrivals (messages that filled a previously observed gap; monotonic clock)
+    pub late_arrivals: u64,
+    pub late_arrival_delay_p50_ms: Option<u64>,
+    pub late_arrival_delay_p90_ms: Option<u64>,
+    pub late_arrival_delay_max_ms: Option<u64>,
+    pub gaps_filled: u64,
+    pub gaps_unfilled: u64,
+
+    // lag/E2E (wall clock: local receive time minus the sender's timestamp)
+    pub lagged_events: u64,
+    // lag episodes (consecutive lagged events up to the next higher seq) and the
+    // seqs skipped across them, as an estimate of what the lagging cost
+    #[serde(default)]
+    pub lag_episodes: u64,
+    #[serde(default)]
+    pub lag_estimated_missed_msgs: u64,
+    #[serde(default)]
+    pub lag_max_episode_msgs: Option<u64>,
+    // latency of the first copy of each seq (since schema version 2; before, every
+    // copy counted, which skewed the distribution toward slow redundant paths)
+    pub lat_min: Option<u64>,
+    pub lat_p50: Option<u64>,
+    pub lat_p90: Option<u64>,
+    pub lat_p99: Option<u64>,
+    pub lat_max: Option<u64>,
+    // latency histogram, mergeable across runs (None without samples)
+    pub lat_histogram: Option<LatencyHistogram>,
+    // mean, standard deviation and median absolute deviation of the same samples
+    #[serde(default)]
+    pub lat_mean: Option<f64>,
+    #[serde(default)]
+    pub lat_stddev: Option<f64>,
+    #[serde(default)]
+    pub lat_mad: Option<f64>,
+    // percentiles without the slowest `lat_trim_pct` % of samples (None unless
+    // `--trim-latency-pct`); loss and duplicate accounting never trim
+    #[serde(default)]
+    pub lat_trim_pct: Option<f64>,
+    #[serde(default)]
+    pub lat_p50_trimmed: Option<u64>,
+    #[serde(default)]
+    pub lat_p90_trimmed: Option<u64>,
+    #[serde(default)]
+    pub lat_p99_trimmed: Option<u64>,
+    #[serde(default)]
+    pub lat_max_trimmed: Option<u64>,
+    // latency trend within the run: least-squares slope against receive time, and
+    // the median of the first and last third of the receive span (None once the
+    // memory guard dropped the timestamps); degrading = slope above
+    // `--lat-trend-threshold`
+    #[serde(default)]
+    pub lat_trend_ms_per_min: Option<f64>,
+    #[serde(default)]
+    pub lat_p50_first_third: Option<u64>,
+    #[serde(default)]
+    pub lat_p50_last_third: Option<u64>,
+    #[serde(default)]
+    pub latency_degrading: bool,
+    // first copies carrying a wire timestamp (see `frame::stamp_wire_ts`), and
+    // their latency from the sender's enqueue and from the hand-off to gossip;
+    // the difference is local queueing (None for older senders, see `lat_*`)
+    #[serde(default)]
+    pub wire_stamped: u64,
+    #[serde(default)]
+    pub lat_from_enqueue_p50: Option<u64>,
+    #[serde(default)]
+    pub lat_from_enqueue_p90: Option<u64>,
+    #[serde(default)]
+    pub lat_from_enqueue_p99: Option<u64>,
+    #[serde(default)]
+    pub lat_from_wire_p50: Option<u64>,
+    #[serde(default)]
+    pub lat_from_wire_p90: Option<u64>,
+    #[serde(default)]
+    pub lat_from_wire_p99: Option<u64>,
+    // first copies by the path (`--conn-sample` probe) of the neighbor that
+    // delivered them, at receive time: direct or relay, with their latency;
+    // mixed or not yet probed neighbors count as unknown
+    #[serde(default)]
+    pub direct_path_msgs: u64,
+    #[serde(default)]
+    pub relay_path_msgs: u64,
+    #[serde(default)]
+    pub unknown_path_msgs: u64,
+    #[serde(default)]
+    pub lat_p50_direct: Option<u64>,
+    #[serde(default)]
+    pub lat_p90_direct: Option<u64>,
+    #[serde(default)]
+    pub lat_p50_relay: Option<u64>,
+    #[serde(default)]
+    pub lat_p90_relay: Option<u64>,
+    // sender pacing error as seen here: `sent_ms` past the intended send time,
+    // against the earliest schedule start implied by any message (None for
+    // senders that do not stamp `sched_offset_ms`)
+    #[serde(default)]
+    pub send_jitter_p50_ms: Option<u64>,
+    #[serde(default)]
+    pub send_jitter_p90_ms: Option<u64>,
+    #[serde(default)]
+    pub send_jitter_p99_ms: Option<u64>,
+    #[serde(default)]
+    pub send_jitter_max_ms: Option<u64>,
+    // latency of duplicate copies, the cost of the redundant paths
+    #[serde(default)]
+    pub dup_lat_p50: Option<u64>,
+    #[serde(default)]
+    pub dup_lat_p90: Option<u64>,
+
+    // LDH (overlay hop counts)
+    pub ldh_min: Option<u64>,
+    pub ldh_p50: Option<u64>,
+    pub ldh_p90: Option<u64>,
+    pub ldh_p99: Option<u64>,
+    pub ldh_max: Option<u64>,
+
+    // neighbor-scope (direct, 1 hop) deliveries among unique messages
+    pub direct_deliveries: u64,
+    pub all_direct: bool,
+
+    // configurable percentiles ("p50", "p99.9", ...) using the selected estimator;
+    // the fixed *_p50/p90/p99 fields above always use nearest-rank
+    pub lat_percentiles: BTreeMap<String, f64>,
+    pub ldh_percentiles: BTreeMap<String, f64>,
+    pub downtime_percentiles_ms: BTreeMap<String, f64>,
+
+    // peer reachability: time-weighted mean of reachable/connected neighbors, where
+    // reachable means the neighbor has a network path with recent inbound activity
+    // (`reach_probe` at the --conn-sample-ms cadence); None when probing is disabled
+    pub pr_avg_ratio: Option<f64>,
+
+    // active neighbours
+    pub avg_connected_peers: Option<f64>,
+
+    // periods with zero active neighbours (monotonic clock, as is the peer-view weighting)
+    pub downtime_total_ms: u64,
+    pub downtime_periods: u64,
+    pub downtime_p50_ms: Option<u64>,
+    pub downtime_p90_ms: Option<u64>,
+    pub downtime_max_ms: Option<u64>,
+    // from join (or run start if unjoined) until the first neighbor; the whole
+    // run if none ever came (None if the peer view was never started)
+    #[serde(default)]
+    pub initial_isolation_ms: Option<u64>,
+
+    // neighbour in active view counts
+    pub neighbour_down: u64,
+    pub neighbour_up: u64,
+    // per-neighbor sessions (up to down, open ones closed at the end) of the
+    // `neighbor_cap` longest-connected peers, how many more there were, and
+    // sessions ended by a NeighborDown per minute of peer view
+    #[serde(default)]
+    pub neighbors: Vec<NeighborLifetime>,
+    #[serde(default)]
+    pub neighbors_overflow: u64,
+    #[serde(default)]
+    pub neighbor_flap_rate_per_min: Option<f64>,
+
+    // rejoin attempts after isolation
+    pub rejoin_attempts: u64,
+    pub rejoin_successes: u64,
+
+    // changes of our observed direct addresses and home relay (`addr_change`)
+    #[serde(default)]
+    pub addr_changes: u64,
+    #[serde(default)]
+    pub relay_changes: u64,
+
+    // echo RTT per neighbor around the run, to set gossip latency against the path
+    #[serde(default)]
+    pub neighbor_rtt_ms: BTreeMap<String, NeighborRtt>,
+
+    // transport errors per kind ("lagged", "stream_closed", ...)
+    pub errors_by_kind: BTreeMap<String, u64>,
+    // transport tasks that ended early, see the `task_exited` events
+    #[serde(default)]
+    pub task_exits: u64,
+
+    // repair layer (None unless `--repair`; lat_* above cover first transmissions only)
+    pub repair: Option<RepairCounters>,
+
+    // compressed frames received (None if no compressed frame arrived)
+    pub compression: Option<CompressionStats>,
+
+    // encryption (crypto is None if no encrypted frame was decrypted)
+    pub crypto: Option<CryptoStats>,
+    pub auth_failures: u64,
+    pub encryption_mismatches: u64,
+    // malformed payloads (unknown flags, bad compression, ...), ignored
+    #[serde(default)]
+    pub decode_errors: u64,
+
+    // chunked frames that never completed (timed out, evicted or pending at the end)
+    pub incomplete_reassemblies: u64,
+
+    // heartbeats (never counted as data); lost seqs sent during a heartbeat outage
+    // point at the overlay, lost seqs while heartbeats arrived at the sender side;
+    // gaps and age use the monotonic clock
+    pub heartbeats_received: u64,
+    pub max_heartbeat_gap_ms: Option<u64>,
+    pub last_heartbeat_age_ms: Option<u64>,
+    pub loss_during_heartbeat_outage: u64,
+    pub loss_with_heartbeats: u64,
+    pub loss_attribution: Option<String>,
+
+    // receiver announcements (`--announce-ms`) broadcast, and distinct other
+    // receivers whose announcements arrived
+    #[serde(default)]
+    pub announces_sent: u64,
+    #[serde(default)]
+    pub peers_heard: u64,
+    // cumulative acks broadcast (`--ack-every`)
+    #[serde(default)]
+    pub acks_sent: u64,
+
+    // messages rejected by `--require-signed`
+    pub rejected_unsigned: u64,
+    pub rejected_bad_sig: u64,
+    pub rejected_not_allowed: u64,
+
+    // messages from senders outside `--expect-sender`, not counted anywhere else
+    #[serde(default)]
+    pub foreign_msgs: u64,
+
+    // data of other tests (a second sender on the topic) arrived during the
+    // active test; their test ids
+    #[serde(default)]
+    pub concurrent_sender_detected: bool,
+    #[serde(default)]
+    pub other_test_ids: Vec<String>,
+
+    // messages older than `--max-msg-age` on arrival, not counted anywhere else
+    #[serde(default)]
+    pub stale_msgs: u64,
+
+    // messages with a seq or total no sender of the test could have produced
+    // (a corrupted or forged field), not counted anywhere else
+    #[serde(default)]
+    pub implausible_seq: u64,
+
+    // `--redundancy` on the sender (None/0 unless copies arrived): highest copy
+    // index received, messages only a later copy delivered, delivery of the
+    // originals alone (the control), what the copies add to delivery_rate, and
+    // arrivals per unique message
+    #[serde(default)]
+    pub max_copy: u32,
+    #[serde(default)]
+    pub redundancy_rescued: u64,
+    #[serde(default)]
+    pub first_copy_delivery_rate: Option<f64>,
+    #[serde(default)]
+    pub effective_delivery_gain: Option<f64>,
+    #[serde(default)]
+    pub redundancy_overhead_ratio: Option<f64>,
+
+    // messages whose padding was not the expected `--pad-pattern` stream (still
+    // counted as delivered)
+    #[serde(default)]
+    pub pad_mismatches: u64,
+    // gossip messages that were a `--batch` of frames, and the frames per batch
+    // (each frame counts as its own message everywhere else)
+    #[serde(default)]
+    pub batches_received: u64,
+    #[serde(default)]
+    pub batch_size_p50: Option<u64>,
+    #[serde(default)]
+    pub batch_size_max: Option<u64>,
+    #[serde(default)]
+    pub batch_size_hist: Option<LatencyHistogram>,
+    // payloads (decompressed, with framing) whose size differs from the one the
+    // sender's heartbeats announced, or the receiver's --size before that, with
+    // the smallest and largest such size (still counted as delivered)
+    #[serde(default)]
+    pub expected_payload_size: Option<u64>,
+    #[serde(default)]
+    pub size_mismatch_msgs: u64,
+    #[serde(default)]
+    pub size_mismatch_min: Option<u64>,
+    #[serde(default)]
+    pub size_mismatch_max: Option<u64>,
+    // messages dropped before decoding for exceeding max_recv_bytes (the effective
+    // cap: --max-recv-bytes, or --size plus framing, at most the gossip maximum)
+    #[serde(default)]
+    pub oversized_msgs: u64,
+    #[serde(default)]
+    pub max_recv_bytes: Option<usize>,
+
+    // byte breakdown of the first message as received (see `SenderSummary`)
+    #[serde(default)]
+    pub payload: Option<PayloadBreakdown>,
+    #[serde(default)]
+    pub efficiency: Option<f64>,
+
+    // iroh-gossip protocol counters over the run (None if unavailable)
+    pub protocol: Option<ProtocolCounters>,
+
+    // bytes on the wire over the run vs unique payload bytes delivered (None if unavailable)
+    pub unique_payload_bytes: u64,
+    pub wire_bytes_tx: Option<u64>,
+    pub wire_bytes_rx: Option<u64>,
+    pub overhead_ratio: Option<f64>,
+
+    // startup/termination flags
+    pub joined: bool,
+    pub join_wait_ms: u64,
+    pub saw_test: bool,
+    // startup timings relative to process start (None if the milestone never happened);
+    // time_to_join_ms is wall clock, the others monotonic
+    pub time_to_join_ms: Option<u64>,
+    pub time_to_first_event_ms: Option<u64>,
+    pub time_to_first_data_ms: Option<u64>,
+    // join milestones (endpoint_bound, relay_connected, subscribe_sent, first_neighbor,
+    // joined) as wall-clock offsets from process start; together they break down join_wait_ms
+    #[serde(default)]
+    pub join_stages: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub join_breakdown: JoinBreakdown,
+    // node ID, bootstrap parsing and per-topic join outcome (the `connect` event)
+    #[serde(default)]
+    pub connect: Option<ConnectReport>,
+    pub timed_out_no_data: bool,
+    // per-topic summaries keyed by topic hex (None unless several topics are subscribed)
+    pub topics: Option<BTreeMap<String, Summary>>,
+    pub terminated_by: Option<Termination>,
+    // most likely reason for zero delivery, `ok` once anything arrived (set by the
+    // receiver, see `Diagnosis::of`)
+    #[serde(default)]
+    pub diagnosis: Option<Diagnosis>,
+    // points pushed to InfluxDB up to the summary (None unless `--influx-url`)
+    #[serde(default)]
+    pub influx: Option<InfluxCounters>,
+    // payloads forwarded over UDP up to the summary (None unless `--forward-udp`)
+    #[serde(default)]
+    pub forward: Option<ForwardCounters>,
+    // receiver RSS/CPU over the run (None unless `--resource-sample` and Linux)
+    #[serde(default)]
+    pub resources: Option<ResourceUsage>,
+    // the memory guard (`--max-stats-memory-mb`) switched latency and LDH to
+    // histograms: their figures are bucket lower bounds and lat_mad is None
+    #[serde(default)]
+    pub stats_degraded: bool,
+    // events lost by the log or another sink (a full disk; see `--strict-logging`)
+    #[serde(default)]
+    pub log_write_errors: u64,
+}
+
+/// Traffic-light rating of a value in `Summary::pretty`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Rating {
+    Good,
+    Warn,
+    Bad,
+    Neutral,
+}
+
+impl Rating {
+    /// `Good` up to `good`, `Warn` up to `warn`, `Bad` beyond (lower is better).
+    fn at_most(v: f64, good: f64, warn: f64) -> Self {
+        if v <= good {
+            Rating::Good
+        } else if v <= warn {
+            Rating::Warn
+        } else {
+            Rating::Bad
+        }
+    }
+
+    fn at_least(v: f64, good: f64, warn: f64) -> Self {
+        if v >= good {
+            Rating::Good
+        } else if v >= warn {
+            Rating::Warn
+        } else {
+            Rating::Bad
+        }
+    }
+
+    fn ansi(self) -> Option<&'static str> {
+        match self {
+            Rating::Good => Some("\x1b[32m"),
+            Rating::Warn => Some("\x1b[33m"),
+            Rating::Bad => Some("\x1b[31m"),
+            Rating::Neutral => None,
+        }
+    }
+}
+
+/// Console rendering of a `Summary`, see `Summary::pretty`.
+pub struct PrettySummary<'a> {
+    summary: &'a Summary,
+    color: bool,
+}
+
+impl Summary {
+    /// Aligned table of the headline metrics for reading on a terminal.
+    ///
+    /// With `color`, values are colored green/yellow/red: delivery below
+    /// 99%/90%, duplicate rate above 10%/50%, latency above 100/1000 ms and
+    /// downtime above 0/1000 ms.
+    pub fn pretty(&self, color: bool) -> PrettySummary<'_> {
+        PrettySummary {
+            summary: self,
+            color,
+        }
+    }
+}
+
+impl std::fmt::Display for PrettySummary<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = self.summary;
+        let ms = |v: Option<u64>| v.map_or("-".to_string(), |v| format!("{v} ms"));
+        let lat = |v: Option<u64>| {
+            v.map_or(Rating::Neutral, |v| {
+                Rating::at_most(v as f64, 100.0, 1000.0)
+            })
+        };
+        let hops = |v: Option<u64>| v.map_or("-".to_string(), |v| v.to_string());
+
+        let delivery = if s.delivery_rate >= 0.99 {
+            Rating::Good
+        } else if s.delivery_rate >= 0.9 {
+            Rating::Warn
+        } else {
+            Rating::Bad
+        };
+        let rows = [
+            (
+                "delivery",
+                format!(
+                    "{:.2} % ({}/{})",
+                    s.delivery_rate * 100.0,
+                    s.received_unique,
+                    s.total_expected
+                ),
+                delivery,
+            ),
+            (
+                "worst bucket",
+                s.worst_bucket_delivery_rate.map_or("-".to_string(), |w| {
+                    format!(
+                        "{:.2} % ({} below 90 %, longest zero {} ms{})",
+                        w * 100.0,
+                        s.buckets_below_90pct,
+                        s.longest_zero_delivery_streak_ms,
+                        if s.bucket_rate_estimated {
+                            ", estimated rate"
+                        } else {
+                            ""
+                        }
+                    )
+                }),
+                s.worst_bucket_delivery_rate
+                    .map_or(Rating::Neutral, |w| Rating::at_least(w, 0.9, 0.5)),
+            ),
+            (
+                "duplicates",
+                format!("{:.2} % ({})", s.duplicate_rate * 100.0, s.duplicates),
+                Rating::at_most(s.duplicate_rate, 0.1, 0.5),
+            ),
+            ("latency p50", ms(s.lat_p50), lat(s.lat_p50)),
+            ("latency p90", ms(s.lat_p90), lat(s.lat_p90)),
+            ("latency p99", ms(s.lat_p99), lat(s.lat_p99)),
+            (
+                "from wire p50",
+                ms(s.lat_from_wire_p50),
+                lat(s.lat_from_wire_p50),
+            ),
+            (
+                "ldh min/p50/max",
+                format!(
+                    "{} / {} / {}",
+                    hops(s.ldh_min),
+                    hops(s.ldh_p50),
+                    hops(s.ldh_max)
+                ),
+                Rating::Neutral,
+            ),
+            (
+                "downtime",
+                format!(
+                    "{} ms ({} periods)",
+                    s.downtime_total_ms, s.downtime_periods
+                ),
+                Rating::at_most(s.downtime_total_ms as f64, 0.0, 1000.0),
+            ),
+            (
+                "joined",
+                format!(
+                    "{} (waited {} ms)",
+                    if s.joined { "yes" } else { "no" },
+                    s.join_wait_ms
+                ),
+                if s.joined { Rating::Good } else { Rating::Bad },
+            ),
+            (
+                "terminated by",
+                s.terminated_by.map_or("-", |t| t.as_str()).to_string(),
+                Rating::Neutral,
+            ),
+            (
+                "diagnosis",
+                s.diagnosis.map_or("-", |d| d.as_str()).to_string(),
+                match s.diagnosis {
+                    Some(Diagnosis::Ok) => Rating::Good,
+                    Some(_) => Rating::Bad,
+                    None => Rating::Neutral,
+                },
+            ),
+        ];
+
+        writeln!(f, "summary test {}", s.test_id.as_deref().unwrap_or("-"))?;
+        for (label, value, rating) in rows {
+            match rating.ansi().filter(|_| self.color) {
+                Some(code) => writeln!(f, "  {label:<16} {code}{value}\x1b[0m")?,
+                None => writeln!(f, "  {label:<16} {value}")?,
+            }
+        }
+        if let Some(d) = s.diagnosis.filter(|&d| d != Diagnosis::Ok) {
+            writeln!(f, "  hint: {}", d.hint())?;
+        }
+        Ok(())
+    }
+}
+
+/// Final summarized metrics for one sender run.
+#[derive(Debug, Clone, Serialize)]
+pub struct SenderSummary {
+    pub test_id: String,
+    pub run_id: String,
+    // topic ID (hex) the data stream was sent on, and the name derivation
+    // ("v1" or "legacy"; None for a hex topic)
+    pub topic: Option<String>,
+    pub topic_derivation: Option<String>,
+    pub messages_sent: u64,
+    pub total: u64,
+    pub rate: u64,
+    pub payload_size: usize,
+    pub duration_ms: u64,
+
+    // iroh-gossip protocol counters over the run (None if unavailable)
+    pub protocol: Option<ProtocolCounters>,
+
+    // transport errors per kind, and transport tasks that ended early
+    pub errors_by_kind: BTreeMap<String, u64>,
+    pub task_exits: u64,
+
+    // broadcast scope ("swarm" or "neighbors")
+    pub broadcast_scope: String,
+
+    // repair layer (None unless `--repair`)
+    pub repair: Option<RepairCounters>,
+
+    // payload compression (None unless `--compress`)
+    pub compression: Option<CompressionStats>,
+
+    // payload encryption (None unless `--encrypt`)
+    pub crypto: Option<CryptoStats>,
+
+    // messages carry a signature (`--sign`)
+    pub signed: bool,
+
+    // chunk data size used for oversized frames, and gossip payloads broadcast
+    pub chunk_bytes: Option<usize>,
+    pub chunks_sent: u64,
+
+    // heartbeat control messages broadcast (`--heartbeat-ms`)
+    pub heartbeats_sent: u64,
+
+    // time from enqueueing a broadcast to the gossip layer accepting it, and the
+    // deepest outgoing queue seen at a send (None if the transport does not queue)
+    #[serde(default)]
+    pub broadcast_lat_p50_ms: Option<f64>,
+    #[serde(default)]
+    pub broadcast_lat_p99_ms: Option<f64>,
+    #[serde(default)]
+    pub max_tx_queue_depth: Option<u64>,
+
+    // send rate chosen by the AIMD controller (None unless `--adaptive`)
+    #[serde(default)]
+    pub adaptive: Option<AdaptiveRate>,
+
+    // ground-truth duplicate/reorder injection (None unless `--inject-*`)
+    #[serde(default)]
+    pub injection: Option<InjectionCounters>,
+
+    // receivers that announced themselves (`--announce-ms` on their side); counts
+    // lag by up to one announcement interval
+    #[serde(default)]
+    pub receivers: ReceiversSeen,
+
+    // startup flags, and join milestones as offsets from process start (see `Summary`)
+    pub joined: bool,
+    pub join_wait_ms: u64,
+    #[serde(default)]
+    pub join_stages: BTreeMap<String, u64>,
+    #[serde(default)]
+    pub join_breakdown: JoinBreakdown,
+
+    // why the send loop ended
+    pub terminated_by: Termination,
+
+    // points pushed to InfluxDB (see `Summary`)
+    #[serde(default)]
+    pub influx: Option<InfluxCounters>,
+
+    // bytes of the first message by purpose, and message-field bytes / wire bytes
+    #[serde(default)]
+    pub payload: Option<PayloadBreakdown>,
+    #[serde(default)]
+    pub efficiency: Option<f64>,
+
+    // acknowledgments per receiver ID (None unless `--ack-every`)
+    #[serde(default)]
+    pub acks: Option<BTreeMap<String, AckStats>>,
+
+    // send pauses, in order (`--pause-schedule`, `--pause-signals`)
+    #[serde(default)]
+    pub pauses: Vec<PauseRecord>,
+
+    // events that could not be written to the log (see `Summary`)
+    #[serde(default)]
+    pub log_write_errors: u64,
+
+    // scheduling error against the trace (None unless `--replay`)
+    #[serde(default)]
+    pub replay: Option<ReplayStats>,
+
+    // test ids of other senders heard during the `--probe` window before the run
+    #[serde(default)]
+    pub concurrent_sender_detected: bool,
+    #[serde(default)]
+    pub other_test_ids: Vec<String>,
+
+    // returned right after the last send (`--no-wait`), and the events drained
+    // in the trailing window (None unless `--linger`)
+    #[serde(default)]
+    pub no_wait: bool,
+    #[serde(default)]
+    pub linger_events: Option<LingerEvents>,
+
+    // `--redundancy`: broadcasts per message, their spacing, and the extra
+    // copies sent (part of the wire bytes, not of messages_sent)
+    #[serde(default)]
+    pub redundancy: u32,
+    #[serde(default)]
+    pub redundancy_gap_ms: u64,
+    #[serde(default)]
+    pub copies_sent: u64,
+
+    // `--batch`: frames per gossip message, the longest a frame waits for its
+    // batch, and the batches broadcast (each one gossip message)
+    #[serde(default)]
+    pub batch: usize,
+    #[serde(default)]
+    pub batch_max_wait_ms: u64,
+    #[serde(default)]
+    pub batches_sent: u64,
+}
+
+impl Stats {
+    /// Create an empty accumulator using the given summary configuration.
+    pub fn with_config(config: StatsConfig) -> Self {
+        Self {
+            config,
+            ..Self::default()
+        }
+    }
+
+    /// Record a successfully decoded DataMsg and update all relevant metrics.
+    ///
+    /// `payload_len` is the size of the received payload in bytes,
+    /// `ldh` is the last-delivery-hop value (if known),
+    /// `recv_ts_ms` is the local wall-clock receive time (latency against the
+    /// sender clock), `recv_elapsed_ms` the monotonic one (`util::elapsed_ms`)
+    /// used for all intra-run spacing.
+    pub fn record(
+        &mut self,
+        message: &DataMsg,
+        payload_len: usize,
+        ldh: Option<u16>,
+        recv_ts_ms: u64,
+        recv_elapsed_ms: u64,
+    ) -> Arrival {
+        let arrival = self.record_arrival(message, payload_len, recv_elapsed_ms);
+
+        // End-to-end latency based on sender timestamp.
+        let lat = recv_ts_ms.saturating_sub(message.sent_ms);
+        match (
+            arrival.duplicate,
+            &mut self.lat_hist,
+            &mut self.dup_lat_hist,
+        ) {
+            (true, _, Some(hist)) => hist.add(lat),
+            (true, _, None) => self.dup_lats.push(lat),
+            (false, Some(hist), _) => {
+                hist.add(lat);
+                self.lat_sum += lat as f64;
+                self.lat_sq_sum += (lat as f64).powi(2);
+            }
+            (false, None, _) => {
+                self.lats.push(lat);
+                self.lat_timeline.push((recv_elapsed_ms, lat));
+            }
+        }
+        if !arrival.duplicate {
+            self.lat_trend.add(recv_elapsed_ms, lat);
+        }
+
+        // LDH sample (if known); neighbor-scope deliveries carry none.
+        if let Some(h) = ldh {
+            match &mut self.ldh_hist {
+                Some(hist) => hist.add(h as u64),
+                None => self.ldhs.push(h as u64),
+            }
+        } else if !arrival.duplicate {
+            self.direct_deliveries += 1;
+        }
+        arrival
+    }
+
+    /// Why `message` could not have come from a sender of the test, if it could
+    /// not: a seq at or beyond its `total`, and for messages of the test
+    /// recorded so far (`same_test`), a `total` or (with no total) a seq more
+    /// than `seq_window` beyond what was seen. Recording such a message would
+    /// turn every later one into a reordering, or inflate `total_expected`.
+    pub fn check_seq(&self, message: &DataMsg, same_test: bool) -> Option<ImplausibleSeq> {
+        let window = self.config.seq_window;
+        let max_seq_seen = u64::try_from(self.max_seq_seen).ok();
+        let reason = if message.total > 0 && message.seq >= message.total {
+            "seq_beyond_total"
+        } else if !same_test {
+            return None;
+        } else if self.total_expected > 0
+            && message.total > self.total_expected.saturating_add(window)
+        {
+            "total_beyond_window"
+        } else if message.total == 0
+            && max_seq_seen.is_some_and(|max| message.seq > max.saturating_add(window))
+        {
+            "seq_beyond_window"
+        } else {
+            return None;
+        };
+        Some(ImplausibleSeq {
+            reason,
+            seq: message.seq,
+            total: message.total,
+            max_seq_seen,
+            total_expected: self.total_expected,
+        })
+    }
+
+    /// Loss, duplicate, gap and reordering accounting shared by all delivery paths.
+    ///
+    /// `recv_ms` is monotonic.
+    fn record_arrival(&mut self, message: &DataMsg, payload_len: usize, recv_ms: u64) -> Arrival {
+        // Track expected total for this test (monotonic max in case of reordering).
+        self.total_expected = self.total_expected.max(message.total);
+        // Count every received message, including duplicates.
+        self.recv_total += 1;
+        self.last_arrival_ms = recv_ms;
+
+        // Duplicate detection by sequence number.
+        let first_copy = self.seen.insert(message.seq);
+        if first_copy {
+            self.unique_payload_bytes += payload_len as u64;
+            while self.seen.contains(&self.contiguous) {
+                self.contiguous += 1;
+            }
+            *self
+                .sent_buckets
+                .entry(message.sent_ms / self.config.bucket_ms.max(1))
+                .or_default() += 1;
+            let point = (message.sent_ms, message.seq);
+            if self.sent_first.is_none_or(|first| point < first) {
+                self.sent_first = Some(point);
+            }
+            if self.sent_last.is_none_or(|last| point > last) {
+                self.sent_last = Some(point);
+            }
+        } else {
+            self.duplicates += 1;
+        }
+
+        // Distance in first-seen sequences between a duplicate and its first copy.
+        if first_copy {
+            if self.degradation.is_none() {
+                self.first_copy_index
+                    .insert(message.seq, self.seen.len() as u64 - 1);
+            }
+        } else if let Some(&index) = self.first_copy_index.get(&message.seq) {
+            let distance = self.seen.len() as u64 - index;
+            self.dedup_measured += 1;
+            self.dedup_required = self.dedup_required.max(distance);
+            for (hits, window) in self.dedup_hits.iter_mut().zip(DEDUP_WINDOWS) {
+                if distance <= window {
+                    *hits += 1;
+                }
+            }
+        }
+
+        // Duplicate spacing within the sliding window of recent sequences.
+        if first_copy {
+            self.first_arrivals.insert(message.seq, (recv_ms, 0));
+        } else if let Some((first_ms, dups)) = self.first_arrivals.get_mut(&message.seq) {
+            *dups += 1;
+            self.dup_delays.push(recv_ms.saturating_sub(*first_ms));
+            self.max_dups_for_single_seq = self.max_dups_for_single_seq.max(*dups);
+        }
+        let window_floor = message.seq.saturating_sub(self.config.dup_window);
+        if self
+            .first_arrivals
+            .first_key_value()
+            .is_some_and(|(&s, _)| s < window_floor)
+        {
+            self.first_arrivals = self.first_arrivals.split_off(&window_floor);
+        }
+
+        // Gap accounting: a first copy below the current max may close an open gap,
+        // a jump above the max opens gaps for every skipped seq.
+        if first_copy {
+            if let Some(opened) = self.gap_opened_ms.remove(&message.seq) {
+                self.gaps_filled += 1;
+                self.late_arrival_delays
+                    .push(recv_ms.saturating_sub(opened));
+            } else if self.gaps_untimed.remove(&message.seq) {
+                self.gaps_filled += 1;
+            } else if let Some((&start, &end)) = self.gap_ranges.range(..=message.seq).next_back()
+                && message.seq < end
+            {
+                self.gap_ranges.remove(&start);
+                if start < message.seq {
+                    self.gap_ranges.insert(start, message.seq);
+                }
+                if message.seq + 1 < end {
+                    self.gap_ranges.insert(message.seq + 1, end);
+                }
+                self.gaps_filled += 1;
+            }
+            let next_expected = if self.max_seq_arrivals.is_empty() {
+                0
+            } else {
+                self.max_seq_seen as u64 + 1
+            };
+            // The seq is untrusted: beyond the window, the skip is one range.
+            let tracked_end = message
+                .seq
+                .min(next_expected.saturating_add(self.config.seq_window));
+            if tracked_end < message.seq {
+                self.gap_ranges.insert(tracked_end, message.seq);
+            }
+            for missing in next_expected..tracked_end {
+                if self.seen.contains(&missing) {
+                    continue;
+                }
+                match self.degradation {
+                    Some(_) => self.gaps_untimed.insert(missing),
+                    None => self.gap_opened_ms.insert(missing, recv_ms).is_none(),
+                };
+            }
+        }
+
+        if self
+            .lag_open
+            .is_some_and(|before| message.seq as i64 > before)
+        {
+            self.close_lag_episode(message.seq);
+        }
+
+        // A later copy arriving first stands in for the original until it comes.
+        self.max_copy = self.max_copy.max(message.copy);
+        match (first_copy, message.copy) {
+            (true, copy) if copy > 0 => {
+                self.rescued_by_copy.insert(message.seq);
+            }
+            (false, 0) => {
+                self.rescued_by_copy.remove(&message.seq);
+            }
+            _ => {}
+        }
+
+        // Out-of-order detection relative to maximum observed sequence; a late
+        // copy of a delivered message is redundancy, not reordering.
+        let arrival = self.arrivals;
+        self.arrivals += 1;
+        let out_of_order =
+            (message.seq as i64) < self.max_seq_seen && (first_copy || message.copy == 0);
+        if out_of_order {
+            self.out_of_order += 1;
+            self.reorder_depths
+                .push(self.max_seq_seen as u64 - message.seq);
+
+            // RFC 4737 extent: distance in arrivals to the earliest message with a higher seq.
+            let idx = self
+                .max_seq_arrivals
+                .partition_point(|&(seq, _)| seq <= message.seq);
+            if let Some(&(_, first_above)) = self.max_seq_arrivals.get(idx) {
+                self.reorder_extent = self.reorder_extent.max(arrival - first_above);
+            }
+        } else {
+            if message.seq as i64 > self.max_seq_seen || self.max_seq_arrivals.is_empty() {
+                self.max_seq_arrivals.push((message.seq, arrival));
+            }
+            self.max_seq_seen = message.seq as i64;
+        }
+
+        self.guard_memory(recv_ms);
+        Arrival {
+            duplicate: !first_copy,
+            out_of_order,
+        }
+    }
+
+    /// Split the latency of a first copy at its wire timestamp (see
+    /// `frame::stamp_wire_ts`): from the sender's enqueue (`sent_ms`) and from
+    /// the hand-off to gossip.
+    pub fn note_wire_stamp(&mut self, sent_ms: u64, wire_ms: u64, recv_ts_ms: u64) {
+        if self.degradation.is_some() {
+            return;
+        }
+        self.wire_split.push((
+            recv_ts_ms.saturating_sub(sent_ms),
+            recv_ts_ms.saturating_sub(wire_ms),
+        ));
+    }
+
+    /// Note the schedule offset of a first copy and return its sender pacing
+    /// error against the earliest schedule start seen so far (None without an
+    /// offset).
+    pub fn note_sched(&mut self, message: &DataMsg) -> Option<u64> {
+        let start = message.sent_ms.saturating_sub(message.sched_offset_ms?);
+        let base = self.sched_base.map_or(start, |b| b.min(start));
+        self.sched_base = Some(base);
+        if self.degradation.is_none() {
+            self.sched_starts.push(start);
+        }
+        Some(start - base)
+    }
+
+    /// Attribute a first copy of latency `lat_ms` to the path type ("direct",
+    /// "relay", anything else unknown) of the neighbor that delivered it.
+    pub fn note_delivery_path(&mut self, path: Option<&str>, lat_ms: u64) {
+        let lats = match path {
+            Some("direct") => {
+                self.direct_path_msgs += 1;
+                &mut self.direct_path_lats
+            }
+            Some("relay") => {
+                self.relay_path_msgs += 1;
+                &mut self.relay_path_lats
+            }
+            _ => {
+                self.unknown_path_msgs += 1;
+                return;
+            }
+        };
+        if self.degradation.is_none() {
+            lats.push(lat_ms);
+        }
+    }
+
+    /// Approximate memory held by the per-message samples and maps (bytes):
+    /// vector lengths times element size, map and set entries at twice their
+    /// payload for node and bucket overhead.
+    pub fn approx_memory_bytes(&self) -> u64 {
+        let samples = self.lats.len()
+            + self.dup_lats.len()
+            + self.ldhs.len()
+            + self.reorder_depths.len()
+            + self.late_arrival_delays.len()
+            + self.dup_delays.len()
+            + self.repair_lats.len()
+            + self.lag_gaps.len()
+            + self.direct_path_lats.len()
+            + self.relay_path_lats.len()
+            + self.sched_starts.len();
+        let pairs = self.max_seq_arrivals.len() + self.lat_timeline.len() + self.wire_split.len();
+        let map_entries =
+            self.gap_opened_ms.len() + self.first_arrivals.len() + self.first_copy_index.len();
+        let set_entries = self.seen.len() + self.gaps_untimed.len();
+        let hist_buckets = [&self.lat_hist, &self.dup_lat_hist, &self.ldh_hist]
+            .into_iter()
+            .flatten()
+            .map(|h| h.buckets.len())
+            .sum::<usize>();
+        (samples * 8 + pairs * 16 + (map_entries + hist_buckets) * 32 + set_entries * 16) as u64
+    }
+
+    /// Degrade once the approximate memory nears `max_memory_bytes`: fold the
+    /// latency and LDH samples into histograms and drop the gap opening times.
+    fn guard_memory(&mut self, now_ms: u64) {
+        let Some(cap) = self.config.max_memory_bytes else {
+            return;
+        };
+        if self.degradation.is_some() {
+            return;
+        }
+        let approx_bytes = self.approx_memory_bytes();
+        if (approx_bytes as f64) < cap as f64 * DEGRADE_AT {
+            return;
+        }
+        let fold = |samples: &mut Vec<u64>| {
+            let hist = LatencyHistogram::from_samples(samples).unwrap_or_default();
+            *samples = Vec::new();
+            hist
+        };
+        self.degradation = Some(StatsDegradation {
+            at_ms: now_ms,
+            approx_bytes,
+            cap_bytes: cap,
+            lat_samples: self.lats.len() as u64,
+            dup_lat_samples: self.dup_lats.len() as u64,
+            ldh_samples: self.ldhs.len() as u64,
+            gap_timestamps: self.gap_opened_ms.len() as u64,
+        });
+        self.lat_sum = self.lats.iter().map(|&v| v as f64).sum();
+        self.lat_sq_sum = self.lats.iter().map(|&v| (v as f64).powi(2)).sum();
+        self.lat_hist = Some(fold(&mut self.lats));
+        self.lat_timeline = Vec::new();
+        self.wire_split = Vec::new();
+        self.direct_path_lats = Vec::new();
+        self.relay_path_lats = Vec::new();
+        self.sched_starts = Vec::new();
+        self.first_copy_index = HashMap::new();
+        self.dup_lat_hist = Some(fold(&mut self.dup_lats));
+        self.ldh_hist = Some(fold(&mut self.ldhs));
+        self.gaps_untimed
+            .extend(std::mem::take(&mut self.gap_opened_ms).into_keys());
+    }
+
+    /// The memory guard's switch, returned once after it happened (for the
+    /// `stats_degraded` event).
+    pub fn take_degradation(&mut self) -> Option<StatsDegradation> {
+        if self.degradation_reported {
+            return None;
+        }
+        self.degradation_reported = self.degradation.is_some();
+        self.degradation.clone()
+    }
+
+    /// Record a message delivered over the repair side-channel.
+    ///
+    /// Counted like any other arrival for loss/duplicates, but its latency is
+    /// kept separate so first-transmission latency stays comparable.
+    pub fn record_repaired(
+        &mut self,
+        message: &DataMsg,
+        payload_len: usize,
+        recv_ts_ms: u64,
+        recv_elapsed_ms: u64,
+    ) {
+        let arrival = self.record_arrival(message, payload_len, recv_elapsed_ms);
+
+        self.repair.repair_bytes += payload_len as u64;
+        if !arrival.duplicate {
+            self.repair.received_via_repair += 1;
+            self.repair_lats
+                .push(recv_ts_ms.saturating_sub(message.sent_ms));
+        }
+    }
+
+    /// Count a compressed frame with `body_len` bytes after and `wire_len` (without header) before decompression.
+    pub fn note_compressed(&mut self, body_len: usize, wire_len: usize) {
+        self.compression.note(body_len, wire_len);
+    }
+
+    /// Count a decrypted frame with `overhead` bytes that took `us` microseconds to unframe.
+    pub fn note_decrypted(&mut self, overhead: usize, us: u64) {
+        self.crypto.note(overhead, us);
+    }
+
+    /// Count a frame that failed authentication.
+    pub fn note_auth_failure(&mut self) {
+        self.auth_failures += 1;
+    }
+
+    /// Count a payload that did not decode as a frame.
+    pub fn note_decode_error(&mut self) {
+        self.decode_errors += 1;
+    }
+
+    /// Count a frame whose encryption setting differs from ours; returns true for the first one.
+    pub fn note_encryption_mismatch(&mut self) -> bool {
+        self.encryption_mismatches += 1;
+        self.encryption_mismatches == 1
+    }
+
+    /// Record a heartbeat received at monotonic time `recv_ts_ms`; returns the gap to the previous one.
+    ///
+    /// A gap longer than twice the heartbeat interval is an outage; the data
+    /// seqs sent meanwhile are remembered for loss attribution.
+    pub fn note_heartbeat(&mut self, heartbeat: &Heartbeat, recv_ts_ms: u64) -> Option<u64> {
+        self.heartbeats_received += 1;
+        self.sender_run_id = Some(heartbeat.run_id);
+        if heartbeat.payload_size.is_some() {
+            self.announced_size = heartbeat.payload_size;
+        }
+        let gap = self.last_heartbeat.map(|(last_ms, last_seq)| {
+            let gap = recv_ts_ms.saturating_sub(last_ms);
+            if gap > 2 * heartbeat.interval_ms && heartbeat.seq > last_seq {
+                self.heartbeat_outages.push((last_seq, heartbeat.seq));
+            }
+            gap
+        });
+        if let Some(gap) = gap {
+            self.max_heartbeat_gap_ms = Some(self.max_heartbeat_gap_ms.unwrap_or(0).max(gap));
+        }
+        self.last_heartbeat = Some((recv_ts_ms, heartbeat.seq));
+        gap
+    }
+
+    /// Count a message rejected by the signature policy.
+    pub fn note_sig_reject(&mut self, reject: SigReject) {
+        match reject {
+            SigReject::Unsigned => self.rejected_unsigned += 1,
+            SigReject::BadSignature => self.rejected_bad_sig += 1,
+            SigReject::NotAllowed => self.rejected_not_allowed += 1,
+        }
+    }
+
+    /// Cumulative ack of what arrived so far, with the bitmap capped at
+    /// `ACK_BITMAP_BITS` and trimmed after its last set bit.
+    pub fn ack(&self, test_id: [u8; 16], receiver_id: String) -> AckUpTo {
+        let mut bitmap = vec![0u8; (ACK_BITMAP_BITS / 8) as usize];
+        for i in 0..ACK_BITMAP_BITS {
+            if self.seen.contains(&(self.contiguous + 1 + i)) {
+                bitmap[(i / 8) as usize] |= 1 << (i % 8);
+            }
+        }
+        let used = bitmap.iter().rposition(|&b| b != 0).map_or(0, |i| i + 1);
+        bitmap.truncate(used);
+        AckUpTo {
+            test_id,
+            receiver_id,
+            highest_contiguous_seq: self.contiguous.checked_sub(1),
+            bitmap,
+        }
+    }
+
+    /// Count a broadcast ack.
+    pub fn note_ack_sent(&mut self) {
+        self.acks_sent += 1;
+    }
 
-    // duplicates
-    pub duplicate_rate: f64,
-    pub duplicates: u64,
-    pub out_of_order: u64,
+    /// Count a message from a sender outside `--expect-sender`.
+    pub fn note_foreign(&mut self) {
+        self.foreign_msgs += 1;
+    }
 
-    // lag/E2E
-    pub lagged_events: u64,
-    pub lat_min: Option<u64>,
-    pub lat_p50: Option<u64>,
-    pub lat_p90: Option<u64>,
-    pub lat_p99: Option<u64>,
-    pub lat_max: Option<u64>,
+    /// Note data of `test_id` during another active test; true the first time.
+    pub fn note_other_test(&mut self, test_id: [u8; 16]) -> bool {
+        self.other_tests.insert(test_id)
+    }
 
-    // LDH (overlay hop counts)
-    pub ldh_min: Option<u64>,
-    pub ldh_p50: Option<u64>,
-    pub ldh_p90: Option<u64>,
-    pub ldh_p99: Option<u64>,
-    pub ldh_max: Option<u64>,
+    /// Byte breakdown recorded for this test, if any.
+    pub fn payload(&self) -> Option<PayloadBreakdown> {
+        self.payload
+    }
 
-    // peer reachability
-    pub pr_avg_ratio: Option<f64>,
+    /// Record the byte breakdown of the test's first message.
+    pub fn note_payload(&mut self, payload: PayloadBreakdown) {
+        self.payload.get_or_insert(payload);
+    }
 
-    // active neighbours
-    pub avg_connected_peers: Option<f64>,
+    /// Count a message older than `--max-msg-age`.
+    pub fn note_stale(&mut self) {
+        self.stale_msgs += 1;
+    }
 
-    // periods with zero active neighbours
-    pub downtime_total_ms: u64,
-    pub downtime_periods: u64,
-    pub downtime_p50_ms: Option<u64>,
-    pub downtime_p90_ms: Option<u64>,
-    pub downtime_max_ms: Option<u64>,
+    /// Count a message rejected by `check_seq`.
+    pub fn note_implausible_seq(&mut self) {
+        self.implausible_seq += 1;
+    }
 
-    // neighbour in active view counts
-    pub neighbour_down: u64,
-    pub neighbour_up: u64,
+    /// Count a received batch of `frames` frames.
+    pub fn note_batch(&mut self, frames: usize) {
+        self.batch_sizes
+            .get_or_insert_with(LatencyHistogram::default)
+            .add(frames as u64);
+    }
 
-    // startup/termination flags
-    pub joined: bool,
-    pub join_wait_ms: u64,
-    pub saw_test: bool,
-    pub timed_out_no_data: bool,
-}
+    /// Payload size arrivals are checked against: the sender's announced one,
+    /// else the configured one.
+    pub fn expected_size(&self) -> Option<u64> {
+        self.announced_size.or(self.config.expected_size)
+    }
 
-impl Stats {
-    /// Record a successfully decoded DataMsg and update all relevant metrics.
-    ///
-    /// `ldh` is the last-delivery-hop value (if known),
-    /// `recv_ts_ms` is the local receive timestamp in ms.
-    pub fn record(&mut self, message: &DataMsg, ldh: Option<u16>, recv_ts_ms: u64) {
-        // Track expected total for this test (monotonic max in case of reordering).
-        self.total_expected = self.total_expected.max(message.total);
-        // Count every received message, including duplicates.
-        self.recv_total += 1;
+    /// Count a payload of `size` bytes if it differs from `expected_size`.
+    /// Returns the expected size for the first `SIZE_MISMATCH_DETAIL`
+    /// mismatches, which are logged in detail.
+    pub fn check_size(&mut self, size: u64) -> Option<u64> {
+        let expected = self.expected_size().filter(|&e| e != size)?;
+        self.size_mismatch_msgs += 1;
+        self.size_mismatch_min = Some(self.size_mismatch_min.map_or(size, |m| m.min(size)));
+        self.size_mismatch_max = Some(self.size_mismatch_max.map_or(size, |m| m.max(size)));
+        (self.size_mismatch_msgs <= SIZE_MISMATCH_DETAIL).then_some(expected)
+    }
 
-        // Duplicate detection by sequence number.
-        if !self.seen.insert(message.seq) {
-            self.duplicates += 1;
-        }
+    /// Count a message whose padding failed the `--pad-pattern` check.
+    pub fn note_pad_mismatch(&mut self) {
+        self.pad_mismatches += 1;
+    }
 
-        // Out-of-order detection relative to maximum observed sequence.
-        if (message.seq as i64) < self.max_seq_seen {
-            self.out_of_order += 1;
+    /// Count a message the transport dropped for exceeding `--max-recv-bytes`.
+    pub fn note_oversized(&mut self) {
+        self.oversized_msgs += 1;
+    }
+
+    /// Count an outgoing NACK for `seqs` sequences.
+    pub fn note_nack(&mut self, seqs: usize) {
+        self.repair.nacks_sent += 1;
+        self.repair.seqs_requested += seqs as u64;
+    }
+
+    /// Sequences considered missing for at least `age_ms` at monotonic time `now_ms`.
+    ///
+    /// Includes open gaps below the highest sequence seen and, once no message
+    /// arrived for `age_ms`, the tail up to the announced total. Lazy, since
+    /// the total is untrusted: callers take only as many as they need.
+    pub fn missing_older_than(&self, now_ms: u64, age_ms: u64) -> impl Iterator<Item = u64> + '_ {
+        let cutoff = now_ms.saturating_sub(age_ms);
+        let timed = self
+            .gap_opened_ms
+            .iter()
+            .filter(move |&(_, &opened)| opened <= cutoff)
+            .map(|(&seq, _)| seq);
+        // Gaps kept without their opening time count from the switch.
+        let untimed = self
+            .degradation
+            .as_ref()
+            .is_some_and(|d| d.at_ms <= cutoff)
+            .then_some(&self.gaps_untimed)
+            .into_iter()
+            .flatten()
+            .copied();
+        let tail = if self.total_expected > 0 && self.last_arrival_ms <= cutoff {
+            (self.max_seq_seen + 1).max(0) as u64..self.total_expected
         } else {
-            self.max_seq_seen = message.seq as i64;
+            0..0
+        };
+        timed.chain(untimed).chain(tail)
+    }
+
+    /// Repair counters including latency percentiles of repaired messages.
+    pub fn repair_counters(&self) -> RepairCounters {
+        let mut sorted = self.repair_lats.clone();
+        sorted.sort_unstable();
+        RepairCounters {
+            repair_lat_p50_ms: Self::quantil(&sorted, 0.50),
+            repair_lat_max_ms: sorted.last().copied(),
+            ..self.repair
         }
+    }
 
-        // End-to-end latency based on sender timestamp.
-        let lat = recv_ts_ms.saturating_sub(message.sent_ms);
-        self.lats.push(lat);
+    /// Number of unique sequences received so far.
+    pub fn received_unique(&self) -> u64 {
+        self.seen.len() as u64
+    }
 
-        // LDH sample (if known).
-        if let Some(h) = ldh {
-            self.ldhs.push(h as u64);
-        }
+    /// Record the endpoint's wire bytes since run start and derive the overhead ratio.
+    pub fn apply_wire_bytes(&self, summary: &mut Summary, wire: Option<WireBytes>) {
+        summary.wire_bytes_tx = wire.map(|w| w.tx);
+        summary.wire_bytes_rx = wire.map(|w| w.rx);
+        summary.overhead_ratio = match wire {
+            Some(w) if self.unique_payload_bytes > 0 => {
+                Some(w.rx as f64 / self.unique_payload_bytes as f64)
+            }
+            _ => None,
+        };
     }
 
     /// Note a lagged transport event (buffer overrun / skipped events).
+    ///
+    /// The first one opens a lag episode at the current max seq; the next
+    /// arrival above it closes the episode (see `close_lag_episode`).
     pub fn note_lagged(&mut self) {
         self.lagged_events += 1;
+        let before = self.max_seq_arrivals.last().map_or(-1, |&(s, _)| s as i64);
+        self.lag_open.get_or_insert(before);
+    }
+
+    /// Close the open lag episode at `upto` (exclusive), attributing the seqs
+    /// above its start that are still missing to it.
+    fn close_lag_episode(&mut self, upto: u64) {
+        if let Some(before) = self.lag_open.take() {
+            let from = (before + 1) as u64;
+            let missed = (from..upto).filter(|s| !self.seen.contains(s)).count();
+            self.lag_gaps.push(missed as u64);
+        }
     }
 
     // Note a neighbour is removed from the active view set
-    pub fn note_neighbour_down(&mut self) {
-        self.neighbour_down +=1;
+    pub fn note_neighbour_down(&mut self, peer: &str, ts_ms: u64) {
+        self.neighbour_down += 1;
+        if let Some(sessions) = self.neighbor_sessions.get_mut(peer)
+            && sessions.open_since_ms.is_some()
+        {
+            sessions.ended += 1;
+            sessions.close(ts_ms);
+        }
     }
 
     // Note a neighbour is added to the active view set
-    pub fn note_neighbour_up(&mut self) {
+    pub fn note_neighbour_up(&mut self, peer: &str, ts_ms: u64) {
         self.neighbour_up += 1;
+        self.neighbor_sessions
+            .entry(peer.to_string())
+            .or_default()
+            .open(ts_ms);
+    }
+
+    /// Open sessions for neighbors present when the peer view starts (they
+    /// raised no NeighborUp of their own).
+    pub fn note_initial_neighbours(&mut self, peers: &[String], ts_ms: u64) {
+        for peer in peers {
+            self.neighbor_sessions
+                .entry(peer.clone())
+                .or_default()
+                .open(ts_ms);
+        }
+    }
+
+    /// Count a broadcast liveness announcement.
+    pub fn note_announce_sent(&mut self) {
+        self.announces_sent += 1;
+    }
+
+    /// Note another receiver's announcement; it is only counted, never answered.
+    pub fn note_peer_heard(&mut self, alive: ReceiverAlive) {
+        self.peers_heard.insert(alive.peer_id);
+    }
+
+    /// Count a transport error of the given kind.
+    pub fn note_error(&mut self, kind: &str) {
+        *self.errors_by_kind.entry(kind.to_string()).or_default() += 1;
+    }
+
+    /// Count a transport task that ended early.
+    pub fn note_task_exit(&mut self) {
+        self.task_exits += 1;
+    }
+
+    /// Note a rejoin attempt after isolation and whether it regained a neighbour.
+    pub fn note_rejoin(&mut self, success: bool) {
+        self.rejoin_attempts += 1;
+        if success {
+            self.rejoin_successes += 1;
+        }
+    }
+
+    /// Note a change of our direct addresses and/or home relay.
+    pub fn note_addr_change(&mut self, direct: bool, relay: bool) {
+        self.addr_changes += u64::from(direct);
+        self.relay_changes += u64::from(relay);
+    }
+
+    /// Take over the neighbor churn of the previous test's `prev`, so the
+    /// counts and sessions of a long-lived receiver span tests (each summary
+    /// shows them as of its end).
+    pub fn carry_churn(&mut self, prev: &Stats) {
+        self.neighbour_up = prev.neighbour_up;
+        self.neighbour_down = prev.neighbour_down;
+        self.neighbor_sessions = prev.neighbor_sessions.clone();
+        self.rejoin_attempts = prev.rejoin_attempts;
+        self.rejoin_successes = prev.rejoin_successes;
+        self.view_origin_ms = prev.view_origin_ms;
+    }
+
+    /// Start the peer view at monotonic time `ts_ms` (join time, or run start
+    /// when unjoined) with `connected` neighbors. Starting isolated opens a
+    /// downtime period, so the time until the first neighbor counts as downtime.
+    pub fn start_peer_view(&mut self, ts_ms: u64, connected: u64) {
+        self.view_origin_ms = Some(ts_ms);
+        self.pr_last_ts = Some(ts_ms);
+        self.pr_last_ratio = None;
+        self.conn_last_connected = connected;
+        if connected == 0 {
+            self.downtime_started_at = Some(ts_ms);
+            self.downtime_periods += 1;
+        } else {
+            self.first_neighbor_ms = Some(ts_ms);
+        }
     }
 
-    /// Record a new snapshot of peer connectivity and reachability.
+    /// Record a new snapshot of peer connectivity and reachability at
+    /// monotonic time `ts_ms` (`util::elapsed_ms`).
     ///
-    /// The ratio is reachable/connected.
+    /// The ratio is reachable/connected; `reachable` is None for snapshots
+    /// without a reachability probe, which keep the last probed ratio.
     /// This method maintains a time-weighted average over the run.
-    pub fn record_peer_view(&mut self, ts_ms: u64, connected: u64, reachable: u64) {
-        let ratio = if connected == 0 {
-            1.0
-        } else {
-            (reachable as f64) / (connected as f64)
-        };
+    pub fn record_peer_view(&mut self, ts_ms: u64, connected: u64, reachable: Option<u64>) {
+        let ratio = reachable.map(|reachable| {
+            if connected == 0 {
+                1.0
+            } else {
+                (reachable as f64) / (connected as f64)
+            }
+        });
 
         if let Some(prev_ts) = self.pr_last_ts {
             let dur = ts_ms.saturating_sub(prev_ts) as f64;
 
             // Accumulate time-weighted ratio since last update.
-            self.pr_acc_ms += dur * self.pr_last_ratio;
-            self.pr_total_ms += dur;
+            if let Some(last) = self.pr_last_ratio {
+                self.pr_acc_ms += dur * last;
+                self.pr_total_ms += dur;
+            }
 
             // Accumulate time-weighted mean of connected_peers
             self.conn_acc_ms += dur * (self.conn_last_connected as f64);
@@ -222,16 +2982,23 @@ impl Stats {
             self.downtime_periods += 1;
         }
 
+        if connected > 0 && self.first_neighbor_ms.is_none() {
+            self.first_neighbor_ms = Some(ts_ms);
+        }
+
         // end of downtime period
-        if self.conn_last_connected == 0 && connected > 0 {
-            if let Some(start) = self.downtime_started_at.take() {
-                let dur = ts_ms.saturating_sub(start);
-                self.downtime_duration_ms.push(dur);
-            }
+        if self.conn_last_connected == 0
+            && connected > 0
+            && let Some(start) = self.downtime_started_at.take()
+        {
+            let dur = ts_ms.saturating_sub(start);
+            self.downtime_duration_ms.push(dur);
         }
 
         self.pr_last_ts = Some(ts_ms);
-        self.pr_last_ratio = ratio;
+        if ratio.is_some() {
+            self.pr_last_ratio = ratio;
+        }
         self.conn_last_connected = connected;
     }
 
@@ -245,15 +3012,231 @@ impl Stats {
         sorted.get(idx).copied()
     }
 
+    /// The dedup window figures of the duplicates so far.
+    fn dedup_window(&self) -> DedupWindow {
+        DedupWindow {
+            duplicates: self.dedup_measured,
+            required_window: self.dedup_required,
+            hit_rate: DEDUP_WINDOWS
+                .iter()
+                .zip(self.dedup_hits)
+                .map(|(window, hits)| {
+                    let rate = match self.dedup_measured {
+                        0 => 1.0,
+                        n => hits as f64 / n as f64,
+                    };
+                    (window.to_string(), rate)
+                })
+                .collect(),
+        }
+    }
+
+    /// Delivery per `bucket_ms` window of the send span, which starts at the
+    /// estimated send time of seq 0 and spans `total_expected` messages at the
+    /// bucket rate. Edge buckets less than half inside the span only extend
+    /// zero-delivery streaks.
+    fn bucket_delivery(&self) -> BucketDelivery {
+        let bucket_ms = self.config.bucket_ms.max(1) as f64;
+        let inferred = match (self.sent_first, self.sent_last) {
+            (Some((t0, s0)), Some((t1, s1))) if t1 > t0 && s1 > s0 => {
+                Some((s1 - s0) as f64 * 1000.0 / (t1 - t0) as f64)
+            }
+            _ => None,
+        };
+        let (rate, estimated) = match self.config.expected_rate {
+            Some(rate) => (Some(rate), false),
+            None => (inferred, true),
+        };
+        let mut out = BucketDelivery {
+            rate,
+            estimated,
+            ..BucketDelivery::default()
+        };
+        let (Some(per_ms), Some((first_ms, first_seq))) = (
+            rate.filter(|&r| r > 0.0).map(|r| r / 1000.0),
+            self.sent_first,
+        ) else {
+            return out;
+        };
+        let start = first_ms as f64 - first_seq as f64 / per_ms;
+        let end = start + self.total_expected.max(1) as f64 / per_ms;
+        let (lo, hi) = ((start / bucket_ms).floor(), (end / bucket_ms).ceil());
+        if lo < 0.0 || hi - lo > MAX_DELIVERY_BUCKETS as f64 {
+            return out;
+        }
+        let mut streak = 0.0;
+        for bucket in lo as u64..hi as u64 {
+            let from = bucket as f64 * bucket_ms;
+            let overlap = (end.min(from + bucket_ms) - start.max(from)).max(0.0);
+            let got = self.sent_buckets.get(&bucket).copied().unwrap_or(0);
+            if got == 0 {
+                streak += overlap;
+                out.zero_streak_ms = out.zero_streak_ms.max(streak as u64);
+            } else {
+                streak = 0.0;
+            }
+            if overlap * 2.0 < bucket_ms {
+                continue;
+            }
+            let delivery = (got as f64 / (overlap * per_ms)).min(1.0);
+            out.worst = Some(out.worst.map_or(delivery, |w| w.min(delivery)));
+            if delivery < 0.9 {
+                out.below_90 += 1;
+            }
+        }
+        out
+    }
+
+    /// Median first-copy latency of the first and the last third of the receive
+    /// span (None without timed samples, e.g. after the memory guard).
+    fn lat_thirds(&self) -> (Option<u64>, Option<u64>) {
+        let (Some(&(first, _)), Some(&(last, _))) =
+            (self.lat_timeline.first(), self.lat_timeline.last())
+        else {
+            return (None, None);
+        };
+        let third = last.saturating_sub(first) / 3;
+        let median = |keep: &dyn Fn(u64) -> bool| {
+            let mut lats: Vec<u64> = self
+                .lat_timeline
+                .iter()
+                .filter(|&&(t, _)| keep(t))
+                .map(|&(_, lat)| lat)
+                .collect();
+            let mid = lats.len() / 2;
+            (!lats.is_empty()).then(|| *lats.select_nth_unstable(mid).1)
+        };
+        (
+            median(&|t| t <= first + third),
+            median(&|t| t >= last - third),
+        )
+    }
+
+    /// Mean, population standard deviation and median absolute deviation of a
+    /// sorted slice (all None if it is empty).
+    fn spread(sorted: &[u64]) -> (Option<f64>, Option<f64>, Option<f64>) {
+        let Some(median) = Self::quantil(sorted, 0.50) else {
+            return (None, None, None);
+        };
+        let n = sorted.len() as f64;
+        let mean = sorted.iter().map(|&v| v as f64).sum::<f64>() / n;
+        let var = sorted
+            .iter()
+            .map(|&v| (v as f64 - mean).powi(2))
+            .sum::<f64>()
+            / n;
+        let mut deviations: Vec<u64> = sorted.iter().map(|&v| v.abs_diff(median)).collect();
+        deviations.sort_unstable();
+        let mad = Self::quantil(&deviations, 0.50).map(|d| d as f64);
+        (Some(mean), Some(var.sqrt()), mad)
+    }
+
+    /// Return the quantile value from a sorted slice using linear interpolation
+    /// between the two neighbouring ranks.
+    fn quantil_linear(sorted: &[u64], quantil: f64) -> Option<f64> {
+        if sorted.is_empty() {
+            return None;
+        }
+
+        let pos = (sorted.len() - 1) as f64 * quantil;
+        let lo = pos.floor() as usize;
+        let hi = pos.ceil() as usize;
+        let (a, b) = (sorted[lo] as f64, sorted[hi] as f64);
+        Some(a + (b - a) * (pos - lo as f64))
+    }
+
+    /// Build a map of percentile label ("p50", "p99.9") to value for the
+    /// configured percentile list and estimator. Empty inputs yield an empty map.
+    fn percentile_map(&self, sorted: &[u64]) -> BTreeMap<String, f64> {
+        self.config
+            .percentiles
+            .iter()
+            .filter_map(|&pct| {
+                let q = pct / 100.0;
+                let value = match self.config.quantile_method {
+                    QuantileMethod::Nearest => Self::quantil(sorted, q).map(|v| v as f64),
+                    QuantileMethod::Linear => Self::quantil_linear(sorted, q),
+                }?;
+                Some((format!("p{pct}"), value))
+            })
+            .collect()
+    }
+
     /// Produce a Summary from the accumulated stats.
     ///
     /// This sorts latency samples, finalizes reachability and connectivity
     /// averaging, and computes all derived rates.
     pub fn summarize(&mut self) -> Summary {
+        // A lag episode still open at the end ran up to the last expected seq.
+        self.close_lag_episode(self.total_expected);
+
+        // Heartbeat loss attribution for seqs covered by heartbeats (sent before the
+        // last one arrived); losses after the last heartbeat stay unattributed.
+        let (mut loss_during_heartbeat_outage, mut loss_with_heartbeats) = (0, 0);
+        if let Some((_, covered)) = self.last_heartbeat {
+            for seq in (0..covered.min(self.total_expected)).filter(|s| !self.seen.contains(s)) {
+                if self
+                    .heartbeat_outages
+                    .iter()
+                    .any(|&(from, to)| (from..to).contains(&seq))
+                {
+                    loss_during_heartbeat_outage += 1;
+                } else {
+                    loss_with_heartbeats += 1;
+                }
+            }
+        }
+        let loss_attribution = self.last_heartbeat.map(|_| {
+            match (loss_during_heartbeat_outage > 0, loss_with_heartbeats > 0) {
+                (false, false) => "none",
+                (true, false) => "overlay",
+                (false, true) => "sender",
+                (true, true) => "mixed",
+            }
+            .to_string()
+        });
+
         // latencies
+        let lat_trend = self.lat_trend.slope_ms_per_min();
+        let (lat_p50_first_third, lat_p50_last_third) = self.lat_thirds();
         self.lats.sort_unstable();
+        self.dup_lats.sort_unstable();
+        let (mut from_enqueue, mut from_wire): (Vec<u64>, Vec<u64>) =
+            self.wire_split.iter().copied().unzip();
+        from_enqueue.sort_unstable();
+        from_wire.sort_unstable();
+        self.direct_path_lats.sort_unstable();
+        self.relay_path_lats.sort_unstable();
+        let mut send_jitter: Vec<u64> = match self.sched_base {
+            Some(base) => self.sched_starts.iter().map(|s| s - base).collect(),
+            None => Vec::new(),
+        };
+        send_jitter.sort_unstable();
+        let (lat_mean, lat_stddev, lat_mad) = Self::spread(&self.lats);
+        let trimmed = (self.config.trim_latency_pct > 0.0).then(|| {
+            let drop = (self.lats.len() as f64 * self.config.trim_latency_pct / 100.0) as usize;
+            &self.lats[..self.lats.len() - drop]
+        });
         // LDH samples
         self.ldhs.sort_unstable();
+        // reordering depths
+        self.reorder_depths.sort_unstable();
+        // late-arrival delays
+        self.late_arrival_delays.sort_unstable();
+        // duplicate spacing
+        self.dup_delays.sort_unstable();
+        let reorder_buffer_droppable = [16u64, 64, 256]
+            .iter()
+            .map(|&slots| {
+                let late = self.reorder_depths.iter().filter(|&&d| d > slots).count();
+                let frac = if self.recv_total == 0 {
+                    0.0
+                } else {
+                    late as f64 / self.recv_total as f64
+                };
+                (slots.to_string(), frac)
+            })
+            .collect();
 
         // delivery
         let received_unique = self.seen.len() as u64;
@@ -273,12 +3256,14 @@ impl Stats {
 
         // Finalize peer reachability by accounting for time since last update.
         if let Some(prev_ts) = self.pr_last_ts {
-            let now = now_ms();
+            let now = elapsed_ms();
             let dur = now.saturating_sub(prev_ts) as f64;
 
             // PR
-            self.pr_acc_ms += dur * self.pr_last_ratio;
-            self.pr_total_ms += dur;
+            if let Some(last) = self.pr_last_ratio {
+                self.pr_acc_ms += dur * last;
+                self.pr_total_ms += dur;
+            }
 
             // av connected_peers
             self.conn_acc_ms += dur * (self.conn_last_connected as f64);
@@ -305,6 +3290,11 @@ impl Stats {
         };
 
         // downtime stats
+        let initial_isolation_ms = self.view_origin_ms.map(|origin| {
+            self.first_neighbor_ms
+                .unwrap_or_else(elapsed_ms)
+                .saturating_sub(origin)
+        });
         let mut downtime_sorted = self.downtime_duration_ms.clone();
         downtime_sorted.sort_unstable();
         let downtime_total_ms: u64 = downtime_sorted.iter().copied().sum();
@@ -312,25 +3302,140 @@ impl Stats {
         let downtime_p90 = Self::quantil(&downtime_sorted, 0.90);
         let downtime_max = downtime_sorted.last().copied();
 
-        Summary {
+        // per-neighbor sessions, open ones ending now
+        let now = elapsed_ms();
+        let mut neighbors: Vec<NeighborLifetime> = self
+            .neighbor_sessions
+            .iter()
+            .map(|(peer, sessions)| {
+                let mut sessions = sessions.clone();
+                sessions.close(now);
+                NeighborLifetime {
+                    peer: peer.clone(),
+                    sessions: sessions.sessions,
+                    connected_ms: sessions.connected_ms,
+                    longest_session_ms: sessions.longest_ms,
+                }
+            })
+            .collect();
+        neighbors.sort_by_key(|n| std::cmp::Reverse(n.connected_ms));
+        let neighbors_overflow = neighbors.len().saturating_sub(self.config.neighbor_cap) as u64;
+        neighbors.truncate(self.config.neighbor_cap);
+        let flaps: u64 = self.neighbor_sessions.values().map(|s| s.ended).sum();
+        let neighbor_flap_rate_per_min = self
+            .view_origin_ms
+            .map(|origin| now.saturating_sub(origin))
+            .filter(|&span| span > 0)
+            .map(|span| flaps as f64 * 60_000.0 / span as f64);
+
+        let buckets = self.bucket_delivery();
+
+        // With copies, the originals alone are the control for their gain.
+        let redundancy = (self.max_copy > 0 && self.total_expected > 0).then(|| {
+            let expected = self.total_expected as f64;
+            let rescued = self.rescued_by_copy.len() as u64;
+            let originals = self.received_unique().saturating_sub(rescued);
+            let overhead = (self.received_unique() > 0)
+                .then(|| self.recv_total as f64 / self.received_unique() as f64);
+            (
+                originals as f64 / expected,
+                rescued as f64 / expected,
+                overhead,
+            )
+        });
+        let mut summary = Summary {
+            schema_version: SUMMARY_SCHEMA_VERSION,
+            test_id: None,
+            run_id: None,
+            sender_run_id: self.sender_run_id.map(hex::encode),
+            build: None,
+            gossip: None,
+            topic: None,
+            topic_derivation: None,
+
             // delivery
             received_unique,
             recv_total: self.recv_total,
             total_expected,
             delivery_rate: delivery,
+            worst_bucket_delivery_rate: buckets.worst,
+            buckets_below_90pct: buckets.below_90,
+            longest_zero_delivery_streak_ms: buckets.zero_streak_ms,
+            bucket_ms: self.config.bucket_ms,
+            bucket_rate: buckets.rate,
+            bucket_rate_estimated: buckets.estimated,
 
             // duplicates/order
             duplicate_rate: dup_rate,
             duplicates: self.duplicates,
             out_of_order: self.out_of_order,
 
+            // duplicate spacing
+            dup_delay_p50_ms: Self::quantil(&self.dup_delays, 0.50),
+            dup_delay_p90_ms: Self::quantil(&self.dup_delays, 0.90),
+            dup_delay_max_ms: self.dup_delays.last().copied(),
+            max_dups_for_single_seq: self.max_dups_for_single_seq,
+            dedup_window: self.degradation.is_none().then(|| self.dedup_window()),
+
+            // reordering depth
+            reorder_depth_p50: Self::quantil(&self.reorder_depths, 0.50),
+            reorder_depth_p90: Self::quantil(&self.reorder_depths, 0.90),
+            reorder_depth_max: self.reorder_depths.last().copied(),
+            reorder_extent: self.reorder_extent,
+            reorder_buffer_droppable,
+
+            // late arrivals
+            late_arrivals: self.late_arrival_delays.len() as u64,
+            late_arrival_delay_p50_ms: Self::quantil(&self.late_arrival_delays, 0.50),
+            late_arrival_delay_p90_ms: Self::quantil(&self.late_arrival_delays, 0.90),
+            late_arrival_delay_max_ms: self.late_arrival_delays.last().copied(),
+            gaps_filled: self.gaps_filled,
+            gaps_unfilled: self.gap_opened_ms.len() as u64
+                + self.gap_ranges.iter().map(|(s, e)| e - s).sum::<u64>(),
+
             // lag/E2E
             lagged_events: self.lagged_events,
+            lag_episodes: self.lag_gaps.len() as u64,
+            lag_estimated_missed_msgs: self.lag_gaps.iter().sum(),
+            lag_max_episode_msgs: self.lag_gaps.iter().max().copied(),
             lat_min: self.lats.first().copied(),
             lat_p50: Self::quantil(&self.lats, 0.50),
             lat_p90: Self::quantil(&self.lats, 0.90),
             lat_p99: Self::quantil(&self.lats, 0.99),
             lat_max: self.lats.last().copied(),
+            lat_histogram: LatencyHistogram::from_samples(&self.lats),
+            lat_mean,
+            lat_stddev,
+            lat_mad,
+            lat_trim_pct: trimmed.map(|_| self.config.trim_latency_pct),
+            lat_p50_trimmed: trimmed.and_then(|t| Self::quantil(t, 0.50)),
+            lat_p90_trimmed: trimmed.and_then(|t| Self::quantil(t, 0.90)),
+            lat_p99_trimmed: trimmed.and_then(|t| Self::quantil(t, 0.99)),
+            lat_max_trimmed: trimmed.and_then(|t| t.last().copied()),
+            lat_trend_ms_per_min: lat_trend,
+            lat_p50_first_third,
+            lat_p50_last_third,
+            latency_degrading: lat_trend.is_some_and(|s| s > self.config.lat_trend_threshold),
+            wire_stamped: self.wire_split.len() as u64,
+            lat_from_enqueue_p50: Self::quantil(&from_enqueue, 0.50),
+            lat_from_enqueue_p90: Self::quantil(&from_enqueue, 0.90),
+            lat_from_enqueue_p99: Self::quantil(&from_enqueue, 0.99),
+            lat_from_wire_p50: Self::quantil(&from_wire, 0.50),
+            lat_from_wire_p90: Self::quantil(&from_wire, 0.90),
+            lat_from_wire_p99: Self::quantil(&from_wire, 0.99),
+            direct_path_msgs: self.direct_path_msgs,
+            relay_path_msgs: self.relay_path_msgs,
+            unknown_path_msgs: self.unknown_path_msgs,
+            lat_p50_direct: Self::quantil(&self.direct_path_lats, 0.50),
+            lat_p90_direct: Self::quantil(&self.direct_path_lats, 0.90),
+            lat_p50_relay: Self::quantil(&self.relay_path_lats, 0.50),
+            lat_p90_relay: Self::quantil(&self.relay_path_lats, 0.90),
+            send_jitter_p50_ms: Self::quantil(&send_jitter, 0.50),
+            send_jitter_p90_ms: Self::quantil(&send_jitter, 0.90),
+            send_jitter_p99_ms: Self::quantil(&send_jitter, 0.99),
+            send_jitter_max_ms: send_jitter.last().copied(),
+            dup_lat_p50: Self::quantil(&self.dup_lats, 0.50),
+            dup_lat_p90: Self::quantil(&self.dup_lats, 0.90),
 
             // LDH
             ldh_min: self.ldhs.first().copied(),
@@ -338,6 +3443,13 @@ impl Stats {
             ldh_p90: Self::quantil(&self.ldhs, 0.90),
             ldh_p99: Self::quantil(&self.ldhs, 0.99),
             ldh_max: self.ldhs.last().copied(),
+            direct_deliveries: self.direct_deliveries,
+            all_direct: !self.seen.is_empty() && self.direct_deliveries == self.seen.len() as u64,
+
+            // configurable percentiles
+            lat_percentiles: self.percentile_map(&self.lats),
+            ldh_percentiles: self.percentile_map(&self.ldhs),
+            downtime_percentiles_ms: self.percentile_map(&downtime_sorted),
 
             // PR
             pr_avg_ratio: pr_avg,
@@ -345,6 +3457,81 @@ impl Stats {
             // count neighbours in active view
             neighbour_down: self.neighbour_down,
             neighbour_up: self.neighbour_up,
+            neighbors,
+            neighbors_overflow,
+            neighbor_flap_rate_per_min,
+
+            // rejoin attempts
+            rejoin_attempts: self.rejoin_attempts,
+            rejoin_successes: self.rejoin_successes,
+            addr_changes: self.addr_changes,
+            relay_changes: self.relay_changes,
+            neighbor_rtt_ms: BTreeMap::new(),
+
+            // transport errors
+            errors_by_kind: self.errors_by_kind.clone(),
+            task_exits: self.task_exits,
+
+            // repair layer (filled by the receiver when enabled)
+            repair: None,
+
+            // compression
+            compression: (self.compression.frames > 0).then_some(self.compression),
+
+            // encryption
+            crypto: (self.crypto.messages > 0).then_some(self.crypto),
+            auth_failures: self.auth_failures,
+            encryption_mismatches: self.encryption_mismatches,
+            decode_errors: self.decode_errors,
+
+            // reassembly (filled by the receiver)
+            incomplete_reassemblies: 0,
+
+            // heartbeats
+            heartbeats_received: self.heartbeats_received,
+            max_heartbeat_gap_ms: self.max_heartbeat_gap_ms,
+            last_heartbeat_age_ms: self
+                .last_heartbeat
+                .map(|(ms, _)| elapsed_ms().saturating_sub(ms)),
+            loss_during_heartbeat_outage,
+            loss_with_heartbeats,
+            loss_attribution,
+
+            // announcements
+            announces_sent: self.announces_sent,
+            acks_sent: self.acks_sent,
+            peers_heard: self.peers_heard.len() as u64,
+
+            // signature policy
+            rejected_unsigned: self.rejected_unsigned,
+            rejected_bad_sig: self.rejected_bad_sig,
+            rejected_not_allowed: self.rejected_not_allowed,
+            foreign_msgs: self.foreign_msgs,
+            concurrent_sender_detected: !self.other_tests.is_empty(),
+            other_test_ids: self.other_tests.iter().map(hex::encode).collect(),
+            stale_msgs: self.stale_msgs,
+            implausible_seq: self.implausible_seq,
+            max_copy: self.max_copy,
+            redundancy_rescued: self.rescued_by_copy.len() as u64,
+            first_copy_delivery_rate: redundancy.map(|(first, _, _)| first),
+            effective_delivery_gain: redundancy.map(|(_, gain, _)| gain),
+            redundancy_overhead_ratio: redundancy.and_then(|(_, _, overhead)| overhead),
+            pad_mismatches: self.pad_mismatches,
+            batches_received: self.batch_sizes.as_ref().map_or(0, LatencyHistogram::count),
+            batch_size_p50: self.batch_sizes.as_ref().and_then(|h| h.quantile(0.50)),
+            batch_size_max: self
+                .batch_sizes
+                .as_ref()
+                .and_then(|h| h.buckets.keys().next_back().copied()),
+            batch_size_hist: self.batch_sizes.clone(),
+            expected_payload_size: self.expected_size(),
+            size_mismatch_msgs: self.size_mismatch_msgs,
+            size_mismatch_min: self.size_mismatch_min,
+            size_mismatch_max: self.size_mismatch_max,
+            oversized_msgs: self.oversized_msgs,
+            max_recv_bytes: None,
+            payload: self.payload,
+            efficiency: self.payload.map(|p| p.efficiency()),
 
             // connectivity
             avg_connected_peers,
@@ -353,12 +3540,536 @@ impl Stats {
             downtime_p50_ms: downtime_p50,
             downtime_p90_ms: downtime_p90,
             downtime_max_ms: downtime_max,
+            initial_isolation_ms,
+
+            // protocol/wire counters are filled in by the runner
+            protocol: None,
+            unique_payload_bytes: self.unique_payload_bytes,
+            wire_bytes_tx: None,
+            wire_bytes_rx: None,
+            overhead_ratio: None,
 
             // startup/termination flags (defaults)
             joined: false,
             join_wait_ms: 0,
             saw_test: false,
+            time_to_join_ms: None,
+            time_to_first_event_ms: None,
+            time_to_first_data_ms: None,
+            join_stages: BTreeMap::new(),
+            join_breakdown: JoinBreakdown::default(),
+            connect: None,
             timed_out_no_data: false,
+            topics: None,
+            terminated_by: None,
+            diagnosis: None,
+            influx: None,
+            forward: None,
+            resources: None,
+            stats_degraded: false,
+            log_write_errors: 0,
+        };
+        self.apply_histograms(&mut summary);
+        summary
+    }
+
+    /// After the memory guard's switch, take the latency and LDH figures from
+    /// the histograms (bucket lower bounds; no MAD, nearest-rank maps).
+    fn apply_histograms(&self, summary: &mut Summary) {
+        if self.degradation.is_none() {
+            return;
+        }
+        summary.stats_degraded = true;
+        summary.gaps_unfilled += self.gaps_untimed.len() as u64;
+        let lo = |h: &LatencyHistogram| h.buckets.keys().next().copied();
+        let hi = |h: &LatencyHistogram| h.buckets.keys().next_back().copied();
+        let map = |h: &LatencyHistogram| -> BTreeMap<String, f64> {
+            self.config
+                .percentiles
+                .iter()
+                .filter_map(|&pct| Some((format!("p{pct}"), h.quantile(pct / 100.0)? as f64)))
+                .collect()
+        };
+        if let Some(h) = self.lat_hist.as_ref() {
+            let n = h.count() as f64;
+            let keep = 1.0 - self.config.trim_latency_pct / 100.0;
+            let trimmed = (self.config.trim_latency_pct > 0.0).then_some(keep);
+            summary.lat_min = lo(h);
+            summary.lat_p50 = h.quantile(0.50);
+            summary.lat_p90 = h.quantile(0.90);
+            summary.lat_p99 = h.quantile(0.99);
+            summary.lat_max = hi(h);
+            summary.lat_histogram = (n > 0.0).then(|| h.clone());
+            let mean = (n > 0.0).then(|| self.lat_sum / n);
+            summary.lat_mean = mean;
+            summary.lat_stddev = mean.map(|m| (self.lat_sq_sum / n - m * m).max(0.0).sqrt());
+            summary.lat_mad = None;
+            summary.lat_p50_trimmed = trimmed.and_then(|k| h.quantile(0.50 * k));
+            summary.lat_p90_trimmed = trimmed.and_then(|k| h.quantile(0.90 * k));
+            summary.lat_p99_trimmed = trimmed.and_then(|k| h.quantile(0.99 * k));
+            summary.lat_max_trimmed = trimmed.and_then(|k| h.quantile(k));
+            summary.lat_percentiles = map(h);
+        }
+        if let Some(h) = self.dup_lat_hist.as_ref() {
+            summary.dup_lat_p50 = h.quantile(0.50);
+            summary.dup_lat_p90 = h.quantile(0.90);
+        }
+        if let Some(h) = self.ldh_hist.as_ref() {
+            summary.ldh_min = lo(h);
+            summary.ldh_p50 = h.quantile(0.50);
+            summary.ldh_p90 = h.quantile(0.90);
+            summary.ldh_p99 = h.quantile(0.99);
+            summary.ldh_max = hi(h);
+            summary.ldh_percentiles = map(h);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn assert_close(a: f64, b: f64) {
+        assert!((a - b).abs() < 1e-9, "{a} != {b}");
+    }
+
+    fn msg(seq: u64) -> DataMsg {
+        DataMsg {
+            test_id: [7; 16],
+            seq,
+            sent_ms: 1_000u64.saturating_add(seq),
+            total: 0,
+            copy: 0,
+            sched_offset_ms: None,
+            pad: Vec::new(),
+        }
+    }
+
+    /// Record `seqs` in order, one ms apart, each with a 5 ms latency.
+    fn feed(stats: &mut Stats, seqs: impl IntoIterator<Item = u64>) -> Vec<Arrival> {
+        seqs.into_iter()
+            .enumerate()
+            .map(|(i, seq)| {
+                let m = msg(seq);
+                let recv = m.sent_ms + 5;
+                stats.record(&m, 16, Some(1), recv, i as u64)
+            })
+            .collect()
+    }
+
+    fn stats_with(percentiles: &[f64], quantile_method: QuantileMethod) -> Stats {
+        Stats::with_config(StatsConfig {
+            percentiles: percentiles.to_vec(),
+            quantile_method,
+            ..StatsConfig::default()
+        })
+    }
+
+    #[test]
+    fn nearest_rank_rounds_to_a_sample() {
+        let sorted = [10, 20, 30, 40];
+        assert_eq!(Stats::quantil(&sorted, 0.0), Some(10));
+        assert_eq!(Stats::quantil(&sorted, 0.5), Some(30));
+        assert_eq!(Stats::quantil(&sorted, 0.9), Some(40));
+        assert_eq!(Stats::quantil(&sorted, 1.0), Some(40));
+        assert_eq!(Stats::quantil(&[10, 20], 0.5), Some(20));
+    }
+
+    #[test]
+    fn linear_interpolates_between_ranks() {
+        let sorted = [10, 20, 30, 40];
+        assert_close(Stats::quantil_linear(&sorted, 0.0).unwrap(), 10.0);
+        assert_close(Stats::quantil_linear(&sorted, 0.5).unwrap(), 25.0);
+        assert_close(Stats::quantil_linear(&sorted, 0.9).unwrap(), 37.0);
+        assert_close(Stats::quantil_linear(&sorted, 1.0).unwrap(), 40.0);
+        assert_close(Stats::quantil_linear(&[10, 20], 0.5).unwrap(), 15.0);
+    }
+
+    #[test]
+    fn single_and_empty_samples() {
+        for q in [0.0, 0.5, 0.999, 1.0] {
+            assert_eq!(Stats::quantil(&[7], q), Some(7));
+            assert_close(Stats::quantil_linear(&[7], q).unwrap(), 7.0);
+            assert_eq!(Stats::quantil(&[], q), None);
+            assert_eq!(Stats::quantil_linear(&[], q), None);
+        }
+    }
+
+    #[test]
+    fn percentile_map_labels_and_estimators() {
+        let sorted = [10, 20, 30, 40];
+        let pcts = [0.0, 50.0, 99.9, 100.0];
+
+        let nearest = stats_with(&pcts, QuantileMethod::Nearest).percentile_map(&sorted);
+        let labels: Vec<_> = nearest.keys().map(String::as_str).collect();
+        assert_eq!(labels, ["p0", "p100", "p50", "p99.9"]);
+        assert_close(nearest["p0"], 10.0);
+        assert_close(nearest["p50"], 30.0);
+        assert_close(nearest["p99.9"], 40.0);
+        assert_close(nearest["p100"], 40.0);
+
+        let linear = stats_with(&pcts, QuantileMethod::Linear).percentile_map(&sorted);
+        assert_close(linear["p0"], 10.0);
+        assert_close(linear["p50"], 25.0);
+        assert_close(linear["p99.9"], 39.97);
+        assert_close(linear["p100"], 40.0);
+
+        let empty = stats_with(&pcts, QuantileMethod::Linear).percentile_map(&[]);
+        assert!(empty.is_empty());
+    }
+
+    #[test]
+    fn reorder_depth_and_extent() {
+        let mut stats = Stats::default();
+        let arrivals = feed(&mut stats, [0, 1, 4, 2, 3, 5]);
+        let late: Vec<_> = arrivals.iter().map(|a| a.out_of_order).collect();
+        assert_eq!(late, [false, false, false, true, true, false]);
+        let summary = stats.summarize();
+        assert_eq!(summary.out_of_order, 2);
+        assert_eq!(summary.reorder_depth_p50, Some(2));
+        assert_eq!(summary.reorder_depth_max, Some(2));
+        // seq 3 arrived two arrivals after seq 4, the first above it.
+        assert_eq!(summary.reorder_extent, 2);
+        assert!(summary.reorder_buffer_droppable.values().all(|&f| f == 0.0));
+    }
+
+    #[test]
+    fn deep_reorder_is_droppable_by_every_buffer() {
+        let mut stats = Stats::default();
+        feed(&mut stats, (0..=300).filter(|&s| s != 10).chain([10]));
+        let summary = stats.summarize();
+        assert_eq!(summary.out_of_order, 1);
+        assert_eq!(summary.reorder_depth_max, Some(290));
+        assert_eq!(summary.reorder_extent, 290);
+        for slots in ["16", "64", "256"] {
+            assert_close(summary.reorder_buffer_droppable[slots], 1.0 / 301.0);
+        }
+    }
+
+    #[test]
+    fn duplicate_spacing_and_worst_seq() {
+        let mut stats = Stats::default();
+        let arrivals = feed(&mut stats, [0, 1, 2, 0, 3, 0, 1]);
+        let dups: Vec<_> = arrivals.iter().map(|a| a.duplicate).collect();
+        assert_eq!(dups, [false, false, false, true, false, true, true]);
+        let summary = stats.summarize();
+        assert_eq!(summary.duplicates, 3);
+        assert_eq!(summary.received_unique, 4);
+        assert_eq!(summary.dup_delay_p50_ms, Some(5));
+        assert_eq!(summary.dup_delay_max_ms, Some(5));
+        assert_eq!(summary.max_dups_for_single_seq, 2);
+    }
+
+    #[test]
+    fn duplicate_spacing_window_is_bounded() {
+        let mut stats = Stats::with_config(StatsConfig {
+            dup_window: 2,
+            ..StatsConfig::default()
+        });
+        feed(&mut stats, [0, 1, 2, 3, 4, 0]);
+        assert_eq!(stats.first_arrivals.len(), 3);
+        let summary = stats.summarize();
+        // The first copy of seq 0 left the window: counted, but not spaced.
+        assert_eq!(summary.duplicates, 1);
+        assert_eq!(summary.dup_delay_max_ms, None);
+        assert_eq!(summary.max_dups_for_single_seq, 0);
+    }
+
+    #[test]
+    fn pretty_summary_snapshot() {
+        let mut stats = Stats::default();
+        feed(&mut stats, [0, 1, 1, 3]);
+        let mut summary = stats.summarize();
+        summary.total_expected = 5;
+        summary.delivery_rate = 0.6;
+        summary.joined = true;
+        summary.join_wait_ms = 40;
+        summary.downtime_total_ms = 250;
+        summary.downtime_periods = 1;
+        assert_eq!(
+            summary.pretty(false).to_string(),
+            "summary test -
+  delivery         60.00 % (3/5)
+  worst bucket     -
+  duplicates       25.00 % (1)
+  latency p50      5 ms
+  latency p90      5 ms
+  latency p99      5 ms
+  from wire p50    -
+  ldh min/p50/max  1 / 1 / 1
+  downtime         250 ms (1 periods)
+  joined           yes (waited 40 ms)
+  terminated by    -
+  diagnosis        -
+"
+        );
+        let colored = summary.pretty(true).to_string();
+        assert!(colored.contains("  delivery         \x1b[31m60.00 % (3/5)\x1b[0m\n"));
+        assert!(colored.contains("  duplicates       \x1b[33m25.00 % (1)\x1b[0m\n"));
+        assert!(colored.contains("  latency p50      \x1b[32m5 ms\x1b[0m\n"));
+        assert!(colored.contains("  worst bucket     -\n"));
+    }
+
+    #[test]
+    fn wall_clock_step_leaves_downtime_alone() {
+        let mut stats = Stats::default();
+        let wall = |mono: u64| {
+            // NTP steps the wall clock back an hour at mono 200.
+            let start = 10_000_000;
+            if mono < 200 {
+                start + mono
+            } else {
+                start + mono - 3_600_000
+            }
+        };
+        stats.start_peer_view(0, 1);
+        for (seq, mono) in [(0, 50), (1, 150), (2, 250), (3, 450)] {
+            let mut m = msg(seq);
+            m.sent_ms = wall(mono) - 5;
+            stats.record(&m, 16, Some(1), wall(mono), mono);
+        }
+        stats.record_peer_view(100, 0, None);
+        stats.record_peer_view(400, 1, None);
+        stats.record_peer_view(1000, 1, None);
+        let summary = stats.summarize();
+        assert_eq!(summary.downtime_total_ms, 300);
+        assert_eq!(summary.downtime_periods, 1);
+        assert_eq!(summary.lat_max, Some(5));
+    }
+
+    #[test]
+    fn never_a_neighbor_is_downtime_from_the_start() {
+        let mut stats = Stats::default();
+        let origin = elapsed_ms();
+        stats.start_peer_view(origin, 0);
+        std::thread::sleep(std::time::Duration::from_millis(20));
+        let summary = stats.summarize();
+        assert_eq!(summary.downtime_periods, 1);
+        assert!(summary.downtime_total_ms >= 20);
+        let isolation = summary.initial_isolation_ms.unwrap();
+        assert!(isolation.abs_diff(summary.downtime_total_ms) <= 1);
+    }
+
+    #[test]
+    fn late_first_neighbor_counts_the_initial_isolation() {
+        let mut stats = Stats::default();
+        stats.start_peer_view(0, 0);
+        stats.record_peer_view(300, 1, None);
+        stats.record_peer_view(500, 0, None);
+        stats.record_peer_view(600, 1, None);
+        let summary = stats.summarize();
+        assert_eq!(summary.initial_isolation_ms, Some(300));
+        assert_eq!(summary.downtime_periods, 2);
+        assert_eq!(summary.downtime_total_ms, 400);
+    }
+
+    #[test]
+    fn lag_episodes_attribute_the_seq_jump() {
+        let mut stats = Stats::default();
+        feed(&mut stats, 0..5);
+        stats.note_lagged();
+        stats.note_lagged();
+        feed(&mut stats, 12..14);
+        stats.note_lagged();
+        feed(&mut stats, [15]);
+        let summary = stats.summarize();
+        assert_eq!(summary.lagged_events, 3);
+        assert_eq!(summary.lag_episodes, 2);
+        assert_eq!(summary.lag_estimated_missed_msgs, 8);
+        assert_eq!(summary.lag_max_episode_msgs, Some(7));
+    }
+
+    #[test]
+    fn neighbor_sessions_from_a_scripted_view() {
+        let mut stats = Stats::with_config(StatsConfig {
+            neighbor_cap: 2,
+            ..StatsConfig::default()
+        });
+        stats.start_peer_view(0, 0);
+        stats.note_neighbour_up("a", 0);
+        stats.note_neighbour_up("b", 50);
+        stats.note_neighbour_down("a", 100);
+        stats.note_neighbour_up("a", 200);
+        stats.note_neighbour_down("b", 250);
+        stats.note_neighbour_down("a", 500);
+        // Still connected at the end: the run end closes the session.
+        stats.note_neighbour_up("c", elapsed_ms());
+        let summary = stats.summarize();
+
+        assert_eq!(summary.neighbour_up, 4);
+        assert_eq!(summary.neighbour_down, 3);
+        let a = &summary.neighbors[0];
+        assert_eq!((a.peer.as_str(), a.sessions), ("a", 2));
+        assert_eq!((a.connected_ms, a.longest_session_ms), (400, 300));
+        let b = &summary.neighbors[1];
+        assert_eq!((b.peer.as_str(), b.sessions, b.connected_ms), ("b", 1, 200));
+        assert_eq!(summary.neighbors.len(), 2);
+        assert_eq!(summary.neighbors_overflow, 1);
+    }
+
+    #[test]
+    fn duplicate_latency_stays_out_of_the_primary_series() {
+        let mut stats = Stats::default();
+        // (seq, latency): late echoes of both messages, the second one first.
+        for (i, (seq, lat)) in [(0, 10), (1, 20), (1, 900), (0, 500), (2, 30)]
+            .into_iter()
+            .enumerate()
+        {
+            let m = msg(seq);
+            stats.record(&m, 16, Some(1), m.sent_ms + lat, i as u64);
+        }
+        let summary = stats.summarize();
+        assert_eq!(summary.duplicates, 2);
+        assert_eq!(summary.lat_min, Some(10));
+        assert_eq!(summary.lat_p50, Some(20));
+        assert_eq!(summary.lat_max, Some(30));
+        assert_eq!(summary.dup_lat_p50, Some(900));
+        assert_eq!(summary.dup_lat_p90, Some(900));
+    }
+
+    #[test]
+    fn trimming_drops_outliers_from_the_trimmed_percentiles_only() {
+        let run = |trim_latency_pct| {
+            let mut stats = Stats::with_config(StatsConfig {
+                trim_latency_pct,
+                ..StatsConfig::default()
+            });
+            // Latencies 1..=98 ms, two 8 s cold-start outliers and a duplicate.
+            let lats = (1..=98).chain([8000, 8000]);
+            for (seq, lat) in lats.enumerate().map(|(i, l)| (i as u64, l)).chain([(0, 3)]) {
+                let mut m = msg(seq);
+                m.total = 100;
+                stats.record(&m, 16, Some(1), m.sent_ms + lat, seq);
+            }
+            stats.summarize()
+        };
+        let plain = run(0.0);
+        let trimmed = run(2.0);
+
+        assert_eq!(plain.lat_p99_trimmed, None);
+        assert_eq!(trimmed.lat_trim_pct, Some(2.0));
+        assert_eq!((trimmed.lat_p99, trimmed.lat_max), (Some(8000), Some(8000)));
+        assert_eq!(trimmed.lat_p99_trimmed, Some(97));
+        assert_eq!(trimmed.lat_max_trimmed, Some(98));
+        assert_eq!(trimmed.lat_p50, plain.lat_p50);
+        assert_close(trimmed.lat_mean.unwrap(), 208.51);
+        assert_eq!(plain.lat_mad, Some(25.0));
+
+        // Delivery and duplicate accounting see every message either way.
+        assert_eq!(trimmed.delivery_rate, 1.0);
+        assert_eq!((trimmed.duplicates, plain.duplicates), (1, 1));
+    }
+
+    #[test]
+    fn memory_guard_switches_to_histograms() {
+        let mut stats = Stats::with_config(StatsConfig {
+            max_memory_bytes: Some(16 * 1024),
+            ..StatsConfig::default()
+        });
+        feed(&mut stats, (0..5000).filter(|&s| s != 10));
+        let degradation = stats.take_degradation().expect("cap reached");
+        assert_eq!(degradation.cap_bytes, 16 * 1024);
+        assert!(degradation.lat_samples > 0);
+        assert!(stats.take_degradation().is_none(), "reported once");
+        assert!(stats.lats.is_empty() && stats.gap_opened_ms.is_empty());
+
+        let summary = stats.summarize();
+        assert!(summary.stats_degraded);
+        assert!(summary.lat_histogram.is_some());
+        assert!(summary.lat_p50.is_some_and(|p| p <= 5));
+        assert_eq!(summary.lat_max, Some(5));
+        assert_eq!(summary.lat_mad, None);
+        assert_eq!(summary.received_unique, 4999);
+        assert_eq!(summary.gaps_unfilled, 1);
+    }
+
+    #[test]
+    fn implausible_seqs_do_not_poison_the_stream() {
+        let mut stats = Stats::with_config(StatsConfig {
+            seq_window: 1000,
+            ..StatsConfig::default()
+        });
+        let poison = |seq, total| DataMsg { total, ..msg(seq) };
+        let mut reasons = Vec::new();
+        for seq in 0..100 {
+            let mut m = msg(seq);
+            m.total = 100;
+            let mut stream = vec![m];
+            match seq {
+                20 => stream.push(poison(u64::MAX, 100)),
+                40 => stream.push(poison(41, u64::MAX)),
+                _ => {}
+            }
+            for m in stream {
+                match stats.check_seq(&m, seq > 0) {
+                    Some(bad) => {
+                        assert_eq!(bad.seq, m.seq);
+                        reasons.push(bad.reason);
+                        stats.note_implausible_seq();
+                    }
+                    None => {
+                        stats.record(&m, 16, Some(1), m.sent_ms + 5, seq);
+                    }
+                }
+            }
         }
+        assert_eq!(reasons, ["seq_beyond_total", "total_beyond_window"]);
+        let summary = stats.summarize();
+        assert_eq!(summary.implausible_seq, 2);
+        assert_eq!(summary.total_expected, 100);
+        assert_eq!(summary.delivery_rate, 1.0);
+        assert_eq!(summary.out_of_order, 0);
+        assert_eq!((summary.lat_p50, summary.lat_max), (Some(5), Some(5)));
+    }
+
+    #[test]
+    fn seq_window_bounds_an_unknown_total() {
+        let stats = {
+            let mut stats = Stats::with_config(StatsConfig {
+                seq_window: 1000,
+                ..StatsConfig::default()
+            });
+            feed(&mut stats, 0..10);
+            stats
+        };
+        assert!(stats.check_seq(&msg(1009), true).is_none());
+        let bad = stats.check_seq(&msg(1010), true).unwrap();
+        assert_eq!(bad.reason, "seq_beyond_window");
+        assert_eq!(bad.max_seq_seen, Some(9));
+        // The first message of a test has nothing to be beyond.
+        assert!(stats.check_seq(&msg(u64::MAX), false).is_none());
+    }
+
+    #[test]
+    fn gap_tracking_stops_at_the_seq_window() {
+        let mut stats = Stats::with_config(StatsConfig {
+            seq_window: 100,
+            ..StatsConfig::default()
+        });
+        feed(&mut stats, [0, 1_000_000]);
+        assert_eq!(stats.gap_opened_ms.len(), 100);
+        assert_eq!(stats.gap_ranges.len(), 1);
+    }
+
+    #[test]
+    fn dedup_window_from_crafted_duplicates() {
+        let mut stats = Stats::default();
+        // First copies 0..2000, then echoes 50, 200 and 1500 sequences back.
+        feed(&mut stats, (0..2000).chain([1950, 1800, 500]));
+        let window = stats.summarize().dedup_window.unwrap();
+        assert_eq!(window.duplicates, 3);
+        assert_eq!(window.required_window, 1500);
+        assert_close(window.hit_rate["128"], 1.0 / 3.0);
+        assert_close(window.hit_rate["1024"], 2.0 / 3.0);
+        assert_close(window.hit_rate["8192"], 1.0);
+    }
+
+    #[test]
+    fn dedup_window_is_trivial_without_duplicates() {
+        let mut stats = Stats::default();
+        feed(&mut stats, 0..10);
+        let window = stats.summarize().dedup_window.unwrap();
+        assert_eq!((window.duplicates, window.required_window), (0, 0));
+        assert!(window.hit_rate.values().all(|&rate| rate == 1.0));
     }
 }
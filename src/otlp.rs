@@ -0,0 +1,309 @@
+//! OTLP trace export (`--otlp-endpoint`): one span per sampled message, posted
+//! as OTLP/HTTP JSON to `<endpoint>/v1/traces` (Jaeger, the OpenTelemetry
+//! Collector, ...).
+//!
+//! Spans are built from the `send` and `recv` log events. The trace ID is the
+//! 16-byte `test_id`, the sender's span ID is derived from `seq`, and every
+//! receiver span names it as parent, so one message's trace shows the send on
+//! the sender and its deliveries on each receiver. Sampling hashes
+//! `(test_id, seq)`, so sender and receivers keep the same messages without
+//! coordinating. Receiver spans only exist for `recv` events that are logged
+//! (see `--log-sample-recv`).
+//!
+//! Export is best effort: a full queue or a failed POST drops spans, which are
+//! counted and reported at exit, never as an error to the benchmark.
+
+use crate::metrics::{EventKind, EventSink, LogEvent};
+use crate::util::diag;
+use serde_json::{Value, json};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::time::Duration;
+use tokio::sync::mpsc;
+use tokio::task::JoinHandle;
+use tokio::time::{Instant, sleep, sleep_until};
+use tracing::warn;
+
+/// Spans per POST, and the longest a partial batch waits.
+const BATCH: usize = 256;
+const FLUSH_MS: u64 = 1000;
+
+/// Per-request timeout, so a dead collector only delays the queue.
+const REQUEST_TIMEOUT: Duration = Duration::from_secs(2);
+
+/// OTLP span kinds used here.
+const SPAN_KIND_PRODUCER: u64 = 4;
+const SPAN_KIND_CONSUMER: u64 = 5;
+
+/// Exporter settings from the CLI.
+#[derive(Debug, Clone)]
+pub struct OtlpOptions {
+    /// Collector base URL, e.g. `http://host:4318`.
+    pub endpoint: String,
+    /// Fraction of messages traced (0-1).
+    pub sample: f64,
+    /// Configured payload size, the `size` attribute of send spans.
+    pub payload_size: usize,
+}
+
+#[derive(Debug, Default)]
+struct Counters {
+    exported: AtomicU64,
+    dropped: AtomicU64,
+    /// Spans queued but not yet posted or dropped.
+    pending: AtomicU64,
+}
+
+/// The writing half, one of the run's `MultiSink` sinks.
+pub struct OtlpSink {
+    tx: mpsc::Sender<Value>,
+    counters: Arc<Counters>,
+    /// Sampling threshold on the `(test_id, seq)` hash.
+    threshold: u64,
+    payload_size: usize,
+}
+
+/// The owning half: the final flush.
+pub struct OtlpHandle {
+    counters: Arc<Counters>,
+    task: JoinHandle<()>,
+}
+
+/// Start the background exporter. Fails only on an invalid endpoint.
+pub fn spawn(
+    opts: OtlpOptions,
+    role: &str,
+    peer_id: Option<&str>,
+) -> anyhow::Result<(OtlpSink, OtlpHandle)> {
+    let mut url = reqwest::Url::parse(&opts.endpoint)
+        .map_err(|e| anyhow::anyhow!("invalid --otlp-endpoint {}: {e}", opts.endpoint))?;
+    url.set_path(&format!("{}/v1/traces", url.path().trim_end_matches('/')));
+    let client = reqwest::Client::builder()
+        .timeout(REQUEST_TIMEOUT)
+        .build()?;
+
+    let mut resource = vec![
+        attr(
+            "service.name",
+            json!({ "stringValue": "iroh-gossip-metrics" }),
+        ),
+        attr("role", json!({ "stringValue": role })),
+        attr("run_id", json!({ "stringValue": crate::util::run_id() })),
+    ];
+    if let Some(peer_id) = peer_id {
+        resource.push(attr("peer_id", json!({ "stringValue": peer_id })));
+    }
+
+    let (tx, rx) = mpsc::channel(BATCH * 10);
+    let counters = Arc::new(Counters::default());
+    let task = tokio::spawn(post_loop(client, url, resource, rx, counters.clone()));
+    Ok((
+        OtlpSink {
+            tx,
+            counters: counters.clone(),
+            threshold: (opts.sample.clamp(0.0, 1.0) * u64::MAX as f64) as u64,
+            payload_size: opts.payload_size,
+        },
+        OtlpHandle { counters, task },
+    ))
+}
+
+impl EventSink for OtlpSink {
+    fn name(&self) -> &'static str {
+        "otlp"
+    }
+
+    fn write(&mut self, ev: &LogEvent) -> anyhow::Result<()> {
+        if !matches!(ev.event, EventKind::Send | EventKind::Recv) {
+            return Ok(());
+        }
+        let (Some(seq), Some(test_id)) = (ev.seq, test_id(ev)) else {
+            return Ok(());
+        };
+        if sample_hash(&test_id, seq) > self.threshold {
+            return Ok(());
+        }
+        match self.tx.try_send(span(ev, &test_id, seq, self.payload_size)) {
+            Ok(()) => {
+                self.counters.pending.fetch_add(1, Ordering::Relaxed);
+            }
+            Err(_) => {
+                self.counters.dropped.fetch_add(1, Ordering::Relaxed);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl OtlpHandle {
+    /// Give queued spans up to `wait` to go out, stop the exporter and
+    /// report what was exported.
+    pub async fn finish(self, wait: Duration) {
+        let deadline = Instant::now() + wait;
+        while self.counters.pending.load(Ordering::Relaxed) > 0 && Instant::now() < deadline {
+            sleep(Duration::from_millis(50)).await;
+        }
+        self.task.abort();
+        diag!(
+            "otlp: {} spans exported, {} dropped",
+            self.counters.exported.load(Ordering::Relaxed),
+            self.counters.dropped.load(Ordering::Relaxed)
+                + self.counters.pending.load(Ordering::Relaxed)
+        );
+    }
+}
+
+/// Collect spans into batches of `BATCH` (or whatever arrived within
+/// `FLUSH_MS`) and post each once until the queue closes.
+async fn post_loop(
+    client: reqwest::Client,
+    url: reqwest::Url,
+    resource: Vec<Value>,
+    mut rx: mpsc::Receiver<Value>,
+    counters: Arc<Counters>,
+) {
+    let mut warned = false;
+    while let Some(first) = rx.recv().await {
+        let mut spans = vec![first];
+        let deadline = Instant::now() + Duration::from_millis(FLUSH_MS);
+        while spans.len() < BATCH {
+            tokio::select! {
+                span = rx.recv() => match span {
+                    Some(span) => spans.push(span),
+                    None => break,
+                },
+                _ = sleep_until(deadline) => break,
+            }
+        }
+
+        let n = spans.len() as u64;
+        let body = json!({
+            "resourceSpans": [{
+                "resource": { "attributes": resource },
+                "scopeSpans": [{
+                    "scope": { "name": "iroh-gossip-metrics", "version": env!("CARGO_PKG_VERSION") },
+                    "spans": spans,
+                }],
+            }],
+        });
+        let res = client
+            .post(url.clone())
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(body.to_string())
+            .send()
+            .await;
+        match res.and_then(|r| r.error_for_status()) {
+            Ok(_) => {
+                counters.exported.fetch_add(n, Ordering::Relaxed);
+            }
+            Err(e) => {
+                // One warning per run; the rest shows in the exit counts.
+                if !warned {
+                    warn!("otlp export failed: {e}");
+                    warned = true;
+                }
+                counters.dropped.fetch_add(n, Ordering::Relaxed);
+            }
+        }
+        counters.pending.fetch_sub(n, Ordering::Relaxed);
+    }
+}
+
+/// The `test_id` extra of a send/recv event.
+fn test_id(ev: &LogEvent) -> Option<[u8; 16]> {
+    let hex_id = ev.extra.get("test_id")?.as_str()?;
+    hex::decode(hex_id).ok()?.try_into().ok()
+}
+
+/// FNV-1a, the same on every host.
+fn fnv1a(bytes: impl IntoIterator<Item = u8>) -> u64 {
+    let mut h: u64 = 0xcbf2_9ce4_8422_2325;
+    for b in bytes {
+        h ^= u64::from(b);
+        h = h.wrapping_mul(0x0000_0100_0000_01b3);
+    }
+    h
+}
+
+/// Sampling hash of `(test_id, seq)`, compared against the threshold.
+fn sample_hash(test_id: &[u8; 16], seq: u64) -> u64 {
+    fnv1a(test_id.iter().copied().chain(seq.to_le_bytes()))
+}
+
+/// Span ID of the send of `seq` (never zero, which OTLP treats as unset).
+fn send_span_id(seq: u64) -> [u8; 8] {
+    seq.wrapping_add(1).to_be_bytes()
+}
+
+/// Span ID of one receiver's delivery of `seq`.
+fn recv_span_id(peer_id: &str, seq: u64) -> [u8; 8] {
+    fnv1a(peer_id.bytes().chain(seq.to_le_bytes()))
+        .max(1)
+        .to_be_bytes()
+}
+
+/// Build the OTLP JSON span of a send or recv event.
+///
+/// A send span covers the broadcast call (`broadcast_lat_ms`, when known); a
+/// recv span runs from the message's send time to its arrival.
+fn span(ev: &LogEvent, test_id: &[u8; 16], seq: u64, payload_size: usize) -> Value {
+    let extra_u64 = |key: &str| ev.extra.get(key).and_then(Value::as_u64);
+    let mut attributes = vec![attr("seq", json!({ "intValue": seq.to_string() }))];
+    let (name, kind, span_id, parent, start_ms) = if ev.event == EventKind::Send {
+        attributes.push(attr(
+            "size",
+            json!({ "intValue": payload_size.to_string() }),
+        ));
+        if let Some(wire) = extra_u64("compressed_size") {
+            attributes.push(attr(
+                "compressed_size",
+                json!({ "intValue": wire.to_string() }),
+            ));
+        }
+        let lat = ev
+            .extra
+            .get("broadcast_lat_ms")
+            .and_then(Value::as_f64)
+            .unwrap_or(0.0);
+        (
+            "send",
+            SPAN_KIND_PRODUCER,
+            send_span_id(seq),
+            None,
+            ev.ts_ms as f64 - lat,
+        )
+    } else {
+        let lat_ms = ev.lat_ms.unwrap_or(0);
+        attributes.push(attr("lat_ms", json!({ "intValue": lat_ms.to_string() })));
+        attributes.push(attr("peer_id", json!({ "stringValue": ev.peer_id })));
+        if let Some(ldh) = ev.ldh {
+            attributes.push(attr("ldh", json!({ "intValue": ldh.to_string() })));
+        }
+        (
+            "recv",
+            SPAN_KIND_CONSUMER,
+            recv_span_id(ev.peer_id, seq),
+            Some(send_span_id(seq)),
+            ev.ts_ms.saturating_sub(lat_ms) as f64,
+        )
+    };
+
+    let mut span = json!({
+        "traceId": hex::encode(test_id),
+        "spanId": hex::encode(span_id),
+        "name": name,
+        "kind": kind,
+        "startTimeUnixNano": ((start_ms.max(0.0) * 1e6) as u64).to_string(),
+        "endTimeUnixNano": (ev.ts_ms * 1_000_000).to_string(),
+        "attributes": attributes,
+    });
+    if let Some(parent) = parent {
+        span["parentSpanId"] = hex::encode(parent).into();
+    }
+    span
+}
+
+/// One OTLP key/value attribute.
+fn attr(key: &str, value: Value) -> Value {
+    json!({ "key": key, "value": value })
+}
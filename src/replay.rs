@@ -0,0 +1,84 @@
+//! Replay traces (`--replay`): send sizes or payloads at recorded offsets
+//! instead of a constant-rate synthetic stream.
+//!
+//! A trace is JSONL, one message per line: `{"offset_ms": 120, "size": 512}`
+//! pads the message to `size` bytes like `--size`, `{"offset_ms": 120,
+//! "payload": "<base64>"}` carries the given bytes as its padding. Offsets
+//! count from the first send and must not decrease. The whole trace is read and
+//! checked up front, so a bad line fails the run before anything is sent.
+
+use crate::frame;
+use base64::Engine;
+use serde::Deserialize;
+use std::io::{BufRead, BufReader, Read};
+
+/// One message of a trace.
+#[derive(Debug, Clone)]
+pub struct ReplayEntry {
+    /// Intended send time, from the start of the replay.
+    pub offset_ms: u64,
+    pub size: usize,
+    /// Bytes carried as the message padding (None = pad to `size`).
+    pub payload: Option<Vec<u8>>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields)]
+struct Line {
+    offset_ms: u64,
+    size: Option<usize>,
+    payload: Option<String>,
+}
+
+/// Read and validate a trace from `path`, or from stdin for `-`.
+pub fn load(path: &str) -> anyhow::Result<Vec<ReplayEntry>> {
+    let input: Box<dyn Read> = match path {
+        "-" => Box::new(std::io::stdin()),
+        _ => Box::new(
+            std::fs::File::open(path)
+                .map_err(|e| anyhow::anyhow!("cannot open --replay {path}: {e}"))?,
+        ),
+    };
+    let mut entries: Vec<ReplayEntry> = Vec::new();
+    for (idx, line) in BufReader::new(input).lines().enumerate() {
+        let line = line?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let at = |msg: String| anyhow::anyhow!("--replay {path} line {}: {msg}", idx + 1);
+        let parsed: Line = serde_json::from_str(&line).map_err(|e| at(e.to_string()))?;
+        let (size, payload) = match (parsed.size, parsed.payload) {
+            (Some(size), None) => (size, None),
+            (None, Some(b64)) => {
+                let bytes = base64::engine::general_purpose::STANDARD
+                    .decode(b64.trim())
+                    .map_err(|e| at(format!("invalid base64 payload: {e}")))?;
+                (bytes.len(), Some(bytes))
+            }
+            _ => return Err(at("expected exactly one of size and payload".to_string())),
+        };
+        if size > frame::MAX_BODY {
+            return Err(at(format!(
+                "{size} bytes exceed the {} byte message limit",
+                frame::MAX_BODY
+            )));
+        }
+        if let Some(prev) = entries.last()
+            && parsed.offset_ms < prev.offset_ms
+        {
+            return Err(at(format!(
+                "offset_ms {} is before the previous {}",
+                parsed.offset_ms, prev.offset_ms
+            )));
+        }
+        entries.push(ReplayEntry {
+            offset_ms: parsed.offset_ms,
+            size,
+            payload,
+        });
+    }
+    if entries.is_empty() {
+        anyhow::bail!("--replay {path} has no messages");
+    }
+    Ok(entries)
+}
@@ -0,0 +1,1986 @@
+//! Offline analysis of summary JSON files written by receiver runs.
+
+use crate::metrics::{
+    EventKind, EventSink, LOG_SCHEMA, LatencyHistogram, LogEvent, LogHeader,
+    SUMMARY_SCHEMA_VERSION, Summary,
+};
+use crate::util::BuildInfo;
+use anyhow::Context;
+use serde::Serialize;
+use serde_json::Value;
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+/// Mean, sample standard deviation and range of one numeric field.
+#[derive(Debug, Serialize)]
+pub struct FieldStats {
+    pub mean: f64,
+    pub stddev: f64,
+    pub min: f64,
+    pub max: f64,
+    pub n: usize,
+}
+
+impl FieldStats {
+    fn from_samples(samples: &[f64]) -> Self {
+        let n = samples.len();
+        let mean = samples.iter().sum::<f64>() / n as f64;
+        let stddev = if n < 2 {
+            0.0
+        } else {
+            let var = samples.iter().map(|v| (v - mean).powi(2)).sum::<f64>() / (n - 1) as f64;
+            var.sqrt()
+        };
+        Self {
+            mean,
+            stddev,
+            min: samples.iter().copied().fold(f64::INFINITY, f64::min),
+            max: samples.iter().copied().fold(f64::NEG_INFINITY, f64::max),
+            n,
+        }
+    }
+}
+
+/// Latency percentiles over the merged histograms of all runs.
+#[derive(Debug, Serialize)]
+pub struct MergedLatency {
+    pub runs: usize,
+    pub samples: u64,
+    pub p50: Option<u64>,
+    pub p90: Option<u64>,
+    pub p99: Option<u64>,
+    pub max: Option<u64>,
+}
+
+/// Result of `aggregate`: per-field statistics keyed by dotted field path
+/// (e.g. `delivery_rate`, `repair.nacks_sent`, `lat_percentiles.p99`).
+#[derive(Debug, Serialize)]
+pub struct Aggregate {
+    pub files: usize,
+    pub fields: BTreeMap<String, FieldStats>,
+    pub lat_merged: Option<MergedLatency>,
+}
+
+/// Expand directories (`*.json` inside) and glob patterns into a sorted file list.
+pub fn expand_inputs(inputs: &[String]) -> anyhow::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    for input in inputs {
+        let path = Path::new(input);
+        if path.is_dir() {
+            let pattern = path.join("*.json");
+            files.extend(glob::glob(&pattern.to_string_lossy())?.filter_map(Result::ok));
+        } else if path.exists() {
+            files.push(path.to_path_buf());
+        } else {
+            let matched: Vec<_> = glob::glob(input)
+                .with_context(|| format!("invalid glob pattern {input}"))?
+                .filter_map(Result::ok)
+                .collect();
+            if matched.is_empty() {
+                eprintln!("aggregate: {input} matched no files");
+            }
+            files.extend(matched);
+        }
+    }
+    files.sort();
+    files.dedup();
+    Ok(files)
+}
+
+/// Load a file as JSON, warning about schema versions other than the current one.
+/// A JSONL log stands for the last final receiver summary embedded in it (see
+/// `embedded_summary`).
+///
+/// Returns None for files that are not receiver summaries, such as sender
+/// summaries or scenario manifests in the same directory.
+fn load_summary_value(path: &Path) -> anyhow::Result<Option<Value>> {
+    let text =
+        std::fs::read_to_string(path).with_context(|| format!("reading {}", path.display()))?;
+    let value: Value = match serde_json::from_str(&text) {
+        Ok(value) => value,
+        Err(e) => match embedded_summary(&text) {
+            Some(value) => value,
+            None => return Err(e).with_context(|| format!("parsing {}", path.display())),
+        },
+    };
+    if value.get("received_unique").is_none() {
+        return Ok(None);
+    }
+    let version = value
+        .get("schema_version")
+        .and_then(Value::as_u64)
+        .unwrap_or(0);
+    if version != SUMMARY_SCHEMA_VERSION as u64 {
+        eprintln!(
+            "{} has schema version {version}, expected {SUMMARY_SCHEMA_VERSION}",
+            path.display()
+        );
+    }
+    Ok(Some(value))
+}
+
+/// The last receiver `summary` event of a JSONL log that is not a snapshot,
+/// as written at the end of every test.
+fn embedded_summary(log: &str) -> Option<Value> {
+    log.lines()
+        .rev()
+        .filter_map(|line| serde_json::from_str::<crate::metrics::LogEvent>(line).ok())
+        .find(|ev| {
+            ev.event == EventKind::Summary
+                && ev.role == "receiver"
+                && ev.extra.get("snapshot").is_none()
+        })
+        .map(|ev| ev.extra)
+}
+
+/// Collect every numeric leaf under `value` into `out`, keyed by dotted path.
+fn collect_numbers(prefix: &str, value: &Value, out: &mut BTreeMap<String, Vec<f64>>) {
+    match value {
+        Value::Number(n) => {
+            if let Some(v) = n.as_f64() {
+                out.entry(prefix.to_string()).or_default().push(v);
+            }
+        }
+        Value::Object(map) => {
+            for (key, v) in map {
+                if prefix.is_empty() && (key == "schema_version" || key == "lat_histogram") {
+                    continue;
+                }
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                collect_numbers(&path, v, out);
+            }
+        }
+        // null (missing value), bools, strings and arrays are not aggregated
+        _ => {}
+    }
+}
+
+/// Aggregate the summaries in `files`.
+pub fn aggregate(files: &[PathBuf]) -> anyhow::Result<Aggregate> {
+    let mut samples = BTreeMap::new();
+    let mut merged: Option<LatencyHistogram> = None;
+    let mut loaded = 0;
+    let mut with_histogram = 0;
+
+    for path in files {
+        let Some(value) = load_summary_value(path)? else {
+            eprintln!(
+                "aggregate: skipping {} (not a receiver summary)",
+                path.display()
+            );
+            continue;
+        };
+        loaded += 1;
+        collect_numbers("", &value, &mut samples);
+
+        // The histogram merge needs the typed summary; older layouts just skip it.
+        match serde_json::from_value::<Summary>(value) {
+            Ok(summary) => {
+                if let Some(hist) = summary.lat_histogram {
+                    merged.get_or_insert_with(Default::default).merge(&hist);
+                    with_histogram += 1;
+                }
+            }
+            Err(e) => eprintln!(
+                "aggregate: {} does not match the current Summary layout ({e}); \
+                 no histogram merge for it",
+                path.display()
+            ),
+        }
+    }
+
+    Ok(Aggregate {
+        files: loaded,
+        fields: samples
+            .into_iter()
+            .map(|(k, v)| (k, FieldStats::from_samples(&v)))
+            .collect(),
+        lat_merged: merged.map(|h| MergedLatency {
+            runs: with_histogram,
+            samples: h.count(),
+            p50: h.quantile(0.50),
+            p90: h.quantile(0.90),
+            p99: h.quantile(0.99),
+            max: h.quantile(1.0),
+        }),
+    })
+}
+
+/// Exit code for runs whose threshold checks (`--fail-if`, `--assert-*`) failed.
+pub const EXIT_THRESHOLD_FAILED: i32 = 3;
+
+/// Summary fields compared side by side (dotted paths into the summary JSON).
+const COMPARE_FIELDS: &[&str] = &[
+    "delivery_rate",
+    "duplicate_rate",
+    "lat_p50",
+    "lat_p90",
+    "lat_p99",
+    "lat_max",
+    "ldh_p50",
+    "ldh_p90",
+    "ldh_p99",
+    "ldh_max",
+    "downtime_total_ms",
+    "downtime_periods",
+    "downtime_max_ms",
+    "avg_connected_peers",
+    "join_wait_ms",
+    "join_breakdown.bind_ms",
+    "join_breakdown.discovery_ms",
+    "join_breakdown.subscribe_ms",
+    "join_breakdown.first_neighbor_ms",
+    "join_breakdown.joined_ms",
+];
+
+/// One compared metric; values missing from a file are None (shown as n/a).
+#[derive(Debug, Serialize)]
+pub struct CompareRow {
+    pub metric: String,
+    pub a: Option<f64>,
+    pub b: Option<f64>,
+    pub delta: Option<f64>,
+    pub rel_delta: Option<f64>,
+}
+
+impl CompareRow {
+    fn new(metric: String, a: Option<f64>, b: Option<f64>) -> Self {
+        let delta = a.zip(b).map(|(a, b)| b - a);
+        let rel_delta = a.zip(delta).and_then(|(a, d)| (a != 0.0).then(|| d / a));
+        Self {
+            metric,
+            a,
+            b,
+            delta,
+            rel_delta,
+        }
+    }
+
+    /// Value addressed by a threshold suffix (`_a`, `_b`, `_delta`, `_rel_delta`).
+    fn value(&self, part: ThresholdPart) -> Option<f64> {
+        match part {
+            ThresholdPart::A => self.a,
+            ThresholdPart::B => self.b,
+            ThresholdPart::Delta => self.delta,
+            ThresholdPart::RelDelta => self.rel_delta,
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum ThresholdPart {
+    A,
+    B,
+    Delta,
+    RelDelta,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum CmpOp {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+    Eq,
+    Ne,
+}
+
+impl CmpOp {
+    fn holds(self, l: f64, r: f64) -> bool {
+        match self {
+            CmpOp::Lt => l < r,
+            CmpOp::Le => l <= r,
+            CmpOp::Gt => l > r,
+            CmpOp::Ge => l >= r,
+            CmpOp::Eq => l == r,
+            CmpOp::Ne => l != r,
+        }
+    }
+}
+
+/// A `--fail-if` expression such as `delivery_rate_delta < -0.02`.
+#[derive(Debug, Clone)]
+pub struct Threshold {
+    expr: String,
+    metric: String,
+    part: ThresholdPart,
+    op: CmpOp,
+    value: f64,
+}
+
+/// Parse `<metric>_{a,b,delta,rel_delta} <op> <number>`, used as clap value parser.
+pub fn parse_threshold(s: &str) -> Result<Threshold, String> {
+    const OPS: [(&str, CmpOp); 6] = [
+        ("<=", CmpOp::Le),
+        (">=", CmpOp::Ge),
+        ("==", CmpOp::Eq),
+        ("!=", CmpOp::Ne),
+        ("<", CmpOp::Lt),
+        (">", CmpOp::Gt),
+    ];
+    let (pos, op_str, op) = OPS
+        .iter()
+        .filter_map(|&(o, op)| s.find(o).map(|p| (p, o, op)))
+        .min_by_key(|&(p, o, _)| (p, std::cmp::Reverse(o.len())))
+        .ok_or_else(|| format!("'{s}' has no comparison operator (<, <=, >, >=, ==, !=)"))?;
+    let lhs = s[..pos].trim();
+    let rhs = s[pos + op_str.len()..].trim();
+    let value: f64 = rhs
+        .parse()
+        .map_err(|_| format!("'{rhs}' in '{s}' is not a number"))?;
+
+    let (metric, part) = [
+        ("_rel_delta", ThresholdPart::RelDelta),
+        ("_delta", ThresholdPart::Delta),
+        ("_a", ThresholdPart::A),
+        ("_b", ThresholdPart::B),
+    ]
+    .iter()
+    .find_map(|&(suffix, part)| lhs.strip_suffix(suffix).map(|m| (m, part)))
+    .ok_or_else(|| {
+        format!("'{lhs}' must end in _a, _b, _delta or _rel_delta (e.g. delivery_rate_delta)")
+    })?;
+    if metric.is_empty() {
+        return Err(format!("'{lhs}' names no metric"));
+    }
+
+    Ok(Threshold {
+        expr: s.trim().to_string(),
+        metric: metric.to_string(),
+        part,
+        op,
+        value,
+    })
+}
+
+/// Result of `compare`.
+#[derive(Debug, Serialize)]
+pub struct Comparison {
+    pub a: PathBuf,
+    pub b: PathBuf,
+    pub rows: Vec<CompareRow>,
+    /// `--fail-if` expressions that held (or could not be evaluated).
+    pub failed: Vec<String>,
+    /// Differences that make the comparison suspect, e.g. different builds.
+    pub warnings: Vec<String>,
+}
+
+impl Comparison {
+    /// Render the rows as an aligned text table.
+    pub fn table(&self) -> String {
+        fn cell(v: Option<f64>) -> String {
+            v.map_or_else(|| "n/a".to_string(), |v| format!("{v:.4}"))
+        }
+        fn pct(v: Option<f64>) -> String {
+            v.map_or_else(|| "n/a".to_string(), |v| format!("{:+.2}%", v * 100.0))
+        }
+
+        let width = self
+            .rows
+            .iter()
+            .map(|r| r.metric.len())
+            .max()
+            .unwrap_or(6)
+            .max(6);
+        let mut out = format!(
+            "a: {}\nb: {}\n{:<width$}  {:>12}  {:>12}  {:>12}  {:>9}\n",
+            self.a.display(),
+            self.b.display(),
+            "metric",
+            "a",
+            "b",
+            "delta",
+            "rel"
+        );
+        for r in &self.rows {
+            out.push_str(&format!(
+                "{:<width$}  {:>12}  {:>12}  {:>12}  {:>9}\n",
+                r.metric,
+                cell(r.a),
+                cell(r.b),
+                cell(r.delta),
+                pct(r.rel_delta)
+            ));
+        }
+        for w in &self.warnings {
+            out.push_str(&format!("WARNING: {w}\n"));
+        }
+        for f in &self.failed {
+            out.push_str(&format!("FAIL: {f}\n"));
+        }
+        out
+    }
+}
+
+/// Look up a numeric value by dotted path (`lat_percentiles.p99`).
+fn lookup(value: &Value, path: &str) -> Option<f64> {
+    path.split('.')
+        .try_fold(value, |v, key| v.get(key))
+        .and_then(Value::as_f64)
+}
+
+/// Compare two summary files and evaluate `thresholds` against the rows.
+pub fn compare(a: &Path, b: &Path, thresholds: &[Threshold]) -> anyhow::Result<Comparison> {
+    let load = |p: &Path| {
+        load_summary_value(p)?.with_context(|| format!("{} is not a receiver summary", p.display()))
+    };
+    let (va, vb) = (load(a)?, load(b)?);
+
+    let mut metrics: Vec<String> = COMPARE_FIELDS.iter().map(|s| s.to_string()).collect();
+    for map in ["lat_percentiles", "ldh_percentiles"] {
+        let mut keys: Vec<String> = [&va, &vb]
+            .iter()
+            .filter_map(|v| v.get(map).and_then(Value::as_object))
+            .flat_map(|m| m.keys().map(|k| format!("{map}.{k}")))
+            .collect();
+        keys.sort();
+        keys.dedup();
+        metrics.extend(keys);
+    }
+    // Thresholds may address any numeric field, not only the default rows.
+    for t in thresholds {
+        if !metrics.contains(&t.metric) {
+            metrics.push(t.metric.clone());
+        }
+    }
+
+    let rows: Vec<CompareRow> = metrics
+        .into_iter()
+        .map(|m| {
+            let (x, y) = (lookup(&va, &m), lookup(&vb, &m));
+            CompareRow::new(m, x, y)
+        })
+        .collect();
+
+    let failed = thresholds
+        .iter()
+        .filter_map(|t| {
+            let row = rows.iter().find(|r| r.metric == t.metric)?;
+            match row.value(t.part) {
+                Some(v) if t.op.holds(v, t.value) => Some(format!("{} (value {v:.4})", t.expr)),
+                Some(_) => None,
+                None => Some(format!("{} (value n/a)", t.expr)),
+            }
+        })
+        .collect();
+
+    let mut warnings = Vec::new();
+    let build = |v: &Value| {
+        v.get("build")
+            .and_then(|b| serde_json::from_value::<BuildInfo>(b.clone()).ok())
+    };
+    match (build(&va), build(&vb)) {
+        (Some(x), Some(y))
+            if (&x.version, &x.iroh, &x.iroh_gossip, &x.git_hash)
+                != (&y.version, &y.iroh, &y.iroh_gossip, &y.git_hash) =>
+        {
+            warnings.push(format!(
+                "version mismatch: a {}, b {}",
+                x.label(),
+                y.label()
+            ));
+        }
+        (Some(_), None) | (None, Some(_)) => {
+            warnings.push("build info recorded in only one summary".to_string());
+        }
+        _ => {}
+    }
+
+    Ok(Comparison {
+        a: a.to_path_buf(),
+        b: b.to_path_buf(),
+        rows,
+        failed,
+        warnings,
+    })
+}
+
+/// Receiver `--assert-*` limits checked against the final summary.
+#[derive(Debug, Clone, Default)]
+pub struct Assertions {
+    pub delivery_min: Option<f64>,
+    pub lat_p99_max: Option<u64>,
+    pub duplicates_max: Option<u64>,
+    pub worst_bucket_min: Option<f64>,
+}
+
+/// Outcome of one assertion (`actual` is None if the summary has no value).
+#[derive(Debug, Serialize)]
+pub struct AssertOutcome {
+    pub name: &'static str,
+    pub limit: Option<f64>,
+    pub actual: Option<f64>,
+    pub passed: bool,
+}
+
+impl Assertions {
+    pub fn is_empty(&self) -> bool {
+        self.delivery_min.is_none()
+            && self.lat_p99_max.is_none()
+            && self.duplicates_max.is_none()
+            && self.worst_bucket_min.is_none()
+    }
+
+    /// Evaluate all configured assertions; a failed join is an implicit failure.
+    pub fn evaluate(&self, summary: &Summary) -> Vec<AssertOutcome> {
+        let mut out = vec![AssertOutcome {
+            name: "joined",
+            limit: None,
+            actual: Some(if summary.joined { 1.0 } else { 0.0 }),
+            passed: summary.joined,
+        }];
+        if let Some(min) = self.delivery_min {
+            out.push(AssertOutcome {
+                name: "delivery_min",
+                limit: Some(min),
+                actual: Some(summary.delivery_rate),
+                passed: summary.delivery_rate >= min,
+            });
+        }
+        if let Some(max) = self.lat_p99_max {
+            out.push(AssertOutcome {
+                name: "lat_p99_max",
+                limit: Some(max as f64),
+                actual: summary.lat_p99.map(|v| v as f64),
+                passed: summary.lat_p99.is_some_and(|v| v <= max),
+            });
+        }
+        if let Some(max) = self.duplicates_max {
+            out.push(AssertOutcome {
+                name: "duplicates_max",
+                limit: Some(max as f64),
+                actual: Some(summary.duplicates as f64),
+                passed: summary.duplicates <= max,
+            });
+        }
+        if let Some(min) = self.worst_bucket_min {
+            out.push(AssertOutcome {
+                name: "worst_bucket_min",
+                limit: Some(min),
+                actual: summary.worst_bucket_delivery_rate,
+                passed: summary.worst_bucket_delivery_rate.is_some_and(|v| v >= min),
+            });
+        }
+        out
+    }
+}
+
+/// Per-receiver delivery in a merged report.
+#[derive(Debug, Serialize)]
+pub struct ReceiverCoverage {
+    pub log: PathBuf,
+    pub peer_id: String,
+    pub run_id: Option<String>,
+    pub received: u64,
+    pub delivery_rate: f64,
+    /// Recv events repeating a `(test_id, seq)` of this log.
+    pub duplicates: u64,
+    /// Recv events below the highest seq of their test seen so far (as `Stats` counts them).
+    pub out_of_order: u64,
+}
+
+/// Duplicates and reorders the sender injected (`inject_dup`/`inject_reorder` events).
+#[derive(Debug, Default, Serialize)]
+pub struct Injected {
+    pub dups: u64,
+    pub reorders: u64,
+}
+
+/// Cross-receiver delivery matrix built by `merge`.
+#[derive(Debug, Serialize)]
+pub struct MergeReport {
+    /// "sender" if send events defined the universe, "total" otherwise.
+    pub universe_from: &'static str,
+    pub messages: u64,
+    pub receivers: Vec<ReceiverCoverage>,
+    /// `coverage[k]` = messages that reached exactly k receivers.
+    pub coverage: Vec<u64>,
+    pub received_by_nobody: u64,
+    pub received_by_all: u64,
+    /// Messages grouped by the sender run ID of their test (when known).
+    pub by_run: BTreeMap<String, RunCoverage>,
+    /// Sender-side ground truth, if a sender log with injection events was merged.
+    pub injected: Option<Injected>,
+}
+
+/// Delivery of one sender run in a merged report.
+#[derive(Debug, Default, Serialize)]
+pub struct RunCoverage {
+    pub messages: u64,
+    pub received_by_nobody: u64,
+    pub received_by_all: u64,
+}
+
+impl MergeReport {
+    /// Render the report as a text table.
+    pub fn table(&self) -> String {
+        let pct = |n: u64| {
+            if self.messages == 0 {
+                0.0
+            } else {
+                n as f64 * 100.0 / self.messages as f64
+            }
+        };
+        let mut out = format!(
+            "{} messages (universe from {}), {} receivers\n\n{:>10}  {:>10}  {:>8}\n",
+            self.messages,
+            self.universe_from,
+            self.receivers.len(),
+            "receivers",
+            "messages",
+            "share"
+        );
+        for (k, &n) in self.coverage.iter().enumerate() {
+            out.push_str(&format!("{k:>10}  {n:>10}  {:>7.2}%\n", pct(n)));
+        }
+        out.push_str(&format!("\n{:>10}  {:>8}  log\n", "received", "rate"));
+        for r in &self.receivers {
+            out.push_str(&format!(
+                "{:>10}  {:>8.4}  {}\n",
+                r.received,
+                r.delivery_rate,
+                r.log.display()
+            ));
+        }
+        if !self.by_run.is_empty() {
+            out.push_str(&format!(
+                "\n{:<16}  {:>10}  {:>10}  {:>10}\n",
+                "sender run", "messages", "by nobody", "by all"
+            ));
+            for (run_id, c) in &self.by_run {
+                out.push_str(&format!(
+                    "{run_id:<16}  {:>10}  {:>10}  {:>10}\n",
+                    c.messages, c.received_by_nobody, c.received_by_all
+                ));
+            }
+        }
+        if let Some(inj) = &self.injected {
+            out.push_str(&format!(
+                "\ninjected: {} duplicates, {} reorders\n{:>10}  {:>8}  {:>10}  {:>8}  log\n",
+                inj.dups, inj.reorders, "dups", "delta", "reordered", "delta"
+            ));
+            for r in &self.receivers {
+                out.push_str(&format!(
+                    "{:>10}  {:>+8}  {:>10}  {:>+8}  {}\n",
+                    r.duplicates,
+                    r.duplicates as i64 - inj.dups as i64,
+                    r.out_of_order,
+                    r.out_of_order as i64 - inj.reorders as i64,
+                    r.log.display()
+                ));
+            }
+        }
+        out
+    }
+}
+
+/// Join `recv` events of several receiver logs by `(test_id, seq)`.
+///
+/// Sender logs among `logs` define the universe of sent messages; without one,
+/// the `total` carried by recv events does, clamped to the highest seq any
+/// receiver logged (the total is untrusted; a tail lost everywhere is not
+/// counted then). Logs are streamed and only a
+/// receiver bitmask per sequence is kept, so at most 64 receivers are supported.
+/// Repaired deliveries (`repair_recv`) are not counted.
+///
+/// Messages are also grouped by the sender run of their test, known from the
+/// `run_id` of sender logs or from heartbeats seen by the receivers.
+///
+/// If the sender injected duplicates or reorders, each receiver's observed
+/// counts are reported next to that ground truth.
+pub fn merge(logs: &[PathBuf]) -> anyhow::Result<MergeReport> {
+    use std::collections::{HashMap, HashSet};
+
+    let mut masks: HashMap<(String, u64), u64> = HashMap::new();
+    let mut sent: HashSet<(String, u64)> = HashSet::new();
+    let mut totals: BTreeMap<String, u64> = BTreeMap::new();
+    let mut seq_end: HashMap<String, u64> = HashMap::new();
+    let mut receivers: Vec<(PathBuf, String, Option<String>)> = Vec::new();
+    let mut run_of_test: HashMap<String, String> = HashMap::new();
+    let mut injected: Option<Injected> = None;
+    let mut observed: Vec<(u64, u64)> = Vec::new();
+
+    for path in logs {
+        let mut role = None;
+        let mut peer_id = String::new();
+        let mut run_id = None;
+        let bit = receivers.len();
+        let mut seen: HashSet<(String, u64)> = HashSet::new();
+        let mut max_seq: HashMap<String, u64> = HashMap::new();
+        let (mut duplicates, mut out_of_order) = (0u64, 0u64);
+        crate::util::for_each_log_event(path, |line_no, ev| {
+            let ev = ev.with_context(|| format!("{}:{line_no}", path.display()))?;
+            role.get_or_insert_with(|| ev.role.to_string());
+            if run_id.is_none() {
+                run_id = ev.run_id.map(str::to_string);
+            }
+            let test_id = ev
+                .extra
+                .get("test_id")
+                .and_then(Value::as_str)
+                .unwrap_or_default();
+            match (ev.role, ev.event, ev.seq) {
+                ("sender", EventKind::Send, Some(seq)) => {
+                    sent.insert((test_id.to_string(), seq));
+                    if let Some(run) = ev.run_id {
+                        run_of_test.insert(test_id.to_string(), run.to_string());
+                    }
+                }
+                ("sender", EventKind::InjectDup, _) => {
+                    injected.get_or_insert_default().dups += 1;
+                }
+                ("sender", EventKind::InjectReorder, _) => {
+                    injected.get_or_insert_default().reorders += 1;
+                }
+                ("receiver", EventKind::Setup, _) => {
+                    if let Some(n) = ev.extra.get("log_sample_recv").and_then(Value::as_u64)
+                        && n > 1
+                    {
+                        eprintln!(
+                            "warning: {} logs only every {n}th recv event, its coverage is understated",
+                            path.display()
+                        );
+                    }
+                }
+                ("receiver", EventKind::Heartbeat, _) => {
+                    if let Some(run) = ev.extra.get("sender_run_id").and_then(Value::as_str) {
+                        run_of_test
+                            .entry(test_id.to_string())
+                            .or_insert_with(|| run.to_string());
+                    }
+                }
+                ("receiver", EventKind::Recv, Some(seq)) => {
+                    if bit >= 64 {
+                        anyhow::bail!("merge supports at most 64 receiver logs");
+                    }
+                    peer_id = ev.peer_id.to_string();
+                    *masks.entry((test_id.to_string(), seq)).or_default() |= 1 << bit;
+                    if !seen.insert((test_id.to_string(), seq)) {
+                        duplicates += 1;
+                    }
+                    match max_seq.get_mut(test_id) {
+                        Some(max) if seq < *max => out_of_order += 1,
+                        Some(max) => *max = seq,
+                        None => {
+                            max_seq.insert(test_id.to_string(), seq);
+                        }
+                    }
+                    if let Some(total) = ev.extra.get("total").and_then(Value::as_u64) {
+                        let t = totals.entry(test_id.to_string()).or_default();
+                        *t = (*t).max(total);
+                    }
+                    let end = seq_end.entry(test_id.to_string()).or_default();
+                    *end = (*end).max(seq.saturating_add(1));
+                }
+                _ => {}
+            }
+            Ok(())
+        })
+        .with_context(|| format!("reading {}", path.display()))?;
+
+        if role.as_deref() == Some("receiver") {
+            receivers.push((path.clone(), peer_id, run_id));
+            observed.push((duplicates, out_of_order));
+        }
+    }
+
+    let k = receivers.len();
+    let mut coverage = vec![0u64; k + 1];
+    let mut per_receiver = vec![0u64; k];
+    let mut by_run: BTreeMap<String, RunCoverage> = BTreeMap::new();
+    let mut count = |test_id: &str, mask: u64| {
+        let hits = mask.count_ones() as usize;
+        coverage[hits] += 1;
+        if let Some(run_id) = run_of_test.get(test_id) {
+            let run = by_run.entry(run_id.clone()).or_default();
+            run.messages += 1;
+            run.received_by_nobody += u64::from(hits == 0);
+            run.received_by_all += u64::from(k > 0 && hits == k);
+        }
+        for (i, n) in per_receiver.iter_mut().enumerate() {
+            if mask & (1 << i) != 0 {
+                *n += 1;
+            }
+        }
+    };
+
+    let universe_from = if sent.is_empty() {
+        for (test_id, &total) in &totals {
+            let end = seq_end.get(test_id).copied().unwrap_or(0);
+            for seq in 0..total.min(end) {
+                count(
+                    test_id,
+                    masks.get(&(test_id.clone(), seq)).copied().unwrap_or(0),
+                );
+            }
+        }
+        "total"
+    } else {
+        for key in &sent {
+            count(&key.0, masks.get(key).copied().unwrap_or(0));
+        }
+        "sender"
+    };
+
+    let messages: u64 = coverage.iter().sum();
+    Ok(MergeReport {
+        universe_from,
+        messages,
+        receivers: receivers
+            .into_iter()
+            .zip(per_receiver)
+            .zip(observed)
+            .map(
+                |(((log, peer_id, run_id), received), (duplicates, out_of_order))| {
+                    ReceiverCoverage {
+                        log,
+                        peer_id,
+                        run_id,
+                        received,
+                        delivery_rate: if messages == 0 {
+                            0.0
+                        } else {
+                            received as f64 / messages as f64
+                        },
+                        duplicates,
+                        out_of_order,
+                    }
+                },
+            )
+            .collect(),
+        received_by_nobody: coverage[0],
+        received_by_all: if k == 0 { 0 } else { coverage[k] },
+        coverage,
+        by_run,
+        injected,
+    })
+}
+
+/// Number of findings listed per category in the validation report.
+const VALIDATE_LIST_MAX: usize = 20;
+
+/// Findings of `validate` for one log file (line numbers are 1-based).
+#[derive(Debug, Default)]
+pub struct Validation {
+    pub lines: usize,
+    pub parse_errors: Vec<(usize, String)>,
+    /// (line, role, previous ts_ms, ts_ms) where time went backwards.
+    pub ts_violations: Vec<(usize, String, u64, u64)>,
+    /// (line, seq) of `send` events repeating a seq of the same test.
+    pub duplicate_sends: Vec<(usize, u64)>,
+    /// (line, seq) of `recv` events with seq >= the largest total seen.
+    pub seq_beyond_total: Vec<(usize, u64)>,
+    pub max_total: Option<u64>,
+    pub has_setup: bool,
+    /// Final `summary` events (snapshots not counted).
+    pub summaries: usize,
+    /// (line, error) of receiver `summary` events that are not a `Summary`.
+    pub bad_summaries: Vec<(usize, String)>,
+    /// `schema` of the header line (None for logs written without one).
+    pub schema: Option<String>,
+    /// Byte offset of a trailing partial line (no newline and not valid JSON).
+    pub truncated_at: Option<u64>,
+}
+
+impl Validation {
+    pub fn is_clean(&self) -> bool {
+        self.parse_errors.is_empty()
+            && self.ts_violations.is_empty()
+            && self.duplicate_sends.is_empty()
+            && self.seq_beyond_total.is_empty()
+            && self.bad_summaries.is_empty()
+            && self.has_setup
+            && self.schema.as_deref().is_none_or(|s| s == LOG_SCHEMA)
+            && self.truncated_at.is_none()
+    }
+
+    /// Human-readable report, listing at most `VALIDATE_LIST_MAX` findings per category.
+    pub fn report(&self, path: &Path) -> String {
+        fn section<T>(out: &mut String, title: &str, items: &[T], fmt: impl Fn(&T) -> String) {
+            if items.is_empty() {
+                return;
+            }
+            out.push_str(&format!("{title}: {}\n", items.len()));
+            for item in items.iter().take(VALIDATE_LIST_MAX) {
+                out.push_str(&format!("  {}\n", fmt(item)));
+            }
+            if items.len() > VALIDATE_LIST_MAX {
+                out.push_str(&format!("  ... {} more\n", items.len() - VALIDATE_LIST_MAX));
+            }
+        }
+
+        let mut out = format!("{}: {} lines\n", path.display(), self.lines);
+        match self.schema.as_deref() {
+            None => out.push_str("no header line (log predates headers)\n"),
+            Some(LOG_SCHEMA) => {}
+            Some(other) => out.push_str(&format!("unsupported log schema {other}\n")),
+        }
+        section(&mut out, "parse errors", &self.parse_errors, |(l, e)| {
+            format!("line {l}: {e}")
+        });
+        section(
+            &mut out,
+            "timestamps going backwards",
+            &self.ts_violations,
+            |(l, role, prev, ts)| format!("line {l}: {role} ts_ms {ts} < previous {prev}"),
+        );
+        section(
+            &mut out,
+            "duplicate send seqs",
+            &self.duplicate_sends,
+            |(l, s)| format!("line {l}: seq {s}"),
+        );
+        section(
+            &mut out,
+            "recv seqs beyond total",
+            &self.seq_beyond_total,
+            |(l, s)| format!("line {l}: seq {s} >= total {}", self.max_total.unwrap_or(0)),
+        );
+        section(
+            &mut out,
+            "unreadable summary events",
+            &self.bad_summaries,
+            |(l, e)| format!("line {l}: {e}"),
+        );
+        if !self.has_setup {
+            out.push_str("missing setup event\n");
+        }
+        match self.summaries {
+            0 => out.push_str("no summary event (run did not finish, or predates them)\n"),
+            1 => {}
+            n => out.push_str(&format!("{n} summary events (one per test)\n")),
+        }
+        if let Some(offset) = self.truncated_at {
+            out.push_str(&format!(
+                "truncated last line at byte {offset} (use --fix-truncated to drop it)\n"
+            ));
+        }
+        if self.is_clean() {
+            out.push_str("clean\n");
+        }
+        out
+    }
+}
+
+/// Stream `path` and check it for parse errors, non-monotonic timestamps per
+/// role, duplicate send seqs, recv seqs beyond the total, unreadable summary
+/// events and a missing setup event.
+pub fn validate(path: &Path) -> anyhow::Result<Validation> {
+    use std::collections::HashSet;
+    use std::io::{BufRead, BufReader};
+
+    let mut reader = BufReader::new(
+        std::fs::File::open(path).with_context(|| format!("opening {}", path.display()))?,
+    );
+    let mut v = Validation::default();
+    let mut last_ts: BTreeMap<String, u64> = BTreeMap::new();
+    let mut sends: HashSet<(String, u64)> = HashSet::new();
+    let mut recv_candidates = Vec::new();
+    let mut line = String::new();
+    let mut offset = 0u64;
+
+    loop {
+        line.clear();
+        let n = reader.read_line(&mut line)?;
+        if n == 0 {
+            break;
+        }
+        v.lines += 1;
+        let line_no = v.lines;
+        let start = offset;
+        offset += n as u64;
+        if line.trim().is_empty() {
+            continue;
+        }
+        if line_no == 1
+            && let Some(header) = LogHeader::parse(line.trim_end())
+        {
+            v.schema = Some(header.schema);
+            continue;
+        }
+
+        let ev: crate::metrics::LogEvent = match serde_json::from_str(line.trim_end()) {
+            Ok(ev) => ev,
+            Err(e) => {
+                if !line.ends_with('\n') {
+                    v.truncated_at = Some(start);
+                } else {
+                    v.parse_errors.push((line_no, e.to_string()));
+                }
+                continue;
+            }
+        };
+
+        if let Some(prev) = last_ts.insert(ev.role.to_string(), ev.ts_ms)
+            && ev.ts_ms < prev
+        {
+            v.ts_violations
+                .push((line_no, ev.role.to_string(), prev, ev.ts_ms));
+        }
+        if ev.event == EventKind::Setup {
+            v.has_setup = true;
+        }
+        if ev.event == EventKind::Summary && ev.extra.get("snapshot").is_none() {
+            v.summaries += 1;
+            if ev.role == "receiver"
+                && let Err(e) = serde_json::from_value::<Summary>(ev.extra.clone())
+            {
+                v.bad_summaries.push((line_no, e.to_string()));
+            }
+        }
+        if let Some(total) = ev.extra.get("total").and_then(Value::as_u64) {
+            v.max_total = Some(v.max_total.map_or(total, |m| m.max(total)));
+        }
+
+        let test_id = ev
+            .extra
+            .get("test_id")
+            .and_then(Value::as_str)
+            .unwrap_or_default();
+        match (ev.event, ev.seq) {
+            (EventKind::Send, Some(seq)) if !sends.insert((test_id.to_string(), seq)) => {
+                v.duplicate_sends.push((line_no, seq));
+            }
+            // Final check against the largest total at the end of the file.
+            (EventKind::Recv, Some(seq)) if v.max_total.is_none_or(|t| seq >= t) => {
+                recv_candidates.push((line_no, seq));
+            }
+            _ => {}
+        }
+    }
+
+    if let Some(total) = v.max_total {
+        v.seq_beyond_total = recv_candidates
+            .into_iter()
+            .filter(|&(_, seq)| seq >= total)
+            .collect();
+    }
+    Ok(v)
+}
+
+/// Drop a trailing partial line found by `validate`.
+pub fn fix_truncated(path: &Path, validation: &Validation) -> anyhow::Result<bool> {
+    let Some(offset) = validation.truncated_at else {
+        return Ok(false);
+    };
+    let file = std::fs::OpenOptions::new()
+        .write(true)
+        .open(path)
+        .with_context(|| format!("opening {} for writing", path.display()))?;
+    file.set_len(offset)?;
+    Ok(true)
+}
+
+/// Options of `export`.
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    /// Rebase timestamps to seconds since the first event (`t_s` column).
+    pub relative_time: bool,
+    /// Emit every Nth latency/send row (1 = all).
+    pub downsample: u64,
+}
+
+/// Lazily created CSV file; files without rows are never written.
+struct CsvOut {
+    path: PathBuf,
+    header: String,
+    file: Option<std::io::BufWriter<std::fs::File>>,
+    rows: u64,
+}
+
+impl CsvOut {
+    fn new(dir: &Path, name: &str, header: String) -> Self {
+        Self {
+            path: dir.join(name),
+            header,
+            file: None,
+            rows: 0,
+        }
+    }
+
+    fn row(&mut self, line: std::fmt::Arguments<'_>) -> anyhow::Result<()> {
+        use std::io::Write;
+        if self.file.is_none() {
+            let mut f = std::io::BufWriter::new(
+                std::fs::File::create(&self.path)
+                    .with_context(|| format!("creating {}", self.path.display()))?,
+            );
+            writeln!(f, "{}", self.header)?;
+            self.file = Some(f);
+        }
+        let f = self.file.as_mut().expect("created above");
+        f.write_fmt(line)?;
+        f.write_all(b"\n")?;
+        self.rows += 1;
+        Ok(())
+    }
+
+    fn finish(self) -> anyhow::Result<Option<(PathBuf, u64)>> {
+        use std::io::Write;
+        match self.file {
+            Some(mut f) => {
+                f.flush()?;
+                Ok(Some((self.path, self.rows)))
+            }
+            None => Ok(None),
+        }
+    }
+}
+
+/// The four CSV series of `export`, fed one event at a time: from a log by
+/// `export`, or live as a run's `--csv-dir` sink.
+pub struct CsvSink {
+    latency: CsvOut,
+    sends: CsvOut,
+    peers: CsvOut,
+    events: CsvOut,
+    opts: ExportOptions,
+    first_ts: Option<u64>,
+    nth_recv: u64,
+    nth_send: u64,
+}
+
+impl CsvSink {
+    /// Series files in `out_dir`, created with their first row.
+    pub fn new(out_dir: &Path, opts: ExportOptions) -> anyhow::Result<Self> {
+        std::fs::create_dir_all(out_dir)
+            .with_context(|| format!("creating output directory {}", out_dir.display()))?;
+        let time_col = if opts.relative_time { "t_s" } else { "ts_ms" };
+        Ok(Self {
+            latency: CsvOut::new(out_dir, "latency.csv", format!("{time_col},seq,lat_ms,ldh")),
+            sends: CsvOut::new(out_dir, "sends.csv", format!("{time_col},seq,total")),
+            peers: CsvOut::new(out_dir, "peers.csv", format!("{time_col},connected")),
+            events: CsvOut::new(out_dir, "events.csv", format!("{time_col},event")),
+            opts,
+            first_ts: None,
+            nth_recv: 0,
+            nth_send: 0,
+        })
+    }
+
+    /// Add the rows of one event.
+    fn row(&mut self, ev: &LogEvent) -> anyhow::Result<()> {
+        // A kind of a newer build: its name is lost, so nothing to export.
+        if ev.event == EventKind::Unknown {
+            return Ok(());
+        }
+        let downsample = self.opts.downsample.max(1);
+        let base = *self.first_ts.get_or_insert(ev.ts_ms);
+        let t = if self.opts.relative_time {
+            format!("{:.3}", ev.ts_ms.saturating_sub(base) as f64 / 1000.0)
+        } else {
+            ev.ts_ms.to_string()
+        };
+        let opt = |v: Option<u64>| v.map(|v| v.to_string()).unwrap_or_default();
+
+        self.events.row(format_args!("{t},{}", ev.event.as_str()))?;
+        match ev.event {
+            EventKind::Recv => {
+                if self.nth_recv.is_multiple_of(downsample) {
+                    self.latency.row(format_args!(
+                        "{t},{},{},{}",
+                        opt(ev.seq),
+                        opt(ev.lat_ms),
+                        opt(ev.ldh.map(u64::from))
+                    ))?;
+                }
+                self.nth_recv += 1;
+            }
+            EventKind::Send => {
+                if self.nth_send.is_multiple_of(downsample) {
+                    let total = ev.extra.get("total").and_then(Value::as_u64);
+                    self.sends
+                        .row(format_args!("{t},{},{}", opt(ev.seq), opt(total)))?;
+                }
+                self.nth_send += 1;
+            }
+            EventKind::NeighborUp | EventKind::NeighborDown | EventKind::NeighborSnapshot => {
+                let connected = ev
+                    .extra
+                    .get("connected")
+                    .or_else(|| ev.extra.get("count"))
+                    .and_then(Value::as_u64);
+                if let Some(c) = connected {
+                    self.peers.row(format_args!("{t},{c}"))?;
+                }
+            }
+            EventKind::Setup
+            | EventKind::Connect
+            | EventKind::ScenarioRun
+            | EventKind::NoJoin
+            | EventKind::JoinConfirmed
+            | EventKind::JoinStage
+            | EventKind::FirstEvent
+            | EventKind::FirstData
+            | EventKind::Heartbeat
+            | EventKind::Lagged
+            | EventKind::RejoinAttempt
+            | EventKind::NetStats
+            | EventKind::Backpressure
+            | EventKind::RateAdjust
+            | EventKind::PayloadBreakdown
+            | EventKind::ReachProbe
+            | EventKind::Coverage
+            | EventKind::RttProbe
+            | EventKind::AddrChange
+            | EventKind::Resource
+            | EventKind::TransportError
+            | EventKind::TaskExited
+            | EventKind::AuthFailure
+            | EventKind::EncryptionMismatch
+            | EventKind::SigReject
+            | EventKind::RecvForeign
+            | EventKind::ConcurrentSenderDetected
+            | EventKind::RecvStale
+            | EventKind::RecvImplausibleSeq
+            | EventKind::PadMismatch
+            | EventKind::SizeMismatch
+            | EventKind::RecvOversized
+            | EventKind::Nack
+            | EventKind::RepairRecv
+            | EventKind::RepairServed
+            | EventKind::MaxRuntimeReached
+            | EventKind::WatchdogStall
+            | EventKind::Assert
+            | EventKind::IrohMetrics
+            | EventKind::InjectDup
+            | EventKind::InjectReorder
+            | EventKind::PauseStart
+            | EventKind::PauseEnd
+            | EventKind::StatsDegraded
+            | EventKind::Summary
+            | EventKind::Control
+            | EventKind::Shutdown
+            | EventKind::Unknown => {}
+        }
+        Ok(())
+    }
+
+    /// Flush the written files; returns them with their row counts.
+    pub fn finish(self) -> anyhow::Result<Vec<(PathBuf, u64)>> {
+        Ok([self.latency, self.sends, self.peers, self.events]
+            .into_iter()
+            .map(CsvOut::finish)
+            .collect::<anyhow::Result<Vec<_>>>()?
+            .into_iter()
+            .flatten()
+            .collect())
+    }
+}
+
+impl EventSink for CsvSink {
+    fn name(&self) -> &'static str {
+        "csv"
+    }
+
+    fn write(&mut self, ev: &LogEvent) -> anyhow::Result<()> {
+        self.row(ev)
+    }
+
+    fn flush(&mut self) {
+        for out in [
+            &mut self.latency,
+            &mut self.sends,
+            &mut self.peers,
+            &mut self.events,
+        ] {
+            if let Some(f) = out.file.as_mut() {
+                let _ = std::io::Write::flush(f);
+            }
+        }
+    }
+}
+
+/// Write tidy CSV series from a sender or receiver log into `out_dir`:
+/// `latency.csv` (recv events), `sends.csv` (send events), `peers.csv`
+/// (neighbor events and snapshots) and `events.csv` (every event).
+/// Returns the written files with their row counts.
+pub fn export(
+    log: &Path,
+    out_dir: &Path,
+    opts: &ExportOptions,
+) -> anyhow::Result<Vec<(PathBuf, u64)>> {
+    let mut sink = CsvSink::new(out_dir, opts.clone())?;
+    crate::util::for_each_log_event(log, |line_no, ev| {
+        let ev = ev.with_context(|| format!("{}:{line_no}", log.display()))?;
+        sink.row(&ev)
+    })
+    .with_context(|| format!("reading {}", log.display()))?;
+    sink.finish()
+}
+
+/// Output format of `report`.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReportFormat {
+    Markdown,
+    Html,
+}
+
+/// Building blocks of a rendered report.
+enum Block {
+    Heading(String),
+    Paragraph(String),
+    Table(Vec<(String, String)>),
+    Pre(String),
+}
+
+/// Number of gap ranges listed in a report.
+const REPORT_GAPS_MAX: usize = 50;
+
+/// Width (characters) of the longest latency histogram bar.
+const HISTOGRAM_WIDTH: usize = 40;
+
+/// Flatten `value` into (dotted key, display value) rows; null renders as n/a.
+fn flatten_rows(prefix: &str, value: &Value, out: &mut Vec<(String, String)>) {
+    match value {
+        Value::Object(map) if !map.is_empty() => {
+            for (key, v) in map {
+                let path = if prefix.is_empty() {
+                    key.clone()
+                } else {
+                    format!("{prefix}.{key}")
+                };
+                flatten_rows(&path, v, out);
+            }
+        }
+        Value::Null => out.push((prefix.to_string(), "n/a".into())),
+        Value::String(s) => out.push((prefix.to_string(), s.clone())),
+        other => out.push((prefix.to_string(), other.to_string())),
+    }
+}
+
+/// Draw a horizontal bar chart of the histogram buckets.
+fn ascii_histogram(hist: &LatencyHistogram) -> String {
+    let max = hist.buckets.values().copied().max().unwrap_or(0).max(1);
+    let mut out = String::new();
+    for (&bucket, &n) in &hist.buckets {
+        let bar = (n as usize * HISTOGRAM_WIDTH).div_ceil(max as usize);
+        out.push_str(&format!(
+            "{bucket:>7} ms | {:<HISTOGRAM_WIDTH$} {n}\n",
+            "#".repeat(bar)
+        ));
+    }
+    out
+}
+
+/// Render missing sequence numbers as coalesced ranges ("3", "10-12").
+fn gap_ranges(received: &std::collections::HashSet<u64>, total: u64) -> Vec<String> {
+    let mut ranges = Vec::new();
+    let mut start = None;
+    for seq in 0..=total {
+        let missing = seq < total && !received.contains(&seq);
+        match (missing, start) {
+            (true, None) => start = Some(seq),
+            (false, Some(s)) => {
+                ranges.push(if s + 1 == seq {
+                    s.to_string()
+                } else {
+                    format!("{s}-{}", seq - 1)
+                });
+                start = None;
+            }
+            _ => {}
+        }
+    }
+    ranges
+}
+
+/// Render a one-page report for `summary_path`, optionally enriched with the
+/// run's JSONL log (setup metadata, gap list, histogram fallback).
+pub fn render_report(
+    summary_path: &Path,
+    log: Option<&Path>,
+    verdict: &Assertions,
+    format: ReportFormat,
+) -> anyhow::Result<String> {
+    let value = load_summary_value(summary_path)?
+        .with_context(|| format!("{} is not a receiver summary", summary_path.display()))?;
+    let summary: Summary = serde_json::from_value(value.clone()).with_context(|| {
+        format!(
+            "{} does not match the Summary layout",
+            summary_path.display()
+        )
+    })?;
+
+    // Header and setup metadata, received seqs and latency samples of the summarized test.
+    let header = log.map(crate::util::read_log_header).transpose()?.flatten();
+    let mut setup = None;
+    let mut connect = None;
+    let mut received = std::collections::HashSet::new();
+    let mut lats = Vec::new();
+    if let Some(log) = log {
+        crate::util::for_each_log_event(log, |line_no, ev| {
+            let ev = ev.with_context(|| format!("{}:{line_no}", log.display()))?;
+            if ev.event == EventKind::Setup && setup.is_none() {
+                setup = Some(ev.extra.clone());
+            }
+            if ev.event == EventKind::Connect && connect.is_none() {
+                connect = Some(ev.extra.clone());
+            }
+            let test_id = ev.extra.get("test_id").and_then(Value::as_str);
+            let same_test = summary.test_id.is_none() || test_id == summary.test_id.as_deref();
+            if ev.event == EventKind::Recv
+                && same_test
+                && let Some(seq) = ev.seq
+            {
+                // Like the summary, latencies count first copies only.
+                if received.insert(seq) {
+                    lats.extend(ev.lat_ms);
+                }
+            }
+            Ok(())
+        })
+        .with_context(|| format!("reading {}", log.display()))?;
+    }
+
+    let mut blocks = vec![Block::Heading(format!(
+        "Run report: {}",
+        summary_path.display()
+    ))];
+
+    let outcomes = verdict.evaluate(&summary);
+    let failed: Vec<_> = outcomes.iter().filter(|o| !o.passed).collect();
+    blocks.push(Block::Paragraph(if failed.is_empty() {
+        format!("Verdict: PASS ({} checks)", outcomes.len())
+    } else {
+        let names: Vec<_> = failed
+            .iter()
+            .map(|o| {
+                format!(
+                    "{} (limit {}, actual {})",
+                    o.name,
+                    o.limit.map_or("-".into(), |v| v.to_string()),
+                    o.actual.map_or("n/a".into(), |v| v.to_string())
+                )
+            })
+            .collect();
+        format!("Verdict: FAIL: {}", names.join(", "))
+    }));
+
+    blocks.push(Block::Heading("Run metadata".into()));
+    let mut meta = vec![
+        ("summary".to_string(), summary_path.display().to_string()),
+        (
+            "schema_version".to_string(),
+            summary.schema_version.to_string(),
+        ),
+        (
+            "test_id".to_string(),
+            summary.test_id.clone().unwrap_or_else(|| "n/a".into()),
+        ),
+    ];
+    if let Some(log) = log {
+        meta.push(("log".into(), log.display().to_string()));
+    }
+    if let Some(header) = &header {
+        meta.push(("log_schema".into(), header.schema.clone()));
+    }
+    match (&setup, &header) {
+        (Some(setup), _) => flatten_rows("setup", setup, &mut meta),
+        // Crashed before the setup event: the header still has the configuration.
+        (None, Some(header)) => flatten_rows("config", &header.config, &mut meta),
+        (None, None) => {}
+    }
+    // Summaries written before `connect` was part of them.
+    if let (None, Some(connect)) = (&summary.connect, &connect) {
+        flatten_rows("connect", connect, &mut meta);
+    }
+    blocks.push(Block::Table(meta));
+
+    blocks.push(Block::Heading("Metrics".into()));
+    let mut rows = Vec::new();
+    if let Value::Object(map) = &value {
+        for (key, v) in map {
+            if key != "schema_version" && key != "test_id" && key != "lat_histogram" {
+                flatten_rows(key, v, &mut rows);
+            }
+        }
+    }
+    blocks.push(Block::Table(rows));
+
+    blocks.push(Block::Heading("Latency histogram".into()));
+    let hist = summary
+        .lat_histogram
+        .clone()
+        .or_else(|| LatencyHistogram::from_samples(&lats));
+    blocks.push(match hist {
+        Some(h) => Block::Pre(ascii_histogram(&h)),
+        None => Block::Paragraph("No latency samples.".into()),
+    });
+
+    blocks.push(Block::Heading("Gaps".into()));
+    blocks.push(match log {
+        None => Block::Paragraph("Pass the run log (--log-file) to list missing sequences.".into()),
+        Some(_) => {
+            let gaps = gap_ranges(&received, summary.total_expected);
+            if gaps.is_empty() {
+                Block::Paragraph("No missing sequences.".into())
+            } else {
+                let mut text = gaps
+                    .iter()
+                    .take(REPORT_GAPS_MAX)
+                    .cloned()
+                    .collect::<Vec<_>>()
+                    .join(", ");
+                if gaps.len() > REPORT_GAPS_MAX {
+                    text.push_str(&format!(
+                        ", ... {} more ranges",
+                        gaps.len() - REPORT_GAPS_MAX
+                    ));
+                }
+                Block::Paragraph(format!("Missing sequences: {text}"))
+            }
+        }
+    });
+
+    Ok(match format {
+        ReportFormat::Markdown => render_markdown(&blocks),
+        ReportFormat::Html => render_html(&blocks),
+    })
+}
+
+fn render_markdown(blocks: &[Block]) -> String {
+    let mut out = String::new();
+    let mut first = true;
+    for block in blocks {
+        match block {
+            Block::Heading(h) if first => out.push_str(&format!("# {h}\n\n")),
+            Block::Heading(h) => out.push_str(&format!("## {h}\n\n")),
+            Block::Paragraph(p) => out.push_str(&format!("{p}\n\n")),
+            Block::Table(rows) => {
+                out.push_str("| field | value |\n|---|---|\n");
+                for (k, v) in rows {
+                    out.push_str(&format!("| {k} | {} |\n", v.replace('|', "\\|")));
+                }
+                out.push('\n');
+            }
+            Block::Pre(text) => out.push_str(&format!("```\n{text}```\n\n")),
+        }
+        first = false;
+    }
+    out
+}
+
+fn html_escape(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+fn render_html(blocks: &[Block]) -> String {
+    let title = match blocks.first() {
+        Some(Block::Heading(h)) => html_escape(h),
+        _ => "Run report".into(),
+    };
+    let mut out = format!(
+        "<!DOCTYPE html>\n<html>\n<head>\n<meta charset=\"utf-8\">\n<title>{title}</title>\n\
+         <style>body{{font-family:sans-serif;max-width:60em;margin:2em auto}}\
+         table{{border-collapse:collapse}}td{{border:1px solid #ccc;padding:2px 8px}}</style>\n\
+         </head>\n<body>\n"
+    );
+    let mut first = true;
+    for block in blocks {
+        match block {
+            Block::Heading(h) if first => out.push_str(&format!("<h1>{}</h1>\n", html_escape(h))),
+            Block::Heading(h) => out.push_str(&format!("<h2>{}</h2>\n", html_escape(h))),
+            Block::Paragraph(p) => out.push_str(&format!("<p>{}</p>\n", html_escape(p))),
+            Block::Table(rows) => {
+                out.push_str("<table>\n");
+                for (k, v) in rows {
+                    out.push_str(&format!(
+                        "<tr><td>{}</td><td>{}</td></tr>\n",
+                        html_escape(k),
+                        html_escape(v)
+                    ));
+                }
+                out.push_str("</table>\n");
+            }
+            Block::Pre(text) => out.push_str(&format!("<pre>{}</pre>\n", html_escape(text))),
+        }
+        first = false;
+    }
+    out.push_str("</body>\n</html>\n");
+    out
+}
+
+/// Rolling state of `tail`: full-run `Stats` plus a latency window.
+struct TailState {
+    /// Run ID from the log header, if the log has one.
+    run_id: Option<String>,
+    stats: crate::metrics::Stats,
+    test_id: Option<String>,
+    window: std::collections::VecDeque<(u64, u64)>,
+    last_ts: u64,
+    /// Final summary of the current test, once the receiver logged it.
+    summary: Option<Summary>,
+}
+
+impl TailState {
+    fn new() -> Self {
+        Self {
+            run_id: None,
+            stats: crate::metrics::Stats::default(),
+            test_id: None,
+            window: Default::default(),
+            last_ts: 0,
+            summary: None,
+        }
+    }
+
+    /// Feed one complete log line; only the header, `recv` and final
+    /// `summary` events are used.
+    fn push(&mut self, line: &[u8], window_ms: u64) {
+        if let Some(header) = std::str::from_utf8(line).ok().and_then(LogHeader::parse) {
+            self.run_id = Some(header.run_id);
+            return;
+        }
+        let Ok(ev) = serde_json::from_slice::<crate::metrics::LogEvent>(line) else {
+            return;
+        };
+        if ev.event == EventKind::Summary
+            && ev.role == "receiver"
+            && ev.extra.get("snapshot").is_none()
+        {
+            self.summary = serde_json::from_value::<Summary>(ev.extra)
+                .ok()
+                .filter(|s| s.test_id.is_some() && s.test_id == self.test_id);
+            return;
+        }
+        let (Some(seq), EventKind::Recv) = (ev.seq, ev.event) else {
+            return;
+        };
+        let test_id = ev.extra.get("test_id").and_then(Value::as_str);
+
+        // A new test restarts the statistics, like the receiver does.
+        if let Some(id) = test_id
+            && self.test_id.as_deref().is_some_and(|t| t != id)
+        {
+            *self = Self {
+                run_id: self.run_id.take(),
+                ..Self::new()
+            };
+        }
+        if self.test_id.is_none() {
+            self.test_id = test_id.map(str::to_string);
+        }
+
+        let lat = ev.lat_ms.unwrap_or(0);
+        let mut msg_test = [0u8; 16];
+        if let Some(id) = test_id {
+            let _ = hex::decode_to_slice(id, &mut msg_test);
+        }
+        let msg = crate::metrics::DataMsg {
+            test_id: msg_test,
+            seq,
+            sent_ms: ev.ts_ms.saturating_sub(lat),
+            total: ev.extra.get("total").and_then(Value::as_u64).unwrap_or(0),
+            copy: ev
+                .extra
+                .get("copy")
+                .and_then(Value::as_u64)
+                .map_or(0, |c| c as u32),
+            sched_offset_ms: ev.extra.get("sched_offset_ms").and_then(Value::as_u64),
+            pad: Vec::new(),
+        };
+        let mono = ev.elapsed_ms.unwrap_or(ev.ts_ms);
+        if !self.stats.record(&msg, 0, ev.ldh, ev.ts_ms, mono).duplicate {
+            self.stats.note_sched(&msg);
+        }
+
+        self.last_ts = self.last_ts.max(ev.ts_ms);
+        self.window.push_back((ev.ts_ms, lat));
+        while self
+            .window
+            .front()
+            .is_some_and(|&(ts, _)| ts + window_ms < self.last_ts)
+        {
+            self.window.pop_front();
+        }
+    }
+
+    /// Rolling line; the counts come from the test's final summary once it
+    /// was logged (recv events may be sampled), else from the recv events.
+    fn line(&self, window_ms: u64) -> String {
+        let (unique, total, duplicates) = match &self.summary {
+            Some(s) => (s.received_unique, s.total_expected, s.duplicates),
+            None => (
+                self.stats.received_unique(),
+                self.stats.total_expected,
+                self.stats.duplicates,
+            ),
+        };
+        let total = total.max(unique);
+        let pct = if total == 0 {
+            0.0
+        } else {
+            unique as f64 * 100.0 / total as f64
+        };
+        let mut lats: Vec<u64> = self.window.iter().map(|&(_, l)| l).collect();
+        lats.sort_unstable();
+        let q = |q: f64| {
+            if lats.is_empty() {
+                "-".to_string()
+            } else {
+                lats[((lats.len() - 1) as f64 * q).round() as usize].to_string()
+            }
+        };
+        let run = self
+            .run_id
+            .as_deref()
+            .map_or(String::new(), |r| format!("run {} ", &r[..r.len().min(8)]));
+        format!(
+            "{run}test {} recv {unique}/{total} ({pct:.1}%) dups {duplicates}{} | last {}s: n={} p50 {} ms p99 {} ms",
+            self.test_id
+                .as_deref()
+                .map_or("-", |t| &t[..t.len().min(8)]),
+            if self.summary.is_some() {
+                " (final)"
+            } else {
+                ""
+            },
+            window_ms / 1000,
+            lats.len(),
+            q(0.50),
+            q(0.99),
+        )
+    }
+}
+
+/// Identity of the file behind a path, to notice rotation (inode on unix).
+fn file_identity(meta: &std::fs::Metadata) -> Option<u64> {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::MetadataExt;
+        Some(meta.ino())
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = meta;
+        None
+    }
+}
+
+/// Follow a growing receiver log and print a rolling summary every second.
+///
+/// Partial last lines are buffered until their newline arrives. A file that
+/// shrinks or is replaced (rotation) is re-read from the start with fresh stats.
+pub async fn tail(path: &Path, window_ms: u64) -> anyhow::Result<()> {
+    use std::io::{Read, Seek, SeekFrom};
+
+    let mut open: Option<(std::fs::File, Option<u64>)> = None;
+    let mut offset = 0u64;
+    let mut pending: Vec<u8> = Vec::new();
+    let mut state = TailState::new();
+
+    loop {
+        match std::fs::metadata(path) {
+            Err(_) => {
+                if open.take().is_some() {
+                    eprintln!("tail: {} disappeared, waiting", path.display());
+                }
+            }
+            Ok(meta) => {
+                let ident = file_identity(&meta);
+                let rotated = open.as_ref().is_some_and(|(_, id)| *id != ident);
+                if rotated || meta.len() < offset {
+                    eprintln!(
+                        "tail: {} was truncated or rotated, restarting",
+                        path.display()
+                    );
+                    open = None;
+                }
+                if open.is_none() {
+                    open = Some((std::fs::File::open(path)?, ident));
+                    offset = 0;
+                    pending.clear();
+                    state = TailState::new();
+                }
+            }
+        }
+
+        if let Some((file, _)) = open.as_mut() {
+            file.seek(SeekFrom::Start(offset))?;
+            let n = file.read_to_end(&mut pending)?;
+            offset += n as u64;
+
+            let mut consumed = 0;
+            while let Some(i) = pending[consumed..].iter().position(|&b| b == b'\n') {
+                state.push(&pending[consumed..consumed + i], window_ms);
+                consumed += i + 1;
+            }
+            pending.drain(..consumed);
+
+            println!("{}", state.line(window_ms));
+        }
+
+        tokio::time::sleep(std::time::Duration::from_secs(1)).await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::metrics::{DataMsg, Stats};
+
+    /// Write the summary of a small run (seq 2 of 0..4 lost) to a temp file.
+    fn summary_file(name: &str) -> (PathBuf, Value) {
+        let mut stats = Stats::default();
+        for seq in [0, 1, 3] {
+            let m = DataMsg {
+                test_id: [1; 16],
+                seq,
+                sent_ms: 1_000,
+                total: 4,
+                copy: 0,
+                sched_offset_ms: None,
+                pad: Vec::new(),
+            };
+            stats.record(&m, 0, Some(2), 1_010 + seq, seq);
+        }
+        let mut summary = stats.summarize();
+        summary.joined = true;
+        let value = serde_json::to_value(summary).unwrap();
+        let path =
+            std::env::temp_dir().join(format!("igm-report-{}-{name}.json", std::process::id()));
+        std::fs::write(&path, value.to_string()).unwrap();
+        (path, value)
+    }
+
+    #[test]
+    fn markdown_lists_every_summary_field() {
+        let (path, value) = summary_file("md");
+        let out = render_report(&path, None, &Assertions::default(), ReportFormat::Markdown);
+        std::fs::remove_file(&path).unwrap();
+        let out = out.unwrap();
+        for key in value.as_object().unwrap().keys() {
+            if key != "lat_histogram" {
+                assert!(out.contains(&format!("| {key}")), "{key} missing");
+            }
+        }
+        assert!(out.contains("Verdict: PASS (1 checks)"));
+        assert!(out.contains("## Latency histogram\n\n```\n"));
+    }
+
+    #[test]
+    fn html_is_standalone_and_reports_a_failed_verdict() {
+        let (path, _) = summary_file("html");
+        let verdict = Assertions {
+            delivery_min: Some(0.99),
+            ..Assertions::default()
+        };
+        let out = render_report(&path, None, &verdict, ReportFormat::Html);
+        std::fs::remove_file(&path).unwrap();
+        let out = out.unwrap();
+        assert!(out.starts_with("<!DOCTYPE html>"));
+        assert!(out.trim_end().ends_with("</html>"));
+        assert!(out.contains("<p>Verdict: FAIL: delivery_min (limit 0.99, actual 0.75)</p>"));
+        assert!(out.contains("<td>delivery_rate</td><td>0.75</td>"));
+    }
+
+    /// A log line as `JsonWriter` writes it.
+    fn log_line(ts_ms: u64, role: &str, event: &str, seq: Option<u64>, extra: Value) -> String {
+        let line = serde_json::json!({
+            "ts_ms": ts_ms,
+            "role": role,
+            "peer_id": "p",
+            "event": event,
+            "seq": seq,
+            "lat_ms": null,
+            "ldh": null,
+            "extra": extra,
+        });
+        format!("{line}\n")
+    }
+
+    /// Write `lines` after a header to a temp file and validate it.
+    fn validate_lines(name: &str, lines: &[String]) -> (PathBuf, Validation) {
+        let header = serde_json::to_string(&LogHeader::new(None, Value::Null)).unwrap();
+        let path =
+            std::env::temp_dir().join(format!("igm-validate-{}-{name}.jsonl", std::process::id()));
+        std::fs::write(&path, format!("{header}\n{}", lines.concat())).unwrap();
+        let v = validate(&path).unwrap();
+        (path, v)
+    }
+
+    #[test]
+    fn validate_accepts_a_consistent_log() {
+        let (path, v) = validate_lines(
+            "clean",
+            &[
+                log_line(
+                    100,
+                    "sender",
+                    "setup",
+                    None,
+                    serde_json::json!({ "total": 3 }),
+                ),
+                log_line(110, "sender", "send", Some(0), serde_json::json!({})),
+                // Timestamps only have to increase per role.
+                log_line(105, "receiver", "recv", Some(0), serde_json::json!({})),
+                log_line(120, "sender", "send", Some(1), serde_json::json!({})),
+                log_line(130, "receiver", "recv", Some(2), serde_json::json!({})),
+            ],
+        );
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(v.schema.as_deref(), Some(LOG_SCHEMA));
+        assert_eq!(v.lines, 6);
+        assert!(v.is_clean(), "{}", v.report(&path));
+    }
+
+    #[test]
+    fn validate_reports_every_kind_of_finding() {
+        let partial = r#"{"ts_ms":300,"role":"rec"#.to_string();
+        let (path, v) = validate_lines(
+            "dirty",
+            &[
+                log_line(
+                    110,
+                    "sender",
+                    "send",
+                    Some(0),
+                    serde_json::json!({ "total": 2 }),
+                ),
+                log_line(105, "sender", "send", Some(0), serde_json::json!({})),
+                "not json\n".to_string(),
+                log_line(200, "receiver", "recv", Some(1), serde_json::json!({})),
+                log_line(210, "receiver", "recv", Some(5), serde_json::json!({})),
+                // Another test may repeat a seq of the first one.
+                log_line(
+                    220,
+                    "sender",
+                    "send",
+                    Some(0),
+                    serde_json::json!({ "test_id": "ab" }),
+                ),
+                partial.clone(),
+            ],
+        );
+        assert_eq!(v.ts_violations, [(3, "sender".to_string(), 110, 105)]);
+        assert_eq!(v.duplicate_sends, [(3, 0)]);
+        assert_eq!(v.parse_errors.len(), 1);
+        assert_eq!(v.parse_errors[0].0, 4);
+        assert_eq!(
+            (v.max_total, v.seq_beyond_total.as_slice()),
+            (Some(2), &[(6, 5)][..])
+        );
+        assert!(!v.has_setup);
+        assert!(!v.is_clean());
+        let report = v.report(&path);
+        assert!(report.contains("line 6: seq 5 >= total 2"), "{report}");
+        assert!(report.contains("missing setup event"), "{report}");
+
+        // --fix-truncated drops exactly the partial line.
+        let len = std::fs::metadata(&path).unwrap().len();
+        assert_eq!(v.truncated_at, Some(len - partial.len() as u64));
+        assert!(fix_truncated(&path, &v).unwrap());
+        let fixed = validate(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+        assert_eq!(fixed.truncated_at, None);
+        assert_eq!(fixed.lines, v.lines - 1);
+    }
+}
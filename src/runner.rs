@@ -0,0 +1,4324 @@
+//! Sender and receiver run loops over a `dyn Transport`.
+
+use crate::control::Control;
+use crate::forward::Forwarder;
+use crate::frame::{self, Compression, Encryption, FrameError, SigReject};
+use crate::metrics::{
+    AckStats, AckUpTo, AdaptiveRate, CompressionStats, CryptoStats, DataMsg, Diagnosis, EventKind,
+    EventSink, ForwardCounters, Heartbeat, InjectionCounters, JoinBreakdown, LingerEvents,
+    LogEvent, NeighborRtt, PauseRecord, PayloadBreakdown, ProtocolCounters, RateSample,
+    ReceiverAlive, ReceiversSeen, RepairCounters, RepairMsg, ReplayStats, ResourceUsage,
+    SenderSummary, Stats, StatsConfig, Summary, Termination, WireBytes,
+};
+use crate::replay::ReplayEntry;
+use crate::transport::{Transport, TransportError, TransportEvent};
+use crate::util::{
+    PadPattern, ResourceSampler, build_info, diag, elapsed_ms, now_ms, pad_matches,
+    pad_payload_with, process_start_ms, run_id, run_id_bytes,
+};
+use anyhow::Result;
+use bytes::Bytes;
+use iroh::PublicKey;
+use rand::rngs::StdRng;
+use rand::{Rng, RngCore, SeedableRng};
+use serde::Serialize;
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::time::Instant;
+use tokio::{
+    select,
+    task::JoinHandle,
+    time::{Duration, sleep, sleep_until},
+};
+use tracing::warn;
+
+/// Queue depth (as a fraction of capacity) counted as backpressure.
+const BACKPRESSURE_FILL: f64 = 0.9;
+
+/// How long the queue must stay that full before a `Backpressure` event.
+const BACKPRESSURE_SUSTAIN_MS: u64 = 1000;
+
+/// Sender-side run options.
+#[derive(Debug, Clone)]
+pub struct SenderOptions {
+    /// Total number of messages to send.
+    pub total: u64,
+    /// Send rate in messages per second.
+    pub rate: u64,
+    /// Size of each payload in bytes (before compression).
+    pub payload_size: usize,
+    /// Padding fill; random padding keeps payloads incompressible.
+    pub pad: PadPattern,
+    /// Seed of the padding stream, with the test id and seq (`--seed`).
+    pub seed: u64,
+    /// Compression applied to each framed payload.
+    pub compression: Compression,
+    /// Encrypt each framed payload with the topic-derived key.
+    pub encryption: Option<Encryption>,
+    /// Sign `(test_id, seq, sent_ms)` of each message with the transport's key.
+    pub sign: bool,
+    /// Chunk data size for frames above the gossip limit (None = derive from the limit).
+    pub chunk_bytes: Option<usize>,
+    /// Broadcast a `Heartbeat` at this interval (ms) while sending (None disables).
+    pub heartbeat_ms: Option<u64>,
+    /// Serve NACKs over the unicast side-channel, lingering this long (ms)
+    /// after the last send/NACK (None = baseline run without repair).
+    pub repair_linger_ms: Option<u64>,
+    /// Stop the run after this long (ms), whatever its state.
+    pub max_runtime_ms: Option<u64>,
+    /// Log a `watchdog_stall` event after this long (ms) without a successful send.
+    pub watchdog_ms: Option<u64>,
+    /// Ground-truth duplicates and reorders for validating the receiver metrics.
+    pub inject: Injection,
+    /// Adjust the rate to what the transport sustains (None = fixed `rate`).
+    pub adaptive: Option<Adaptive>,
+    /// Collect receiver acks (`--ack-every`) and wait up to `ACK_LINGER_MS`
+    /// for them after the last send.
+    pub collect_acks: bool,
+    /// Pause sending during these windows.
+    pub pause_schedule: Vec<PauseWindow>,
+    /// Pause on SIGUSR1 and resume on SIGUSR2 (Unix only).
+    pub pause_signals: bool,
+    /// After a pause, send the messages the pause held back without pacing
+    /// (default: resume at the normal cadence).
+    pub catch_up: bool,
+    /// Send these messages at their trace offsets instead of `total` messages
+    /// of `payload_size` at `rate` (`--replay`).
+    pub replay: Option<Vec<ReplayEntry>>,
+    /// Listen this long (ms) before sending for another sender on the topic
+    /// (0 = don't).
+    pub probe_ms: u64,
+    /// Abort instead of warning when the probe hears another sender.
+    pub exclusive: bool,
+    /// Return right after the last send: no final pacing interval, repair
+    /// linger or ack wait (`--no-wait`).
+    pub no_wait: bool,
+    /// Drain transport events this long (ms) after sending, counting them in
+    /// `SenderSummary::linger_events` (0 = don't).
+    pub linger_ms: u64,
+    /// Broadcast every message this many times (1 = once), see `Redundancy`.
+    pub redundancy: u32,
+    /// Spacing (ms) of the copies of one message.
+    pub redundancy_gap_ms: u64,
+    /// Frames per gossip message (1 = unbatched), see `Batcher`.
+    pub batch: usize,
+    /// Longest (ms) a frame waits in an incomplete batch.
+    pub batch_max_wait_ms: u64,
+}
+
+/// AIMD send-rate control (`--adaptive`), starting at `SenderOptions::rate`.
+///
+/// A broadcast error or a queue deeper than `queue_depth` halves the rate;
+/// every `interval_ms` without either adds `increase` messages per second.
+#[derive(Debug, Clone, Copy)]
+pub struct Adaptive {
+    pub queue_depth: usize,
+    pub increase: u64,
+    pub interval_ms: u64,
+}
+
+/// Factor applied to the rate on a broadcast error or a deep queue.
+const ADAPTIVE_DECREASE: f64 = 0.5;
+
+/// Minimum time between two decreases, so one burst of trouble cuts the rate once.
+const ADAPTIVE_HOLDOFF_MS: u64 = 200;
+
+/// Sender-side fault injection (`--inject-dup-prob`, `--inject-reorder`); off by default.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct Injection {
+    /// Re-broadcast each message right after it with this probability (0 = never).
+    pub dup_prob: f64,
+    /// Hold back every `every`-th message and send it `delay` messages later.
+    pub reorder: Option<Reorder>,
+}
+
+/// `--inject-reorder k:d`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Reorder {
+    pub every: u64,
+    pub delay: u64,
+}
+
+impl Serialize for Reorder {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{}:{}", self.every, self.delay))
+    }
+}
+
+impl Injection {
+    pub fn is_active(&self) -> bool {
+        self.dup_prob > 0.0 || self.reorder.is_some()
+    }
+}
+
+/// Parse `--inject-reorder` values of the form `k:d` (both at least 1).
+pub fn parse_reorder(s: &str) -> Result<Reorder, String> {
+    let (k, d) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected k:d, got `{s}`"))?;
+    let every: u64 = k
+        .trim()
+        .parse()
+        .map_err(|e| format!("invalid k `{k}`: {e}"))?;
+    let delay: u64 = d
+        .trim()
+        .parse()
+        .map_err(|e| format!("invalid d `{d}`: {e}"))?;
+    if every == 0 || delay == 0 {
+        return Err("k and d must be at least 1".to_string());
+    }
+    Ok(Reorder { every, delay })
+}
+
+/// `--pause-schedule at:duration`: pause sending `at` after the start of the
+/// run for `duration`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PauseWindow {
+    pub at_ms: u64,
+    pub duration_ms: u64,
+}
+
+impl Serialize for PauseWindow {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(&format!("{}ms:{}ms", self.at_ms, self.duration_ms))
+    }
+}
+
+/// Parse one `--pause-schedule` window of the form `at:duration`, e.g. `60s:20s`.
+pub fn parse_pause(s: &str) -> Result<PauseWindow, String> {
+    let (at, duration) = s
+        .split_once(':')
+        .ok_or_else(|| format!("expected at:duration, got `{s}`"))?;
+    let at_ms = crate::util::parse_duration(at)?;
+    let duration_ms = crate::util::parse_duration(duration)?;
+    if duration_ms == 0 {
+        return Err(format!("pause `{s}` has no duration"));
+    }
+    Ok(PauseWindow { at_ms, duration_ms })
+}
+
+/// How often a pause checks for a resume command and the runtime limits.
+const PAUSE_POLL_MS: u64 = 100;
+
+/// Pause state of the send loop.
+struct Pauser {
+    /// Remaining windows, earliest first.
+    schedule: VecDeque<PauseWindow>,
+    /// Task queueing SIGUSR1/SIGUSR2 as `Control` commands.
+    signals: Option<JoinHandle<()>>,
+    /// Source of a `Control::Pause` not yet resumed ("signal" or "stdin").
+    commanded: Option<&'static str>,
+    catch_up: bool,
+    /// Messages still to be sent without pacing (`--catch-up`).
+    owed: u64,
+    records: Vec<PauseRecord>,
+}
+
+impl Pauser {
+    fn new(mut schedule: Vec<PauseWindow>, signals: bool, catch_up: bool) -> Result<Self> {
+        schedule.sort_by_key(|w| w.at_ms);
+        let signals = match signals {
+            true => Some(crate::control::spawn_signals()?),
+            false => None,
+        };
+        Ok(Self {
+            schedule: schedule.into(),
+            signals,
+            commanded: None,
+            catch_up,
+            owed: 0,
+            records: Vec::new(),
+        })
+    }
+
+    /// A pause that starts now: its source and, for a scheduled one, its end.
+    /// Windows that ended while sending was held up otherwise are dropped.
+    fn due(&mut self, run_start_ms: u64) -> Option<(&'static str, Option<u64>)> {
+        let now = now_ms();
+        while let Some(w) = self.schedule.front().copied()
+            && run_start_ms + w.at_ms <= now
+        {
+            self.schedule.pop_front();
+            let end = run_start_ms + w.at_ms + w.duration_ms;
+            if end > now {
+                return Some(("schedule", Some(end)));
+            }
+        }
+        self.commanded.map(|source| (source, None))
+    }
+
+    /// Whether a pause ending at `end_ms` (None: on `Control::Resume`) still
+    /// holds; a commanded pause also extends a scheduled one.
+    fn holding(&self, end_ms: Option<u64>) -> bool {
+        end_ms.is_some_and(|end| now_ms() < end) || self.commanded.is_some()
+    }
+
+    fn finish(self) -> Vec<PauseRecord> {
+        if let Some(task) = self.signals {
+            task.abort();
+        }
+        self.records
+    }
+}
+
+/// One framed message, ready to broadcast.
+struct Outgoing {
+    seq: u64,
+    sent_ms: u64,
+    sched_ms: u64,
+    /// Whole frame as kept for retransmission.
+    frame: Bytes,
+    wire_size: usize,
+    chunks: Vec<Vec<u8>>,
+    breakdown: PayloadBreakdown,
+}
+
+/// Result of broadcasting the chunks of one frame.
+enum ChunkSend {
+    Sent,
+    Failed(TransportError),
+    Stopped(Termination),
+}
+
+/// Broadcast every chunk of a frame, stopping at the first transport error
+/// or when a run limit fires.
+async fn send_chunks(
+    transport: &dyn Transport,
+    log: &mut dyn EventSink,
+    guard: &mut RunGuard,
+    chunks: &[Vec<u8>],
+    chunks_sent: &mut u64,
+) -> anyhow::Result<ChunkSend> {
+    for chunk in chunks {
+        let send = transport.broadcast(Bytes::from(chunk.clone()));
+        match guard.guard(transport, log, send).await? {
+            Err(cause) => return Ok(ChunkSend::Stopped(cause)),
+            Ok(Err(e)) => return Ok(ChunkSend::Failed(e)),
+            Ok(Ok(())) => *chunks_sent += 1,
+        }
+    }
+    Ok(ChunkSend::Sent)
+}
+
+/// Redundant copies of every message (`--redundancy`): copy `k` of a message
+/// goes out `k * gap_ms` after the original, as its own frame with the copy
+/// index and the original's `sent_ms` and schedule offset, so receivers
+/// deduplicate it by seq.
+struct Redundancy {
+    count: u32,
+    gap_ms: u64,
+    test_id: [u8; 16],
+    total: u64,
+    topic: Option<String>,
+    /// Copies still to send: (due ms, seq, copy index, original sent_ms and
+    /// schedule offset).
+    pending: BTreeSet<(u64, u64, u32, u64, u64)>,
+    sent: u64,
+}
+
+impl Redundancy {
+    /// Schedule the copies of a message whose original went out at `sent_at`.
+    fn schedule(&mut self, original: &Outgoing, sent_at: u64) {
+        for copy in 1..self.count {
+            let due = sent_at + u64::from(copy) * self.gap_ms;
+            self.pending
+                .insert((due, original.seq, copy, original.sent_ms, original.sched_ms));
+        }
+    }
+
+    fn next_due(&self) -> Option<u64> {
+        self.pending.first().map(|&(due, ..)| due)
+    }
+
+    /// Broadcast the copies that are due, logging each as a `send` with its
+    /// `copy` index. Returns the cause if the run must stop.
+    async fn send_due(
+        &mut self,
+        transport: &dyn Transport,
+        log: &mut dyn EventSink,
+        guard: &mut RunGuard,
+        encode: &mut impl FnMut(u64, u64, u64, u32) -> anyhow::Result<Outgoing>,
+        chunks_sent: &mut u64,
+        errors_by_kind: &mut BTreeMap<String, u64>,
+    ) -> anyhow::Result<Option<Termination>> {
+        while let Some(&(due, seq, copy, sent_ms, sched_ms)) = self.pending.first()
+            && due <= now_ms()
+        {
+            self.pending.pop_first();
+            let outgoing = encode(seq, sent_ms, sched_ms, copy)?;
+            match send_chunks(transport, log, guard, &outgoing.chunks, chunks_sent).await? {
+                ChunkSend::Stopped(cause) => return Ok(Some(cause)),
+                ChunkSend::Failed(e) => {
+                    *errors_by_kind.entry(e.kind().to_string()).or_default() += 1;
+                    warn!("broadcast error: {e}");
+                    if e.is_fatal() {
+                        return Ok(Some(Termination::TransportClosed));
+                    }
+                    continue;
+                }
+                ChunkSend::Sent => self.sent += 1,
+            }
+            guard.progress();
+            log.emit(LogEvent {
+                ts_ms: now_ms(),
+                elapsed_ms: None,
+                role: "sender",
+                peer_id: &transport.id(),
+                run_id: None,
+                event: EventKind::Send,
+                seq: Some(seq),
+                lat_ms: None,
+                ldh: None,
+                extra: serde_json::json!({
+                    "test_id": hex::encode(self.test_id),
+                    "total": self.total,
+                    "topic": self.topic,
+                    "copy": copy,
+                }),
+            })?;
+        }
+        Ok(None)
+    }
+}
+
+/// Data frames waiting to go out together as one gossip message (`--batch`):
+/// flushed once `size` frames are in, when the next one would not fit the
+/// gossip message limit, or once the oldest has waited `max_wait_ms`. Frames
+/// that need chunks, redundant copies and injected duplicates go out alone.
+struct Batcher {
+    size: usize,
+    max_wait_ms: u64,
+    /// Largest batch payload (None: no limit known).
+    max_bytes: Option<usize>,
+    frames: Vec<Bytes>,
+    bytes: usize,
+    since_ms: u64,
+    sent: u64,
+}
+
+impl Batcher {
+    fn enabled(&self) -> bool {
+        self.size > 1
+    }
+
+    /// When the pending frames must go out.
+    fn due(&self) -> Option<u64> {
+        (!self.frames.is_empty()).then_some(self.since_ms + self.max_wait_ms)
+    }
+
+    fn fits(&self, frame_len: usize) -> bool {
+        self.max_bytes
+            .is_none_or(|max| 1 + self.bytes + frame::batch_entry_len(frame_len) <= max)
+    }
+
+    fn push(&mut self, frame: Bytes) {
+        if self.frames.is_empty() {
+            self.since_ms = now_ms();
+        }
+        self.bytes += frame::batch_entry_len(frame.len());
+        self.frames.push(frame);
+    }
+
+    fn full(&self) -> bool {
+        self.frames.len() >= self.size
+    }
+
+    /// Broadcast the pending frames as one batch (a lone frame as is).
+    /// Returns the cause if the run must stop.
+    async fn flush(
+        &mut self,
+        transport: &dyn Transport,
+        log: &mut dyn EventSink,
+        guard: &mut RunGuard,
+        chunks_sent: &mut u64,
+        errors_by_kind: &mut BTreeMap<String, u64>,
+    ) -> anyhow::Result<Option<Termination>> {
+        if self.frames.is_empty() {
+            return Ok(None);
+        }
+        let batch = frame::encode_batch(&std::mem::take(&mut self.frames));
+        self.bytes = 0;
+        match send_chunks(transport, log, guard, &[batch.to_vec()], chunks_sent).await? {
+            ChunkSend::Stopped(cause) => return Ok(Some(cause)),
+            ChunkSend::Failed(e) => {
+                *errors_by_kind.entry(e.kind().to_string()).or_default() += 1;
+                warn!("batch broadcast error: {e}");
+                if e.is_fatal() {
+                    return Ok(Some(Termination::TransportClosed));
+                }
+            }
+            ChunkSend::Sent => self.sent += 1,
+        }
+        Ok(None)
+    }
+}
+
+/// Sender-side broadcast latency and queue depth, fed from
+/// `Transport::drain_broadcast_queue` after every send.
+#[derive(Debug, Default)]
+struct BroadcastMeter {
+    lat_ms: Vec<f64>,
+    /// Queue depth at the last poll, and the deepest seen.
+    depth: usize,
+    max_depth: Option<usize>,
+    /// Since when the queue has been at least `BACKPRESSURE_FILL` full.
+    full_since_ms: Option<u64>,
+    /// The current full stretch was already reported.
+    reported: bool,
+}
+
+impl BroadcastMeter {
+    /// Take the finished broadcasts and sample the queue depth, logging a
+    /// `Backpressure` event once per sustained full stretch. Returns the
+    /// latency of the newest broadcast if it has already completed.
+    fn poll(
+        &mut self,
+        transport: &dyn Transport,
+        log: &mut dyn EventSink,
+    ) -> anyhow::Result<Option<f64>> {
+        let Some(queue) = transport.drain_broadcast_queue() else {
+            return Ok(None);
+        };
+        self.lat_ms.extend(queue.done.iter().map(|d| d.lat_ms));
+        self.depth = queue.depth;
+        self.max_depth = self.max_depth.max(Some(queue.depth));
+
+        let now = elapsed_ms();
+        if (queue.depth as f64) < queue.capacity as f64 * BACKPRESSURE_FILL {
+            self.full_since_ms = None;
+            self.reported = false;
+        } else {
+            let since = *self.full_since_ms.get_or_insert(now);
+            let sustained_ms = now - since;
+            if sustained_ms >= BACKPRESSURE_SUSTAIN_MS && !self.reported {
+                self.reported = true;
+                warn!(
+                    "broadcast queue at {}/{} for {sustained_ms} ms",
+                    queue.depth, queue.capacity
+                );
+                log.emit(LogEvent {
+                    ts_ms: now_ms(),
+                    elapsed_ms: None,
+                    role: "sender",
+                    peer_id: &transport.id(),
+                    run_id: None,
+                    event: EventKind::Backpressure,
+                    seq: None,
+                    lat_ms: None,
+                    ldh: None,
+                    extra: serde_json::json!({
+                        "depth": queue.depth,
+                        "capacity": queue.capacity,
+                        "sustained_ms": sustained_ms,
+                    }),
+                })?;
+            }
+        }
+
+        let newest = queue.enqueued.checked_sub(1);
+        Ok(queue
+            .done
+            .iter()
+            .find(|d| Some(d.ticket) == newest)
+            .map(|d| d.lat_ms))
+    }
+
+    /// Like `poll`, but if the newest broadcast is still the only one in flight,
+    /// wait one timer tick for it so the send event can carry its latency.
+    async fn newest(
+        &mut self,
+        transport: &dyn Transport,
+        log: &mut dyn EventSink,
+    ) -> anyhow::Result<Option<f64>> {
+        let lat = self.poll(transport, log)?;
+        if lat.is_some() || self.depth > 1 {
+            return Ok(lat);
+        }
+        sleep(Duration::from_millis(1)).await;
+        self.poll(transport, log)
+    }
+
+    /// Nearest-rank quantile of the recorded latencies.
+    fn quantile(&self, q: f64) -> Option<f64> {
+        let mut sorted = self.lat_ms.clone();
+        sorted.sort_by(f64::total_cmp);
+        let idx = ((sorted.len().checked_sub(1)?) as f64 * q).round() as usize;
+        sorted.get(idx).copied()
+    }
+}
+
+/// State of the `--adaptive` rate controller.
+struct RateController {
+    opts: Adaptive,
+    rate: u64,
+    start_ms: u64,
+    /// Start of the current stretch without trouble or rate change.
+    clean_since_ms: u64,
+    last_cut_ms: Option<u64>,
+    summary: AdaptiveRate,
+}
+
+impl RateController {
+    fn new(opts: Adaptive, rate: u64) -> Self {
+        let start_ms = elapsed_ms();
+        Self {
+            opts,
+            rate,
+            start_ms,
+            clean_since_ms: start_ms,
+            last_cut_ms: None,
+            summary: AdaptiveRate {
+                final_rate: rate,
+                min_rate: rate,
+                max_rate: rate,
+                series: vec![RateSample {
+                    elapsed_ms: 0,
+                    rate,
+                }],
+            },
+        }
+    }
+
+    /// Feed one send outcome (`trigger` names the trouble, None after a clean
+    /// send) and log a `RateAdjust` event if the rate changes.
+    fn observe(
+        &mut self,
+        transport: &dyn Transport,
+        log: &mut dyn EventSink,
+        trigger: Option<&'static str>,
+    ) -> anyhow::Result<()> {
+        let now = elapsed_ms();
+        let (new_rate, reason) = match trigger {
+            Some(reason) => {
+                self.clean_since_ms = now;
+                if self
+                    .last_cut_ms
+                    .is_some_and(|t| now.saturating_sub(t) < ADAPTIVE_HOLDOFF_MS)
+                {
+                    return Ok(());
+                }
+                self.last_cut_ms = Some(now);
+                let cut = (self.rate as f64 * ADAPTIVE_DECREASE) as u64;
+                (cut.max(1), reason)
+            }
+            None if now.saturating_sub(self.clean_since_ms) >= self.opts.interval_ms => {
+                (self.rate + self.opts.increase, "clean")
+            }
+            None => return Ok(()),
+        };
+        self.clean_since_ms = now;
+        if new_rate == self.rate {
+            return Ok(());
+        }
+
+        log.emit(LogEvent {
+            ts_ms: now_ms(),
+            elapsed_ms: None,
+            role: "sender",
+            peer_id: &transport.id(),
+            run_id: None,
+            event: EventKind::RateAdjust,
+            seq: None,
+            lat_ms: None,
+            ldh: None,
+            extra: serde_json::json!({
+                "old_rate": self.rate,
+                "new_rate": new_rate,
+                "trigger": reason,
+            }),
+        })?;
+        self.set(new_rate);
+        Ok(())
+    }
+
+    /// Switch to `new_rate` (also by a `rate` command), recording it in the summary.
+    fn set(&mut self, new_rate: u64) {
+        let now = elapsed_ms();
+        self.rate = new_rate;
+        self.clean_since_ms = now;
+        let s = &mut self.summary;
+        s.final_rate = new_rate;
+        s.min_rate = s.min_rate.min(new_rate);
+        s.max_rate = s.max_rate.max(new_rate);
+        s.series.push(RateSample {
+            elapsed_ms: now.saturating_sub(self.start_ms),
+            rate: new_rate,
+        });
+    }
+}
+
+/// Pause between sends (ms) for a rate in messages per second.
+fn send_interval_ms(rate: u64) -> u64 {
+    (1000 / rate.max(1)).max(1)
+}
+
+/// Log an injected duplicate or reorder of `seq`.
+fn log_inject(
+    transport: &dyn Transport,
+    log: &mut dyn EventSink,
+    event: EventKind,
+    test_id: [u8; 16],
+    seq: u64,
+    delay: Option<u64>,
+) -> anyhow::Result<()> {
+    let mut extra = serde_json::json!({ "test_id": hex::encode(test_id) });
+    if let Some(d) = delay {
+        extra["delay"] = d.into();
+    }
+    log.emit(LogEvent {
+        ts_ms: now_ms(),
+        elapsed_ms: None,
+        role: "sender",
+        peer_id: &transport.id(),
+        run_id: None,
+        event,
+        seq: Some(seq),
+        lat_ms: None,
+        ldh: None,
+        extra,
+    })?;
+    Ok(())
+}
+
+/// Log a run-time command from `source` and its `effect` as a `control` event.
+fn log_control(
+    transport: &dyn Transport,
+    log: &mut dyn EventSink,
+    role: &'static str,
+    cmd: Control,
+    source: &str,
+    effect: serde_json::Value,
+) -> anyhow::Result<()> {
+    let mut extra = serde_json::json!({
+        "command": cmd.as_str(),
+        "source": source,
+        "effect": effect,
+    });
+    if let Control::Rate(rate) = cmd {
+        extra["rate"] = rate.into();
+    }
+    log.emit(LogEvent {
+        ts_ms: now_ms(),
+        elapsed_ms: None,
+        role,
+        peer_id: &transport.id(),
+        run_id: None,
+        event: EventKind::Control,
+        seq: None,
+        lat_ms: None,
+        ldh: None,
+        extra,
+    })
+}
+
+/// Effect of a `rejoin` command: re-join the bootstrap peers on the send topic.
+async fn rejoin_now(transport: &dyn Transport) -> serde_json::Value {
+    match transport.rejoin().await {
+        Ok(true) => {
+            serde_json::json!({ "rejoined": true, "neighbors": transport.neighbors().len() })
+        }
+        Ok(false) => {
+            serde_json::json!({ "rejoined": false, "reason": "no send topic subscription" })
+        }
+        Err(e) => serde_json::json!({ "rejoined": false, "error": e.to_string() }),
+    }
+}
+
+/// Apply the queued run-time commands to the send loop; `progress` is
+/// (messages sent, next seq) for `snapshot`. `quit` stops the run like Ctrl-C.
+async fn sender_controls(
+    transport: &dyn Transport,
+    log: &mut dyn EventSink,
+    pauser: &mut Pauser,
+    rate: &mut u64,
+    mut rate_control: Option<&mut RateController>,
+    progress: (u64, u64),
+) -> anyhow::Result<()> {
+    while let Some((cmd, source)) = crate::control::next() {
+        let effect = match cmd {
+            Control::Snapshot => {
+                let (messages_sent, next_seq) = progress;
+                let snapshot = serde_json::json!({
+                    "messages_sent": messages_sent,
+                    "next_seq": next_seq,
+                    "rate": rate_control.as_ref().map_or(*rate, |c| c.rate),
+                    "paused": pauser.commanded.is_some(),
+                });
+                diag!("snapshot {snapshot}");
+                snapshot
+            }
+            Control::Rate(new_rate) => {
+                let old_rate = rate_control.as_ref().map_or(*rate, |c| c.rate);
+                *rate = new_rate;
+                if let Some(c) = rate_control.as_deref_mut() {
+                    c.set(new_rate);
+                }
+                serde_json::json!({ "old_rate": old_rate, "new_rate": new_rate })
+            }
+            Control::Pause => {
+                let was_paused = pauser.commanded.is_some();
+                pauser.commanded.get_or_insert(source);
+                serde_json::json!({ "paused": true, "was_paused": was_paused })
+            }
+            Control::Resume => {
+                let was_paused = pauser.commanded.take().is_some();
+                serde_json::json!({ "paused": false, "was_paused": was_paused })
+            }
+            Control::Rejoin => rejoin_now(transport).await,
+            Control::Quit => {
+                crate::util::interrupt();
+                serde_json::json!({ "stopping": true })
+            }
+        };
+        log_control(transport, log, "sender", cmd, source, effect)?;
+    }
+    Ok(())
+}
+
+/// Run-level limits shared by both roles: `--max-runtime-ms`, the
+/// no-progress watchdog and Ctrl-C.
+struct RunGuard {
+    role: &'static str,
+    start_ms: u64,
+    max_runtime_ms: Option<u64>,
+    watchdog_ms: Option<u64>,
+    last_progress_ms: u64,
+    last_stall_log_ms: u64,
+}
+
+impl RunGuard {
+    /// Interval at which `guard` re-checks the limits while awaiting.
+    const POLL_MS: u64 = 100;
+
+    fn new(role: &'static str, max_runtime_ms: Option<u64>, watchdog_ms: Option<u64>) -> Self {
+        let now = elapsed_ms();
+        Self {
+            role,
+            start_ms: now,
+            max_runtime_ms,
+            watchdog_ms,
+            last_progress_ms: now,
+            last_stall_log_ms: 0,
+        }
+    }
+
+    /// Next monotonic time (ms) at which `check` may act: the runtime cap or the next watchdog check.
+    fn next_deadline(&self) -> Option<u64> {
+        let runtime = self.max_runtime_ms.map(|max| self.start_ms + max);
+        let watchdog = self
+            .watchdog_ms
+            .map(|period| self.last_progress_ms.max(self.last_stall_log_ms) + period);
+        runtime.into_iter().chain(watchdog).min()
+    }
+
+    /// Note progress (a send or a transport event), resetting the watchdog.
+    fn progress(&mut self) {
+        self.last_progress_ms = elapsed_ms();
+    }
+
+    /// Check the limits, logging `max_runtime_reached` and `watchdog_stall`
+    /// (at most once per watchdog period). Returns the cause if the run must stop.
+    fn check(
+        &mut self,
+        transport: &dyn Transport,
+        log: &mut dyn EventSink,
+    ) -> anyhow::Result<Option<Termination>> {
+        let now = elapsed_ms();
+        if crate::util::interrupted() {
+            return Ok(Some(Termination::Interrupt));
+        }
+        let runtime_ms = now.saturating_sub(self.start_ms);
+        if let Some(max) = self.max_runtime_ms
+            && runtime_ms >= max
+        {
+            log.emit(LogEvent {
+                ts_ms: now_ms(),
+                elapsed_ms: None,
+                role: self.role,
+                peer_id: &transport.id(),
+                run_id: None,
+                event: EventKind::MaxRuntimeReached,
+                seq: None,
+                lat_ms: None,
+                ldh: None,
+                extra: serde_json::json!({ "elapsed_ms": runtime_ms, "max_runtime_ms": max }),
+            })?;
+            return Ok(Some(Termination::MaxRuntime));
+        }
+        if let Some(period) = self.watchdog_ms {
+            let stalled_ms = now.saturating_sub(self.last_progress_ms);
+            if stalled_ms >= period && now.saturating_sub(self.last_stall_log_ms) >= period {
+                self.last_stall_log_ms = now;
+                warn!("no progress for {stalled_ms} ms");
+                log.emit(LogEvent {
+                    ts_ms: now_ms(),
+                    elapsed_ms: None,
+                    role: self.role,
+                    peer_id: &transport.id(),
+                    run_id: None,
+                    event: EventKind::WatchdogStall,
+                    seq: None,
+                    lat_ms: None,
+                    ldh: None,
+                    extra: serde_json::json!({ "stalled_ms": stalled_ms }),
+                })?;
+            }
+        }
+        Ok(None)
+    }
+
+    /// Await `fut` while checking the limits, so a hung transport call cannot
+    /// outlive them. Returns `Err(cause)` if a limit fired first.
+    async fn guard<F: std::future::Future>(
+        &mut self,
+        transport: &dyn Transport,
+        log: &mut dyn EventSink,
+        fut: F,
+    ) -> anyhow::Result<Result<F::Output, Termination>> {
+        tokio::pin!(fut);
+        loop {
+            select! {
+                out = &mut fut => return Ok(Ok(out)),
+                _ = sleep(Duration::from_millis(Self::POLL_MS)) => {
+                    if let Some(cause) = self.check(transport, log)? {
+                        return Ok(Err(cause));
+                    }
+                }
+            }
+        }
+    }
+}
+
+/// Runs the sender role: generates `DataMsg`s, sends them at a given rate,
+/// and logs each send event.
+///
+/// With repair enabled, the pause between sends is used to answer NACKs by
+/// unicasting the requested messages back to the receiver, and the sender
+/// keeps serving NACKs until none arrived for `repair_linger_ms`.
+///
+/// # Parameters
+/// * `transport` – Active gossip transport.
+/// * `log` – JSONL writer for structured logs.
+/// * `opts` – Message count, rate, payload size and repair settings.
+pub async fn run_sender(
+    transport: &mut dyn Transport,
+    log: &mut dyn EventSink,
+    opts: SenderOptions,
+) -> anyhow::Result<SenderSummary> {
+    let SenderOptions {
+        total: test_total,
+        rate: rate_per_sec,
+        payload_size,
+        pad,
+        seed,
+        compression,
+        encryption,
+        sign,
+        chunk_bytes,
+        heartbeat_ms,
+        repair_linger_ms,
+        max_runtime_ms,
+        watchdog_ms,
+        inject,
+        adaptive,
+        collect_acks,
+        pause_schedule,
+        pause_signals,
+        catch_up,
+        replay,
+        probe_ms,
+        exclusive,
+        no_wait,
+        linger_ms,
+        redundancy: copies_per_msg,
+        redundancy_gap_ms,
+        batch,
+        batch_max_wait_ms,
+    } = opts;
+    let test_total = replay.as_ref().map_or(test_total, |r| r.len() as u64);
+
+    // A sender left running from an earlier run would garble this one's stats.
+    let other_tests = probe_other_senders(transport, log, encryption.as_ref(), probe_ms).await?;
+    if !other_tests.is_empty() {
+        diag!(
+            "WARNING: another sender is active on this topic (test_id {})",
+            other_tests
+                .iter()
+                .map(hex::encode)
+                .collect::<Vec<_>>()
+                .join(", ")
+        );
+        if exclusive {
+            anyhow::bail!("another sender is active on this topic (--exclusive)");
+        }
+    }
+
+    let start_ms = now_ms();
+    let mut guard = RunGuard::new("sender", max_runtime_ms, watchdog_ms);
+    let mut terminated_by = Termination::Complete;
+    let protocol_start = transport.protocol_counters();
+    // Topic carrying the data stream, logged with every send.
+    let send_topic = transport.topics().into_iter().next();
+
+    let mut test_id = [0u8; 16];
+    rand::rng().fill_bytes(&mut test_id);
+
+    let mut messages_sent = 0u64;
+    let mut chunks_sent = 0u64;
+    let mut heartbeats_sent = 0u64;
+    let mut next_heartbeat_ms = now_ms();
+    let mut control = ControlSeen::new(test_id, test_total, collect_acks);
+    let mut pauser = Pauser::new(pause_schedule, pause_signals, catch_up)?;
+    let mut meter = BroadcastMeter::default();
+    let chunk_bytes =
+        chunk_bytes.or_else(|| transport.max_message_size().map(frame::chunk_size_for));
+    let mut errors_by_kind: BTreeMap<String, u64> = BTreeMap::new();
+    let mut repair = repair_linger_ms.map(|_| RepairServer::new(test_id));
+    let mut compression_stats = CompressionStats::default();
+    let mut crypto_stats = CryptoStats::default();
+    let signer = match sign {
+        true => Some(
+            transport
+                .secret_key()
+                .ok_or_else(|| anyhow::anyhow!("transport has no key to sign with"))?,
+        ),
+        false => None,
+    };
+
+    // The inter-send interval follows the rate, which `--adaptive` and the
+    // `rate` command adjust.
+    let mut rate_control = adaptive.map(|a| RateController::new(a, rate_per_sec));
+    let mut base_rate = rate_per_sec;
+
+    // Build a message, then serialize and pad its payload (framing overhead counts
+    // towards the size) and frame it, compressing, encrypting and/or signing if
+    // requested. Frames that exceed the gossip message limit are split into chunks.
+    // A replayed message pads to its own size or carries its trace payload.
+    let mut encode =
+        |seq: u64, sent_ms: u64, sched_ms: u64, copy: u32| -> anyhow::Result<Outgoing> {
+            let entry = replay.as_ref().map(|r| &r[seq as usize]);
+            let msg = DataMsg {
+                test_id,
+                seq,
+                sent_ms,
+                total: test_total,
+                copy,
+                sched_offset_ms: Some(sched_ms),
+                pad: entry.and_then(|e| e.payload.clone()).unwrap_or_default(),
+            };
+            let overhead = frame::overhead(encryption.is_some(), signer.is_some());
+            let body = postcard::to_allocvec(&msg)?;
+            let header_bytes = body.len();
+            let size = match entry {
+                Some(e) if e.payload.is_some() => 0,
+                Some(e) => e.size,
+                None => payload_size,
+            };
+            let (body, pad_bytes) = pad_payload_with(
+                body,
+                size.saturating_sub(overhead),
+                pad,
+                &test_id,
+                seq,
+                seed,
+            );
+            let signature = signer
+                .as_ref()
+                .map(|key| frame::sign(key, &test_id, seq, msg.sent_ms));
+            let framing_start = Instant::now();
+            let bytes = Bytes::from(frame::encode(
+                &body,
+                compression,
+                encryption.as_ref(),
+                signature.as_ref(),
+            )?);
+            let framing_us = framing_start.elapsed().as_micros() as u64;
+            if compression != Compression::None {
+                compression_stats.note(body.len(), bytes.len() - overhead);
+            }
+            if encryption.is_some() {
+                let crypto_overhead = frame::overhead(true, false) - frame::HEADER_LEN;
+                crypto_stats.note(crypto_overhead, framing_us);
+            }
+            let chunks = match chunk_bytes {
+                Some(n) => frame::split(bytes.to_vec(), &test_id, seq, n),
+                None => vec![bytes.to_vec()],
+            };
+            let breakdown = PayloadBreakdown {
+                header_bytes,
+                pad_bytes,
+                sealed_bytes: bytes.len() - overhead,
+                framing_bytes: overhead,
+                wire_bytes: bytes.len(),
+            };
+            Ok(Outgoing {
+                seq,
+                sent_ms: msg.sent_ms,
+                sched_ms,
+                wire_size: bytes.len(),
+                frame: bytes,
+                chunks,
+                breakdown,
+            })
+        };
+
+    // Byte breakdown of the first message, logged once.
+    let mut payload: Option<PayloadBreakdown> = None;
+
+    // Frames held back by `--inject-reorder`, with the seq after which each is sent.
+    let mut held: VecDeque<(u64, Outgoing)> = VecDeque::new();
+    let mut inject_rng = StdRng::from_rng(&mut rand::rng());
+    let mut injection = InjectionCounters {
+        dup_prob: inject.dup_prob,
+        reorder_every: inject.reorder.map(|r| r.every),
+        reorder_delay: inject.reorder.map(|r| r.delay),
+        ..Default::default()
+    };
+
+    let mut redundancy = Redundancy {
+        count: copies_per_msg.max(1),
+        gap_ms: redundancy_gap_ms,
+        test_id,
+        total: test_total,
+        topic: send_topic.clone(),
+        pending: BTreeSet::new(),
+        sent: 0,
+    };
+
+    let mut batcher = Batcher {
+        size: batch.max(1),
+        max_wait_ms: batch_max_wait_ms,
+        max_bytes: transport
+            .max_message_size()
+            .map(|max| max.saturating_sub(frame::GOSSIP_ENVELOPE_OVERHEAD)),
+        frames: Vec::new(),
+        bytes: 0,
+        since_ms: 0,
+        sent: 0,
+    };
+
+    // Replay offsets count from here; actual minus intended send time per message.
+    let replay_start = now_ms();
+    let mut sched_errors = Vec::new();
+    // Intended send time of the next message after the start: one interval
+    // per message at the current rate plus the pauses; stamped into every
+    // message as its `sched_offset_ms`.
+    let mut sched_ms = 0u64;
+
+    'send: for seq in 0..test_total {
+        sender_controls(
+            &*transport,
+            log,
+            &mut pauser,
+            &mut base_rate,
+            rate_control.as_mut(),
+            (messages_sent, seq),
+        )
+        .await?;
+        if let Some(cause) = guard.check(&*transport, log)? {
+            terminated_by = cause;
+            break;
+        }
+
+        // Pause windows hold the loop, still serving NACKs and control messages.
+        if let Some((source, end_ms)) = pauser.due(start_ms) {
+            if let Some(cause) = batcher
+                .flush(
+                    &*transport,
+                    log,
+                    &mut guard,
+                    &mut chunks_sent,
+                    &mut errors_by_kind,
+                )
+                .await?
+            {
+                terminated_by = cause;
+                break;
+            }
+            let paused_at = now_ms();
+            let last_seq_before = seq.checked_sub(1);
+            log.emit(LogEvent {
+                ts_ms: paused_at,
+                elapsed_ms: None,
+                role: "sender",
+                peer_id: &transport.id(),
+                run_id: None,
+                event: EventKind::PauseStart,
+                seq: Some(seq),
+                lat_ms: None,
+                ldh: None,
+                extra: serde_json::json!({
+                    "source": source,
+                    "last_seq_before": last_seq_before,
+                    "next_seq": seq,
+                    "planned_ms": end_ms.map(|end| end.saturating_sub(paused_at)),
+                }),
+            })?;
+            let mut stop = None;
+            while pauser.holding(end_ms) {
+                sender_controls(
+                    &*transport,
+                    log,
+                    &mut pauser,
+                    &mut base_rate,
+                    rate_control.as_mut(),
+                    (messages_sent, seq),
+                )
+                .await?;
+                // A pause is not a stall.
+                guard.progress();
+                if let Some(cause) = guard.check(&*transport, log)? {
+                    stop = Some(cause);
+                    break;
+                }
+                let step = now_ms() + PAUSE_POLL_MS;
+                let step = end_ms.map_or(step, |end| step.min(end));
+                if !wait_until(
+                    transport,
+                    log,
+                    repair.as_mut(),
+                    &mut errors_by_kind,
+                    &mut control,
+                    step,
+                )
+                .await?
+                {
+                    stop = Some(Termination::TransportClosed);
+                    break;
+                }
+            }
+            guard.progress();
+            let duration_ms = now_ms().saturating_sub(paused_at);
+            let rate = rate_control.as_ref().map_or(base_rate, |c| c.rate);
+            let catch_up_msgs = match pauser.catch_up {
+                true => (duration_ms * rate / 1000).min(test_total - seq),
+                false => 0,
+            };
+            pauser.owed += catch_up_msgs;
+            sched_ms += duration_ms;
+            log.emit(LogEvent {
+                ts_ms: now_ms(),
+                elapsed_ms: None,
+                role: "sender",
+                peer_id: &transport.id(),
+                run_id: None,
+                event: EventKind::PauseEnd,
+                seq: Some(seq),
+                lat_ms: None,
+                ldh: None,
+                extra: serde_json::json!({
+                    "source": source,
+                    "last_seq_before": last_seq_before,
+                    "next_seq": seq,
+                    "duration_ms": duration_ms,
+                    "catch_up_msgs": catch_up_msgs,
+                }),
+            })?;
+            pauser.records.push(PauseRecord {
+                source: source.to_string(),
+                start_ms: paused_at.saturating_sub(start_ms),
+                duration_ms,
+                last_seq_before,
+                next_seq: seq,
+                catch_up_msgs,
+            });
+            if let Some(cause) = stop {
+                terminated_by = cause;
+                break;
+            }
+        }
+
+        // Heartbeat with the sender clock and progress, on its own schedule.
+        if let Some(hb_ms) = heartbeat_ms
+            && now_ms() >= next_heartbeat_ms
+        {
+            let hb = Heartbeat {
+                test_id,
+                run_id: run_id_bytes(),
+                sent_ms: now_ms(),
+                seq,
+                interval_ms: hb_ms,
+                payload_size: replay.is_none().then_some(payload_size as u64),
+            };
+            next_heartbeat_ms = hb.sent_ms + hb_ms;
+            let send = transport.broadcast(Bytes::from(frame::encode_heartbeat(&hb)?));
+            match guard.guard(&*transport, log, send).await? {
+                Err(cause) => {
+                    terminated_by = cause;
+                    break;
+                }
+                Ok(Ok(())) => heartbeats_sent += 1,
+                Ok(Err(e)) => {
+                    *errors_by_kind.entry(e.kind().to_string()).or_default() += 1;
+                    warn!("heartbeat error: {e}");
+                    if e.is_fatal() {
+                        terminated_by = Termination::TransportClosed;
+                        break;
+                    }
+                }
+            }
+        }
+
+        // A replayed message waits for its trace offset.
+        if let Some(entry) = replay.as_ref().map(|r| &r[seq as usize]) {
+            if !wait_until(
+                transport,
+                log,
+                repair.as_mut(),
+                &mut errors_by_kind,
+                &mut control,
+                replay_start + entry.offset_ms,
+            )
+            .await?
+            {
+                terminated_by = Termination::TransportClosed;
+                break;
+            }
+            guard.progress();
+        }
+
+        let offset = replay
+            .as_ref()
+            .map_or(sched_ms, |r| r[seq as usize].offset_ms);
+        let outgoing = encode(seq, now_ms(), offset, 0)?;
+        if payload.is_none() {
+            payload = Some(outgoing.breakdown);
+            log.emit(LogEvent {
+                ts_ms: now_ms(),
+                elapsed_ms: None,
+                role: "sender",
+                peer_id: &transport.id(),
+                run_id: None,
+                event: EventKind::PayloadBreakdown,
+                seq: Some(seq),
+                lat_ms: None,
+                ldh: None,
+                extra: outgoing.breakdown.to_extra(),
+            })?;
+        }
+        if let Some(server) = repair.as_mut() {
+            server.sent.push(outgoing.frame.clone());
+        }
+
+        // Injected reordering holds the frame back; released frames follow the current one.
+        let mut ready = Vec::with_capacity(1);
+        match inject.reorder {
+            Some(r) if (seq + 1).is_multiple_of(r.every) && seq + 1 < test_total => {
+                injection.reorders_injected += 1;
+                log_inject(
+                    transport,
+                    log,
+                    EventKind::InjectReorder,
+                    test_id,
+                    seq,
+                    Some(r.delay),
+                )?;
+                held.push_back((seq + r.delay, outgoing));
+            }
+            _ => ready.push(outgoing),
+        }
+        while held.front().is_some_and(|(release, _)| *release <= seq) {
+            ready.extend(held.pop_front().map(|(_, o)| o));
+        }
+        if seq + 1 == test_total {
+            ready.extend(held.drain(..).map(|(_, o)| o));
+        }
+
+        for outgoing in ready {
+            let sent = if batcher.enabled() && outgoing.chunks.len() == 1 {
+                let mut stop = None;
+                if !batcher.fits(outgoing.frame.len()) {
+                    stop = batcher
+                        .flush(
+                            &*transport,
+                            log,
+                            &mut guard,
+                            &mut chunks_sent,
+                            &mut errors_by_kind,
+                        )
+                        .await?;
+                }
+                batcher.push(outgoing.frame.clone());
+                if stop.is_none() && batcher.full() {
+                    stop = batcher
+                        .flush(
+                            &*transport,
+                            log,
+                            &mut guard,
+                            &mut chunks_sent,
+                            &mut errors_by_kind,
+                        )
+                        .await?;
+                }
+                match stop {
+                    Some(cause) => ChunkSend::Stopped(cause),
+                    None => ChunkSend::Sent,
+                }
+            } else {
+                send_chunks(
+                    &*transport,
+                    log,
+                    &mut guard,
+                    &outgoing.chunks,
+                    &mut chunks_sent,
+                )
+                .await?
+            };
+            match sent {
+                ChunkSend::Stopped(cause) => {
+                    terminated_by = cause;
+                    break 'send;
+                }
+                ChunkSend::Failed(e) => {
+                    *errors_by_kind.entry(e.kind().to_string()).or_default() += 1;
+                    warn!("broadcast error: {e}");
+                    if e.is_fatal() {
+                        terminated_by = Termination::TransportClosed;
+                        break 'send;
+                    }
+                    if let Some(control) = rate_control.as_mut() {
+                        control.observe(&*transport, log, Some("broadcast_error"))?;
+                    }
+                    continue;
+                }
+                ChunkSend::Sent => {}
+            }
+            messages_sent += 1;
+            redundancy.schedule(&outgoing, now_ms());
+            control.note_sent(outgoing.seq);
+            control.progress(&*transport, log)?;
+            let intended_ms = replay
+                .as_ref()
+                .map(|r| replay_start + r[outgoing.seq as usize].offset_ms);
+            if let Some(intended) = intended_ms {
+                sched_errors.push(outgoing.sent_ms.saturating_sub(intended));
+            }
+            guard.progress();
+            let broadcast_lat_ms = meter.newest(&*transport, log).await?;
+            if let Some(control) = rate_control.as_mut() {
+                let deep = meter.depth > control.opts.queue_depth;
+                control.observe(&*transport, log, deep.then_some("queue_depth"))?;
+            }
+
+            // Log the event.
+            log.emit(LogEvent {
+                ts_ms: now_ms(),
+                elapsed_ms: None,
+                role: "sender",
+                peer_id: &transport.id(),
+                run_id: None,
+                event: EventKind::Send,
+                seq: Some(outgoing.seq),
+                lat_ms: None,
+                ldh: None,
+                extra: {
+                    let mut extra = serde_json::json!({
+                        "test_id": hex::encode(test_id),
+                        "total": test_total,
+                        "topic": send_topic,
+                    });
+                    if compression != Compression::None {
+                        extra["compressed_size"] = outgoing.wire_size.into();
+                    }
+                    if outgoing.chunks.len() > 1 {
+                        extra["chunks"] = outgoing.chunks.len().into();
+                    }
+                    if let Some(lat) = broadcast_lat_ms {
+                        extra["broadcast_lat_ms"] = lat.into();
+                    }
+                    if let Some(intended) = intended_ms {
+                        extra["intended_ms"] = intended.into();
+                        extra["actual_ms"] = outgoing.sent_ms.into();
+                    }
+                    if redundancy.count > 1 {
+                        extra["copy"] = 0.into();
+                    }
+                    extra
+                },
+            })?;
+
+            // Injected duplicate: a copy framed with a later sent_ms, so gossip
+            // does not deduplicate it as the same message. With --encrypt it is
+            // sealed under a nonce of its own, never the original's.
+            if inject.dup_prob > 0.0 && inject_rng.random_bool(inject.dup_prob) {
+                let dup = encode(
+                    outgoing.seq,
+                    now_ms().max(outgoing.sent_ms + 1),
+                    outgoing.sched_ms,
+                    0,
+                )?;
+                match send_chunks(&*transport, log, &mut guard, &dup.chunks, &mut chunks_sent)
+                    .await?
+                {
+                    ChunkSend::Stopped(cause) => {
+                        terminated_by = cause;
+                        break 'send;
+                    }
+                    ChunkSend::Failed(e) => {
+                        *errors_by_kind.entry(e.kind().to_string()).or_default() += 1;
+                        warn!("broadcast error: {e}");
+                    }
+                    ChunkSend::Sent => {
+                        injection.dups_injected += 1;
+                        log_inject(
+                            transport,
+                            log,
+                            EventKind::InjectDup,
+                            test_id,
+                            outgoing.seq,
+                            None,
+                        )?;
+                    }
+                }
+            }
+        }
+
+        if no_wait && seq + 1 == test_total {
+            break;
+        }
+
+        // Maintain the configured send rate (answering NACKs meanwhile in repair
+        // mode), collecting receiver announcements either way, and send the
+        // copies and batches due in between. Messages owed after a pause with `--catch-up`
+        // go out back to back. Replayed messages wait for their offset instead.
+        let rate = rate_control.as_ref().map_or(base_rate, |c| c.rate);
+        let deadline = if replay.is_some() {
+            now_ms()
+        } else if pauser.owed > 0 {
+            pauser.owed -= 1;
+            now_ms()
+        } else {
+            sched_ms += send_interval_ms(rate);
+            now_ms() + send_interval_ms(rate)
+        };
+        loop {
+            let step = [redundancy.next_due(), batcher.due()]
+                .into_iter()
+                .flatten()
+                .fold(deadline, u64::min);
+            if !wait_until(
+                transport,
+                log,
+                repair.as_mut(),
+                &mut errors_by_kind,
+                &mut control,
+                step,
+            )
+            .await?
+            {
+                terminated_by = Termination::TransportClosed;
+                break 'send;
+            }
+            if let Some(cause) = redundancy
+                .send_due(
+                    &*transport,
+                    log,
+                    &mut guard,
+                    &mut encode,
+                    &mut chunks_sent,
+                    &mut errors_by_kind,
+                )
+                .await?
+            {
+                terminated_by = cause;
+                break 'send;
+            }
+            if batcher.due().is_some_and(|due| due <= now_ms())
+                && let Some(cause) = batcher
+                    .flush(
+                        &*transport,
+                        log,
+                        &mut guard,
+                        &mut chunks_sent,
+                        &mut errors_by_kind,
+                    )
+                    .await?
+            {
+                terminated_by = cause;
+                break 'send;
+            }
+            if now_ms() >= deadline {
+                break;
+            }
+        }
+    }
+
+    // Messages counted as sent must not stay in an incomplete batch.
+    if terminated_by != Termination::TransportClosed
+        && let Some(cause) = batcher
+            .flush(
+                &*transport,
+                log,
+                &mut guard,
+                &mut chunks_sent,
+                &mut errors_by_kind,
+            )
+            .await?
+        && terminated_by == Termination::Complete
+    {
+        terminated_by = cause;
+    }
+
+    // Copies of the last messages are still part of the stream.
+    while terminated_by == Termination::Complete
+        && let Some(due) = redundancy.next_due()
+    {
+        if !wait_until(
+            transport,
+            log,
+            repair.as_mut(),
+            &mut errors_by_kind,
+            &mut control,
+            due,
+        )
+        .await?
+        {
+            terminated_by = Termination::TransportClosed;
+            break;
+        }
+        if let Some(cause) = redundancy
+            .send_due(
+                &*transport,
+                log,
+                &mut guard,
+                &mut encode,
+                &mut chunks_sent,
+                &mut errors_by_kind,
+            )
+            .await?
+        {
+            terminated_by = cause;
+        }
+    }
+
+    // Keep answering NACKs until the receivers have gone quiet.
+    if let (Some(server), Some(linger_ms)) = (repair.as_mut(), repair_linger_ms)
+        && terminated_by == Termination::Complete
+        && !no_wait
+    {
+        loop {
+            if let Some(cause) = guard.check(&*transport, log)? {
+                terminated_by = cause;
+                break;
+            }
+            let deadline = now_ms() + linger_ms;
+            match server
+                .serve_until(transport, log, &mut errors_by_kind, &mut control, deadline)
+                .await?
+            {
+                Some(0) | None => break,
+                Some(_) => {}
+            }
+        }
+    }
+
+    // Give the final acks time to arrive.
+    if control.acks.is_some() && terminated_by == Termination::Complete && !no_wait {
+        let deadline = now_ms() + ACK_LINGER_MS;
+        while !control.all_acked(messages_sent) && now_ms() < deadline {
+            let step = (now_ms() + ACK_POLL_MS).min(deadline);
+            listen_until(transport, log, &mut control, step).await?;
+        }
+    }
+
+    // Trailing window for what the swarm does once the stream has stopped.
+    let linger_events = match linger_ms > 0 && terminated_by == Termination::Complete {
+        true => Some(linger(transport, log, &mut errors_by_kind, &mut control, linger_ms).await?),
+        false => None,
+    };
+
+    meter.poll(&*transport, log)?;
+    control.receivers.finish(now_ms(), messages_sent);
+    let join_stages = transport.join_stages();
+    let summary = SenderSummary {
+        test_id: hex::encode(test_id),
+        run_id: run_id().to_string(),
+        topic: transport.topic(),
+        topic_derivation: transport.topic_derivation().map(|d| d.as_str().to_string()),
+        messages_sent,
+        total: test_total,
+        rate: rate_per_sec,
+        payload_size,
+        duration_ms: now_ms().saturating_sub(start_ms),
+        protocol: protocol_delta(transport, protocol_start),
+        errors_by_kind,
+        task_exits: transport.task_exits(),
+        broadcast_scope: transport.broadcast_scope().as_str().to_string(),
+        repair: repair.map(|s| s.counters),
+        compression: (compression != Compression::None).then_some(compression_stats),
+        crypto: encryption.is_some().then_some(crypto_stats),
+        signed: signer.is_some(),
+        chunk_bytes,
+        chunks_sent,
+        heartbeats_sent,
+        broadcast_lat_p50_ms: meter.quantile(0.50),
+        broadcast_lat_p99_ms: meter.quantile(0.99),
+        max_tx_queue_depth: meter.max_depth.map(|d| d as u64),
+        adaptive: rate_control.map(|c| c.summary),
+        injection: inject.is_active().then_some(injection),
+        acks: control.acks.map(|a| a.summary(messages_sent)),
+        pauses: pauser.finish(),
+        log_write_errors: log.write_errors(),
+        replay: replay.is_some().then(|| ReplayStats::new(sched_errors)),
+        concurrent_sender_detected: !other_tests.is_empty(),
+        other_test_ids: other_tests.iter().map(hex::encode).collect(),
+        no_wait,
+        linger_events,
+        redundancy: redundancy.count,
+        redundancy_gap_ms,
+        copies_sent: redundancy.sent,
+        batch: batcher.size,
+        batch_max_wait_ms,
+        batches_sent: batcher.sent,
+        receivers: control.receivers,
+        joined: transport.joined(),
+        join_wait_ms: transport.join_wait_ms(),
+        join_stages: join_stages.clone(),
+        join_breakdown: JoinBreakdown::from_stages(&join_stages),
+        terminated_by,
+        influx: None,
+        payload,
+        efficiency: payload.map(|p| p.efficiency()),
+    };
+    log_summary(&*transport, log, "sender", &summary, false)?;
+    Ok(summary)
+}
+
+/// Longest the sender waits for outstanding acks after the last send, and how
+/// often it checks whether they are complete.
+const ACK_LINGER_MS: u64 = 2000;
+const ACK_POLL_MS: u64 = 50;
+
+/// Interval of the sender's coverage progress line.
+const COVERAGE_PROGRESS_MS: u64 = 1000;
+
+/// Receiver control messages seen by the sender: announcements, and acks
+/// when they are collected.
+struct ControlSeen {
+    test_id: [u8; 16],
+    total: u64,
+    sent: u64,
+    receivers: ReceiversSeen,
+    next_progress_ms: u64,
+    acks: Option<AckCollector>,
+}
+
+impl ControlSeen {
+    fn new(test_id: [u8; 16], total: u64, collect_acks: bool) -> Self {
+        Self {
+            test_id,
+            total,
+            sent: 0,
+            receivers: ReceiversSeen::default(),
+            next_progress_ms: 0,
+            acks: collect_acks.then(AckCollector::default),
+        }
+    }
+
+    /// Count a sent message, noting the send time of `seq` for the ack lag.
+    fn note_sent(&mut self, seq: u64) {
+        self.sent += 1;
+        if let Some(acks) = self.acks.as_mut() {
+            acks.sent_ms.entry(seq).or_insert_with(now_ms);
+        }
+    }
+
+    /// Take a gossip payload if it is a receiver control message; an
+    /// announcement updates the coverage table and logs a `coverage` event.
+    fn note(
+        &mut self,
+        transport: &dyn Transport,
+        log: &mut dyn EventSink,
+        content: &[u8],
+    ) -> anyhow::Result<()> {
+        if let Some(alive) = frame::decode_announce(content) {
+            let peer = alive.peer_id.clone();
+            self.receivers.note(alive, now_ms());
+            self.log_coverage(transport, log, &peer)?;
+        } else if let Some(acks) = self.acks.as_mut()
+            && let Some(ack) = frame::decode_ack(content)
+            && ack.test_id == self.test_id
+        {
+            acks.note(&ack);
+        }
+        Ok(())
+    }
+
+    fn log_coverage(
+        &self,
+        transport: &dyn Transport,
+        log: &mut dyn EventSink,
+        peer: &str,
+    ) -> anyhow::Result<()> {
+        log.emit(LogEvent {
+            ts_ms: now_ms(),
+            elapsed_ms: None,
+            role: "sender",
+            peer_id: &transport.id(),
+            run_id: None,
+            event: EventKind::Coverage,
+            seq: None,
+            lat_ms: None,
+            ldh: None,
+            extra: serde_json::json!({
+                "receiver": peer,
+                "received_unique": self.receivers.last_unique.get(peer),
+                "messages_sent": self.sent,
+                "stale": self.receivers.stale.contains(peer),
+            }),
+        })
+    }
+
+    /// Once a second while receivers announce: mark the silent ones stale and
+    /// print the coverage table.
+    fn progress(
+        &mut self,
+        transport: &dyn Transport,
+        log: &mut dyn EventSink,
+    ) -> anyhow::Result<()> {
+        let now = now_ms();
+        if self.receivers.last_unique.is_empty() || now < self.next_progress_ms {
+            return Ok(());
+        }
+        self.next_progress_ms = now + COVERAGE_PROGRESS_MS;
+        for peer in self.receivers.mark_stale(now) {
+            self.log_coverage(transport, log, &peer)?;
+        }
+        let table: Vec<String> = self
+            .receivers
+            .last_unique
+            .iter()
+            .map(|(peer, unique)| {
+                let short: String = peer
+                    .rsplit(':')
+                    .next()
+                    .unwrap_or(peer)
+                    .chars()
+                    .take(8)
+                    .collect();
+                let stale = if self.receivers.stale.contains(peer) {
+                    " (stale)"
+                } else {
+                    ""
+                };
+                format!("{short}={unique}{stale}")
+            })
+            .collect();
+        diag!(
+            "progress msg {}/{}, acked coverage: {}",
+            self.sent,
+            self.total,
+            table.join(" ")
+        );
+        Ok(())
+    }
+
+    /// Whether every receiver heard from (announced or acked) has acked all
+    /// `messages_sent` messages.
+    fn all_acked(&self, messages_sent: u64) -> bool {
+        let Some(acks) = self.acks.as_ref() else {
+            return true;
+        };
+        !acks.receivers.is_empty()
+            && acks
+                .receivers
+                .values()
+                .all(|r| r.contiguous >= messages_sent)
+            && self
+                .receivers
+                .last_unique
+                .keys()
+                .all(|id| acks.receivers.contains_key(id))
+    }
+}
+
+/// Sender-side ack bookkeeping (`--ack-every`).
+#[derive(Default)]
+struct AckCollector {
+    /// When each seq was first sent.
+    sent_ms: BTreeMap<u64, u64>,
+    receivers: BTreeMap<String, ReceiverAcks>,
+}
+
+#[derive(Default)]
+struct ReceiverAcks {
+    acks: u64,
+    /// Every seq below this is acked.
+    contiguous: u64,
+    /// Acked seqs at or above `contiguous`.
+    beyond: BTreeSet<u64>,
+    /// Ack lag of each newly acked seq.
+    lags: Vec<u64>,
+}
+
+impl AckCollector {
+    fn note(&mut self, ack: &AckUpTo) {
+        let now = now_ms();
+        let sent_ms = &self.sent_ms;
+        let r = self.receivers.entry(ack.receiver_id.clone()).or_default();
+        r.acks += 1;
+        let newly_acked = |seq: u64, lags: &mut Vec<u64>| {
+            if let Some(sent) = sent_ms.get(&seq) {
+                lags.push(now.saturating_sub(*sent));
+            }
+        };
+        for seq in r.contiguous..ack.contiguous() {
+            if !r.beyond.remove(&seq) {
+                newly_acked(seq, &mut r.lags);
+            }
+        }
+        r.contiguous = r.contiguous.max(ack.contiguous());
+        for seq in ack.bitmap_seqs() {
+            if seq >= r.contiguous && r.beyond.insert(seq) {
+                newly_acked(seq, &mut r.lags);
+            }
+        }
+    }
+
+    fn summary(self, messages_sent: u64) -> BTreeMap<String, AckStats> {
+        self.receivers
+            .into_iter()
+            .map(|(id, r)| (id, AckStats::new(r.acks, r.lags, messages_sent)))
+            .collect()
+    }
+}
+
+/// Waits until `deadline_ms`, answering NACKs meanwhile in repair mode and
+/// noting receiver control messages either way. Returns `false` once the event
+/// stream has ended in repair mode.
+async fn wait_until(
+    transport: &mut dyn Transport,
+    log: &mut dyn EventSink,
+    repair: Option<&mut RepairServer>,
+    errors_by_kind: &mut BTreeMap<String, u64>,
+    control: &mut ControlSeen,
+    deadline_ms: u64,
+) -> anyhow::Result<bool> {
+    match repair {
+        Some(server) => Ok(server
+            .serve_until(transport, log, errors_by_kind, control, deadline_ms)
+            .await?
+            .is_some()),
+        None => {
+            listen_until(transport, log, control, deadline_ms).await?;
+            Ok(true)
+        }
+    }
+}
+
+/// Sleeps until `deadline_ms` while noting receiver control messages and join stages.
+async fn listen_until(
+    transport: &mut dyn Transport,
+    log: &mut dyn EventSink,
+    control: &mut ControlSeen,
+    deadline_ms: u64,
+) -> anyhow::Result<()> {
+    loop {
+        let remaining = Duration::from_millis(deadline_ms.saturating_sub(now_ms()));
+        if remaining.is_zero() {
+            return Ok(());
+        }
+        let event = select! {
+            _ = sleep(remaining) => return Ok(()),
+            event = transport.next() => event,
+        };
+        match event {
+            Some(Ok(TransportEvent::Msg { content, .. })) => {
+                control.note(&*transport, log, &content)?
+            }
+            Some(Ok(TransportEvent::JoinProgress { stage, ts_ms })) => {
+                log_join_stage(transport, log, "sender", stage, ts_ms)?;
+            }
+            Some(Ok(TransportEvent::TaskExited { which, reason })) => {
+                log_task_exit(transport, log, "sender", which, &reason)?;
+            }
+            Some(_) => {}
+            // The stream has ended; keep the send pacing regardless.
+            None => {
+                sleep(remaining).await;
+                return Ok(());
+            }
+        }
+    }
+}
+
+/// Drains transport events for `linger_ms` after the last send, logging
+/// neighbor changes and counting every event by type. Ends early if the
+/// event stream does.
+async fn linger(
+    transport: &mut dyn Transport,
+    log: &mut dyn EventSink,
+    errors_by_kind: &mut BTreeMap<String, u64>,
+    control: &mut ControlSeen,
+    linger_ms: u64,
+) -> anyhow::Result<LingerEvents> {
+    let start_ms = now_ms();
+    let deadline_ms = start_ms + linger_ms;
+    let mut counts = LingerEvents::default();
+    loop {
+        let remaining = Duration::from_millis(deadline_ms.saturating_sub(now_ms()));
+        if remaining.is_zero() {
+            break;
+        }
+        let event = select! {
+            _ = sleep(remaining) => break,
+            event = transport.next() => event,
+        };
+        let (kind, peer) = match event {
+            Some(Ok(TransportEvent::Msg { content, .. })) => {
+                counts.messages += 1;
+                control.note(&*transport, log, &content)?;
+                continue;
+            }
+            Some(Ok(TransportEvent::NeighborUp(peer))) => {
+                counts.neighbor_up += 1;
+                (EventKind::NeighborUp, peer)
+            }
+            Some(Ok(TransportEvent::NeighborDown(peer))) => {
+                counts.neighbor_down += 1;
+                (EventKind::NeighborDown, peer)
+            }
+            Some(Ok(TransportEvent::RejoinAttempt { .. })) => {
+                counts.rejoin_attempts += 1;
+                continue;
+            }
+            Some(Ok(TransportEvent::JoinProgress { stage, ts_ms })) => {
+                log_join_stage(transport, log, "sender", stage, ts_ms)?;
+                continue;
+            }
+            Some(Ok(TransportEvent::TaskExited { which, reason })) => {
+                log_task_exit(transport, log, "sender", which, &reason)?;
+                continue;
+            }
+            Some(Ok(_)) => {
+                counts.other += 1;
+                continue;
+            }
+            Some(Err(e)) => {
+                counts.errors += 1;
+                *errors_by_kind.entry(e.kind().to_string()).or_default() += 1;
+                if e.is_fatal() {
+                    break;
+                }
+                continue;
+            }
+            None => break,
+        };
+        log.emit(LogEvent {
+            ts_ms: now_ms(),
+            elapsed_ms: None,
+            role: "sender",
+            peer_id: &transport.id(),
+            run_id: None,
+            event: kind,
+            seq: None,
+            lat_ms: None,
+            ldh: None,
+            extra: serde_json::json!({ "peer": peer, "linger": true }),
+        })?;
+    }
+    counts.linger_ms = now_ms().saturating_sub(start_ms);
+    Ok(counts)
+}
+
+/// Listen for `probe_ms` for data or heartbeats of another sender, logging a
+/// `concurrent_sender_detected` event per test id heard.
+async fn probe_other_senders(
+    transport: &mut dyn Transport,
+    log: &mut dyn EventSink,
+    encryption: Option<&Encryption>,
+    probe_ms: u64,
+) -> anyhow::Result<BTreeSet<[u8; 16]>> {
+    let deadline_ms = now_ms() + probe_ms;
+    let mut seen = BTreeSet::new();
+    loop {
+        let remaining = Duration::from_millis(deadline_ms.saturating_sub(now_ms()));
+        if remaining.is_zero() {
+            return Ok(seen);
+        }
+        let event = select! {
+            _ = sleep(remaining) => return Ok(seen),
+            event = transport.next() => event,
+        };
+        let content = match event {
+            Some(Ok(TransportEvent::Msg { content, .. })) => content,
+            Some(Ok(TransportEvent::JoinProgress { stage, ts_ms })) => {
+                log_join_stage(transport, log, "sender", stage, ts_ms)?;
+                continue;
+            }
+            Some(_) => continue,
+            None => return Ok(seen),
+        };
+        let (test_id, source) = match frame::decode_heartbeat(&content) {
+            Some(hb) => (hb.test_id, "heartbeat"),
+            None => match frame::decode(&content, encryption)
+                .ok()
+                .and_then(|f| postcard::from_bytes::<DataMsg>(&f.body).ok())
+            {
+                Some(m) => (m.test_id, "data"),
+                None => continue,
+            },
+        };
+        if seen.insert(test_id) {
+            log.emit(LogEvent {
+                ts_ms: now_ms(),
+                elapsed_ms: None,
+                role: "sender",
+                peer_id: &transport.id(),
+                run_id: None,
+                event: EventKind::ConcurrentSenderDetected,
+                seq: None,
+                lat_ms: None,
+                ldh: None,
+                extra: serde_json::json!({
+                    "test_id": hex::encode(test_id),
+                    "source": source,
+                }),
+            })?;
+        }
+    }
+}
+
+/// Log a transport task that ended early, with its reason.
+fn log_task_exit(
+    transport: &dyn Transport,
+    log: &mut dyn EventSink,
+    role: &'static str,
+    which: &str,
+    reason: &str,
+) -> anyhow::Result<()> {
+    log.emit(LogEvent {
+        ts_ms: now_ms(),
+        elapsed_ms: None,
+        role,
+        peer_id: &transport.id(),
+        run_id: None,
+        event: EventKind::TaskExited,
+        seq: None,
+        lat_ms: None,
+        ldh: None,
+        extra: serde_json::json!({ "task": which, "reason": reason }),
+    })
+}
+
+/// Log a join milestone, keeping the time it was reached.
+fn log_join_stage(
+    transport: &dyn Transport,
+    log: &mut dyn EventSink,
+    role: &'static str,
+    stage: &str,
+    ts_ms: u64,
+) -> anyhow::Result<()> {
+    // Stamped when written, so the log stays ordered; the milestone time is in `extra`.
+    let offset_ms = ts_ms.saturating_sub(process_start_ms());
+    log.emit(LogEvent {
+        ts_ms: now_ms(),
+        elapsed_ms: None,
+        role,
+        peer_id: &transport.id(),
+        run_id: None,
+        event: EventKind::JoinStage,
+        seq: None,
+        lat_ms: None,
+        ldh: None,
+        extra: serde_json::json!({
+            "stage": stage,
+            "stage_ts_ms": ts_ms,
+            "offset_ms": offset_ms,
+        }),
+    })
+}
+
+/// Sender-side state of the repair layer: every sent payload, kept so NACKed
+/// sequences can be retransmitted verbatim.
+struct RepairServer {
+    test_id: [u8; 16],
+    sent: Vec<Bytes>,
+    counters: RepairCounters,
+}
+
+impl RepairServer {
+    fn new(test_id: [u8; 16]) -> Self {
+        Self {
+            test_id,
+            sent: Vec::new(),
+            counters: RepairCounters::default(),
+        }
+    }
+
+    /// Answers NACKs until `deadline_ms`, noting receiver control messages meanwhile.
+    ///
+    /// Returns the number of NACKs handled, or `None` once the event stream has ended.
+    async fn serve_until(
+        &mut self,
+        transport: &mut dyn Transport,
+        log: &mut dyn EventSink,
+        errors_by_kind: &mut BTreeMap<String, u64>,
+        control: &mut ControlSeen,
+        deadline_ms: u64,
+    ) -> anyhow::Result<Option<u64>> {
+        let mut handled = 0;
+        loop {
+            let remaining = deadline_ms.saturating_sub(now_ms());
+            if remaining == 0 {
+                return Ok(Some(handled));
+            }
+            let event = select! {
+                _ = sleep(Duration::from_millis(remaining)) => return Ok(Some(handled)),
+                event = transport.next() => event,
+            };
+            let (from, content) = match event {
+                Some(Ok(TransportEvent::Unicast { from, content })) => (from, content),
+                Some(Ok(TransportEvent::Msg { content, .. })) => {
+                    control.note(&*transport, log, &content)?;
+                    continue;
+                }
+                Some(Ok(TransportEvent::JoinProgress { stage, ts_ms })) => {
+                    log_join_stage(transport, log, "sender", stage, ts_ms)?;
+                    continue;
+                }
+                Some(Ok(TransportEvent::TaskExited { which, reason })) => {
+                    log_task_exit(transport, log, "sender", which, &reason)?;
+                    continue;
+                }
+                Some(Ok(_)) => continue,
+                Some(Err(e)) => {
+                    *errors_by_kind.entry(e.kind().to_string()).or_default() += 1;
+                    if e.is_fatal() {
+                        return Ok(None);
+                    }
+                    continue;
+                }
+                None => return Ok(None),
+            };
+            let Ok(RepairMsg::Nack { test_id, seqs }) = postcard::from_bytes(&content) else {
+                continue;
+            };
+            if test_id != self.test_id {
+                continue;
+            }
+            handled += 1;
+            self.counters.nacks_received += 1;
+            self.counters.seqs_requested += seqs.len() as u64;
+
+            // The list comes off the network: answer each sent seq once, and no
+            // more of them than a receiver asks for in one NACK.
+            let requested = seqs.len();
+            let seqs: BTreeSet<u64> = seqs
+                .into_iter()
+                .filter(|&seq| seq < self.sent.len() as u64)
+                .collect();
+            let ignored = requested - seqs.len().min(MAX_NACK_SEQS);
+            let mut resent = 0u64;
+            for seq in seqs.into_iter().take(MAX_NACK_SEQS) {
+                let data = &self.sent[seq as usize];
+                let msg = RepairMsg::Retransmit {
+                    data: data.to_vec(),
+                };
+                let bytes = Bytes::from(postcard::to_allocvec(&msg)?);
+                let len = bytes.len() as u64;
+                match transport.send_to(&from, bytes).await {
+                    Ok(()) => {
+                        resent += 1;
+                        self.counters.retransmissions += 1;
+                        self.counters.repair_bytes += len;
+                    }
+                    Err(e) => {
+                        *errors_by_kind.entry(e.kind().to_string()).or_default() += 1;
+                        warn!("repair send error: {e}");
+                        break;
+                    }
+                }
+            }
+
+            log.emit(LogEvent {
+                ts_ms: now_ms(),
+                elapsed_ms: None,
+                role: "sender",
+                peer_id: &transport.id(),
+                run_id: None,
+                event: EventKind::RepairServed,
+                seq: None,
+                lat_ms: None,
+                ldh: None,
+                extra: serde_json::json!({ "to": from, "resent": resent, "ignored": ignored }),
+            })?;
+        }
+    }
+}
+
+/// Decodes a received frame, accounting decryption cost and failures.
+///
+/// Authentication failures are logged as `auth_failure`. The first frame whose
+/// encryption setting differs from ours is logged as `encryption_mismatch` and
+/// reported on stderr, so a mismatched run doesn't end at 0% delivery without
+/// explanation. Malformed frames are ignored like any non-benchmark payload.
+fn unframe(
+    transport: &dyn Transport,
+    log: &mut dyn EventSink,
+    stats: &mut Stats,
+    content: &[u8],
+    encryption: Option<&Encryption>,
+) -> anyhow::Result<Option<frame::Decoded>> {
+    let start = Instant::now();
+    let err = match frame::decode(content, encryption) {
+        Ok(decoded) => {
+            if encryption.is_some() {
+                let overhead = frame::overhead(true, false) - frame::HEADER_LEN;
+                stats.note_decrypted(overhead, start.elapsed().as_micros() as u64);
+            }
+            return Ok(Some(decoded));
+        }
+        Err(e) => e,
+    };
+
+    let event = match &err {
+        FrameError::AuthFailed => {
+            stats.note_auth_failure();
+            EventKind::AuthFailure
+        }
+        e if e.is_mismatch() => {
+            if !stats.note_encryption_mismatch() {
+                return Ok(None);
+            }
+            diag!("encryption_mismatch: {e}");
+            EventKind::EncryptionMismatch
+        }
+        _ => {
+            stats.note_decode_error();
+            return Ok(None);
+        }
+    };
+    log.emit(LogEvent {
+        ts_ms: now_ms(),
+        elapsed_ms: None,
+        role: "receiver",
+        peer_id: &transport.id(),
+        run_id: None,
+        event,
+        seq: None,
+        lat_ms: None,
+        ldh: None,
+        extra: serde_json::json!({ "error": err.to_string() }),
+    })?;
+    Ok(None)
+}
+
+/// Applies the receiver's signature policy to a decoded message.
+///
+/// Returns whether the message may be counted; rejects are tallied and logged
+/// as `sig_reject` events. Without a policy every message is accepted.
+fn accept_signed(
+    transport: &dyn Transport,
+    log: &mut dyn EventSink,
+    stats: &mut Stats,
+    policy: Option<&SignaturePolicy>,
+    frame: &frame::Decoded,
+    m: &DataMsg,
+) -> anyhow::Result<bool> {
+    let Some(policy) = policy else {
+        return Ok(true);
+    };
+    let result = match &frame.signature {
+        None => Err(SigReject::Unsigned),
+        Some(sig) => sig.check(&m.test_id, m.seq, m.sent_ms, &policy.allow),
+    };
+    let reject = match result {
+        Ok(_) => return Ok(true),
+        Err(reject) => reject,
+    };
+
+    stats.note_sig_reject(reject);
+    log.emit(LogEvent {
+        ts_ms: now_ms(),
+        elapsed_ms: None,
+        role: "receiver",
+        peer_id: &transport.id(),
+        run_id: None,
+        event: EventKind::SigReject,
+        seq: Some(m.seq),
+        lat_ms: None,
+        ldh: None,
+        extra: serde_json::json!({ "reason": reject.as_str() }),
+    })?;
+    Ok(false)
+}
+
+/// With `--expect-sender`, log a message of another origin as `recv_foreign`,
+/// count it and return true so it stays out of the statistics.
+///
+/// The origin is the signer of a signed frame, which is exact. Unsigned frames
+/// only carry the neighbor that delivered them, which is the sender only on a
+/// direct hop, so on a multi-hop overlay they are matched approximately.
+fn reject_foreign(
+    transport: &dyn Transport,
+    log: &mut dyn EventSink,
+    stats: &mut Stats,
+    expect: &[PublicKey],
+    frame: &frame::Decoded,
+    m: &DataMsg,
+    delivered_from: &str,
+) -> anyhow::Result<bool> {
+    if expect.is_empty() {
+        return Ok(false);
+    }
+    let signer = frame
+        .signature
+        .as_ref()
+        .and_then(|sig| sig.check(&m.test_id, m.seq, m.sent_ms, &[]).ok());
+    let (origin, source) = match signer {
+        Some(signer) => (Some(signer), "signature"),
+        None => (delivered_from.parse::<PublicKey>().ok(), "delivered_from"),
+    };
+    if origin.is_some_and(|o| expect.contains(&o)) {
+        return Ok(false);
+    }
+
+    stats.note_foreign();
+    log.emit(LogEvent {
+        ts_ms: now_ms(),
+        elapsed_ms: None,
+        role: "receiver",
+        peer_id: &transport.id(),
+        run_id: None,
+        event: EventKind::RecvForeign,
+        seq: Some(m.seq),
+        lat_ms: None,
+        ldh: None,
+        extra: serde_json::json!({
+            "origin": origin.map(|o| o.to_string()),
+            "origin_source": source,
+            "from": delivered_from,
+            "test_id": hex::encode(m.test_id),
+        }),
+    })?;
+    Ok(true)
+}
+
+/// Broadcast this receiver's cumulative ack of `test_id` (`--ack-every`).
+async fn send_ack(
+    transport: &dyn Transport,
+    stats: &mut Stats,
+    test_id: [u8; 16],
+) -> anyhow::Result<()> {
+    let ack = stats.ack(test_id, transport.id());
+    match transport
+        .broadcast(Bytes::from(frame::encode_ack(&ack)?))
+        .await
+    {
+        Ok(()) => stats.note_ack_sent(),
+        Err(e) => {
+            stats.note_error(e.kind());
+            warn!("ack error: {e}");
+        }
+    }
+    Ok(())
+}
+
+/// With `--max-msg-age`, log a message sent more than `max_age_ms` before
+/// `recv_ts` as `recv_stale`, count it and return true so it stays out of the
+/// statistics. The age is measured against the sender's clock, uncorrected.
+fn reject_stale(
+    transport: &dyn Transport,
+    log: &mut dyn EventSink,
+    stats: &mut Stats,
+    max_age_ms: Option<u64>,
+    m: &DataMsg,
+    recv_ts: u64,
+) -> anyhow::Result<bool> {
+    let age_ms = recv_ts.saturating_sub(m.sent_ms);
+    if max_age_ms.is_none_or(|max| age_ms <= max) {
+        return Ok(false);
+    }
+
+    stats.note_stale();
+    log.emit(LogEvent {
+        ts_ms: recv_ts,
+        elapsed_ms: None,
+        role: "receiver",
+        peer_id: &transport.id(),
+        run_id: None,
+        event: EventKind::RecvStale,
+        seq: Some(m.seq),
+        lat_ms: Some(age_ms),
+        ldh: None,
+        extra: serde_json::json!({ "test_id": hex::encode(m.test_id) }),
+    })?;
+    Ok(true)
+}
+
+/// Log a message `Stats::check_seq` rejects as `recv_implausible_seq` with
+/// the bogus values, count it and return true so it stays out of the
+/// statistics.
+fn reject_implausible(
+    transport: &dyn Transport,
+    log: &mut dyn EventSink,
+    stats: &mut Stats,
+    current_test: Option<[u8; 16]>,
+    m: &DataMsg,
+    recv_ts: u64,
+) -> anyhow::Result<bool> {
+    let Some(bad) = stats.check_seq(m, current_test == Some(m.test_id)) else {
+        return Ok(false);
+    };
+
+    stats.note_implausible_seq();
+    let mut extra = serde_json::to_value(bad)?;
+    extra["test_id"] = hex::encode(m.test_id).into();
+    log.emit(LogEvent {
+        ts_ms: recv_ts,
+        elapsed_ms: None,
+        role: "receiver",
+        peer_id: &transport.id(),
+        run_id: None,
+        event: EventKind::RecvImplausibleSeq,
+        seq: Some(m.seq),
+        lat_ms: None,
+        ldh: None,
+        extra,
+    })?;
+    Ok(true)
+}
+
+/// Wire bytes since `start`, if the transport exposes them.
+fn wire_delta(transport: &dyn Transport, start: Option<WireBytes>) -> Option<WireBytes> {
+    let (now, start) = (transport.wire_bytes()?, start?);
+    Some(WireBytes {
+        tx: now.tx.saturating_sub(start.tx),
+        rx: now.rx.saturating_sub(start.rx),
+    })
+}
+
+/// Protocol counter increase since `start`, if the transport exposes counters.
+fn protocol_delta(
+    transport: &dyn Transport,
+    start: Option<ProtocolCounters>,
+) -> Option<ProtocolCounters> {
+    Some(transport.protocol_counters()?.since(&start?))
+}
+
+/// Receiver-side run options.
+#[derive(Debug, Clone)]
+pub struct ReceiverOptions {
+    /// Idle timeout (ms) before summarizing.
+    pub idle_report_ms: u64,
+    /// Interval (ms) between `neighbor_snapshot` events (0 disables snapshots).
+    pub neighbor_sample_ms: u64,
+    /// Interval (ms) between connection-level samples such as `net_stats` (0 disables).
+    pub conn_sample_ms: u64,
+    /// Interval (ms) between `resource` samples of this process (0 disables).
+    pub resource_sample_ms: u64,
+    /// Percentiles and quantile estimator used for the summary.
+    pub stats: StatsConfig,
+    /// NACK-based repair over the unicast side-channel (None = baseline run).
+    pub repair: Option<RepairOptions>,
+    /// Expect payloads encrypted with the topic-derived key.
+    pub encryption: Option<Encryption>,
+    /// Only count signed messages (optionally from allowed signers only).
+    pub require_signed: Option<SignaturePolicy>,
+    /// Only count messages originating from these senders (empty = all), see
+    /// `reject_foreign`.
+    pub expect_sender: Vec<PublicKey>,
+    /// Leave out messages sent longer than this (ms) before they arrived (None = keep all).
+    pub max_msg_age_ms: Option<u64>,
+    /// Probe the RTT to every neighbor before and after the run (None = skip).
+    pub rtt_probe: Option<RttProbeOptions>,
+    /// Check the padding of each data message against this pattern and seed
+    /// (None = unchecked).
+    pub verify_pad: Option<(PadPattern, u64)>,
+    /// Re-send received payloads to a local UDP socket (`--forward-udp`).
+    pub forward: Option<Forwarder>,
+    /// Partially reassembled frames are dropped after this long (ms).
+    pub reassembly_timeout_ms: u64,
+    /// Cap on buffered chunk data; the oldest partials are evicted beyond it.
+    pub reassembly_max_bytes: usize,
+    /// Stop after this long (ms) even if messages keep trickling in.
+    pub max_runtime_ms: Option<u64>,
+    /// Log a `watchdog_stall` event after this long (ms) without any transport event.
+    pub watchdog_ms: Option<u64>,
+    /// Once every expected message arrived, stop after this long (ms).
+    pub complete_grace_ms: u64,
+    /// Wake the receiver loop at least this often (ms) in addition to its
+    /// computed deadlines (None = purely event- and deadline-driven).
+    pub poll_floor_ms: Option<u64>,
+    /// Write only every Nth `recv` event (1 = all), see `RecvSampler`.
+    pub log_sample_recv: u64,
+    /// Broadcast a `ReceiverAlive` announcement at this interval (ms) (None disables).
+    pub announce_ms: Option<u64>,
+    /// Broadcast a cumulative `AckUpTo` every this many new messages, and once
+    /// the test is complete or ends (None disables).
+    pub ack_every: Option<u64>,
+}
+
+/// Receiver-side signature requirements.
+#[derive(Debug, Clone, Default)]
+pub struct SignaturePolicy {
+    /// Allowed signer keys (empty = any valid signature).
+    pub allow: Vec<PublicKey>,
+}
+
+/// RTT probes bracketing a receiver run.
+#[derive(Debug, Clone, Copy)]
+pub struct RttProbeOptions {
+    /// Echo probes per neighbor.
+    pub count: u32,
+    /// Longest (ms) one round of probes may hold up the run.
+    pub budget_ms: u64,
+}
+
+/// Receiver-side repair settings.
+#[derive(Debug, Clone)]
+pub struct RepairOptions {
+    /// Node ID of the sender that serves retransmissions.
+    pub peer: String,
+    /// Sequences missing for this long (ms) are NACKed (and re-NACKed after as long again).
+    pub after_ms: u64,
+}
+
+/// Maximum number of sequences requested in one NACK, and answered per NACK
+/// by the sender.
+const MAX_NACK_SEQS: usize = 512;
+
+/// Minimum spacing of `recv_oversized` events; the ones in between are only counted.
+const OVERSIZED_LOG_MS: u64 = 1000;
+
+/// Runs the receiver role: continuously listens for incoming messages,
+/// records statistics, and returns a summarized `Summary`.
+///
+/// Termination is two-stage, unless all expected messages arrived first (then the
+/// receiver stops `complete_grace_ms` later, still counting duplicates):
+/// 1) If we have seen at least one valid test message (total_expected > 0),
+///    we stop after `report_after_idle_ms` without further valid test messages.
+/// 2) If we have NOT seen any valid test message at all,
+///    we still stop after `report_after_idle_ms` since start.
+///    This prevents "forever receivers" under high loss/partition.
+///
+/// `max_runtime_ms` and Ctrl-C end the run regardless; the summary's
+/// `terminated_by` records which condition fired.
+///
+/// # Parameters
+/// * `transport` – Active gossip transport.
+/// * `log` – JSONL writer for structured logs.
+/// * `opts` – Idle timeout, sampling intervals and summary configuration.
+pub async fn run_receiver(
+    transport: &mut dyn Transport,
+    log: &mut dyn EventSink,
+    opts: ReceiverOptions,
+) -> anyhow::Result<Summary> {
+    let mut summary = None;
+    receive(transport, log, opts, false, false, |s| {
+        summary = Some(s);
+        Ok(())
+    })
+    .await?;
+    Ok(summary.expect("receive always emits a final summary"))
+}
+
+/// Like `run_receiver`, but every new test_id closes the current test and
+/// starts fresh statistics; `on_summary` is called once per test (in arrival
+/// order) and once more for the last one when the receiver goes idle.
+pub async fn run_receiver_per_test(
+    transport: &mut dyn Transport,
+    log: &mut dyn EventSink,
+    opts: ReceiverOptions,
+    on_summary: impl FnMut(Summary) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    receive(transport, log, opts, true, false, on_summary).await
+}
+
+/// Like `run_receiver_per_test`, but a test that completes or goes idle is
+/// summarized as well, and the receiver keeps listening for the next one
+/// until Ctrl-C or `max_runtime_ms`, which summarize the test in flight (if
+/// any). Every summary is also logged as a `summary` event; neighbor churn
+/// spans the tests.
+pub async fn run_receiver_continuous(
+    transport: &mut dyn Transport,
+    log: &mut dyn EventSink,
+    opts: ReceiverOptions,
+    on_summary: impl FnMut(Summary) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    receive(transport, log, opts, true, true, on_summary).await
+}
+
+/// A `recv` line that was sampled out but may still be written as the last of its test.
+struct HeldRecv {
+    ts_ms: u64,
+    elapsed_ms: u64,
+    seq: u64,
+    lat_ms: u64,
+    ldh: Option<u16>,
+    extra: serde_json::Value,
+}
+
+/// Thins out `recv` log lines for high-rate runs (`--log-sample-recv`).
+///
+/// Every Nth recv of a test is written, plus its first and last ones and any
+/// anomalous arrival (duplicate, out of order, or sent "after" it arrived,
+/// i.e. clock skew). `Stats` still sees every message.
+struct RecvSampler {
+    every: u64,
+    seen: u64,
+    held: Option<HeldRecv>,
+}
+
+impl RecvSampler {
+    fn new(every: u64) -> Self {
+        Self {
+            every: every.max(1),
+            seen: 0,
+            held: None,
+        }
+    }
+
+    /// Write `recv` now or hold it as the potential last line of the test.
+    fn offer(
+        &mut self,
+        transport: &dyn Transport,
+        log: &mut dyn EventSink,
+        recv: HeldRecv,
+        anomalous: bool,
+    ) -> anyhow::Result<()> {
+        let n = self.seen;
+        self.seen += 1;
+        if n.is_multiple_of(self.every) || anomalous {
+            self.held = None;
+            Self::write(transport, log, recv)
+        } else {
+            self.held = Some(recv);
+            Ok(())
+        }
+    }
+
+    /// End of a test: write the held last line and start counting anew.
+    fn finish(&mut self, transport: &dyn Transport, log: &mut dyn EventSink) -> anyhow::Result<()> {
+        self.seen = 0;
+        match self.held.take() {
+            Some(recv) => Self::write(transport, log, recv),
+            None => Ok(()),
+        }
+    }
+
+    fn write(
+        transport: &dyn Transport,
+        log: &mut dyn EventSink,
+        recv: HeldRecv,
+    ) -> anyhow::Result<()> {
+        log.emit(LogEvent {
+            ts_ms: recv.ts_ms,
+            elapsed_ms: Some(recv.elapsed_ms),
+            role: "receiver",
+            peer_id: &transport.id(),
+            run_id: None,
+            event: EventKind::Recv,
+            seq: Some(recv.seq),
+            lat_ms: Some(recv.lat_ms),
+            ldh: recv.ldh,
+            extra: recv.extra,
+        })
+    }
+}
+
+/// Per-test baselines and milestones used to complete a `Summary`.
+struct RunMarks {
+    test_id: Option<[u8; 16]>,
+    protocol_start: Option<ProtocolCounters>,
+    wire_start: Option<WireBytes>,
+    incomplete_reassemblies: u64,
+    time_to_join_ms: Option<u64>,
+    time_to_first_event_ms: Option<u64>,
+    time_to_first_data_ms: Option<u64>,
+    terminated_by: Termination,
+    resources: Option<ResourceUsage>,
+    log_write_errors: u64,
+    forward: Option<ForwardCounters>,
+    neighbor_rtt: BTreeMap<String, NeighborRtt>,
+}
+
+/// Statistics of one topic when several are subscribed; each topic follows
+/// the first test seen on it.
+#[derive(Clone)]
+struct TopicRun {
+    test_id: [u8; 16],
+    stats: Stats,
+}
+
+impl TopicRun {
+    fn complete(&self) -> bool {
+        self.stats.total_expected > 0 && self.stats.received_unique() >= self.stats.total_expected
+    }
+}
+
+/// Summarize `stats` and fill in the transport-level fields.
+///
+/// Per-topic runs become `Summary::topics`, carrying the delivery and
+/// latency metrics of their own stream.
+fn finish_summary(
+    transport: &dyn Transport,
+    stats: &mut Stats,
+    topic_runs: &mut BTreeMap<String, TopicRun>,
+    repair: bool,
+    marks: RunMarks,
+) -> Summary {
+    let mut summary = stats.summarize();
+
+    summary.topics = (!topic_runs.is_empty()).then(|| {
+        topic_runs
+            .iter_mut()
+            .map(|(topic, run)| {
+                let mut s = run.stats.summarize();
+                s.test_id = Some(hex::encode(run.test_id));
+                s.run_id = Some(run_id().to_string());
+                s.sender_run_id = summary.sender_run_id.clone();
+                s.joined = transport.joined();
+                s.join_wait_ms = transport.join_wait_ms();
+                s.saw_test = s.total_expected > 0;
+                s.timed_out_no_data = !s.saw_test;
+                s.terminated_by = Some(marks.terminated_by);
+                (topic.clone(), s)
+            })
+            .collect()
+    });
+
+    summary.test_id = marks.test_id.map(hex::encode);
+    summary.run_id = Some(run_id().to_string());
+    summary.build = Some(build_info());
+    summary.gossip = transport.gossip_config();
+    summary.topic = transport.topic();
+    summary.topic_derivation = transport.topic_derivation().map(|d| d.as_str().to_string());
+    summary.protocol = protocol_delta(transport, marks.protocol_start);
+    summary.repair = repair.then(|| stats.repair_counters());
+    summary.incomplete_reassemblies = marks.incomplete_reassemblies;
+    stats.apply_wire_bytes(&mut summary, wire_delta(transport, marks.wire_start));
+    summary.joined = transport.joined();
+    summary.join_wait_ms = transport.join_wait_ms();
+    summary.saw_test = summary.total_expected > 0;
+    summary.timed_out_no_data = !summary.saw_test;
+    summary.time_to_join_ms = marks.time_to_join_ms;
+    summary.time_to_first_event_ms = marks.time_to_first_event_ms;
+    summary.time_to_first_data_ms = marks.time_to_first_data_ms;
+    summary.join_stages = transport.join_stages();
+    summary.join_breakdown = JoinBreakdown::from_stages(&summary.join_stages);
+    summary.connect = transport.connect_report();
+    summary.max_recv_bytes = transport.max_recv_bytes();
+    summary.terminated_by = Some(marks.terminated_by);
+    summary.resources = marks.resources;
+    summary.log_write_errors = marks.log_write_errors;
+    summary.forward = marks.forward;
+    summary.neighbor_rtt_ms = marks.neighbor_rtt;
+    summary.diagnosis = Some(Diagnosis::of(&summary));
+    summary
+}
+
+/// Probe the RTT to every neighbor at `phase` ("start" or "end") of the run,
+/// logging an `rtt_probe` event per neighbor and keeping min/median in `rtt`.
+async fn probe_rtt(
+    transport: &dyn Transport,
+    log: &mut dyn EventSink,
+    opts: RttProbeOptions,
+    phase: &str,
+    rtt: &mut BTreeMap<String, NeighborRtt>,
+) -> anyhow::Result<()> {
+    let budget = Duration::from_millis(opts.budget_ms);
+    let Some(probes) = transport.probe_rtt(opts.count, budget).await else {
+        return Ok(());
+    };
+    for probe in probes {
+        let (min, median) = probe.min_median();
+        let entry = rtt.entry(probe.peer.clone()).or_default();
+        if phase == "start" {
+            (entry.start_min_ms, entry.start_median_ms) = (min, median);
+        } else {
+            (entry.end_min_ms, entry.end_median_ms) = (min, median);
+        }
+        let mut extra = serde_json::to_value(&probe)?;
+        extra["phase"] = phase.into();
+        extra["min_ms"] = min.into();
+        extra["median_ms"] = median.into();
+        log.emit(LogEvent {
+            ts_ms: now_ms(),
+            elapsed_ms: None,
+            role: "receiver",
+            peer_id: &transport.id(),
+            run_id: None,
+            event: EventKind::RttProbe,
+            seq: None,
+            lat_ms: None,
+            ldh: None,
+            extra,
+        })?;
+    }
+    Ok(())
+}
+
+/// Receiver loop shared by `run_receiver`, `run_receiver_per_test` and
+/// `run_receiver_continuous`.
+///
+/// With `split_tests`, a message of a different test closes the active one;
+/// otherwise messages of other tests are ignored. With `continuous`,
+/// completion and idleness close the active test instead of ending the loop.
+async fn receive(
+    transport: &mut dyn Transport,
+    log: &mut dyn EventSink,
+    opts: ReceiverOptions,
+    split_tests: bool,
+    continuous: bool,
+    mut on_summary: impl FnMut(Summary) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let report_after_idle_ms = opts.idle_report_ms;
+    // Intra-run timing uses the monotonic clock; wall-clock `now_ms` only stamps
+    // log lines and measures latency against the sender's clock.
+    let start_ms = elapsed_ms();
+    let mut next_snapshot_ms = start_ms + opts.neighbor_sample_ms;
+    let mut protocol_start = transport.protocol_counters();
+    let mut wire_start = transport.wire_bytes();
+    let mut next_conn_sample_ms = start_ms + opts.conn_sample_ms;
+    let mut neighbor_paths: HashMap<String, &'static str> = HashMap::new();
+    let mut observed_addrs = transport.observed_addrs();
+    let mut next_announce_ms = start_ms;
+    let mut next_resource_sample_ms = start_ms + opts.resource_sample_ms;
+    let mut resources = (opts.resource_sample_ms > 0).then(ResourceSampler::new);
+    if resources.is_some() && !ResourceSampler::supported() {
+        diag!("--resource-sample: /proc/self is not available here, no resource samples");
+        resources = None;
+    }
+
+    // Last time we saw a valid message for the active test.
+    let mut last_valid_ms = start_ms;
+    // Time the last missing sequence of the active test arrived.
+    let mut complete_since_ms: Option<u64> = None;
+
+    let mut stats = Stats::with_config(opts.stats.clone());
+    let mut current_test: Option<[u8; 16]> = None;
+
+    // Per-topic statistics, only kept when several topics are subscribed.
+    let multi_topic = transport.topics().len() > 1;
+    let mut topic_runs: BTreeMap<String, TopicRun> = BTreeMap::new();
+
+    let mut sampler = RecvSampler::new(opts.log_sample_recv);
+    let mut guard = RunGuard::new("receiver", opts.max_runtime_ms, opts.watchdog_ms);
+    let mut terminated_by = Termination::Idle;
+
+    // The peer view starts at join time with the neighbors the join found (that
+    // first NeighborUp is consumed by the join), or isolated at run start.
+    let mut connected_peers: u64 = 0;
+    match transport.joined_at_ms() {
+        Some(joined_at) if transport.joined() => {
+            let neighbors = transport.neighbors();
+            connected_peers = neighbors.len() as u64;
+            let join_mono = start_ms.saturating_sub(now_ms().saturating_sub(joined_at));
+            stats.start_peer_view(join_mono, connected_peers);
+            stats.note_initial_neighbours(&neighbors, join_mono);
+        }
+        _ => stats.start_peer_view(start_ms, 0),
+    }
+
+    // Reassembly of chunked frames.
+    let mut reassembler =
+        frame::Reassembler::new(opts.reassembly_timeout_ms, opts.reassembly_max_bytes);
+    let mut incomplete_reassemblies = 0u64;
+
+    // Frames of a received batch not yet handled.
+    let mut unbatched: VecDeque<TransportEvent> = VecDeque::new();
+
+    // Repair: last NACK time per sequence.
+    let mut nacked: HashMap<u64, u64> = HashMap::new();
+    let mut next_repair_ms = start_ms;
+
+    // Startup milestones relative to process start.
+    let process_start = process_start_ms();
+    let mut time_to_join_ms = transport
+        .joined_at_ms()
+        .map(|ts| ts.saturating_sub(process_start));
+    let mut time_to_first_event_ms: Option<u64> = None;
+    let mut time_to_first_data_ms: Option<u64> = None;
+
+    if let Some(offset) = time_to_join_ms {
+        log.emit(LogEvent {
+            ts_ms: now_ms(),
+            elapsed_ms: None,
+            role: "receiver",
+            peer_id: &transport.id(),
+            run_id: None,
+            event: EventKind::JoinConfirmed,
+            seq: None,
+            lat_ms: None,
+            ldh: None,
+            extra: serde_json::json!({ "time_to_join_ms": offset }),
+        })?;
+    }
+
+    // Write log event if receiver couldn't join topic
+    if !transport.joined() && !transport.join_pending() {
+        log.emit(LogEvent {
+            ts_ms: now_ms(),
+            elapsed_ms: None,
+            role: "receiver",
+            peer_id: &transport.id(),
+            run_id: None,
+            event: EventKind::NoJoin,
+            seq: None,
+            lat_ms: None,
+            ldh: None,
+            extra: serde_json::json!({
+                "join_wait_ms": transport.join_wait_ms(),
+            }),
+        })?;
+    }
+
+    // The "never saw data" idle clock starts once the join is over (at run start,
+    // unless the transport still joins in the background).
+    let mut join_done_ms = (!transport.join_pending()).then_some(start_ms);
+    // No gossip message or neighbor for half the idle timeout after the join
+    // suggests a topic mismatch.
+    let mut gossip_heard = false;
+    // last `recv_oversized` event (monotonic) and drops since then
+    let mut oversized_logged_ms: Option<u64> = None;
+    let mut oversized_unlogged = 0u64;
+    let mut silence_warned = false;
+
+    // Path RTT baseline, bounded by its budget so the data phase starts on time.
+    let mut neighbor_rtt = BTreeMap::new();
+    if let Some(rtt_opts) = opts.rtt_probe {
+        probe_rtt(&*transport, log, rtt_opts, "start", &mut neighbor_rtt).await?;
+    }
+
+    // Summarize the active test and start fresh statistics at monotonic `$at`.
+    macro_rules! close_test {
+        ($cause:expr, $at:expr) => {{
+            let marks = RunMarks {
+                test_id: current_test,
+                protocol_start,
+                wire_start,
+                incomplete_reassemblies,
+                time_to_join_ms,
+                time_to_first_event_ms,
+                time_to_first_data_ms,
+                terminated_by: $cause,
+                resources: resources.as_mut().and_then(ResourceSampler::take_usage),
+                log_write_errors: log.write_errors(),
+                forward: opts.forward.as_ref().map(Forwarder::counters),
+                neighbor_rtt: neighbor_rtt.clone(),
+            };
+            if let (Some(_), Some(test_id)) = (opts.ack_every, current_test) {
+                send_ack(&*transport, &mut stats, test_id).await?;
+            }
+            sampler.finish(transport, log)?;
+            let summary = finish_summary(
+                transport,
+                &mut stats,
+                &mut topic_runs,
+                opts.repair.is_some(),
+                marks,
+            );
+            log_summary(&*transport, log, "receiver", &summary, false)?;
+            on_summary(summary)?;
+
+            let prev = std::mem::replace(&mut stats, Stats::with_config(opts.stats.clone()));
+            topic_runs.clear();
+            stats.start_peer_view($at, connected_peers);
+            if continuous {
+                stats.carry_churn(&prev);
+            }
+            stats.note_initial_neighbours(&transport.neighbors(), $at);
+            current_test = None;
+            complete_since_ms = None;
+            time_to_first_data_ms = None;
+            incomplete_reassemblies = 0;
+            nacked.clear();
+            protocol_start = transport.protocol_counters();
+            wire_start = transport.wire_bytes();
+        }};
+    }
+
+    loop {
+        // Wake exactly when the next timer-driven check below is due (a
+        // continuous receiver without a test waits for one indefinitely).
+        let idle_base_ms = if stats.total_expected > 0 {
+            Some(last_valid_ms)
+        } else {
+            join_done_ms.filter(|_| !continuous)
+        };
+        let due_ms = [
+            idle_base_ms.map(|base| base + report_after_idle_ms + 1),
+            complete_since_ms.map(|since| since + opts.complete_grace_ms),
+            (opts.neighbor_sample_ms > 0).then_some(next_snapshot_ms),
+            (opts.conn_sample_ms > 0).then_some(next_conn_sample_ms),
+            resources.as_ref().map(|_| next_resource_sample_ms),
+            opts.announce_ms.map(|_| next_announce_ms),
+            (opts.repair.is_some() && current_test.is_some()).then_some(next_repair_ms),
+            reassembler.next_expiry(),
+            guard.next_deadline(),
+            opts.poll_floor_ms.map(|floor| elapsed_ms() + floor),
+            join_done_ms
+                .filter(|_| !silence_warned && !gossip_heard)
+                .map(|done| done + report_after_idle_ms / 2 + 1),
+        ]
+        .into_iter()
+        .flatten()
+        .min()
+        .unwrap_or_default();
+        let deadline = tokio::time::Instant::now()
+            + Duration::from_millis(due_ms.saturating_sub(elapsed_ms()));
+
+        select! {
+            biased;
+            _ = sleep_until(deadline) => {},
+            _ = crate::util::wait_interrupted() => {},
+            _ = crate::control::wait() => {},
+
+            event = next_event(&mut *transport, &mut unbatched, &mut stats) => {
+                // Join milestones, completion and task exits are not network events.
+                let network = event.is_some()
+                    && !matches!(
+                        event,
+                        Some(Ok(TransportEvent::JoinProgress { .. }
+                            | TransportEvent::Joined { .. }
+                            | TransportEvent::TaskExited { .. }))
+                    );
+                if network {
+                    guard.progress();
+                }
+                gossip_heard |= matches!(
+                    event,
+                    Some(Ok(TransportEvent::Msg { .. }
+                        | TransportEvent::Oversized { .. }
+                        | TransportEvent::NeighborUp(_)
+                        | TransportEvent::NeighborDown(_)
+                        | TransportEvent::Unicast { .. }))
+                );
+                if time_to_first_event_ms.is_none() && network {
+                    let ts = now_ms();
+                    let offset = elapsed_ms();
+                    time_to_first_event_ms = Some(offset);
+                    log.emit(LogEvent {
+                        ts_ms: ts,
+                        elapsed_ms: None,
+                        role: "receiver",
+                        peer_id: &transport.id(),
+                        run_id: None,
+                        event: EventKind::FirstEvent,
+                        seq: None,
+                        lat_ms: None,
+                        ldh: None,
+                        extra: serde_json::json!({ "time_to_first_event_ms": offset }),
+                    })?;
+                }
+
+                match event {
+                    Some(Ok(TransportEvent::JoinProgress { stage, ts_ms })) => {
+                        log_join_stage(transport, log, "receiver", stage, ts_ms)?;
+                    }
+
+                    // Background join finished: the peer view gets the neighbors it found.
+                    Some(Ok(TransportEvent::Joined { wait_ms })) => {
+                        let now = elapsed_ms();
+                        join_done_ms = Some(now);
+                        let neighbors = transport.neighbors();
+                        connected_peers = neighbors.len() as u64;
+                        stats.record_peer_view(now, connected_peers, None);
+                        stats.note_initial_neighbours(&neighbors, now);
+                        time_to_join_ms = transport
+                            .joined_at_ms()
+                            .map(|ts| ts.saturating_sub(process_start));
+                        log.emit(LogEvent {
+                            ts_ms: now_ms(),
+                            elapsed_ms: None,
+                            role: "receiver",
+                            peer_id: &transport.id(),
+                            run_id: None,
+                            event: EventKind::JoinConfirmed,
+                            seq: None,
+                            lat_ms: None,
+                            ldh: None,
+                            extra: serde_json::json!({
+                                "time_to_join_ms": time_to_join_ms,
+                                "join_wait_ms": wait_ms,
+                                "connect": transport.connect_report(),
+                            }),
+                        })?;
+                    }
+
+                    // Other receivers' announcements are only counted: never data, never answered.
+                    Some(Ok(TransportEvent::Msg { content, .. })) if frame::is_announce(&content) => {
+                        if let Some(alive) = frame::decode_announce(&content) {
+                            stats.note_peer_heard(alive);
+                        }
+                    }
+
+                    // Other receivers' acks are for the sender only.
+                    Some(Ok(TransportEvent::Msg { content, .. })) if frame::is_ack(&content) => {}
+
+                    Some(Ok(TransportEvent::Msg { topic, content, .. })) if frame::is_heartbeat(&content) => {
+                        let recv_ts = now_ms();
+
+                        // Heartbeats of the active (or any, before data) test; never counted as data.
+                        if let Some(hb) = frame::decode_heartbeat(&content)
+                            && current_test.is_none_or(|t| t == hb.test_id)
+                        {
+                            let gap_ms = stats.note_heartbeat(&hb, elapsed_ms());
+                            log.emit(LogEvent {
+                                ts_ms: recv_ts,
+                                elapsed_ms: None,
+                                role: "receiver",
+                                peer_id: &transport.id(),
+                                run_id: None,
+                                event: EventKind::Heartbeat,
+                                seq: Some(hb.seq),
+                                lat_ms: Some(recv_ts.saturating_sub(hb.sent_ms)),
+                                ldh: None,
+                                extra: serde_json::json!({
+                                    "sender_ms": hb.sent_ms,
+                                    "gap_ms": gap_ms,
+                                    "test_id": hex::encode(hb.test_id),
+                                    "sender_run_id": hex::encode(hb.run_id),
+                                    "payload_size": hb.payload_size,
+                                    "topic": topic,
+                                }),
+                            })?;
+                        }
+                    }
+
+                    Some(Ok(TransportEvent::Msg { topic, content, ldh, delivered_from })) => {
+                        let recv_ts = now_ms();
+                        let recv_mono = elapsed_ms();
+
+                        // Chunks are buffered until their frame is complete.
+                        let content = if frame::is_chunk(&content) {
+                            reassembler
+                                .push(&content, recv_mono, &mut incomplete_reassemblies)
+                                .map(Bytes::from)
+                        } else {
+                            Some(content)
+                        };
+
+                        let payload_len = content.as_ref().map_or(0, |c| c.len());
+                        if let (Some(fwd), Some(c)) = (&opts.forward, &content)
+                            && fwd.raw
+                        {
+                            fwd.forward(c.clone());
+                        }
+                        let decoded = match &content {
+                            Some(c) => unframe(transport, log, &mut stats, c, opts.encryption.as_ref())?,
+                            None => None,
+                        };
+                        if let Some(frame) = decoded
+                            && let Ok((m, pad_bytes)) = postcard::take_from_bytes::<DataMsg>(&frame.body)
+                            && accept_signed(transport, log, &mut stats, opts.require_signed.as_ref(), &frame, &m)?
+                            && !reject_foreign(transport, log, &mut stats, &opts.expect_sender, &frame, &m, &delivered_from)?
+                            && !reject_stale(transport, log, &mut stats, opts.max_msg_age_ms, &m, recv_ts)?
+                            && !reject_implausible(transport, log, &mut stats, current_test, &m, recv_ts)?
+                        {
+
+                            // A different test closes the active one when splitting per test.
+                            if split_tests && current_test.is_some_and(|t| t != m.test_id) {
+                                close_test!(Termination::Complete, recv_mono);
+                            }
+
+                            // First valid DataMsg defines the active test.
+                            if current_test.is_none() {
+                                current_test = Some(m.test_id);
+                            }
+
+                            // Each topic records its own first test.
+                            let mut arrival = None;
+                            let mut send_jitter_ms = None;
+                            if multi_topic {
+                                let run = topic_runs.entry(topic.clone()).or_insert_with(|| TopicRun {
+                                    test_id: m.test_id,
+                                    stats: Stats::with_config(opts.stats.clone()),
+                                });
+                                if run.test_id == m.test_id {
+                                    last_valid_ms = recv_mono;
+                                    arrival = Some(run.stats.record(&m, payload_len, ldh, recv_ts, recv_mono));
+                                }
+                            }
+                            let active = Some(m.test_id) == current_test;
+
+                            // Another test while this one runs (per topic when several are
+                            // subscribed) means a second sender on the topic.
+                            let other_test = match topic_runs.get(&topic) {
+                                Some(run) => run.test_id != m.test_id,
+                                None => !active,
+                            };
+                            if other_test && stats.note_other_test(m.test_id) {
+                                diag!("WARNING: a second sender is active on this topic (test_id {})", hex::encode(m.test_id));
+                                log.emit(LogEvent {
+                                    ts_ms: recv_ts,
+                                    elapsed_ms: None,
+                                    role: "receiver",
+                                    peer_id: &transport.id(),
+                                    run_id: None,
+                                    event: EventKind::ConcurrentSenderDetected,
+                                    seq: Some(m.seq),
+                                    lat_ms: None,
+                                    ldh: None,
+                                    extra: serde_json::json!({
+                                        "test_id": hex::encode(m.test_id),
+                                        "active_test_id": current_test.map(hex::encode),
+                                        "topic": topic,
+                                    }),
+                                })?;
+                            }
+
+                            // Only record messages for the active test.
+                            if active {
+                                last_valid_ms = recv_mono;
+                                if frame.compressed {
+                                    stats.note_compressed(frame.body.len(), frame.sealed_len);
+                                }
+
+                                if time_to_first_data_ms.is_none() {
+                                    let offset = recv_mono;
+                                    time_to_first_data_ms = Some(offset);
+                                    log.emit(LogEvent {
+                                        ts_ms: recv_ts,
+                                        elapsed_ms: None,
+                                        role: "receiver",
+                                        peer_id: &transport.id(),
+                                        run_id: None,
+                                        event: EventKind::FirstData,
+                                        seq: Some(m.seq),
+                                        lat_ms: None,
+                                        ldh,
+                                        extra: serde_json::json!({ "time_to_first_data_ms": offset }),
+                                    })?;
+                                }
+
+                                arrival = Some(stats.record(&m, payload_len, ldh, recv_ts, recv_mono));
+                                if let Some(wire_ms) = frame.wire_ms
+                                    && arrival.as_ref().is_some_and(|a| !a.duplicate)
+                                {
+                                    stats.note_wire_stamp(m.sent_ms, wire_ms, recv_ts);
+                                }
+                                if arrival.as_ref().is_some_and(|a| !a.duplicate) {
+                                    send_jitter_ms = stats.note_sched(&m);
+                                }
+                                if opts.conn_sample_ms > 0 && arrival.as_ref().is_some_and(|a| !a.duplicate) {
+                                    let path = neighbor_paths.get(&delivered_from).copied();
+                                    stats.note_delivery_path(path, recv_ts.saturating_sub(m.sent_ms));
+                                }
+                                if let Some(degradation) = stats.take_degradation() {
+                                    log.emit(LogEvent {
+                                        ts_ms: recv_ts,
+                                        elapsed_ms: None,
+                                        role: "receiver",
+                                        peer_id: &transport.id(),
+                                        run_id: None,
+                                        event: EventKind::StatsDegraded,
+                                        seq: Some(m.seq),
+                                        lat_ms: None,
+                                        ldh: None,
+                                        extra: serde_json::to_value(&degradation)?,
+                                    })?;
+                                }
+                                if let Some((pattern, seed)) = opts.verify_pad
+                                    && !pad_matches(pad_bytes, pattern, &m.test_id, m.seq, seed)
+                                {
+                                    stats.note_pad_mismatch();
+                                    log.emit(LogEvent {
+                                        ts_ms: recv_ts,
+                                        elapsed_ms: None,
+                                        role: "receiver",
+                                        peer_id: &transport.id(),
+                                        run_id: None,
+                                        event: EventKind::PadMismatch,
+                                        seq: Some(m.seq),
+                                        lat_ms: None,
+                                        ldh: None,
+                                        extra: serde_json::json!({
+                                            "pattern": pattern,
+                                            "pad_bytes": pad_bytes.len(),
+                                        }),
+                                    })?;
+                                }
+
+                                // Size as sent: the decompressed body plus the framing.
+                                let size = (frame.body.len() + payload_len).saturating_sub(frame.sealed_len);
+                                if let Some(expected) = stats.check_size(size as u64) {
+                                    log.emit(LogEvent {
+                                        ts_ms: recv_ts,
+                                        elapsed_ms: None,
+                                        role: "receiver",
+                                        peer_id: &transport.id(),
+                                        run_id: None,
+                                        event: EventKind::SizeMismatch,
+                                        seq: Some(m.seq),
+                                        lat_ms: None,
+                                        ldh: None,
+                                        extra: serde_json::json!({
+                                            "test_id": hex::encode(m.test_id),
+                                            "size": size,
+                                            "expected": expected,
+                                            "wire_bytes": payload_len,
+                                            "body_bytes": frame.body.len(),
+                                            "compressed": frame.compressed,
+                                            "from": delivered_from,
+                                        }),
+                                    })?;
+                                }
+
+                                // The first message shows what the bytes on the wire are made of.
+                                if stats.payload().is_none() {
+                                    let header_bytes = postcard::to_allocvec(&m)?.len();
+                                    let breakdown = PayloadBreakdown {
+                                        header_bytes,
+                                        pad_bytes: frame.body.len().saturating_sub(header_bytes),
+                                        sealed_bytes: frame.sealed_len,
+                                        framing_bytes: payload_len.saturating_sub(frame.sealed_len),
+                                        wire_bytes: payload_len,
+                                    };
+                                    stats.note_payload(breakdown);
+                                    log.emit(LogEvent {
+                                        ts_ms: recv_ts,
+                                        elapsed_ms: None,
+                                        role: "receiver",
+                                        peer_id: &transport.id(),
+                                        run_id: None,
+                                        event: EventKind::PayloadBreakdown,
+                                        seq: Some(m.seq),
+                                        lat_ms: None,
+                                        ldh: None,
+                                        extra: breakdown.to_extra(),
+                                    })?;
+                                }
+
+                                // Cumulative ack every N new messages and on completion.
+                                let unique = stats.received_unique();
+                                if let Some(every) = opts.ack_every
+                                    && arrival.as_ref().is_some_and(|a| !a.duplicate)
+                                    && (unique.is_multiple_of(every) || unique >= stats.total_expected)
+                                {
+                                    send_ack(&*transport, &mut stats, m.test_id).await?;
+                                }
+                            }
+
+                            if let Some(arrival) = arrival {
+                                let recv = HeldRecv {
+                                    ts_ms: recv_ts,
+                                    elapsed_ms: recv_mono,
+                                    seq: m.seq,
+                                    lat_ms: recv_ts.saturating_sub(m.sent_ms),
+                                    ldh,
+                                    extra: {
+                                        let mut extra = serde_json::json!({
+                                            "from": delivered_from,
+                                            "test_id": hex::encode(m.test_id),
+                                            "total": m.total,
+                                            "topic": topic,
+                                        });
+                                        if m.copy > 0 {
+                                            extra["copy"] = m.copy.into();
+                                        }
+                                        if let Some(offset) = m.sched_offset_ms {
+                                            extra["sched_offset_ms"] = offset.into();
+                                        }
+                                        if let Some(jitter) = send_jitter_ms {
+                                            extra["send_jitter_ms"] = jitter.into();
+                                        }
+                                        extra
+                                    },
+                                };
+                                let anomalous = arrival.duplicate || arrival.out_of_order || m.sent_ms > recv_ts;
+                                sampler.offer(transport, log, recv, anomalous)?;
+                            }
+
+                            if let (Some(fwd), Some(c)) = (&opts.forward, &content)
+                                && !fwd.raw
+                            {
+                                fwd.forward(c.clone());
+                            }
+                        }
+                    }
+
+                    Some(Ok(TransportEvent::Unicast { from, content })) => {
+                        let recv_ts = now_ms();
+                        let recv_mono = elapsed_ms();
+
+                        // Only retransmissions for the active test of a repair run count,
+                        // through the same origin and age filters as gossip frames.
+                        if opts.repair.is_some()
+                            && let Ok(RepairMsg::Retransmit { data }) =
+                                postcard::from_bytes::<RepairMsg>(&content)
+                            && let Some(frame) = unframe(transport, log, &mut stats, &data, opts.encryption.as_ref())?
+                            && let Ok(m) = postcard::from_bytes::<DataMsg>(&frame.body)
+                            && accept_signed(transport, log, &mut stats, opts.require_signed.as_ref(), &frame, &m)?
+                            && Some(m.test_id) == current_test
+                            && !reject_foreign(transport, log, &mut stats, &opts.expect_sender, &frame, &m, &from)?
+                            && !reject_stale(transport, log, &mut stats, opts.max_msg_age_ms, &m, recv_ts)?
+                            && !reject_implausible(transport, log, &mut stats, current_test, &m, recv_ts)?
+                        {
+                            last_valid_ms = recv_mono;
+                            if frame.compressed {
+                                stats.note_compressed(frame.body.len(), frame.sealed_len);
+                            }
+                            stats.record_repaired(&m, data.len(), recv_ts, recv_mono);
+
+                            log.emit(LogEvent {
+                                ts_ms: recv_ts,
+                                elapsed_ms: None,
+                                role: "receiver",
+                                peer_id: &transport.id(),
+                                run_id: None,
+                                event: EventKind::RepairRecv,
+                                seq: Some(m.seq),
+                                lat_ms: Some(recv_ts.saturating_sub(m.sent_ms)),
+                                ldh: None,
+                                extra: serde_json::json!({}),
+                            })?;
+                        }
+                    }
+
+                    Some(Ok(TransportEvent::Oversized { topic, size, delivered_from })) => {
+                        stats.note_oversized();
+                        let now = elapsed_ms();
+                        if oversized_logged_ms.is_some_and(|at| now < at + OVERSIZED_LOG_MS) {
+                            oversized_unlogged += 1;
+                        } else {
+                            oversized_logged_ms = Some(now);
+                            log.emit(LogEvent {
+                                ts_ms: now_ms(),
+                                elapsed_ms: None,
+                                role: "receiver",
+                                peer_id: &transport.id(),
+                                run_id: None,
+                                event: EventKind::RecvOversized,
+                                seq: None,
+                                lat_ms: None,
+                                ldh: None,
+                                extra: serde_json::json!({
+                                    "size": size,
+                                    "max_recv_bytes": transport.max_recv_bytes(),
+                                    "delivered_from": delivered_from,
+                                    "topic": topic,
+                                    "unlogged_before": std::mem::take(&mut oversized_unlogged),
+                                }),
+                            })?;
+                        }
+                    }
+
+                    Some(Err(TransportError::Lagged)) => {
+                        stats.note_error(TransportError::Lagged.kind());
+                        stats.note_lagged();
+                        log.emit(LogEvent {
+                            ts_ms: now_ms(),
+                            elapsed_ms: None,
+                            role: "receiver",
+                            peer_id: &transport.id(),
+                            run_id: None,
+                            event: EventKind::Lagged,
+                            seq: None,
+                            lat_ms: None,
+                            ldh: None,
+                            extra: serde_json::json!({}),
+                        })?;
+                    }
+
+                    Some(Ok(TransportEvent::NeighborDown(peer))) => {
+                        let ts = now_ms();
+                        connected_peers = connected_peers.saturating_sub(1);
+                        let now = elapsed_ms();
+                        stats.note_neighbour_down(&peer, now);
+                        stats.record_peer_view(now, connected_peers, None);
+
+                        log.emit(LogEvent {
+                            ts_ms: ts,
+                            elapsed_ms: None,
+                            role: "receiver",
+                            peer_id: &transport.id(),
+                            run_id: None,
+                            event: EventKind::NeighborDown,
+                            seq: None,
+                            lat_ms: None,
+                            ldh: None,
+                            extra: serde_json::json!({
+                                "peer": peer,
+                                "connected": connected_peers,
+                            }),
+                        })?;
+                    }
+
+                    Some(Ok(TransportEvent::NeighborUp(peer))) => {
+                        let ts = now_ms();
+                        connected_peers += 1;
+                        let now = elapsed_ms();
+                        stats.note_neighbour_up(&peer, now);
+                        stats.record_peer_view(now, connected_peers, None);
+
+                        log.emit(LogEvent {
+                            ts_ms: ts,
+                            elapsed_ms: None,
+                            role: "receiver",
+                            peer_id: &transport.id(),
+                            run_id: None,
+                            event: EventKind::NeighborUp,
+                            seq: None,
+                            lat_ms: None,
+                            ldh: None,
+                            extra: serde_json::json!({
+                                "peer": peer,
+                                "connected": connected_peers,
+                            }),
+                        })?;
+                    }
+
+                    Some(Ok(TransportEvent::RejoinAttempt { success, isolated_ms, error })) => {
+                        stats.note_rejoin(success);
+                        log.emit(LogEvent {
+                            ts_ms: now_ms(),
+                            elapsed_ms: None,
+                            role: "receiver",
+                            peer_id: &transport.id(),
+                            run_id: None,
+                            event: EventKind::RejoinAttempt,
+                            seq: None,
+                            lat_ms: None,
+                            ldh: None,
+                            extra: serde_json::json!({
+                                "success": success,
+                                "isolated_ms": isolated_ms,
+                                "error": error,
+                            }),
+                        })?;
+                    }
+
+                    // A dead receive task is followed by StreamClosed, which ends the test.
+                    Some(Ok(TransportEvent::TaskExited { which, reason })) => {
+                        stats.note_task_exit();
+                        log_task_exit(&*transport, log, "receiver", which, &reason)?;
+                    }
+
+                    Some(Err(e)) => {
+                        stats.note_error(e.kind());
+                        warn!("transport error: {e}");
+                        log.emit(LogEvent {
+                            ts_ms: now_ms(),
+                            elapsed_ms: None,
+                            role: "receiver",
+                            peer_id: &transport.id(),
+                            run_id: None,
+                            event: EventKind::TransportError,
+                            seq: None,
+                            lat_ms: None,
+                            ldh: None,
+                            extra: serde_json::json!({
+                                "kind": e.kind(),
+                                "error": e.to_string(),
+                            }),
+                        })?;
+                        if e.is_fatal() {
+                            terminated_by = Termination::TransportClosed;
+                            break;
+                        }
+                    }
+
+                    None => {
+                        terminated_by = Termination::TransportClosed;
+                        break;
+                    }
+                }
+            }
+        }
+
+        // Run-time commands; pausing and the rate only concern the sender.
+        while let Some((cmd, source)) = crate::control::next() {
+            let effect = match cmd {
+                Control::Snapshot => {
+                    let marks = RunMarks {
+                        test_id: current_test,
+                        protocol_start,
+                        wire_start,
+                        incomplete_reassemblies: incomplete_reassemblies + reassembler.pending(),
+                        time_to_join_ms,
+                        time_to_first_event_ms,
+                        time_to_first_data_ms,
+                        terminated_by: Termination::Idle,
+                        resources: None,
+                        log_write_errors: log.write_errors(),
+                        forward: opts.forward.as_ref().map(Forwarder::counters),
+                        neighbor_rtt: neighbor_rtt.clone(),
+                    };
+                    let mut summary = finish_summary(
+                        transport,
+                        &mut stats.clone(),
+                        &mut topic_runs.clone(),
+                        opts.repair.is_some(),
+                        marks,
+                    );
+                    summary.terminated_by = None;
+                    let color = std::io::IsTerminal::is_terminal(&std::io::stderr());
+                    crate::util::diag_str(&summary.pretty(color).to_string());
+                    log_summary(&*transport, log, "receiver", &summary, true)?;
+                    serde_json::json!({
+                        "test_id": summary.test_id,
+                        "received_unique": summary.received_unique,
+                        "delivery_rate": summary.delivery_rate,
+                    })
+                }
+                Control::Rate(_) | Control::Pause | Control::Resume => {
+                    serde_json::json!({ "ignored": "sender only" })
+                }
+                Control::Rejoin => rejoin_now(&*transport).await,
+                Control::Quit => {
+                    crate::util::interrupt();
+                    serde_json::json!({ "stopping": true })
+                }
+            };
+            log_control(transport, log, "receiver", cmd, source, effect)?;
+        }
+
+        if let Some(cause) = guard.check(&*transport, log)? {
+            terminated_by = cause;
+            break;
+        }
+
+        let now = elapsed_ms();
+
+        // A background join that gave up starts the idle clock as well.
+        if join_done_ms.is_none() && !transport.join_pending() {
+            join_done_ms = Some(now);
+            if !transport.joined() {
+                log.emit(LogEvent {
+                    ts_ms: now_ms(),
+                    elapsed_ms: None,
+                    role: "receiver",
+                    peer_id: &transport.id(),
+                    run_id: None,
+                    event: EventKind::NoJoin,
+                    seq: None,
+                    lat_ms: None,
+                    ldh: None,
+                    extra: serde_json::json!({
+                        "join_wait_ms": transport.join_wait_ms(),
+                    }),
+                })?;
+            }
+        }
+
+        // Periodic neighbor snapshot: resynchronizes the connected count with the
+        // transport's actual active set.
+        if opts.neighbor_sample_ms > 0 && now >= next_snapshot_ms {
+            next_snapshot_ms = now + opts.neighbor_sample_ms;
+            let neighbors = transport.neighbors();
+            connected_peers = neighbors.len() as u64;
+            stats.record_peer_view(now, connected_peers, None);
+
+            log.emit(LogEvent {
+                ts_ms: now_ms(),
+                elapsed_ms: None,
+                role: "receiver",
+                peer_id: &transport.id(),
+                run_id: None,
+                event: EventKind::NeighborSnapshot,
+                seq: None,
+                lat_ms: None,
+                ldh: None,
+                extra: serde_json::json!({
+                    "count": neighbors.len(),
+                    "neighbors": neighbors,
+                }),
+            })?;
+            diag!(
+                "progress received={} neighbors={}",
+                stats.received_unique(),
+                connected_peers
+            );
+        }
+
+        // Periodic connection-level sample: cumulative wire bytes since run start.
+        if opts.conn_sample_ms > 0 && now >= next_conn_sample_ms {
+            next_conn_sample_ms = now + opts.conn_sample_ms;
+
+            // Reachability probe of every current neighbor; its path type
+            // attributes the deliveries until the next sample.
+            if let Some(probes) = transport.probe_neighbors() {
+                neighbor_paths = probes.iter().map(|p| (p.peer.clone(), p.path)).collect();
+                for probe in &probes {
+                    log.emit(LogEvent {
+                        ts_ms: now_ms(),
+                        elapsed_ms: None,
+                        role: "receiver",
+                        peer_id: &transport.id(),
+                        run_id: None,
+                        event: EventKind::ReachProbe,
+                        seq: None,
+                        lat_ms: None,
+                        ldh: None,
+                        extra: serde_json::to_value(probe)?,
+                    })?;
+                }
+                let reachable = probes.iter().filter(|p| p.reachable).count() as u64;
+                connected_peers = probes.len() as u64;
+                stats.record_peer_view(now, connected_peers, Some(reachable));
+            }
+            if let Some(wire) = wire_delta(transport, wire_start) {
+                log.emit(LogEvent {
+                    ts_ms: now_ms(),
+                    elapsed_ms: None,
+                    role: "receiver",
+                    peer_id: &transport.id(),
+                    run_id: None,
+                    event: EventKind::NetStats,
+                    seq: None,
+                    lat_ms: None,
+                    ldh: None,
+                    extra: serde_json::json!({
+                        "tx_bytes": wire.tx,
+                        "rx_bytes": wire.rx,
+                    }),
+                })?;
+            }
+
+            // Rebinding shows up as a change of our own addresses.
+            if let (Some(prev), Some(current)) =
+                (observed_addrs.as_ref(), transport.observed_addrs())
+            {
+                let (direct, relay) = current.changed_from(prev);
+                if direct || relay {
+                    stats.note_addr_change(direct, relay);
+                    log.emit(LogEvent {
+                        ts_ms: now_ms(),
+                        elapsed_ms: None,
+                        role: "receiver",
+                        peer_id: &transport.id(),
+                        run_id: None,
+                        event: EventKind::AddrChange,
+                        seq: None,
+                        lat_ms: None,
+                        ldh: None,
+                        extra: serde_json::json!({
+                            "direct": current.direct,
+                            "relay": current.relay,
+                            "prev_direct": prev.direct,
+                            "prev_relay": prev.relay,
+                            "direct_changed": direct,
+                            "relay_changed": relay,
+                        }),
+                    })?;
+                }
+                observed_addrs = Some(current);
+            }
+        }
+
+        // Periodic self-profile, to tell a saturated receiver from a slow network.
+        if let Some(sampler) = resources.as_mut()
+            && now >= next_resource_sample_ms
+        {
+            next_resource_sample_ms = now + opts.resource_sample_ms;
+            if let Some(sample) = sampler.sample() {
+                log.emit(LogEvent {
+                    ts_ms: now_ms(),
+                    elapsed_ms: None,
+                    role: "receiver",
+                    peer_id: &transport.id(),
+                    run_id: None,
+                    event: EventKind::Resource,
+                    seq: None,
+                    lat_ms: None,
+                    ldh: None,
+                    extra: serde_json::to_value(sample)?,
+                })?;
+            }
+        }
+
+        // Liveness announcement with our progress, for the sender's receiver count.
+        if let Some(announce_ms) = opts.announce_ms
+            && now >= next_announce_ms
+        {
+            next_announce_ms = now + announce_ms;
+            let alive = ReceiverAlive {
+                peer_id: transport.id(),
+                received_unique: stats.received_unique(),
+            };
+            match transport
+                .broadcast(Bytes::from(frame::encode_announce(&alive)?))
+                .await
+            {
+                Ok(()) => stats.note_announce_sent(),
+                Err(e) => {
+                    stats.note_error(e.kind());
+                    warn!("announce error: {e}");
+                }
+            }
+        }
+
+        // Partial reassemblies that timed out count as incomplete (their seqs stay lost).
+        incomplete_reassemblies += reassembler.expire(now);
+
+        // Repair: NACK sequences that have been missing for longer than the threshold.
+        if let Some(repair) = &opts.repair
+            && let Some(test_id) = current_test
+            && now >= next_repair_ms
+        {
+            next_repair_ms = now + (repair.after_ms / 2).max(10);
+            let seqs: Vec<u64> = stats
+                .missing_older_than(now, repair.after_ms)
+                .filter(|s| {
+                    nacked
+                        .get(s)
+                        .is_none_or(|&t| now.saturating_sub(t) >= repair.after_ms)
+                })
+                .take(MAX_NACK_SEQS)
+                .collect();
+
+            if !seqs.is_empty() {
+                for &s in &seqs {
+                    nacked.insert(s, now);
+                }
+                let count = seqs.len();
+                let nack = postcard::to_allocvec(&RepairMsg::Nack { test_id, seqs })?;
+                // Sent in the background: connecting to the sender must not stall receiving.
+                match transport.send_to_background(&repair.peer, Bytes::from(nack)) {
+                    Ok(()) => stats.note_nack(count),
+                    Err(e) => {
+                        stats.note_error(e.kind());
+                        warn!("nack send error: {e}");
+                    }
+                }
+
+                log.emit(LogEvent {
+                    ts_ms: now_ms(),
+                    elapsed_ms: None,
+                    role: "receiver",
+                    peer_id: &transport.id(),
+                    run_id: None,
+                    event: EventKind::Nack,
+                    seq: None,
+                    lat_ms: None,
+                    ldh: None,
+                    extra: serde_json::json!({ "to": repair.peer, "seqs": count }),
+                })?;
+            }
+        }
+
+        // Case 0: every expected message arrived -> stop after a short grace window
+        // that still counts trailing duplicates (not when splitting per test,
+        // unless continuous).
+        if (!split_tests || continuous)
+            && stats.total_expected > 0
+            && stats.received_unique() >= stats.total_expected
+            && topic_runs.values().all(TopicRun::complete)
+        {
+            let since = *complete_since_ms.get_or_insert(now);
+            if now.saturating_sub(since) >= opts.complete_grace_ms {
+                if continuous {
+                    close_test!(Termination::Complete, now);
+                    continue;
+                }
+                terminated_by = Termination::Complete;
+                break;
+            }
+        }
+
+        if !silence_warned
+            && !gossip_heard
+            && join_done_ms.is_some_and(|done| now.saturating_sub(done) > report_after_idle_ms / 2)
+        {
+            silence_warned = true;
+            diag!(
+                "WARNING: no gossip events {} ms after joining topic {}; check that \
+                 --topic-hex/--topic-name match the sender's (names are case-sensitive)",
+                now.saturating_sub(join_done_ms.unwrap_or(now)),
+                transport.topic().as_deref().unwrap_or("<none>")
+            );
+        }
+
+        // Case 1: test seen -> idle based on valid test data.
+        if stats.total_expected > 0 && now.saturating_sub(last_valid_ms) > report_after_idle_ms {
+            if continuous {
+                close_test!(Termination::Idle, now);
+                continue;
+            }
+            break;
+        }
+
+        // Case 2: no test seen at all -> timeout since the join ended.
+        if stats.total_expected == 0
+            && !continuous
+            && join_done_ms.is_some_and(|done| now.saturating_sub(done) > report_after_idle_ms)
+        {
+            break;
+        }
+    }
+
+    // A continuous receiver summarized every finished test already.
+    if continuous && current_test.is_none() {
+        return Ok(());
+    }
+
+    if let (Some(_), Some(test_id)) = (opts.ack_every, current_test) {
+        send_ack(&*transport, &mut stats, test_id).await?;
+    }
+    sampler.finish(transport, log)?;
+    if let Some(rtt_opts) = opts.rtt_probe {
+        probe_rtt(&*transport, log, rtt_opts, "end", &mut neighbor_rtt).await?;
+    }
+    let marks = RunMarks {
+        test_id: current_test,
+        protocol_start,
+        wire_start,
+        incomplete_reassemblies: incomplete_reassemblies + reassembler.pending(),
+        time_to_join_ms,
+        time_to_first_event_ms,
+        time_to_first_data_ms,
+        terminated_by,
+        resources: resources.as_mut().and_then(ResourceSampler::take_usage),
+        log_write_errors: log.write_errors(),
+        forward: opts.forward.as_ref().map(Forwarder::counters),
+        neighbor_rtt,
+    };
+    let summary = finish_summary(
+        transport,
+        &mut stats,
+        &mut topic_runs,
+        opts.repair.is_some(),
+        marks,
+    );
+    log_summary(&*transport, log, "receiver", &summary, false)?;
+    on_summary(summary)
+}
+
+/// The next transport event, with a `--batch` message split into one `Msg`
+/// per frame: the first is returned, the rest queued in `unbatched` and
+/// handed out before the transport is polled again.
+async fn next_event(
+    transport: &mut dyn Transport,
+    unbatched: &mut VecDeque<TransportEvent>,
+    stats: &mut Stats,
+) -> Option<Result<TransportEvent, TransportError>> {
+    if let Some(event) = unbatched.pop_front() {
+        return Some(Ok(event));
+    }
+    let event = transport.next().await;
+    let Some(Ok(TransportEvent::Msg {
+        topic,
+        content,
+        ldh,
+        delivered_from,
+    })) = event
+    else {
+        return event;
+    };
+    // A malformed batch goes on as is and fails to decode like any bad frame.
+    let frames = match frame::decode_batch(&content) {
+        Some(frames) if !frames.is_empty() => frames,
+        _ => {
+            return Some(Ok(TransportEvent::Msg {
+                topic,
+                content,
+                ldh,
+                delivered_from,
+            }));
+        }
+    };
+    stats.note_batch(frames.len());
+    unbatched.extend(frames.into_iter().map(|content| TransportEvent::Msg {
+        topic: topic.clone(),
+        content,
+        ldh,
+        delivered_from: delivered_from.clone(),
+    }));
+    unbatched.pop_front().map(Ok)
+}
+
+/// Log `summary` as a `summary` event; a `snapshot` one (`snapshot` command)
+/// leaves the test running. A final one is flushed right away, so the log
+/// carries it even if the process dies before its regular exit.
+fn log_summary(
+    transport: &dyn Transport,
+    log: &mut dyn EventSink,
+    role: &'static str,
+    summary: &impl Serialize,
+    snapshot: bool,
+) -> anyhow::Result<()> {
+    let mut extra = serde_json::to_value(summary)?;
+    if snapshot {
+        extra["snapshot"] = true.into();
+    }
+    log.emit(LogEvent {
+        ts_ms: now_ms(),
+        elapsed_ms: None,
+        role,
+        peer_id: &transport.id(),
+        run_id: None,
+        event: EventKind::Summary,
+        seq: None,
+        lat_ms: None,
+        ldh: None,
+        extra,
+    })?;
+    if !snapshot {
+        log.flush();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::tests::opts;
+    use crate::transport::{TransportKind, create};
+    use iroh::SecretKey;
+
+    /// Sink keeping the kind and extra of every event.
+    #[derive(Default)]
+    struct Recorder(Vec<(EventKind, serde_json::Value)>);
+
+    impl EventSink for Recorder {
+        fn name(&self) -> &'static str {
+            "recorder"
+        }
+
+        fn write(&mut self, ev: &LogEvent) -> anyhow::Result<()> {
+            self.0.push((ev.event, ev.extra.clone()));
+            Ok(())
+        }
+    }
+
+    fn data(seq: u64, sent_ms: u64) -> DataMsg {
+        DataMsg {
+            test_id: [3; 16],
+            seq,
+            sent_ms,
+            total: 10,
+            copy: 0,
+            sched_offset_ms: None,
+            pad: Vec::new(),
+        }
+    }
+
+    #[tokio::test]
+    async fn require_signed_rejects_unsigned_and_unlisted_signers() {
+        let transport = create(TransportKind::Mem, opts(rand::random()))
+            .await
+            .unwrap();
+        let mut log = Recorder::default();
+        let mut stats = Stats::default();
+        let m = data(4, 1000);
+        let secret = SecretKey::from_bytes(&rand::random());
+        let sig = frame::sign(&secret, &m.test_id, m.seq, m.sent_ms);
+        let unsigned = frame::decode(
+            &frame::encode(b"", Compression::None, None, None).unwrap(),
+            None,
+        )
+        .unwrap();
+        let signed = frame::decode(
+            &frame::encode(b"", Compression::None, None, Some(&sig)).unwrap(),
+            None,
+        )
+        .unwrap();
+        let mut accept = |policy: Option<&SignaturePolicy>, frame: &frame::Decoded| {
+            accept_signed(transport.as_ref(), &mut log, &mut stats, policy, frame, &m).unwrap()
+        };
+
+        assert!(accept(None, &unsigned));
+        let any = SignaturePolicy::default();
+        assert!(!accept(Some(&any), &unsigned));
+        assert!(accept(Some(&any), &signed));
+        let others = SignaturePolicy {
+            allow: vec![SecretKey::from_bytes(&rand::random()).public()],
+        };
+        assert!(!accept(Some(&others), &signed));
+
+        let reasons: Vec<_> = log
+            .0
+            .iter()
+            .map(|(kind, extra)| (*kind, extra["reason"].clone()))
+            .collect();
+        assert_eq!(
+            reasons,
+            [
+                (EventKind::SigReject, "unsigned".into()),
+                (EventKind::SigReject, "not_allowed".into()),
+            ]
+        );
+        let summary = stats.summarize();
+        assert_eq!(
+            (summary.rejected_unsigned, summary.rejected_not_allowed),
+            (1, 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn max_msg_age_rejects_only_aged_out_messages() {
+        let transport = create(TransportKind::Mem, opts(rand::random()))
+            .await
+            .unwrap();
+        let mut log = Recorder::default();
+        let mut stats = Stats::default();
+        let mut stale = |max_age_ms, m: &DataMsg| {
+            reject_stale(
+                transport.as_ref(),
+                &mut log,
+                &mut stats,
+                max_age_ms,
+                m,
+                10_000,
+            )
+            .unwrap()
+        };
+
+        assert!(!stale(None, &data(0, 0)));
+        assert!(!stale(Some(500), &data(1, 9_500)));
+        assert!(stale(Some(500), &data(2, 9_499)));
+        // A sender clock ahead of ours is never stale.
+        assert!(!stale(Some(500), &data(3, 20_000)));
+
+        assert_eq!(log.0.len(), 1);
+        assert_eq!(log.0[0].0, EventKind::RecvStale);
+        assert_eq!(stats.summarize().stale_msgs, 1);
+    }
+
+    /// Receiver options for the in-memory tests: no sampling, probes or side tasks.
+    fn recv_opts(idle_report_ms: u64) -> ReceiverOptions {
+        ReceiverOptions {
+            idle_report_ms,
+            neighbor_sample_ms: 0,
+            conn_sample_ms: 0,
+            resource_sample_ms: 0,
+            stats: StatsConfig::default(),
+            repair: None,
+            encryption: None,
+            require_signed: None,
+            expect_sender: Vec::new(),
+            max_msg_age_ms: None,
+            rtt_probe: None,
+            verify_pad: None,
+            forward: None,
+            reassembly_timeout_ms: 5000,
+            reassembly_max_bytes: 64 * 1024 * 1024,
+            max_runtime_ms: None,
+            watchdog_ms: None,
+            complete_grace_ms: 250,
+            poll_floor_ms: None,
+            log_sample_recv: 1,
+            announce_ms: None,
+            ack_every: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn receiver_stops_within_the_idle_bound_after_the_last_message() {
+        const IDLE_MS: u64 = 200;
+        let topic = rand::random();
+        let mut receiver = create(TransportKind::Mem, opts(topic)).await.unwrap();
+        let sender = create(TransportKind::Mem, opts(topic)).await.unwrap();
+        let mut log = Recorder::default();
+
+        let send = async {
+            for seq in 0..3 {
+                let body = postcard::to_allocvec(&data(seq, now_ms())).unwrap();
+                let bytes = frame::encode(&body, Compression::None, None, None).unwrap();
+                sender.broadcast(Bytes::from(bytes)).await.unwrap();
+                sleep(Duration::from_millis(20)).await;
+            }
+            Instant::now()
+        };
+        let recv = async {
+            let summary = run_receiver(receiver.as_mut(), &mut log, recv_opts(IDLE_MS)).await;
+            (summary.unwrap(), Instant::now())
+        };
+        let (last_sent, (summary, stopped)) =
+            tokio::time::timeout(Duration::from_secs(5), async { tokio::join!(send, recv) })
+                .await
+                .expect("receiver did not stop");
+
+        assert_eq!(summary.received_unique, 3);
+        assert_eq!(summary.terminated_by, Some(Termination::Idle));
+        // The last message arrived 20 ms before `last_sent`; no fixed poll on top.
+        let idle = stopped.duration_since(last_sent);
+        assert!(idle >= Duration::from_millis(IDLE_MS - 20), "{idle:?}");
+        assert!(idle < Duration::from_millis(IDLE_MS + 50), "{idle:?}");
+    }
+
+    /// Sender options for the in-memory tests: 100 msg/s, returning right after the last send.
+    fn send_opts(total: u64, probe_ms: u64) -> SenderOptions {
+        SenderOptions {
+            total,
+            rate: 100,
+            payload_size: 64,
+            pad: PadPattern::Zero,
+            seed: 0,
+            compression: Compression::None,
+            encryption: None,
+            sign: false,
+            chunk_bytes: None,
+            heartbeat_ms: None,
+            repair_linger_ms: None,
+            max_runtime_ms: None,
+            watchdog_ms: None,
+            inject: Injection::default(),
+            adaptive: None,
+            collect_acks: false,
+            pause_schedule: Vec::new(),
+            pause_signals: false,
+            catch_up: false,
+            replay: None,
+            probe_ms,
+            exclusive: false,
+            no_wait: true,
+            linger_ms: 0,
+            redundancy: 1,
+            redundancy_gap_ms: 0,
+            batch: 1,
+            batch_max_wait_ms: 0,
+        }
+    }
+
+    #[tokio::test]
+    async fn two_senders_are_detected_and_counted_apart() {
+        let topic = rand::random();
+        let mut receiver = create(TransportKind::Mem, opts(topic)).await.unwrap();
+        let mut first = create(TransportKind::Mem, opts(topic)).await.unwrap();
+        let mut second = create(TransportKind::Mem, opts(topic)).await.unwrap();
+        let (mut recv_log, mut first_log, mut second_log) = (
+            Recorder::default(),
+            Recorder::default(),
+            Recorder::default(),
+        );
+
+        // The second sender probes while the first one sends, then sends its own
+        // test while the receiver is still in the first one's grace period.
+        let (summary, first, second) = tokio::time::timeout(Duration::from_secs(10), async {
+            tokio::join!(
+                run_receiver(
+                    receiver.as_mut(),
+                    &mut recv_log,
+                    ReceiverOptions {
+                        complete_grace_ms: 1000,
+                        ..recv_opts(1000)
+                    }
+                ),
+                run_sender(first.as_mut(), &mut first_log, send_opts(10, 0)),
+                run_sender(second.as_mut(), &mut second_log, send_opts(10, 50)),
+            )
+        })
+        .await
+        .expect("run did not finish");
+        let (summary, first, second) = (summary.unwrap(), first.unwrap(), second.unwrap());
+
+        assert_ne!(first.test_id, second.test_id);
+        assert!(!first.concurrent_sender_detected);
+        assert!(second.concurrent_sender_detected);
+        assert_eq!(second.other_test_ids, [first.test_id.as_str()]);
+
+        // Only the first test is counted; the second one is flagged, not mixed in.
+        assert_eq!(summary.test_id.as_deref(), Some(first.test_id.as_str()));
+        assert_eq!((summary.received_unique, summary.total_expected), (10, 10));
+        assert_eq!(summary.duplicates, 0);
+        assert!(summary.concurrent_sender_detected);
+        assert_eq!(summary.other_test_ids, [second.test_id.as_str()]);
+        let detected = |log: &Recorder| {
+            log.0
+                .iter()
+                .filter(|(kind, _)| *kind == EventKind::ConcurrentSenderDetected)
+                .count()
+        };
+        assert_eq!(
+            (
+                detected(&recv_log),
+                detected(&first_log),
+                detected(&second_log)
+            ),
+            (1, 0, 1)
+        );
+    }
+}
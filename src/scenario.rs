@@ -0,0 +1,280 @@
+//! Scenario runner: a matrix of sender parameters executed sequentially
+//! against one already-connected transport.
+//!
+//! The scenario file is TOML with these keys:
+//!
+//! ```toml
+//! rates = [10, 50]      # messages per second
+//! sizes = [256, 4096]   # payload bytes
+//! repeats = 2           # runs per (rate, size) pair, default 1
+//! duration_s = 10       # per-run duration; each run sends rate * duration_s messages
+//! cooldown_ms = 2000    # pause between runs, default 0
+//! ```
+//!
+//! Runs are numbered in rate, size, repeat order. The sender writes
+//! `run-<idx>.jsonl`, `run-<idx>-sender.json` and `manifest.json` to the
+//! output directory; receivers write `test-<test_id>-receiver.json` per test
+//! and `receiver-manifest.json`. A receiver cannot know the run index (a test
+//! may never reach it), so receiver summaries are matched to runs through the
+//! test_id in the sender's manifest.
+
+use crate::metrics::{EventKind, EventSink, LogEvent, LogHeader, Summary, Termination};
+use crate::runner::{self, ReceiverOptions, SenderOptions};
+use crate::transport::Transport;
+use crate::util::{JsonWriter, diag, now_ms};
+use anyhow::Context;
+use serde::{Deserialize, Serialize};
+use std::fs;
+use std::path::Path;
+use std::time::Duration;
+
+/// Parsed and validated scenario file.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Scenario {
+    pub rates: Vec<u64>,
+    pub sizes: Vec<usize>,
+    #[serde(default = "default_repeats")]
+    pub repeats: u32,
+    pub duration_s: u64,
+    #[serde(default)]
+    pub cooldown_ms: u64,
+}
+
+fn default_repeats() -> u32 {
+    1
+}
+
+/// One cell of the matrix.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct Run {
+    pub index: usize,
+    pub rate: u64,
+    pub size: usize,
+    pub repeat: u32,
+    pub total: u64,
+}
+
+/// Manifest entry: run parameters plus the test_id the sender used.
+#[derive(Debug, Serialize)]
+struct ManifestRun {
+    #[serde(flatten)]
+    run: Run,
+    test_id: String,
+    messages_sent: u64,
+    log: String,
+}
+
+/// Receiver manifest entry: the summary file written for one test_id.
+#[derive(Debug, Serialize)]
+struct ReceiverManifestRun {
+    test_id: Option<String>,
+    summary: String,
+    received_unique: u64,
+    total_expected: u64,
+}
+
+#[derive(Debug, Serialize)]
+struct ReceiverManifest<'a> {
+    scenario_runs: usize,
+    tests: &'a [ReceiverManifestRun],
+}
+
+#[derive(Debug, Serialize)]
+struct Manifest<'a> {
+    scenario: &'a Path,
+    duration_s: u64,
+    cooldown_ms: u64,
+    runs: &'a [ManifestRun],
+}
+
+impl Scenario {
+    /// Read and validate a scenario file.
+    pub fn load(path: &Path) -> anyhow::Result<Self> {
+        let text = fs::read_to_string(path)
+            .with_context(|| format!("reading scenario file {}", path.display()))?;
+        let scenario: Scenario = toml::from_str(&text)
+            .map_err(|e| anyhow::anyhow!("invalid scenario file {}: {e}", path.display()))?;
+        scenario
+            .validate()
+            .map_err(|e| anyhow::anyhow!("invalid scenario file {}: {e}", path.display()))?;
+        Ok(scenario)
+    }
+
+    /// Check value ranges; errors name the offending key.
+    fn validate(&self) -> Result<(), String> {
+        if self.rates.is_empty() {
+            return Err("`rates` must list at least one rate".into());
+        }
+        if let Some(i) = self.rates.iter().position(|&r| r == 0) {
+            return Err(format!("`rates[{i}]` must be greater than 0"));
+        }
+        if self.sizes.is_empty() {
+            return Err("`sizes` must list at least one size".into());
+        }
+        if let Some(i) = self.sizes.iter().position(|&s| s == 0) {
+            return Err(format!("`sizes[{i}]` must be greater than 0"));
+        }
+        if self.repeats == 0 {
+            return Err("`repeats` must be at least 1".into());
+        }
+        if self.duration_s == 0 {
+            return Err("`duration_s` must be greater than 0".into());
+        }
+        Ok(())
+    }
+
+    /// All runs of the matrix in execution order.
+    pub fn runs(&self) -> Vec<Run> {
+        let mut runs = Vec::new();
+        for &rate in &self.rates {
+            for &size in &self.sizes {
+                for repeat in 0..self.repeats {
+                    runs.push(Run {
+                        index: runs.len(),
+                        rate,
+                        size,
+                        repeat,
+                        total: rate * self.duration_s,
+                    });
+                }
+            }
+        }
+        runs
+    }
+}
+
+/// Execute every run of `scenario` with `base` as the template for all
+/// options the matrix does not vary.
+///
+/// `manifest.json` is rewritten after each run, so an aborted scenario
+/// still documents the runs that completed.
+pub async fn run_sender_scenario(
+    transport: &mut dyn Transport,
+    scenario: &Scenario,
+    scenario_path: &Path,
+    base: SenderOptions,
+    out_dir: &Path,
+) -> anyhow::Result<()> {
+    fs::create_dir_all(out_dir)
+        .with_context(|| format!("creating output directory {}", out_dir.display()))?;
+
+    let runs = scenario.runs();
+    let mut done = Vec::with_capacity(runs.len());
+
+    for (i, run) in runs.iter().enumerate() {
+        let log_name = format!("run-{}.jsonl", run.index);
+        let header = LogHeader::new(Some(transport.id()), serde_json::to_value(run)?);
+        let mut log = JsonWriter::with_header(out_dir.join(&log_name), &header)?;
+        log.emit(LogEvent {
+            ts_ms: now_ms(),
+            elapsed_ms: None,
+            role: "sender",
+            peer_id: &transport.id(),
+            run_id: None,
+            event: EventKind::ScenarioRun,
+            seq: None,
+            lat_ms: None,
+            ldh: None,
+            extra: serde_json::to_value(run)?,
+        })?;
+
+        let opts = SenderOptions {
+            total: run.total,
+            rate: run.rate,
+            payload_size: run.size,
+            // Our own earlier runs are no other sender; probe once up front.
+            probe_ms: if i == 0 { base.probe_ms } else { 0 },
+            ..base.clone()
+        };
+        let summary = runner::run_sender(transport, &mut log, opts).await?;
+        write_json(
+            &out_dir.join(format!("run-{}-sender.json", run.index)),
+            &summary,
+        )?;
+
+        done.push(ManifestRun {
+            run: *run,
+            test_id: summary.test_id.clone(),
+            messages_sent: summary.messages_sent,
+            log: log_name,
+        });
+        write_json(
+            &out_dir.join("manifest.json"),
+            &Manifest {
+                scenario: scenario_path,
+                duration_s: scenario.duration_s,
+                cooldown_ms: scenario.cooldown_ms,
+                runs: &done,
+            },
+        )?;
+
+        if summary.terminated_by == Termination::Interrupt {
+            break;
+        }
+        if run.index + 1 < runs.len() {
+            tokio::time::sleep(Duration::from_millis(scenario.cooldown_ms)).await;
+        }
+    }
+    Ok(())
+}
+
+/// Receive a scenario: one summary per test_id, written as
+/// `test-<test_id>-receiver.json` and listed in `receiver-manifest.json` in
+/// arrival order.
+///
+/// The receiver stops once it has been idle for `opts.idle_report_ms`, so that
+/// must exceed the scenario's cool-down. Returns the number of summaries written.
+pub async fn run_receiver_scenario(
+    transport: &mut dyn Transport,
+    log: &mut dyn EventSink,
+    scenario: &Scenario,
+    opts: ReceiverOptions,
+    out_dir: &Path,
+) -> anyhow::Result<usize> {
+    if opts.idle_report_ms <= scenario.cooldown_ms {
+        anyhow::bail!(
+            "--idle-report-ms ({}) must exceed the scenario's `cooldown_ms` ({}), \
+             or the receiver stops between runs.",
+            opts.idle_report_ms,
+            scenario.cooldown_ms
+        );
+    }
+    fs::create_dir_all(out_dir)
+        .with_context(|| format!("creating output directory {}", out_dir.display()))?;
+
+    let expected = scenario.runs().len();
+    let mut tests = Vec::new();
+    runner::run_receiver_per_test(transport, log, opts, |summary: Summary| {
+        let file = match &summary.test_id {
+            Some(id) => format!("test-{id}-receiver.json"),
+            None => format!("test-unknown-{}-receiver.json", tests.len()),
+        };
+        write_json(&out_dir.join(&file), &summary)?;
+        tests.push(ReceiverManifestRun {
+            test_id: summary.test_id.clone(),
+            summary: file,
+            received_unique: summary.received_unique,
+            total_expected: summary.total_expected,
+        });
+        write_json(
+            &out_dir.join("receiver-manifest.json"),
+            &ReceiverManifest {
+                scenario_runs: expected,
+                tests: &tests,
+            },
+        )
+    })
+    .await?;
+
+    let written = tests.len();
+    if written != expected {
+        diag!("scenario: wrote {written} receiver summaries, scenario has {expected} runs");
+    }
+    Ok(written)
+}
+
+fn write_json<S: Serialize>(path: &Path, value: &S) -> anyhow::Result<()> {
+    let text = serde_json::to_string_pretty(value)?;
+    fs::write(path, text).with_context(|| format!("writing {}", path.display()))
+}
@@ -0,0 +1,398 @@
+//! Self-test: one sender and one receiver transport in the same process on a
+//! random topic with local discovery, to check that an environment can run
+//! experiments at all.
+//!
+//! The sender's key is generated up front so the receiver can bootstrap from
+//! it while both transports join concurrently. Logs go to `sender.jsonl` and
+//! `receiver.jsonl` in the output directory.
+//!
+//! `--transport mem` runs both on the in-process hub instead, which checks
+//! the run loops without a network.
+//!
+//! A second check runs a receiver on a transport that never joins
+//! (`TransportOpts::force_no_join`), logging to
+//! `receiver-no-join.jsonl`: it must stop within its idle timeout, log
+//! `no_join` and report `joined: false` with the `join_skipped` diagnosis.
+
+use crate::frame::Compression;
+use crate::metrics::{Diagnosis, EventKind, EventSink, LogEvent, LogHeader, StatsConfig, Summary};
+use crate::runner::{self, Injection, ReceiverOptions, SenderOptions};
+use crate::transport::{
+    self, BroadcastScope, Discovery, GossipTuning, Transport, TransportKind, TransportOpts,
+};
+use crate::util::{self, JsonWriter, PadPattern};
+use anyhow::Context;
+use iroh::SecretKey;
+use rand::RngCore;
+use std::fs;
+use std::path::Path;
+use std::time::{Duration, Instant};
+use tokio::time::timeout;
+
+/// Whole self-test budget, joins included.
+const DEADLINE: Duration = Duration::from_secs(30);
+
+/// Minimum delivery rate for a PASS.
+pub const DELIVERY_MIN: f64 = 0.99;
+
+/// Idle timeout of the no-join check, and the time the receiver gets to stop.
+const NO_JOIN_IDLE_MS: u64 = 2000;
+
+/// Self-test parameters.
+#[derive(Debug, Clone)]
+pub struct SelftestOptions {
+    pub total: u64,
+    pub rate: u64,
+    pub payload_size: usize,
+    /// `TransportKind::Iroh` or `TransportKind::Mem`.
+    pub transport: TransportKind,
+}
+
+/// Result of a self-test: the receiver summary if the run got that far, and
+/// the reason for a FAIL (None = PASS).
+#[derive(Debug)]
+pub struct Outcome {
+    pub summary: Option<Summary>,
+    pub join_wait_ms: Option<u64>,
+    pub failure: Option<String>,
+}
+
+impl Outcome {
+    fn fail(summary: Option<Summary>, join_wait_ms: Option<u64>, why: String) -> Self {
+        Self {
+            summary,
+            join_wait_ms,
+            failure: Some(why),
+        }
+    }
+
+    /// One-line PASS/FAIL verdict.
+    pub fn verdict(&self) -> String {
+        match (&self.failure, &self.summary) {
+            (Some(why), _) => format!("selftest: FAIL ({why})"),
+            (None, Some(s)) => format!(
+                "selftest: PASS (delivery {:.1}%, join {} ms)",
+                s.delivery_rate * 100.0,
+                self.join_wait_ms.unwrap_or(0)
+            ),
+            (None, None) => "selftest: FAIL (no summary)".to_string(),
+        }
+    }
+}
+
+/// Run the self-test; errors are reserved for local I/O problems, every
+/// network-level failure is reported through `Outcome::failure`.
+pub async fn run(opts: &SelftestOptions, out_dir: &Path) -> anyhow::Result<Outcome> {
+    fs::create_dir_all(out_dir)
+        .with_context(|| format!("creating output directory {}", out_dir.display()))?;
+    let start = Instant::now();
+
+    let mut key = [0u8; 32];
+    rand::rng().fill_bytes(&mut key);
+    let sender_id = SecretKey::from_bytes(&key).public().to_string();
+
+    let config = serde_json::json!({
+        "selftest": true,
+        "total": opts.total,
+        "rate": opts.rate,
+        "payload_size": opts.payload_size,
+        "transport": opts.transport.as_str(),
+    });
+    let mut sender_log = JsonWriter::with_header(
+        out_dir.join("sender.jsonl"),
+        &LogHeader::new(
+            Some(format!("{}:{sender_id}", opts.transport.id_prefix())),
+            config.clone(),
+        ),
+    )?;
+    let mut receiver_log = JsonWriter::with_header(
+        out_dir.join("receiver.jsonl"),
+        &LogHeader::new(None, config),
+    )?;
+    let mut topic = [0u8; 32];
+    rand::rng().fill_bytes(&mut topic);
+
+    let base = TransportOpts {
+        topic_hex: vec![hex::encode(topic)],
+        topic_name: Vec::new(),
+        topic_derivation: Default::default(),
+        send_topic: None,
+        secret_hex: None,
+        bootstrap: Vec::new(),
+        udp_bind: None,
+        discovery: Discovery::Direct,
+        run_time: opts.total / opts.rate.max(1),
+        rejoin_on_isolation_ms: None,
+        broadcast_scope: BroadcastScope::Swarm,
+        unicast: false,
+        rx_buffer: None,
+        gossip: GossipTuning::default(),
+        join_in_background: false,
+        force_no_join: false,
+        max_recv_bytes: None,
+    };
+    let sender_opts = TransportOpts {
+        secret_hex: Some(hex::encode(key)),
+        ..base.clone()
+    };
+    let receiver_opts = TransportOpts {
+        bootstrap: vec![sender_id],
+        ..base.clone()
+    };
+
+    // Both joins wait for a neighbor, so they must run concurrently.
+    let joins = timeout(DEADLINE, async {
+        tokio::join!(
+            transport::create(opts.transport, sender_opts),
+            transport::create(opts.transport, receiver_opts)
+        )
+    })
+    .await;
+    let (mut sender, mut receiver) = match joins {
+        Ok((Ok(s), Ok(r))) => (s, r),
+        Ok((Err(e), _)) | Ok((_, Err(e))) => {
+            return Ok(Outcome::fail(
+                None,
+                None,
+                format!("endpoint setup failed: {e}"),
+            ));
+        }
+        Err(_) => {
+            return Ok(Outcome::fail(
+                None,
+                None,
+                format!("join did not finish within {} s", DEADLINE.as_secs()),
+            ));
+        }
+    };
+    log_setup(sender.as_ref(), &mut sender_log, "sender", opts)?;
+    log_setup(receiver.as_ref(), &mut receiver_log, "receiver", opts)?;
+
+    let join_wait_ms = Some(sender.join_wait_ms().max(receiver.join_wait_ms()));
+    let diagnosis = if !sender.joined() || !receiver.joined() {
+        Some(format!(
+            "join timeout (sender joined={}, receiver joined={})",
+            sender.joined(),
+            receiver.joined()
+        ))
+    } else if receiver.neighbors().is_empty() {
+        Some("receiver joined with zero neighbors".to_string())
+    } else {
+        None
+    };
+    if let Some(why) = diagnosis {
+        close_both(sender.as_mut(), receiver.as_mut()).await;
+        return Ok(Outcome::fail(None, join_wait_ms, why));
+    }
+
+    let send = SenderOptions {
+        total: opts.total,
+        rate: opts.rate,
+        payload_size: opts.payload_size,
+        pad: PadPattern::Zero,
+        seed: 0,
+        compression: Compression::None,
+        encryption: None,
+        sign: false,
+        chunk_bytes: None,
+        heartbeat_ms: None,
+        repair_linger_ms: None,
+        max_runtime_ms: None,
+        watchdog_ms: None,
+        inject: Injection::default(),
+        adaptive: None,
+        collect_acks: false,
+        pause_schedule: Vec::new(),
+        pause_signals: false,
+        catch_up: false,
+        replay: None,
+        probe_ms: 0,
+        exclusive: false,
+        no_wait: false,
+        linger_ms: 0,
+        redundancy: 1,
+        redundancy_gap_ms: 0,
+        batch: 1,
+        batch_max_wait_ms: 0,
+    };
+    let recv = ReceiverOptions {
+        idle_report_ms: 3000,
+        neighbor_sample_ms: 5000,
+        conn_sample_ms: 1000,
+        resource_sample_ms: 0,
+        stats: StatsConfig::default(),
+        repair: None,
+        encryption: None,
+        require_signed: None,
+        expect_sender: Vec::new(),
+        max_msg_age_ms: None,
+        rtt_probe: None,
+        verify_pad: None,
+        reassembly_timeout_ms: 5000,
+        reassembly_max_bytes: 64 * 1024 * 1024,
+        max_runtime_ms: None,
+        watchdog_ms: None,
+        complete_grace_ms: 250,
+        poll_floor_ms: None,
+        log_sample_recv: 1,
+        announce_ms: None,
+        ack_every: None,
+        forward: None,
+    };
+    let no_join_recv = ReceiverOptions {
+        idle_report_ms: NO_JOIN_IDLE_MS,
+        ..recv.clone()
+    };
+
+    let remaining = DEADLINE.saturating_sub(start.elapsed());
+    let run = timeout(remaining, async {
+        tokio::join!(
+            runner::run_sender(sender.as_mut(), &mut sender_log, send),
+            runner::run_receiver(receiver.as_mut(), &mut receiver_log, recv)
+        )
+    })
+    .await;
+    close_both(sender.as_mut(), receiver.as_mut()).await;
+
+    let summary = match run {
+        Ok((Ok(_), Ok(summary))) => summary,
+        Ok((Err(e), _)) => {
+            return Ok(Outcome::fail(
+                None,
+                join_wait_ms,
+                format!("sender failed: {e}"),
+            ));
+        }
+        Ok((_, Err(e))) => {
+            return Ok(Outcome::fail(
+                None,
+                join_wait_ms,
+                format!("receiver failed: {e}"),
+            ));
+        }
+        Err(_) => {
+            return Ok(Outcome::fail(
+                None,
+                join_wait_ms,
+                format!("run did not finish within {} s", DEADLINE.as_secs()),
+            ));
+        }
+    };
+
+    if summary.received_unique == 0 {
+        return Ok(Outcome::fail(
+            Some(summary),
+            join_wait_ms,
+            "receiver got no messages".to_string(),
+        ));
+    }
+    if summary.delivery_rate < DELIVERY_MIN {
+        let why = format!(
+            "delivery {:.1}% below {:.0}%",
+            summary.delivery_rate * 100.0,
+            DELIVERY_MIN * 100.0
+        );
+        return Ok(Outcome::fail(Some(summary), join_wait_ms, why));
+    }
+    if let Some(why) = no_join_check(opts, base, no_join_recv, out_dir).await? {
+        return Ok(Outcome::fail(Some(summary), join_wait_ms, why));
+    }
+    Ok(Outcome {
+        summary: Some(summary),
+        join_wait_ms,
+        failure: None,
+    })
+}
+
+/// Run a receiver on a transport that skips the join; returns why it failed
+/// the check, if it did.
+async fn no_join_check(
+    selftest: &SelftestOptions,
+    opts: TransportOpts,
+    recv: ReceiverOptions,
+    out_dir: &Path,
+) -> anyhow::Result<Option<String>> {
+    let log_path = out_dir.join("receiver-no-join.jsonl");
+    let config = serde_json::json!({ "selftest": true, "force_no_join": true });
+    let mut log = JsonWriter::with_header(&log_path, &LogHeader::new(None, config))?;
+    let opts = TransportOpts {
+        force_no_join: true,
+        ..opts
+    };
+    let mut transport = match transport::create(selftest.transport, opts).await {
+        Ok(t) => t,
+        Err(e) => return Ok(Some(format!("no-join check: endpoint setup failed: {e}"))),
+    };
+    log_setup(transport.as_ref(), &mut log, "receiver", selftest)?;
+    let budget = Duration::from_millis(recv.idle_report_ms);
+    let run = timeout(
+        budget,
+        runner::run_receiver(transport.as_mut(), &mut log, recv),
+    )
+    .await;
+    let _ = transport.close().await;
+    let summary = match run {
+        Ok(Ok(summary)) => summary,
+        Ok(Err(e)) => return Ok(Some(format!("no-join check: receiver failed: {e}"))),
+        Err(_) => {
+            return Ok(Some(format!(
+                "no-join check: receiver still running after {} ms",
+                budget.as_millis()
+            )));
+        }
+    };
+
+    let mut no_join_logged = false;
+    util::for_each_log_event(&log_path, |_, ev| {
+        no_join_logged |= ev.is_ok_and(|ev| ev.event == EventKind::NoJoin);
+        Ok(())
+    })?;
+    let why = if summary.joined {
+        "summary reports joined: true".to_string()
+    } else if !no_join_logged {
+        "no no_join event logged".to_string()
+    } else if summary.diagnosis != Some(Diagnosis::JoinSkipped) {
+        format!(
+            "diagnosis {} instead of join_skipped",
+            summary.diagnosis.map_or("none", |d| d.as_str())
+        )
+    } else {
+        return Ok(None);
+    };
+    Ok(Some(format!("no-join check: {why}")))
+}
+
+/// Log the `setup` event the sender and receiver roles start their logs with.
+fn log_setup(
+    transport: &dyn Transport,
+    log: &mut JsonWriter,
+    role: &'static str,
+    opts: &SelftestOptions,
+) -> anyhow::Result<()> {
+    log.emit(LogEvent {
+        ts_ms: util::now_ms(),
+        elapsed_ms: None,
+        role,
+        peer_id: &transport.id(),
+        run_id: None,
+        event: EventKind::Setup,
+        seq: None,
+        lat_ms: None,
+        ldh: None,
+        extra: serde_json::json!({
+            "selftest": true,
+            "topic": transport.topic(),
+            "topics": transport.topics(),
+            "transport": opts.transport.as_str(),
+            "num": opts.total,
+            "rate": opts.rate,
+            "size": opts.payload_size,
+            "joined": transport.joined(),
+            "join_wait_ms": transport.join_wait_ms(),
+        }),
+    })
+}
+
+async fn close_both(sender: &mut dyn Transport, receiver: &mut dyn Transport) {
+    let _ = tokio::join!(sender.close(), receiver.close());
+}
@@ -0,0 +1,1124 @@
+//! iroh-gossip transport: an iroh endpoint with the gossip protocol, the
+//! unicast side-channel (`--repair`) and the RTT probe echo.
+
+use super::{
+    BroadcastDone, BroadcastQueue, BroadcastScope, ConnectReport, Discovery,
+    EVENT_CHANNEL_CAPACITY, GossipConfig, JoinOutcome, NeighborProbe, ObservedAddrs, RttProbe,
+    TopicJoin, Transport, TransportError, TransportEvent, TransportKind, TransportOpts,
+    resolve_topics, secret_key, spawn_watched,
+};
+use crate::frame;
+use crate::metrics::{ProtocolCounters, WireBytes};
+use crate::util::{TopicDerivation, diag, now_ms, process_start_ms};
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use iroh::NodeId;
+use iroh::endpoint::{Connection, ConnectionType};
+use iroh::protocol::{AcceptError, ProtocolHandler};
+use iroh::{Endpoint, RelayMode, SecretKey, Watcher, protocol::Router};
+use iroh_gossip::proto::{HyparviewConfig, PlumtreeConfig};
+use iroh_gossip::{ALPN, api::JoinOptions, net::Gossip, proto::TopicId};
+use iroh_metrics::{MetricsSource, Registry};
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Instant;
+use tokio::{
+    task::JoinHandle,
+    time::{Duration, sleep, timeout},
+};
+use tokio_stream::StreamExt;
+use tracing::warn;
+
+/// ALPN of the unicast side-channel, registered only when `TransportOpts::unicast` is set.
+pub const REPAIR_ALPN: &[u8] = b"iroh-gossip-metrics/repair/0";
+
+/// ALPN of the RTT probe echo, registered on every node.
+pub const PING_ALPN: &[u8] = b"iroh-gossip-metrics/ping/0";
+
+/// Upper bound for a single unicast message.
+const MAX_UNICAST_BYTES: usize = 16 * 1024 * 1024;
+
+/// A neighbor counts as reachable if something arrived from it this recently;
+/// iroh's QUIC keep-alive (1 s) keeps idle but live connections below it.
+const REACH_RECENT_MS: u64 = 3000;
+
+/// Capacity of the outgoing broadcast queue of `IrohGossip`.
+const TX_QUEUE_CAPACITY: usize = 128;
+
+/// Join milestones of a transport, wall-clock ms by stage.
+type JoinStages = Arc<Mutex<BTreeMap<&'static str, u64>>>;
+
+/// Record the first time `stage` is reached and queue it as `JoinProgress`.
+fn mark_stage(
+    stages: &JoinStages,
+    ev_tx: &tokio::sync::mpsc::Sender<Result<TransportEvent, TransportError>>,
+    stage: &'static str,
+) {
+    let ts_ms = now_ms();
+    let mut stages = stages.lock().unwrap();
+    if !stages.contains_key(stage) {
+        stages.insert(stage, ts_ms);
+        let _ = ev_tx.try_send(Ok(TransportEvent::JoinProgress { stage, ts_ms }));
+    }
+}
+
+impl GossipConfig {
+    fn membership(&self) -> HyparviewConfig {
+        HyparviewConfig {
+            active_view_capacity: self.active_view,
+            passive_view_capacity: self.passive_view,
+            active_random_walk_length: self.active_walk.into(),
+            passive_random_walk_length: self.passive_walk.into(),
+            shuffle_random_walk_length: self.shuffle_walk.into(),
+            shuffle_active_view_count: self.shuffle_active,
+            shuffle_passive_view_count: self.shuffle_passive,
+            shuffle_interval: Duration::from_millis(self.shuffle_interval_ms),
+            neighbor_request_timeout: Duration::from_millis(self.neighbor_timeout_ms),
+        }
+    }
+
+    fn broadcast(&self) -> PlumtreeConfig {
+        PlumtreeConfig {
+            graft_timeout_1: Duration::from_millis(self.graft_timeout_1_ms),
+            graft_timeout_2: Duration::from_millis(self.graft_timeout_2_ms),
+            dispatch_timeout: Duration::from_millis(self.dispatch_timeout_ms),
+            optimization_threshold: self.optimization_threshold.into(),
+            message_cache_retention: Duration::from_millis(self.cache_retention_ms),
+            message_id_retention: Duration::from_millis(self.id_retention_ms),
+            cache_evict_interval: Duration::from_millis(self.cache_evict_ms),
+        }
+    }
+
+    /// Spawn gossip with this configuration. iroh-gossip rejects invalid
+    /// settings by panicking in `spawn`; the panic message is returned as the error.
+    fn spawn(&self, endpoint: Endpoint) -> Result<Gossip> {
+        let builder = Gossip::builder()
+            .max_message_size(self.max_message_size)
+            .membership_config(self.membership())
+            .broadcast_config(self.broadcast());
+        let hook = std::panic::take_hook();
+        std::panic::set_hook(Box::new(|_| {}));
+        let spawned =
+            std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| builder.spawn(endpoint)));
+        std::panic::set_hook(hook);
+        spawned.map_err(|panic| {
+            let msg = panic
+                .downcast_ref::<String>()
+                .cloned()
+                .or_else(|| panic.downcast_ref::<&str>().map(|s| s.to_string()))
+                .unwrap_or_else(|| "unknown error".to_string());
+            anyhow::anyhow!("invalid gossip configuration: {msg}")
+        })
+    }
+}
+
+/// Implementation of the gossip-based transport using the 'iroh-gossip' protocol.
+pub struct IrohGossip {
+    /// Local node ID (as string for logging)
+    id: String,
+    /// Stream for receiving messages
+    rx: tokio_stream::wrappers::ReceiverStream<Result<TransportEvent, TransportError>>,
+    /// Sender side of `rx` for background sends; weak so it never keeps the stream open
+    events: tokio::sync::mpsc::WeakSender<Result<TransportEvent, TransportError>>,
+    /// Channel for outgoing messages, stamped with their enqueue time
+    tx: tokio::sync::mpsc::Sender<(Instant, Bytes)>,
+    /// Broadcasts enqueued so far
+    enqueued: AtomicU64,
+    /// Broadcasts completed by the outgoing task, drained by `drain_broadcast_queue`
+    broadcasts_done: Arc<Mutex<Vec<BroadcastDone>>>,
+    /// Underlying iroh endpoint
+    endpoint: Endpoint,
+    /// Router integrating gossip into the iroh protocol stack
+    router: Router,
+    /// The gossip protocol instance itself
+    gossip: Gossip,
+    /// Discovery mode (direct or relay)
+    _discovery: Discovery,
+    /// Subscribed topics (hex), the send topic first
+    topics: Vec<String>,
+    /// Derivation of the topics subscribed by name (None without names)
+    topic_derivation: Option<TopicDerivation>,
+    /// Scope of outgoing broadcasts
+    broadcast_scope: BroadcastScope,
+    /// Effective gossip protocol parameters
+    gossip_config: GossipConfig,
+    /// join status for metrics/termination, updated when the join finishes
+    join: Arc<Mutex<JoinState>>,
+    /// Join milestones, see `TransportEvent::JoinProgress`
+    join_stages: JoinStages,
+    /// Current direct neighbors on the send topic, maintained by its incoming event task
+    neighbors: Arc<Mutex<Vec<String>>>,
+    /// Spawned join/broadcast/receive tasks, stopped on `close()`
+    tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    /// Broadcast/receive tasks of the send topic that ended before `close()`
+    task_exits: Arc<AtomicU64>,
+    /// Whether the unicast side-channel is enabled
+    unicast: bool,
+    /// Outgoing unicast connections, reused across `send_to` calls
+    unicast_conns: Arc<tokio::sync::Mutex<HashMap<NodeId, Connection>>>,
+    /// Received messages above this size become `TransportEvent::Oversized`
+    max_recv_bytes: usize,
+    /// Bootstrap node IDs passed in, and how many parsed
+    bootstraps_given: usize,
+    bootstraps_parsed: usize,
+}
+
+/// Accepts unicast side-channel connections and forwards every uni stream
+/// as a `TransportEvent::Unicast`.
+///
+/// Holds a weak sender so a failed join still closes the event stream.
+#[derive(Debug, Clone)]
+struct UnicastHandler {
+    ev_tx: tokio::sync::mpsc::WeakSender<Result<TransportEvent, TransportError>>,
+}
+
+impl ProtocolHandler for UnicastHandler {
+    async fn accept(&self, connection: Connection) -> Result<(), AcceptError> {
+        let from = connection.remote_node_id()?.to_string();
+        // Ends when the peer closes the connection.
+        while let Ok(mut recv) = connection.accept_uni().await {
+            let content = recv
+                .read_to_end(MAX_UNICAST_BYTES)
+                .await
+                .map_err(AcceptError::from_err)?;
+            let Some(ev_tx) = self.ev_tx.upgrade() else {
+                break;
+            };
+            let event = TransportEvent::Unicast {
+                from: from.clone(),
+                content: content.into(),
+            };
+            let _ = ev_tx.send(Ok(event)).await;
+        }
+        Ok(())
+    }
+}
+
+/// Echoes every 8-byte probe of an RTT probe stream.
+#[derive(Debug, Clone)]
+struct PingHandler;
+
+impl ProtocolHandler for PingHandler {
+    async fn accept(&self, connection: Connection) -> Result<(), AcceptError> {
+        let (mut send, mut recv) = connection
+            .accept_bi()
+            .await
+            .map_err(AcceptError::from_err)?;
+        let mut probe = [0u8; 8];
+        // Ends when the prober finishes the stream.
+        while recv.read_exact(&mut probe).await.is_ok() {
+            send.write_all(&probe)
+                .await
+                .map_err(AcceptError::from_err)?;
+        }
+        Ok(())
+    }
+}
+
+/// Time `count` echoes to `peer` over a fresh `PING_ALPN` connection.
+async fn ping(
+    endpoint: &Endpoint,
+    peer: &str,
+    count: u32,
+    rtts_ms: &mut Vec<f64>,
+) -> Result<(), String> {
+    let node_id = peer.parse::<NodeId>().map_err(|e| e.to_string())?;
+    let conn = endpoint
+        .connect(node_id, PING_ALPN)
+        .await
+        .map_err(|e| format!("connect: {e}"))?;
+    let (mut send, mut recv) = conn.open_bi().await.map_err(|e| e.to_string())?;
+    let mut echo = [0u8; 8];
+    for i in 0..count {
+        let start = Instant::now();
+        send.write_all(&u64::from(i).to_le_bytes())
+            .await
+            .map_err(|e| e.to_string())?;
+        recv.read_exact(&mut echo)
+            .await
+            .map_err(|e| e.to_string())?;
+        rtts_ms.push(start.elapsed().as_secs_f64() * 1000.0);
+    }
+    let _ = send.finish();
+    conn.close(0u32.into(), b"done");
+    Ok(())
+}
+
+impl IrohGossip {
+    /// Establishes an iroh-gossip connection for the given topics and discovery mode.
+    ///
+    /// This version is fully robust:
+    /// - subscribing and joining are wrapped in timeouts (prevents hanging forever)
+    /// - If join fails or times out, a functional Transport is still returned
+    /// - The receiver loop will then run with an empty RX-stream and terminate cleanly
+    /// - This guarantees that test runs *always* finish and always produce logs.
+    ///
+    /// The `joined` flag and `join_wait_ms` reflect whether the join actually succeeded
+    /// (on every topic, when several are subscribed).
+    ///
+    /// Messages of all topics are forwarded, tagged with their topic. Broadcasts,
+    /// neighbor events and the rejoin supervisor use the send topic only.
+    ///
+    /// With `rejoin_on_isolation_ms`, a supervisor task re-triggers joining the
+    /// bootstrap peers on the existing subscription whenever the node has had no
+    /// neighbors for that long, emitting a `TransportEvent::RejoinAttempt`.
+    pub async fn connect(opts: TransportOpts) -> Result<Self> {
+        Self::establish(opts, false).await
+    }
+
+    /// Like `connect`, but returns as soon as the endpoint is bound; subscribing
+    /// and joining continue in a background task, which emits
+    /// `TransportEvent::Joined` on success. Until then `joined()` is false and
+    /// `join_pending()` true.
+    pub async fn connect_lazy(opts: TransportOpts) -> Result<Self> {
+        Self::establish(opts, true).await
+    }
+
+    /// Like `connect`, but never subscribes: the endpoint is bound and the
+    /// transport comes back in the shape of a failed join (`joined()` false,
+    /// event stream closed, broadcasts failing), to exercise the no-join paths.
+    pub async fn connect_unjoined_for_test(opts: TransportOpts) -> Result<Self> {
+        Self::establish(
+            TransportOpts {
+                force_no_join: true,
+                ..opts
+            },
+            false,
+        )
+        .await
+    }
+
+    async fn establish(opts: TransportOpts, lazy: bool) -> Result<Self> {
+        let TransportOpts {
+            topic_hex,
+            topic_name,
+            topic_derivation,
+            send_topic,
+            secret_hex,
+            bootstrap,
+            udp_bind: _,
+            discovery,
+            run_time,
+            rejoin_on_isolation_ms,
+            broadcast_scope,
+            unicast,
+            rx_buffer,
+            gossip: tuning,
+            max_recv_bytes,
+            join_in_background: _,
+            force_no_join,
+        } = opts;
+        let gossip_config = tuning.resolve();
+        let max_recv_bytes = max_recv_bytes.map_or(gossip_config.max_message_size, |cap| {
+            cap.min(gossip_config.max_message_size)
+        });
+
+        // -------------------------------------------------------------
+        // 1) Build endpoint (optional deterministic secret key)
+        // -------------------------------------------------------------
+        let mut builder = Endpoint::builder();
+
+        if let Some(ref hex) = secret_hex {
+            builder = builder.secret_key(secret_key(Some(hex))?);
+        }
+
+        // Select discovery mode
+        builder = match discovery {
+            Discovery::Direct => builder
+                .discovery_local_network()
+                .relay_mode(RelayMode::Disabled),
+            Discovery::Relay => builder.discovery_n0().relay_mode(RelayMode::Default),
+        };
+
+        // Outgoing and incoming channels (always created so caller can run)
+        let (tx, tx_rx) = tokio::sync::mpsc::channel::<(Instant, Bytes)>(TX_QUEUE_CAPACITY);
+        let (ev_tx, ev_rx) = tokio::sync::mpsc::channel::<Result<TransportEvent, TransportError>>(
+            rx_buffer.unwrap_or(EVENT_CHANNEL_CAPACITY),
+        );
+        let join_stages = JoinStages::default();
+        let events = ev_tx.downgrade();
+
+        // Create endpoint
+        let endpoint = builder.bind().await?;
+        let id = endpoint.node_id().to_string();
+        diag!("node_id={}", id);
+        mark_stage(&join_stages, &ev_tx, "endpoint_bound");
+
+        // The home relay may come up before or after the join; the watcher holds
+        // a weak sender so it never keeps the event stream open.
+        let mut tasks = Vec::new();
+        if matches!(discovery, Discovery::Relay) {
+            let mut home_relay = endpoint.home_relay();
+            let stages = join_stages.clone();
+            let ev_tx = ev_tx.downgrade();
+            tasks.push(tokio::spawn(async move {
+                while home_relay.get().is_empty() {
+                    if home_relay.updated().await.is_err() {
+                        return;
+                    }
+                }
+                if let Some(ev_tx) = ev_tx.upgrade() {
+                    mark_stage(&stages, &ev_tx, "relay_connected");
+                }
+            }));
+        }
+
+        // Start gossip + router (plus the unicast side-channel if requested)
+        let gossip = gossip_config.spawn(endpoint.clone())?;
+        let mut router = Router::builder(endpoint.clone())
+            .accept(ALPN, gossip.clone())
+            .accept(PING_ALPN, PingHandler);
+        if unicast {
+            router = router.accept(
+                REPAIR_ALPN,
+                UnicastHandler {
+                    ev_tx: ev_tx.downgrade(),
+                },
+            );
+        }
+        let router = router.spawn();
+
+        // -------------------------------------------------------------
+        // 2) Determine topic IDs (send topic first)
+        // -------------------------------------------------------------
+        let topics = resolve_topics(&topic_hex, &topic_name, topic_derivation, send_topic)?;
+        let topic_tags: Vec<String> = topics.iter().map(|t| t.to_string()).collect();
+
+        // Parse bootstrap NodeIDs
+        let bootstraps_given = bootstrap.len();
+        let node_ids: Vec<NodeId> = bootstrap
+            .into_iter()
+            .filter_map(|b| b.parse::<NodeId>().ok())
+            .collect();
+        let bootstraps_parsed = node_ids.len();
+
+        // -------------------------------------------------------------
+        // 3) subscribe + join MUST NOT HANG → wrap in timeout
+        // -------------------------------------------------------------
+        let neighbors = Arc::new(Mutex::new(Vec::new()));
+        let join = Arc::new(Mutex::new(JoinState {
+            started_ms: now_ms(),
+            ..JoinState::default()
+        }));
+        let tasks = Arc::new(Mutex::new(tasks));
+        let task_exits = Arc::new(AtomicU64::new(0));
+        let broadcasts_done = Arc::new(Mutex::new(Vec::new()));
+        let plan = JoinPlan {
+            gossip: gossip.clone(),
+            topics,
+            topic_tags: topic_tags.clone(),
+            node_ids,
+            join_timeout: Duration::from_secs(run_time + 10),
+            rejoin_on_isolation_ms,
+            broadcast_scope,
+            rx_buffer,
+            max_recv_bytes,
+            outgoing: tx_rx,
+            broadcasts_done: broadcasts_done.clone(),
+            ev_tx,
+            join_stages: join_stages.clone(),
+            neighbors: neighbors.clone(),
+            tasks: tasks.clone(),
+            task_exits: task_exits.clone(),
+            join: join.clone(),
+            lazy,
+        };
+        if force_no_join {
+            plan.skip();
+        } else if lazy {
+            let handle = tokio::spawn(plan.run());
+            tasks.lock().unwrap().push(handle);
+        } else {
+            plan.run().await;
+        }
+
+        // -------------------------------------------------------------
+        // 4) Always return a fully usable Transport object
+        // -------------------------------------------------------------
+        Ok(Self {
+            id,
+            rx: tokio_stream::wrappers::ReceiverStream::new(ev_rx),
+            events,
+            tx,
+            enqueued: AtomicU64::new(0),
+            broadcasts_done,
+            endpoint,
+            router,
+            gossip,
+            _discovery: discovery,
+            topics: topic_tags,
+            topic_derivation: (!topic_name.is_empty()).then_some(topic_derivation),
+            broadcast_scope,
+            gossip_config,
+            join,
+            join_stages,
+            neighbors,
+            tasks,
+            task_exits,
+            unicast,
+            unicast_conns: Arc::new(tokio::sync::Mutex::new(HashMap::new())),
+            max_recv_bytes,
+            bootstraps_given,
+            bootstraps_parsed,
+        })
+    }
+
+    /// Node ID of a unicast peer, if the side-channel is enabled.
+    fn unicast_peer(&self, peer: &str) -> Result<NodeId, TransportError> {
+        if !self.unicast {
+            return Err(TransportError::Other(
+                "unicast side-channel disabled".into(),
+            ));
+        }
+        peer.parse::<NodeId>()
+            .map_err(|e| TransportError::Other(format!("invalid peer id {peer}: {e}")))
+    }
+}
+
+/// Send `bytes` as one uni stream to `node_id`, reusing (or opening) the
+/// side-channel connection in `conns`.
+async fn unicast_send(
+    endpoint: &Endpoint,
+    conns: &tokio::sync::Mutex<HashMap<NodeId, Connection>>,
+    node_id: NodeId,
+    bytes: Bytes,
+) -> Result<(), TransportError> {
+    let conn = {
+        let mut conns = conns.lock().await;
+        match conns.get(&node_id) {
+            Some(conn) if conn.close_reason().is_none() => conn.clone(),
+            _ => {
+                let conn = endpoint
+                    .connect(node_id, REPAIR_ALPN)
+                    .await
+                    .map_err(|e| TransportError::Other(format!("unicast connect: {e}")))?;
+                conns.insert(node_id, conn.clone());
+                conn
+            }
+        }
+    };
+    let unicast_err = |e: &dyn std::fmt::Display| TransportError::Other(format!("unicast: {e}"));
+    let mut send = conn.open_uni().await.map_err(|e| unicast_err(&e))?;
+    send.write_all(&bytes).await.map_err(|e| unicast_err(&e))?;
+    send.finish().map_err(|e| unicast_err(&e))?;
+    Ok(())
+}
+
+/// Everything the subscribe-and-join step needs, so it can run inline
+/// (`connect`) or in a background task (`connect_lazy`).
+struct JoinPlan {
+    gossip: Gossip,
+    topics: Vec<TopicId>,
+    topic_tags: Vec<String>,
+    node_ids: Vec<NodeId>,
+    join_timeout: Duration,
+    rejoin_on_isolation_ms: Option<u64>,
+    broadcast_scope: BroadcastScope,
+    rx_buffer: Option<usize>,
+    max_recv_bytes: usize,
+    outgoing: tokio::sync::mpsc::Receiver<(Instant, Bytes)>,
+    broadcasts_done: Arc<Mutex<Vec<BroadcastDone>>>,
+    ev_tx: tokio::sync::mpsc::Sender<Result<TransportEvent, TransportError>>,
+    join_stages: JoinStages,
+    neighbors: Arc<Mutex<Vec<String>>>,
+    tasks: Arc<Mutex<Vec<JoinHandle<()>>>>,
+    task_exits: Arc<AtomicU64>,
+    join: Arc<Mutex<JoinState>>,
+    lazy: bool,
+}
+
+/// Outcome of the join, filled in once `JoinPlan::run` finishes.
+#[derive(Debug, Default)]
+struct JoinState {
+    started_ms: u64,
+    done: bool,
+    joined: bool,
+    wait_ms: u64,
+    topics: Vec<TopicJoin>,
+    /// Send topic handle and bootstrap peers, for `Transport::rejoin`
+    rejoin: Option<(iroh_gossip::api::GossipSender, Vec<NodeId>)>,
+}
+
+impl JoinPlan {
+    /// Subscribe to every topic, wait for the joins and spawn the forwarding tasks.
+    async fn run(self) {
+        let JoinPlan {
+            gossip,
+            topics,
+            topic_tags,
+            node_ids,
+            join_timeout,
+            rejoin_on_isolation_ms,
+            broadcast_scope,
+            rx_buffer,
+            max_recv_bytes,
+            outgoing,
+            broadcasts_done,
+            ev_tx,
+            join_stages,
+            neighbors,
+            tasks,
+            task_exits,
+            join,
+            lazy,
+        } = self;
+        let join_start = join.lock().unwrap().started_ms;
+
+        let mut joined = true;
+        let mut topic_joins = Vec::with_capacity(topics.len());
+        let mut outgoing = Some(outgoing);
+        let multi = topics.len() > 1;
+
+        // Subscribe to every topic before waiting for any join, so peers listing
+        // their topics in a different order still meet.
+        let mut subscriptions = Vec::with_capacity(topics.len());
+        for topic in &topics {
+            let mut join_opts = JoinOptions::with_bootstrap(node_ids.clone());
+            if let Some(capacity) = rx_buffer {
+                join_opts.subscription_capacity = capacity;
+            }
+            subscriptions
+                .push(timeout(join_timeout, gossip.subscribe_with_opts(*topic, join_opts)).await);
+            mark_stage(&join_stages, &ev_tx, "subscribe_sent");
+        }
+
+        for (i, (topic_handle_result, tag)) in
+            subscriptions.into_iter().zip(&topic_tags).enumerate()
+        {
+            let outcome = |outcome, error| TopicJoin {
+                topic: tag.clone(),
+                outcome,
+                error,
+                wait_ms: now_ms().saturating_sub(join_start),
+            };
+            match topic_handle_result {
+                // Case A: subscribe completed (success or error)
+                Ok(Ok(mut topic_handle)) => {
+                    // Now wait on topic_handle.joined() but also time-limited
+                    let topic_joined = match timeout(join_timeout, topic_handle.joined()).await {
+                        Ok(Ok(())) => {
+                            mark_stage(&join_stages, &ev_tx, "first_neighbor");
+                            true
+                        }
+                        _ => false,
+                    };
+                    topic_joins.push(outcome(
+                        if topic_joined {
+                            JoinOutcome::Joined
+                        } else {
+                            JoinOutcome::JoinTimeout
+                        },
+                        None,
+                    ));
+                    if !topic_joined {
+                        joined = false;
+                        let _ = ev_tx.try_send(Err(TransportError::JoinTimeout {
+                            waited_ms: now_ms().saturating_sub(join_start),
+                        }));
+                    }
+
+                    let (sender, mut receiver) = topic_handle.split();
+                    let tag = tag.clone();
+
+                    // Other topics only contribute their messages; the sender half
+                    // is kept so the subscription stays alive.
+                    let Some(mut tx_rx) = outgoing.take().filter(|_| i == 0) else {
+                        let ev_tx = ev_tx.clone();
+                        tasks.lock().unwrap().push(tokio::spawn(async move {
+                            let _sender = sender;
+                            while let Some(item) = receiver.next().await {
+                                let item = item.map_err(Into::into).and_then(|e| {
+                                    TransportEvent::from_gossip(&tag, e, max_recv_bytes)
+                                });
+                                if let Ok(
+                                    TransportEvent::NeighborUp(_) | TransportEvent::NeighborDown(_),
+                                ) = item
+                                {
+                                    continue;
+                                }
+                                let _ = ev_tx.send(item).await;
+                            }
+                        }));
+                        continue;
+                    };
+
+                    join.lock().unwrap().rejoin = Some((sender.clone(), node_ids.clone()));
+
+                    // Spawn isolation supervisor (optional rejoin)
+                    if let Some(isolation_ms) = rejoin_on_isolation_ms {
+                        tasks.lock().unwrap().push(tokio::spawn(rejoin_supervisor(
+                            sender.clone(),
+                            node_ids.clone(),
+                            neighbors.clone(),
+                            ev_tx.clone(),
+                            isolation_ms,
+                        )));
+                    }
+
+                    // Spawn outgoing broadcast task; tickets follow the channel order,
+                    // data frames get their wire timestamp.
+                    let ev_tx_out = ev_tx.clone();
+                    let broadcasts_done = broadcasts_done.clone();
+                    let broadcast = async move {
+                        let mut ticket = 0;
+                        while let Some((enqueued_at, msg)) = tx_rx.recv().await {
+                            let msg = frame::stamp_wire_ts(msg, now_ms());
+                            let res = match broadcast_scope {
+                                BroadcastScope::Swarm => sender.broadcast(msg).await,
+                                BroadcastScope::Neighbors => sender.broadcast_neighbors(msg).await,
+                            };
+                            broadcasts_done.lock().unwrap().push(BroadcastDone {
+                                ticket,
+                                lat_ms: enqueued_at.elapsed().as_secs_f64() * 1000.0,
+                            });
+                            ticket += 1;
+                            if let Err(e) = res {
+                                warn!("broadcast error: {e:?}");
+                                let err = TransportError::BroadcastFailed(e.to_string());
+                                let _ = ev_tx_out.send(Err(err)).await;
+                            }
+                        }
+                        "outgoing queue closed"
+                    };
+                    tasks.lock().unwrap().push(spawn_watched(
+                        "broadcast",
+                        broadcast,
+                        ev_tx.downgrade(),
+                        task_exits.clone(),
+                    ));
+
+                    // Spawn incoming event task
+                    let neighbors_rx = neighbors.clone();
+                    let snapshot = |receiver: &iroh_gossip::api::GossipReceiver| {
+                        receiver
+                            .neighbors()
+                            .map(|n| n.to_string())
+                            .collect::<Vec<_>>()
+                    };
+                    *neighbors.lock().unwrap() = snapshot(&receiver);
+                    let ev_tx_rx = ev_tx.clone();
+                    let receive = async move {
+                        while let Some(item) = receiver.next().await {
+                            *neighbors_rx.lock().unwrap() = snapshot(&receiver);
+                            let _ = ev_tx_rx
+                                .send(item.map_err(Into::into).and_then(|e| {
+                                    TransportEvent::from_gossip(&tag, e, max_recv_bytes)
+                                }))
+                                .await;
+                        }
+                        "gossip stream ended"
+                    };
+                    tasks.lock().unwrap().push(spawn_watched(
+                        "receive",
+                        receive,
+                        ev_tx.downgrade(),
+                        task_exits.clone(),
+                    ));
+                }
+
+                // Case B: subscribe returned an error immediately
+                Ok(Err(e)) => {
+                    topic_joins.push(outcome(JoinOutcome::SubscribeError, Some(e.to_string())));
+                    joined = false;
+                    let _ = ev_tx.try_send(Err(TransportError::SubscribeFailed(e.to_string())));
+                }
+
+                // Case C: subscribe timed out entirely
+                Err(_) => {
+                    topic_joins.push(outcome(JoinOutcome::SubscribeTimeout, None));
+                    joined = false;
+                    let _ = ev_tx.try_send(Err(TransportError::JoinTimeout {
+                        waited_ms: now_ms().saturating_sub(join_start),
+                    }));
+                }
+            }
+        }
+        let join_wait_ms = now_ms().saturating_sub(join_start);
+
+        // One line for humans; the details go to the `connect` event.
+        let failed: String = topic_joins
+            .iter()
+            .filter(|t| t.outcome != JoinOutcome::Joined)
+            .map(|t| match (multi, &t.error) {
+                (true, _) => format!(" {}={}", t.topic, t.outcome.as_str()),
+                (false, Some(e)) => format!(" {}: {e}", t.outcome.as_str()),
+                (false, None) => format!(" {}", t.outcome.as_str()),
+            })
+            .collect();
+        diag!(
+            "joined={} wait_ms={join_wait_ms} bootstraps={}{failed}",
+            u8::from(joined),
+            node_ids.len()
+        );
+        {
+            let mut state = join.lock().unwrap();
+            state.done = true;
+            state.joined = joined;
+            state.wait_ms = join_wait_ms;
+            state.topics = topic_joins;
+        }
+        if joined {
+            mark_stage(&join_stages, &ev_tx, "joined");
+            if lazy {
+                let _ = ev_tx.try_send(Ok(TransportEvent::Joined {
+                    wait_ms: join_wait_ms,
+                }));
+            }
+        }
+
+        // Without a live subscription, closing the sender terminates RX immediately
+        drop(ev_tx);
+    }
+
+    /// Record every topic as skipped and finish the join unjoined, dropping
+    /// the channels as a join without any live subscription does.
+    fn skip(self) {
+        let topics = self
+            .topic_tags
+            .iter()
+            .map(|tag| TopicJoin {
+                topic: tag.clone(),
+                outcome: JoinOutcome::Skipped,
+                error: None,
+                wait_ms: 0,
+            })
+            .collect();
+        diag!(
+            "joined=0 wait_ms=0 bootstraps={} skipped (--force-no-join)",
+            self.node_ids.len()
+        );
+        let mut state = self.join.lock().unwrap();
+        state.done = true;
+        state.joined = false;
+        state.topics = topics;
+    }
+}
+
+/// Watches the neighbor set and re-joins the bootstrap peers once the node has
+/// been isolated for `isolation_ms`.
+///
+/// This uses `join_peers` on the existing subscription rather than a fresh
+/// subscribe, so an old subscription that is actually still alive (and any
+/// in-flight messages on it) is left undisturbed.
+async fn rejoin_supervisor(
+    sender: iroh_gossip::api::GossipSender,
+    bootstrap: Vec<NodeId>,
+    neighbors: Arc<Mutex<Vec<String>>>,
+    ev_tx: tokio::sync::mpsc::Sender<Result<TransportEvent, TransportError>>,
+    isolation_ms: u64,
+) {
+    let tick = Duration::from_millis((isolation_ms / 4).clamp(10, 250));
+    let mut isolated_since: Option<u64> = None;
+
+    loop {
+        sleep(tick).await;
+        let now = now_ms();
+
+        if !neighbors.lock().unwrap().is_empty() {
+            isolated_since = None;
+            continue;
+        }
+        let since = *isolated_since.get_or_insert(now);
+        let isolated_ms = now.saturating_sub(since);
+        if isolated_ms < isolation_ms {
+            continue;
+        }
+
+        let error = sender
+            .join_peers(bootstrap.clone())
+            .await
+            .err()
+            .map(|e| e.to_string());
+
+        // Give the join up to another isolation period to produce a neighbor.
+        let deadline = now_ms() + isolation_ms;
+        while neighbors.lock().unwrap().is_empty() && now_ms() < deadline {
+            sleep(tick).await;
+        }
+        let success = !neighbors.lock().unwrap().is_empty();
+
+        let ev = TransportEvent::RejoinAttempt {
+            success,
+            isolated_ms,
+            error,
+        };
+        if ev_tx.send(Ok(ev)).await.is_err() {
+            break;
+        }
+        isolated_since = None;
+    }
+}
+
+#[async_trait]
+impl Transport for IrohGossip {
+    fn id(&self) -> String {
+        format!("{}:{}", TransportKind::Iroh.id_prefix(), self.id)
+    }
+
+    fn broadcast_scope(&self) -> BroadcastScope {
+        self.broadcast_scope
+    }
+
+    fn task_exits(&self) -> u64 {
+        self.task_exits.load(Ordering::Relaxed)
+    }
+
+    fn drain_broadcast_queue(&self) -> Option<BroadcastQueue> {
+        Some(BroadcastQueue {
+            depth: self.tx.max_capacity() - self.tx.capacity(),
+            capacity: self.tx.max_capacity(),
+            enqueued: self.enqueued.load(Ordering::Relaxed),
+            done: std::mem::take(&mut *self.broadcasts_done.lock().unwrap()),
+        })
+    }
+
+    fn topics(&self) -> Vec<String> {
+        self.topics.clone()
+    }
+
+    fn secret_key(&self) -> Option<SecretKey> {
+        Some(self.endpoint.secret_key().clone())
+    }
+
+    fn max_message_size(&self) -> Option<usize> {
+        Some(self.gossip.max_message_size())
+    }
+
+    fn max_recv_bytes(&self) -> Option<usize> {
+        Some(self.max_recv_bytes)
+    }
+
+    fn gossip_config(&self) -> Option<GossipConfig> {
+        Some(self.gossip_config.clone())
+    }
+
+    fn topic_derivation(&self) -> Option<TopicDerivation> {
+        self.topic_derivation
+    }
+
+    fn joined(&self) -> bool {
+        let join = self.join.lock().unwrap();
+        join.done && join.joined
+    }
+
+    fn join_pending(&self) -> bool {
+        !self.join.lock().unwrap().done
+    }
+
+    fn join_wait_ms(&self) -> u64 {
+        let join = self.join.lock().unwrap();
+        match join.done {
+            true => join.wait_ms,
+            false => now_ms().saturating_sub(join.started_ms),
+        }
+    }
+
+    fn joined_at_ms(&self) -> Option<u64> {
+        let join = self.join.lock().unwrap();
+        (join.done && join.joined).then_some(join.started_ms + join.wait_ms)
+    }
+
+    fn join_stages(&self) -> BTreeMap<String, u64> {
+        let start = process_start_ms();
+        self.join_stages
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(stage, ts)| (stage.to_string(), ts.saturating_sub(start)))
+            .collect()
+    }
+
+    fn neighbors(&self) -> Vec<String> {
+        self.neighbors.lock().unwrap().clone()
+    }
+
+    fn protocol_counters(&self) -> Option<ProtocolCounters> {
+        let m = self.gossip.metrics();
+        Some(ProtocolCounters {
+            data_msgs_sent: m.msgs_data_sent.get(),
+            data_msgs_recv: m.msgs_data_recv.get(),
+            data_bytes_sent: m.msgs_data_sent_size.get(),
+            data_bytes_recv: m.msgs_data_recv_size.get(),
+            ctrl_msgs_sent: m.msgs_ctrl_sent.get(),
+            ctrl_msgs_recv: m.msgs_ctrl_recv.get(),
+            ctrl_bytes_sent: m.msgs_ctrl_sent_size.get(),
+            ctrl_bytes_recv: m.msgs_ctrl_recv_size.get(),
+            neighbor_up: m.neighbor_up.get(),
+            neighbor_down: m.neighbor_down.get(),
+        })
+    }
+
+    fn probe_neighbors(&self) -> Option<Vec<NeighborProbe>> {
+        let probe = |peer: String| {
+            let info = peer
+                .parse::<NodeId>()
+                .ok()
+                .and_then(|id| self.endpoint.remote_info(id));
+            let Some(info) = info else {
+                return NeighborProbe {
+                    peer,
+                    path: "none",
+                    latency_ms: None,
+                    last_recv_ms: None,
+                    reachable: false,
+                };
+            };
+            let path = match info.conn_type {
+                ConnectionType::Direct(_) => "direct",
+                ConnectionType::Relay(_) => "relay",
+                ConnectionType::Mixed(..) => "mixed",
+                ConnectionType::None => "none",
+            };
+            // Direct paths track inbound activity; relay-only paths only overall use.
+            let last_recv_ms = info
+                .last_received()
+                .or(info.last_used)
+                .map(|d| d.as_millis() as u64);
+            NeighborProbe {
+                peer,
+                path,
+                latency_ms: info.latency.map(|d| d.as_millis() as u64),
+                last_recv_ms,
+                reachable: path != "none" && last_recv_ms.is_some_and(|ms| ms <= REACH_RECENT_MS),
+            }
+        };
+        Some(self.neighbors().into_iter().map(probe).collect())
+    }
+
+    fn connect_report(&self) -> Option<ConnectReport> {
+        let join = self.join.lock().unwrap();
+        Some(ConnectReport {
+            node_id: self.id.clone(),
+            bootstraps_given: self.bootstraps_given,
+            bootstraps_parsed: self.bootstraps_parsed,
+            join_done: join.done,
+            topics: join.topics.clone(),
+        })
+    }
+
+    fn observed_addrs(&self) -> Option<ObservedAddrs> {
+        let mut direct: Vec<String> = self
+            .endpoint
+            .direct_addresses()
+            .get()
+            .unwrap_or_default()
+            .iter()
+            .map(|a| a.addr.to_string())
+            .collect();
+        direct.sort();
+        direct.dedup();
+        let relay = self
+            .endpoint
+            .home_relay()
+            .get()
+            .first()
+            .map(|u| u.to_string());
+        Some(ObservedAddrs { direct, relay })
+    }
+
+    async fn probe_rtt(&self, count: u32, budget: Duration) -> Option<Vec<RttProbe>> {
+        let mut probes = tokio::task::JoinSet::new();
+        for peer in self.neighbors() {
+            let endpoint = self.endpoint.clone();
+            probes.spawn(async move {
+                let mut rtts_ms = Vec::new();
+                let error = match timeout(budget, ping(&endpoint, &peer, count, &mut rtts_ms)).await
+                {
+                    Ok(Ok(())) => None,
+                    Ok(Err(e)) => Some(e),
+                    Err(_) => Some("timeout".to_string()),
+                };
+                RttProbe {
+                    peer,
+                    rtts_ms,
+                    error,
+                }
+            });
+        }
+        Some(probes.join_all().await)
+    }
+
+    fn wire_bytes(&self) -> Option<WireBytes> {
+        let m = &self.endpoint.metrics().magicsock;
+        Some(WireBytes {
+            tx: m.send_data.get(),
+            rx: m.recv_data_ipv4.get() + m.recv_data_ipv6.get() + m.recv_data_relay.get(),
+        })
+    }
+
+    async fn close(&mut self) -> Result<(), TransportError> {
+        // Stop the forwarding tasks first so nothing feeds closed channels.
+        for task in self.tasks.lock().unwrap().drain(..) {
+            task.abort();
+        }
+
+        // Router shutdown also closes the endpoint; bound both so we never linger.
+        if timeout(Duration::from_millis(1000), self.router.shutdown())
+            .await
+            .is_err()
+        {
+            warn!("router shutdown timed out");
+        }
+        if !self.endpoint.is_closed()
+            && timeout(Duration::from_millis(500), self.endpoint.close())
+                .await
+                .is_err()
+        {
+            warn!("endpoint close timed out");
+        }
+        Ok(())
+    }
+
+    fn metrics_dump(&self) -> Option<String> {
+        let mut registry = Registry::default();
+        registry.register(self.gossip.metrics().clone());
+        registry.register_all(self.endpoint.metrics());
+        registry.encode_openmetrics_to_string().ok()
+    }
+
+    async fn broadcast(&self, bytes: Bytes) -> Result<(), TransportError> {
+        self.tx
+            .send((Instant::now(), bytes))
+            .await
+            .map_err(|_| TransportError::StreamClosed)?;
+        self.enqueued.fetch_add(1, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn send_to(&self, peer: &str, bytes: Bytes) -> Result<(), TransportError> {
+        let node_id = self.unicast_peer(peer)?;
+        unicast_send(&self.endpoint, &self.unicast_conns, node_id, bytes).await
+    }
+
+    fn send_to_background(&self, peer: &str, bytes: Bytes) -> Result<(), TransportError> {
+        let node_id = self.unicast_peer(peer)?;
+        let endpoint = self.endpoint.clone();
+        let conns = self.unicast_conns.clone();
+        let events = self.events.clone();
+        let task = tokio::spawn(async move {
+            if let Err(e) = unicast_send(&endpoint, &conns, node_id, bytes).await
+                && let Some(ev_tx) = events.upgrade()
+            {
+                let _ = ev_tx.send(Err(e)).await;
+            }
+        });
+        let mut tasks = self.tasks.lock().unwrap();
+        tasks.retain(|t| !t.is_finished());
+        tasks.push(task);
+        Ok(())
+    }
+
+    async fn rejoin(&self) -> Result<bool, TransportError> {
+        let Some((sender, bootstrap)) = self.join.lock().unwrap().rejoin.clone() else {
+            return Ok(false);
+        };
+        sender.join_peers(bootstrap).await?;
+        Ok(true)
+    }
+
+    async fn next(&mut self) -> Option<Result<TransportEvent, TransportError>> {
+        self.rx.next().await
+    }
+}
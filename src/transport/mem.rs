@@ -0,0 +1,326 @@
+//! In-memory transport: every `MemTransport` of the process subscribed to a
+//! topic is a direct neighbor of every other one on it, and a broadcast is
+//! handed straight to their event channels. No sockets and no overlay, so
+//! two transports in one process (the self-test, a test harness) can run
+//! the sender and receiver loops without a network.
+
+use super::{
+    BroadcastScope, ConnectReport, EVENT_CHANNEL_CAPACITY, JoinOutcome, TopicJoin, Transport,
+    TransportError, TransportEvent, TransportKind, TransportOpts, resolve_topics, secret_key,
+};
+use crate::util::{TopicDerivation, diag, now_ms};
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use iroh::SecretKey;
+use iroh_gossip::proto::TopicId;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, LazyLock, Mutex};
+use tokio::sync::mpsc;
+
+type EventTx = mpsc::Sender<Result<TransportEvent, TransportError>>;
+
+/// Subscribers of every topic in the process, by node ID.
+static HUB: LazyLock<Mutex<HashMap<TopicId, BTreeMap<String, Member>>>> =
+    LazyLock::new(Default::default);
+
+/// One subscriber as the other transports see it.
+#[derive(Clone)]
+struct Member {
+    ev_tx: EventTx,
+    max_recv_bytes: Option<usize>,
+    unicast: bool,
+    /// Set when an event was dropped on a full channel; the owner reports it
+    /// as `Lagged` on its next `next()`.
+    lagged: Arc<AtomicBool>,
+}
+
+impl Member {
+    fn deliver(&self, event: TransportEvent) {
+        if self.ev_tx.try_send(Ok(event)).is_err() {
+            self.lagged.store(true, Ordering::Relaxed);
+        }
+    }
+}
+
+/// Transport over the in-process hub; see the module docs.
+pub struct MemTransport {
+    id: String,
+    key: SecretKey,
+    topics: Vec<TopicId>,
+    topic_tags: Vec<String>,
+    topic_derivation: Option<TopicDerivation>,
+    broadcast_scope: BroadcastScope,
+    unicast: bool,
+    rx: mpsc::Receiver<Result<TransportEvent, TransportError>>,
+    lagged: Arc<AtomicBool>,
+    /// Whether this transport is (still) registered with the hub.
+    subscribed: bool,
+    joined_at_ms: Option<u64>,
+    bootstraps_given: usize,
+}
+
+impl MemTransport {
+    /// Subscribe to the topics of `opts` on the hub. Subscribing completes
+    /// at once: the node is joined, and every transport already on the send
+    /// topic is a neighbor from the start, in `neighbors()` but without a
+    /// `NeighborUp` (as a gossip join consumes the first one); they get a
+    /// `NeighborUp` for this node. With `force_no_join` nothing is
+    /// subscribed and the event stream is closed from the start.
+    pub fn connect(opts: TransportOpts) -> Result<Self> {
+        let key = secret_key(opts.secret_hex.as_deref())?;
+        let id = key.public().to_string();
+        diag!("node_id={}", id);
+        let topics = resolve_topics(
+            &opts.topic_hex,
+            &opts.topic_name,
+            opts.topic_derivation,
+            opts.send_topic,
+        )?;
+        let (ev_tx, rx) = mpsc::channel(opts.rx_buffer.unwrap_or(EVENT_CHANNEL_CAPACITY));
+        let lagged = Arc::new(AtomicBool::new(false));
+        let subscribed = !opts.force_no_join;
+        if subscribed {
+            let me = Member {
+                ev_tx,
+                max_recv_bytes: opts.max_recv_bytes,
+                unicast: opts.unicast,
+                lagged: lagged.clone(),
+            };
+            let mut hub = HUB.lock().unwrap();
+            for (i, topic) in topics.iter().enumerate() {
+                let members = hub.entry(*topic).or_default();
+                // Neighbor events only for the send topic, like the gossip active view.
+                if i == 0 {
+                    for member in members.values() {
+                        member.deliver(TransportEvent::NeighborUp(id.clone()));
+                    }
+                }
+                members.insert(id.clone(), me.clone());
+            }
+        }
+        Ok(Self {
+            id,
+            key,
+            topic_tags: topics.iter().map(|t| t.to_string()).collect(),
+            topics,
+            topic_derivation: (!opts.topic_name.is_empty()).then_some(opts.topic_derivation),
+            broadcast_scope: opts.broadcast_scope,
+            unicast: opts.unicast,
+            rx,
+            lagged,
+            subscribed,
+            joined_at_ms: subscribed.then(now_ms),
+            bootstraps_given: opts.bootstrap.len(),
+        })
+    }
+
+    /// Leave every topic, telling the send topic's members the node is gone.
+    fn unsubscribe(&mut self) {
+        if !std::mem::take(&mut self.subscribed) {
+            return;
+        }
+        let mut hub = HUB.lock().unwrap();
+        for (i, topic) in self.topics.iter().enumerate() {
+            let Some(members) = hub.get_mut(topic) else {
+                continue;
+            };
+            members.remove(&self.id);
+            if i == 0 {
+                for member in members.values() {
+                    member.deliver(TransportEvent::NeighborDown(self.id.clone()));
+                }
+            }
+            if members.is_empty() {
+                hub.remove(topic);
+            }
+        }
+    }
+
+    /// Other members of the send topic.
+    fn peers(&self) -> Vec<(String, Member)> {
+        if !self.subscribed {
+            return Vec::new();
+        }
+        let hub = HUB.lock().unwrap();
+        hub.get(&self.topics[0])
+            .into_iter()
+            .flatten()
+            .filter(|(peer, _)| **peer != self.id)
+            .map(|(peer, member)| (peer.clone(), member.clone()))
+            .collect()
+    }
+}
+
+impl Drop for MemTransport {
+    fn drop(&mut self) {
+        self.unsubscribe();
+    }
+}
+
+#[async_trait]
+impl Transport for MemTransport {
+    fn id(&self) -> String {
+        format!("{}:{}", TransportKind::Mem.id_prefix(), self.id)
+    }
+
+    fn topics(&self) -> Vec<String> {
+        self.topic_tags.clone()
+    }
+
+    fn topic_derivation(&self) -> Option<TopicDerivation> {
+        self.topic_derivation
+    }
+
+    fn broadcast_scope(&self) -> BroadcastScope {
+        self.broadcast_scope
+    }
+
+    fn secret_key(&self) -> Option<SecretKey> {
+        Some(self.key.clone())
+    }
+
+    async fn broadcast(&self, bytes: Bytes) -> Result<(), TransportError> {
+        if !self.subscribed {
+            return Err(TransportError::StreamClosed);
+        }
+        // Every member is a direct neighbor, so the last delivery hop is 0.
+        let ldh = match self.broadcast_scope {
+            BroadcastScope::Swarm => Some(0),
+            BroadcastScope::Neighbors => None,
+        };
+        for (_, member) in self.peers() {
+            let event = match member.max_recv_bytes {
+                Some(max) if bytes.len() > max => TransportEvent::Oversized {
+                    topic: self.topic_tags[0].clone(),
+                    size: bytes.len(),
+                    delivered_from: self.id.clone(),
+                },
+                _ => TransportEvent::Msg {
+                    topic: self.topic_tags[0].clone(),
+                    content: bytes.clone(),
+                    ldh,
+                    delivered_from: self.id.clone(),
+                },
+            };
+            member.deliver(event);
+        }
+        Ok(())
+    }
+
+    async fn send_to(&self, peer: &str, bytes: Bytes) -> Result<(), TransportError> {
+        self.send_to_background(peer, bytes)
+    }
+
+    fn send_to_background(&self, peer: &str, bytes: Bytes) -> Result<(), TransportError> {
+        if !self.unicast {
+            return Err(TransportError::Other(
+                "unicast side-channel disabled".into(),
+            ));
+        }
+        let member = self
+            .peers()
+            .into_iter()
+            .find(|(id, _)| id == peer)
+            .map(|(_, member)| member)
+            .ok_or_else(|| TransportError::Other(format!("no such peer: {peer}")))?;
+        if !member.unicast {
+            return Err(TransportError::Other(format!(
+                "{peer} does not accept unicast"
+            )));
+        }
+        member.deliver(TransportEvent::Unicast {
+            from: self.id.clone(),
+            content: bytes,
+        });
+        Ok(())
+    }
+
+    async fn next(&mut self) -> Option<Result<TransportEvent, TransportError>> {
+        if self.lagged.swap(false, Ordering::Relaxed) {
+            return Some(Err(TransportError::Lagged));
+        }
+        if !self.subscribed {
+            return None;
+        }
+        self.rx.recv().await
+    }
+
+    fn joined(&self) -> bool {
+        self.joined_at_ms.is_some()
+    }
+
+    fn joined_at_ms(&self) -> Option<u64> {
+        self.joined_at_ms
+    }
+
+    fn neighbors(&self) -> Vec<String> {
+        self.peers().into_iter().map(|(peer, _)| peer).collect()
+    }
+
+    fn connect_report(&self) -> Option<ConnectReport> {
+        let outcome = match self.joined_at_ms {
+            Some(_) => JoinOutcome::Joined,
+            None => JoinOutcome::Skipped,
+        };
+        Some(ConnectReport {
+            node_id: self.id.clone(),
+            // Every member of the topic is a neighbor; bootstraps are not needed.
+            bootstraps_given: self.bootstraps_given,
+            bootstraps_parsed: self.bootstraps_given,
+            join_done: true,
+            topics: self
+                .topic_tags
+                .iter()
+                .map(|topic| TopicJoin {
+                    topic: topic.clone(),
+                    outcome,
+                    error: None,
+                    wait_ms: 0,
+                })
+                .collect(),
+        })
+    }
+
+    async fn close(&mut self) -> Result<(), TransportError> {
+        self.unsubscribe();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::transport::tests::opts;
+    use std::time::Duration;
+
+    /// Broadcast `n` messages to a receiver with an `rx_buffer` of `buffer`
+    /// that reads nothing meanwhile; whether it then reports `Lagged`.
+    async fn lags(buffer: usize, n: usize) -> bool {
+        let topic = rand::random();
+        let mut receiver = MemTransport::connect(TransportOpts {
+            rx_buffer: Some(buffer),
+            ..opts(topic)
+        })
+        .unwrap();
+        let sender = MemTransport::connect(opts(topic)).unwrap();
+        for _ in 0..n {
+            sender.broadcast(Bytes::from_static(b"x")).await.unwrap();
+        }
+        let mut lagged = false;
+        while let Ok(Some(event)) =
+            tokio::time::timeout(Duration::from_millis(50), receiver.next()).await
+        {
+            lagged |= matches!(event, Err(TransportError::Lagged));
+        }
+        lagged
+    }
+
+    #[tokio::test]
+    async fn rx_buffer_large_enough_avoids_lagged() {
+        // One slot for the sender's NeighborUp, one per message.
+        assert!(lags(8, 100).await);
+        assert!(!lags(101, 100).await);
+    }
+}
@@ -0,0 +1,847 @@
+//! Transport abstraction: the `Transport` trait the run loops drive, the
+//! events and errors it surfaces, and `create` to build one by kind.
+//!
+//! Each implementation lives in its own submodule; adding one means
+//! implementing the trait and adding a `TransportKind` arm to `create`.
+
+mod iroh;
+mod mem;
+mod udp;
+
+pub use self::iroh::IrohGossip;
+pub use self::mem::MemTransport;
+pub use self::udp::UdpTransport;
+
+use crate::metrics::{ProtocolCounters, WireBytes};
+use crate::util::{TopicDerivation, diag, topic_from_name};
+use ::iroh::SecretKey;
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use iroh_gossip::api::{ApiError, Event};
+use iroh_gossip::proto::{DeliveryScope, HyparviewConfig, PlumtreeConfig, TopicId};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, HashSet};
+use std::net::SocketAddr;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::sync::atomic::{AtomicU64, Ordering};
+use tokio::task::JoinHandle;
+use tokio::time::Duration;
+
+/// Transport implementations, selected with `--transport`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TransportKind {
+    /// iroh endpoint running iroh-gossip (`IrohGossip`).
+    Iroh,
+    /// Datagrams straight to every known peer (`UdpTransport`).
+    Udp,
+    /// In-process hub, no network (`MemTransport`).
+    Mem,
+}
+
+impl TransportKind {
+    /// Name used on the command line and in logs.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TransportKind::Iroh => "iroh",
+            TransportKind::Udp => "udp",
+            TransportKind::Mem => "mem",
+        }
+    }
+
+    /// Prefix of `Transport::id` (and of log peer IDs) for this kind.
+    pub fn id_prefix(&self) -> &'static str {
+        match self {
+            TransportKind::Iroh => "gossip",
+            TransportKind::Udp => "udp",
+            TransportKind::Mem => "mem",
+        }
+    }
+}
+
+/// Defines the discovery mode for gossip peers.
+#[derive(Debug, Clone, Copy)]
+pub enum Discovery {
+    /// Direct discovery (peers connect directly to each other, typically via local LAN discovery like mDNS).
+    Direct,
+    /// Relay-assisted discovery using one or more bootstrap peers
+    /// This mode relies on iroh's n0 discovery and can traverse NATs via relays.
+    Relay,
+}
+
+/// Path state of one neighbor, from the endpoint's view of the remote node.
+#[derive(Debug, Clone, Serialize)]
+pub struct NeighborProbe {
+    pub peer: String,
+    /// "direct", "relay", "mixed" or "none".
+    pub path: &'static str,
+    pub latency_ms: Option<u64>,
+    /// Time since anything was last received from the neighbor.
+    pub last_recv_ms: Option<u64>,
+    /// A path exists and `last_recv_ms` is within `REACH_RECENT_MS`.
+    pub reachable: bool,
+}
+
+/// Echo round trips to one neighbor.
+#[derive(Debug, Clone, Serialize)]
+pub struct RttProbe {
+    pub peer: String,
+    pub rtts_ms: Vec<f64>,
+    /// Why fewer probes than requested were answered.
+    pub error: Option<String>,
+}
+
+impl RttProbe {
+    /// Lowest and median round trip, if any probe was answered.
+    pub fn min_median(&self) -> (Option<f64>, Option<f64>) {
+        let mut sorted = self.rtts_ms.clone();
+        sorted.sort_by(f64::total_cmp);
+        (
+            sorted.first().copied(),
+            sorted.get(sorted.len() / 2).copied(),
+        )
+    }
+}
+
+/// Addresses other nodes can reach us on, as the endpoint currently sees them.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize)]
+pub struct ObservedAddrs {
+    /// Direct (local and public) socket addresses, sorted.
+    pub direct: Vec<String>,
+    pub relay: Option<String>,
+}
+
+impl ObservedAddrs {
+    /// Which of direct addresses and relay changed since `prev`; the first
+    /// addresses and the first relay are not a change.
+    pub fn changed_from(&self, prev: &ObservedAddrs) -> (bool, bool) {
+        (
+            !prev.direct.is_empty() && prev.direct != self.direct,
+            prev.relay.is_some() && prev.relay != self.relay,
+        )
+    }
+}
+
+/// What establishing a transport found out: the facts `connect` used to print
+/// only to stderr, for the `connect` log event and the summary.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ConnectReport {
+    pub node_id: String,
+    /// Bootstrap node IDs passed in, and how many of them parsed.
+    pub bootstraps_given: usize,
+    pub bootstraps_parsed: usize,
+    /// Whether subscribing and joining has finished (false while a background
+    /// join runs).
+    pub join_done: bool,
+    /// Join outcome per subscribed topic, the send topic first.
+    pub topics: Vec<TopicJoin>,
+}
+
+impl ConnectReport {
+    /// First subscribe error of any topic.
+    pub fn subscribe_error(&self) -> Option<&str> {
+        self.topics
+            .iter()
+            .find(|t| t.outcome == JoinOutcome::SubscribeError)
+            .and_then(|t| t.error.as_deref())
+    }
+
+    /// Whether the join was skipped on purpose (`--force-no-join`).
+    pub fn join_skipped(&self) -> bool {
+        self.topics
+            .iter()
+            .any(|t| t.outcome == JoinOutcome::Skipped)
+    }
+}
+
+/// Join outcome of one topic.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct TopicJoin {
+    pub topic: String,
+    pub outcome: JoinOutcome,
+    /// Error text of a failed subscribe.
+    pub error: Option<String>,
+    /// Time from the start of the join until this outcome.
+    pub wait_ms: u64,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum JoinOutcome {
+    Joined,
+    /// Subscribed, but no neighbor came up within the join timeout.
+    JoinTimeout,
+    SubscribeError,
+    /// The subscribe itself did not complete within the join timeout.
+    SubscribeTimeout,
+    /// Never subscribed (`--force-no-join`).
+    Skipped,
+}
+
+impl JoinOutcome {
+    /// Name used in logs and console output (same as the serialized form).
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            JoinOutcome::Joined => "joined",
+            JoinOutcome::JoinTimeout => "join_timeout",
+            JoinOutcome::SubscribeError => "subscribe_error",
+            JoinOutcome::SubscribeTimeout => "subscribe_timeout",
+            JoinOutcome::Skipped => "skipped",
+        }
+    }
+}
+
+/// A queued broadcast that the gossip layer has accepted (or rejected).
+#[derive(Debug, Clone, Copy)]
+pub struct BroadcastDone {
+    /// Position of the `broadcast` call, counting from 0.
+    pub ticket: u64,
+    /// Time from enqueueing to the inner broadcast completing.
+    pub lat_ms: f64,
+}
+
+/// Outgoing broadcast queue state, see `Transport::drain_broadcast_queue`.
+#[derive(Debug, Clone, Default)]
+pub struct BroadcastQueue {
+    pub depth: usize,
+    pub capacity: usize,
+    /// Broadcasts enqueued so far; the newest has ticket `enqueued - 1`.
+    pub enqueued: u64,
+    /// Completions since the previous drain, in ticket order.
+    pub done: Vec<BroadcastDone>,
+}
+
+/// Which peers a broadcast is addressed to.
+///
+/// `Neighbors` sends only to the direct neighbors of the sender, giving a
+/// 1-hop baseline over the same connections. Forwarder nodes do not relay
+/// neighbor-scope messages, so peers that are not direct neighbors of the
+/// sender receive nothing in this mode.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum BroadcastScope {
+    /// Broadcast to the whole swarm (multi-hop via plumtree).
+    #[default]
+    Swarm,
+    /// Broadcast only to direct neighbors (1 hop).
+    Neighbors,
+}
+
+impl BroadcastScope {
+    /// Name used in logs and summaries.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            BroadcastScope::Swarm => "swarm",
+            BroadcastScope::Neighbors => "neighbors",
+        }
+    }
+}
+
+/// Transport-level events delivered to the run loops.
+///
+/// Gossip events are translated into these so the runners don't depend on
+/// the iroh-gossip event type directly, and the transport can inject its own
+/// events (e.g. rejoin attempts).
+#[derive(Debug, Clone)]
+pub enum TransportEvent {
+    /// A message received on one of the subscribed topics.
+    Msg {
+        /// Topic the message arrived on (hex ID).
+        topic: String,
+        /// Raw payload bytes.
+        content: Bytes,
+        /// Last-delivery-hop (overlay hop count); `None` for neighbor-scope deliveries.
+        ldh: Option<u16>,
+        /// Neighbor that delivered the message (not necessarily the author).
+        delivered_from: String,
+    },
+    /// A direct neighbor was added to the active view.
+    NeighborUp(String),
+    /// A direct neighbor was removed from the active view.
+    NeighborDown(String),
+    /// A message arrived over the unicast side-channel.
+    Unicast { from: String, content: Bytes },
+    /// A rejoin with the bootstrap peers was attempted after isolation.
+    RejoinAttempt {
+        /// Whether at least one neighbor was regained after the attempt.
+        success: bool,
+        /// How long the node had been without neighbors before the attempt.
+        isolated_ms: u64,
+        /// Error returned by the gossip API, if any.
+        error: Option<String>,
+    },
+    /// A message above `TransportOpts::max_recv_bytes` was dropped unread.
+    Oversized {
+        /// Topic the message arrived on (hex ID).
+        topic: String,
+        /// Payload size in bytes.
+        size: usize,
+        /// Neighbor that delivered the message.
+        delivered_from: String,
+    },
+    /// A background join (`IrohGossip::connect_lazy`) succeeded.
+    Joined {
+        /// How long subscribing and joining took.
+        wait_ms: u64,
+    },
+    /// The join reached a milestone (queued during `connect`, so it arrives
+    /// before any network event of the run).
+    JoinProgress {
+        /// endpoint_bound, relay_connected, subscribe_sent, first_neighbor or joined.
+        stage: &'static str,
+        /// Wall-clock time the stage was reached.
+        ts_ms: u64,
+    },
+    /// A background task of the transport ended before `close()`; after a
+    /// "receive" task only `StreamClosed` follows.
+    TaskExited {
+        /// "broadcast" or "receive".
+        which: &'static str,
+        /// Why it ended: the reason it returned, or the panic message.
+        reason: String,
+    },
+}
+
+/// Errors surfaced by transports.
+///
+/// `StreamClosed` is fatal (no further events will arrive); all other
+/// variants are transient and the run loops count them and continue.
+#[derive(Debug, Clone)]
+pub enum TransportError {
+    /// Joining the topic did not complete within the timeout.
+    JoinTimeout { waited_ms: u64 },
+    /// Subscribing to the topic failed.
+    SubscribeFailed(String),
+    /// The gossip layer rejected a broadcast.
+    BroadcastFailed(String),
+    /// The event stream or outgoing channel is closed.
+    StreamClosed,
+    /// The subscriber fell behind and missed events.
+    Lagged,
+    /// Any other transport failure.
+    Other(String),
+}
+
+impl TransportError {
+    /// Stable snake_case name used for logging and `errors_by_kind`.
+    pub fn kind(&self) -> &'static str {
+        match self {
+            TransportError::JoinTimeout { .. } => "join_timeout",
+            TransportError::SubscribeFailed(_) => "subscribe_failed",
+            TransportError::BroadcastFailed(_) => "broadcast_failed",
+            TransportError::StreamClosed => "stream_closed",
+            TransportError::Lagged => "lagged",
+            TransportError::Other(_) => "other",
+        }
+    }
+
+    /// Whether the run loop should terminate on this error.
+    pub fn is_fatal(&self) -> bool {
+        matches!(self, TransportError::StreamClosed)
+    }
+}
+
+impl std::fmt::Display for TransportError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            TransportError::JoinTimeout { waited_ms } => {
+                write!(f, "join timed out after {waited_ms} ms")
+            }
+            TransportError::SubscribeFailed(e) => write!(f, "subscribe failed: {e}"),
+            TransportError::BroadcastFailed(e) => write!(f, "broadcast failed: {e}"),
+            TransportError::StreamClosed => write!(f, "event stream closed"),
+            TransportError::Lagged => write!(f, "subscriber lagged and missed events"),
+            TransportError::Other(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+impl std::error::Error for TransportError {}
+
+impl From<ApiError> for TransportError {
+    fn from(e: ApiError) -> Self {
+        match e {
+            ApiError::Closed { .. } => TransportError::StreamClosed,
+            other => TransportError::Other(other.to_string()),
+        }
+    }
+}
+
+impl TransportEvent {
+    /// Translates a gossip event of `topic`; messages above `max_recv_bytes`
+    /// become `Oversized` without their payload.
+    ///
+    /// Gossip `Lagged` events are surfaced as a transient `TransportError::Lagged`.
+    fn from_gossip(
+        topic: &str,
+        event: Event,
+        max_recv_bytes: usize,
+    ) -> Result<Self, TransportError> {
+        Ok(match event {
+            Event::Received(m) if m.content.len() > max_recv_bytes => TransportEvent::Oversized {
+                topic: topic.to_string(),
+                size: m.content.len(),
+                delivered_from: m.delivered_from.to_string(),
+            },
+            Event::Received(m) => {
+                let ldh = match m.scope {
+                    DeliveryScope::Swarm(round) => {
+                        let v = serde_json::to_value(round).unwrap();
+                        Some(v.as_u64().unwrap_or(0) as u16)
+                    }
+                    DeliveryScope::Neighbors => None,
+                };
+                TransportEvent::Msg {
+                    topic: topic.to_string(),
+                    content: m.content,
+                    ldh,
+                    delivered_from: m.delivered_from.to_string(),
+                }
+            }
+            Event::NeighborUp(n) => TransportEvent::NeighborUp(n.to_string()),
+            Event::NeighborDown(n) => TransportEvent::NeighborDown(n.to_string()),
+            Event::Lagged => return Err(TransportError::Lagged),
+        })
+    }
+}
+
+/// Options for establishing a transport.
+#[derive(Debug, Clone)]
+pub struct TransportOpts {
+    /// Topic hex IDs to subscribe to.
+    pub topic_hex: Vec<String>,
+    /// Topic names, each hashed into a topic ID and subscribed after `topic_hex`.
+    pub topic_name: Vec<String>,
+    /// How `topic_name` (and a `send_topic` name) is hashed.
+    pub topic_derivation: TopicDerivation,
+    /// Subscribed topic (hex ID or name) that carries outgoing broadcasts; the first if unset.
+    pub send_topic: Option<String>,
+    /// Optional secret key (32-byte hex) for a deterministic node ID.
+    pub secret_hex: Option<String>,
+    /// Bootstrap node IDs (socket addresses for `TransportKind::Udp`).
+    pub bootstrap: Vec<String>,
+    /// Local address of the UDP socket (`TransportKind::Udp`); an ephemeral
+    /// port on all interfaces if unset.
+    pub udp_bind: Option<SocketAddr>,
+    /// Discovery mode.
+    pub discovery: Discovery,
+    /// Expected run time (s), used to bound the join timeout.
+    pub run_time: u64,
+    /// Rejoin the bootstrap peers after this long without neighbors (None disables).
+    pub rejoin_on_isolation_ms: Option<u64>,
+    /// Scope of outgoing broadcasts.
+    pub broadcast_scope: BroadcastScope,
+    /// Accept and allow the unicast side-channel (`send_to`), used by `--repair`.
+    pub unicast: bool,
+    /// Capacity of the per-topic gossip subscription and of the event channel
+    /// (None keeps the defaults); a full subscription shows up as `Lagged`.
+    pub rx_buffer: Option<usize>,
+    /// iroh-gossip protocol parameters (`--gossip-*`).
+    pub gossip: GossipTuning,
+    /// Drop received gossip messages above this many bytes before they reach
+    /// the event channel (None: the gossip maximum message size).
+    pub max_recv_bytes: Option<usize>,
+    /// Return once the endpoint is bound and join in the background
+    /// (`IrohGossip::connect_lazy`) instead of waiting for the join.
+    pub join_in_background: bool,
+    /// Skip subscribing and joining entirely, leaving the transport as a
+    /// failed join does (`--force-no-join`, `IrohGossip::connect_unjoined_for_test`).
+    pub force_no_join: bool,
+}
+
+/// Overrides for iroh-gossip's membership (HyParView) and broadcast
+/// (Plumtree) parameters; unset fields keep the library defaults.
+#[derive(Debug, Clone, Default)]
+pub struct GossipTuning {
+    pub max_message_size: Option<usize>,
+    pub active_view: Option<usize>,
+    pub passive_view: Option<usize>,
+    pub active_walk: Option<u16>,
+    pub passive_walk: Option<u16>,
+    pub shuffle_walk: Option<u16>,
+    pub shuffle_active: Option<usize>,
+    pub shuffle_passive: Option<usize>,
+    pub shuffle_interval_ms: Option<u64>,
+    pub neighbor_timeout_ms: Option<u64>,
+    pub graft_timeout_1_ms: Option<u64>,
+    pub graft_timeout_2_ms: Option<u64>,
+    pub dispatch_timeout_ms: Option<u64>,
+    pub optimization_threshold: Option<u16>,
+    pub cache_retention_ms: Option<u64>,
+    pub id_retention_ms: Option<u64>,
+    pub cache_evict_ms: Option<u64>,
+}
+
+/// Effective iroh-gossip configuration of a transport, recorded in the setup
+/// event and the receiver summary.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct GossipConfig {
+    pub max_message_size: usize,
+    pub active_view: usize,
+    pub passive_view: usize,
+    pub active_walk: u16,
+    pub passive_walk: u16,
+    pub shuffle_walk: u16,
+    pub shuffle_active: usize,
+    pub shuffle_passive: usize,
+    pub shuffle_interval_ms: u64,
+    pub neighbor_timeout_ms: u64,
+    pub graft_timeout_1_ms: u64,
+    pub graft_timeout_2_ms: u64,
+    pub dispatch_timeout_ms: u64,
+    pub optimization_threshold: u16,
+    pub cache_retention_ms: u64,
+    pub id_retention_ms: u64,
+    pub cache_evict_ms: u64,
+}
+
+impl GossipTuning {
+    /// Library defaults with the overrides applied.
+    pub fn resolve(&self) -> GossipConfig {
+        let m = HyparviewConfig::default();
+        let b = PlumtreeConfig::default();
+        let ms = |d: Duration| d.as_millis() as u64;
+        // Round is a private newtype; its serialized form is the plain number.
+        let threshold = serde_json::to_value(b.optimization_threshold)
+            .ok()
+            .and_then(|v| v.as_u64())
+            .unwrap_or(0) as u16;
+        GossipConfig {
+            max_message_size: self
+                .max_message_size
+                .unwrap_or(iroh_gossip::proto::DEFAULT_MAX_MESSAGE_SIZE),
+            active_view: self.active_view.unwrap_or(m.active_view_capacity),
+            passive_view: self.passive_view.unwrap_or(m.passive_view_capacity),
+            active_walk: self.active_walk.unwrap_or(m.active_random_walk_length.0),
+            passive_walk: self.passive_walk.unwrap_or(m.passive_random_walk_length.0),
+            shuffle_walk: self.shuffle_walk.unwrap_or(m.shuffle_random_walk_length.0),
+            shuffle_active: self.shuffle_active.unwrap_or(m.shuffle_active_view_count),
+            shuffle_passive: self.shuffle_passive.unwrap_or(m.shuffle_passive_view_count),
+            shuffle_interval_ms: self.shuffle_interval_ms.unwrap_or(ms(m.shuffle_interval)),
+            neighbor_timeout_ms: self
+                .neighbor_timeout_ms
+                .unwrap_or(ms(m.neighbor_request_timeout)),
+            graft_timeout_1_ms: self.graft_timeout_1_ms.unwrap_or(ms(b.graft_timeout_1)),
+            graft_timeout_2_ms: self.graft_timeout_2_ms.unwrap_or(ms(b.graft_timeout_2)),
+            dispatch_timeout_ms: self.dispatch_timeout_ms.unwrap_or(ms(b.dispatch_timeout)),
+            optimization_threshold: self.optimization_threshold.unwrap_or(threshold),
+            cache_retention_ms: self
+                .cache_retention_ms
+                .unwrap_or(ms(b.message_cache_retention)),
+            id_retention_ms: self.id_retention_ms.unwrap_or(ms(b.message_id_retention)),
+            cache_evict_ms: self.cache_evict_ms.unwrap_or(ms(b.cache_evict_interval)),
+        }
+    }
+}
+
+/// Trait for irop-gossip transport
+#[async_trait]
+pub trait Transport: Send + Sync {
+    /// Returns a human-readable identifier for this transport instance.
+    fn id(&self) -> String;
+
+    /// Broadcasts a byte buffer to all peers in the send topic.
+    async fn broadcast(&self, bytes: Bytes) -> Result<(), TransportError>;
+
+    /// Subscribed topic IDs (hex), the send topic first.
+    fn topics(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// The joined topic ID (hex): the send topic if there are several.
+    fn topic(&self) -> Option<String> {
+        self.topics().into_iter().next()
+    }
+
+    /// Derivation of the topic IDs subscribed by name (None without names).
+    fn topic_derivation(&self) -> Option<TopicDerivation> {
+        None
+    }
+
+    /// Scope used by `broadcast`.
+    fn broadcast_scope(&self) -> BroadcastScope {
+        BroadcastScope::Swarm
+    }
+
+    /// Background tasks that ended before `close()` so far (see
+    /// `TransportEvent::TaskExited`).
+    fn task_exits(&self) -> u64 {
+        0
+    }
+
+    /// Outgoing queue state, taking the completions recorded since the last
+    /// call; None if `broadcast` is not queued.
+    fn drain_broadcast_queue(&self) -> Option<BroadcastQueue> {
+        None
+    }
+
+    /// Largest payload a single `broadcast` accepts, if limited.
+    fn max_message_size(&self) -> Option<usize> {
+        None
+    }
+
+    /// Largest received message passed on as `Msg`, if limited.
+    fn max_recv_bytes(&self) -> Option<usize> {
+        None
+    }
+
+    /// Effective gossip protocol parameters, if the transport is gossip-based.
+    fn gossip_config(&self) -> Option<GossipConfig> {
+        None
+    }
+
+    /// Key used to sign benchmark messages (`--sign`), if the transport has one.
+    fn secret_key(&self) -> Option<SecretKey> {
+        None
+    }
+
+    /// Sends a byte buffer directly to one peer, bypassing gossip.
+    async fn send_to(&self, _peer: &str, _bytes: Bytes) -> Result<(), TransportError> {
+        Err(TransportError::Other("unicast not supported".into()))
+    }
+
+    /// Like `send_to`, but returns at once and sends in the background, so a
+    /// slow connect does not hold up the caller; a failure arrives later as
+    /// an error from `next`.
+    fn send_to_background(&self, _peer: &str, _bytes: Bytes) -> Result<(), TransportError> {
+        Err(TransportError::Other("unicast not supported".into()))
+    }
+
+    /// Waits for and returns the next recieved message (or an error).
+    async fn next(&mut self) -> Option<Result<TransportEvent, TransportError>>;
+
+    /// Re-join the bootstrap peers on the send topic now (`rejoin` command);
+    /// Ok(false) if this transport cannot or has no subscription yet.
+    async fn rejoin(&self) -> Result<bool, TransportError> {
+        Ok(false)
+    }
+
+    /// Whether this peer observed a confirmed gossip join.
+    ///
+    /// For degraded scenarios, join may time out. In that case
+    /// transports should return `false` and the benchmark must
+    /// still terminate and log this fact.
+    fn joined(&self) -> bool {
+        true
+    }
+
+    /// How long we waited for join confirmation (ms).
+    fn join_wait_ms(&self) -> u64 {
+        0
+    }
+
+    /// Wall-clock timestamp (ms) at which the join was confirmed, if it was.
+    fn joined_at_ms(&self) -> Option<u64> {
+        None
+    }
+
+    /// Whether a background join (`IrohGossip::connect_lazy`) is still running.
+    fn join_pending(&self) -> bool {
+        false
+    }
+
+    /// Join milestones reached so far, as ms offsets from process start.
+    fn join_stages(&self) -> BTreeMap<String, u64> {
+        BTreeMap::new()
+    }
+
+    /// Current direct neighbors as reported by the gossip layer.
+    ///
+    /// Used to periodically resynchronize the receiver's view of the active set,
+    /// which can drift from the NeighborUp/Down deltas after lagged events.
+    fn neighbors(&self) -> Vec<String> {
+        Vec::new()
+    }
+
+    /// Current protocol-layer counters, if the transport exposes them.
+    fn protocol_counters(&self) -> Option<ProtocolCounters> {
+        None
+    }
+
+    /// Cumulative bytes sent/received on the wire by the underlying endpoint, if available.
+    fn wire_bytes(&self) -> Option<WireBytes> {
+        None
+    }
+
+    /// Probe the connection to every current neighbor (None if the transport cannot tell).
+    fn probe_neighbors(&self) -> Option<Vec<NeighborProbe>> {
+        None
+    }
+
+    /// Our currently observed direct addresses and home relay, if known.
+    fn observed_addrs(&self) -> Option<ObservedAddrs> {
+        None
+    }
+
+    /// Send `count` echo probes to every current neighbor, all of them within
+    /// `budget` (None if the transport cannot).
+    async fn probe_rtt(&self, _count: u32, _budget: Duration) -> Option<Vec<RttProbe>> {
+        None
+    }
+
+    /// Node ID, bootstrap parsing and join outcome, if the transport has them.
+    fn connect_report(&self) -> Option<ConnectReport> {
+        None
+    }
+
+    /// Full metrics dump (OpenMetrics text) for debugging, if available.
+    fn metrics_dump(&self) -> Option<String> {
+        None
+    }
+
+    /// Gracefully shuts down the transport (background tasks, protocol stack, sockets).
+    ///
+    /// Implementations must return promptly even if the network is unresponsive.
+    async fn close(&mut self) -> Result<(), TransportError> {
+        Ok(())
+    }
+}
+
+/// Event channel capacity unless `TransportOpts::rx_buffer` is set.
+const EVENT_CHANNEL_CAPACITY: usize = 1024;
+
+/// Aborts a task once dropped, so a watched task stops with its watcher.
+struct AbortOnDrop(tokio::task::AbortHandle);
+
+impl Drop for AbortOnDrop {
+    fn drop(&mut self) {
+        self.0.abort();
+    }
+}
+
+/// Spawn `task` (`which` is "broadcast" or "receive") and a watcher that
+/// reports its end as `TransportEvent::TaskExited`, with the reason it
+/// returned or its panic message. A dead receive task also closes the event
+/// stream with `StreamClosed`. The returned handle is the watcher's; aborting
+/// it on `close()` stops the task without a report.
+fn spawn_watched(
+    which: &'static str,
+    task: impl Future<Output = &'static str> + Send + 'static,
+    ev_tx: tokio::sync::mpsc::WeakSender<Result<TransportEvent, TransportError>>,
+    task_exits: Arc<AtomicU64>,
+) -> JoinHandle<()> {
+    let task = tokio::spawn(task);
+    let stop = AbortOnDrop(task.abort_handle());
+    tokio::spawn(async move {
+        let _stop = stop;
+        let reason = match task.await {
+            Ok(reason) => reason.to_string(),
+            Err(e) if e.is_panic() => {
+                let panic = e.into_panic();
+                let msg = panic
+                    .downcast_ref::<&str>()
+                    .map(|s| s.to_string())
+                    .or_else(|| panic.downcast_ref::<String>().cloned())
+                    .unwrap_or_else(|| "non-string payload".to_string());
+                format!("panicked: {msg}")
+            }
+            Err(e) => e.to_string(),
+        };
+        task_exits.fetch_add(1, Ordering::Relaxed);
+        diag!("WARNING: transport {which} task exited: {reason}");
+        let Some(ev_tx) = ev_tx.upgrade() else {
+            return;
+        };
+        let _ = ev_tx
+            .send(Ok(TransportEvent::TaskExited { which, reason }))
+            .await;
+        if which == "receive" {
+            let _ = ev_tx.send(Err(TransportError::StreamClosed)).await;
+        }
+    })
+}
+
+/// Topic IDs subscribed by `topic_hex` and `topic_name` (a random one if
+/// both are empty), deduplicated, with the send topic moved to the front.
+fn resolve_topics(
+    topic_hex: &[String],
+    topic_name: &[String],
+    derivation: TopicDerivation,
+    send_topic: Option<String>,
+) -> Result<Vec<TopicId>> {
+    let mut topics = Vec::new();
+    for h in topic_hex {
+        topics.push(TopicId::from_str(h)?);
+    }
+    topics.extend(
+        topic_name
+            .iter()
+            .map(|n| TopicId::from_bytes(topic_from_name(n, derivation))),
+    );
+    if topics.is_empty() {
+        let mut rnd = [0u8; 32];
+        rand::rng().fill_bytes(&mut rnd);
+        topics.push(TopicId::from_bytes(rnd));
+    }
+    let mut seen = HashSet::new();
+    topics.retain(|t| seen.insert(*t));
+
+    if let Some(s) = send_topic {
+        let wanted = TopicId::from_str(&s)
+            .unwrap_or_else(|_| TopicId::from_bytes(topic_from_name(&s, derivation)));
+        let idx = topics
+            .iter()
+            .position(|t| *t == wanted)
+            .ok_or_else(|| anyhow::anyhow!("--send-topic {s} is not a subscribed topic"))?;
+        let send = topics.remove(idx);
+        topics.insert(0, send);
+    }
+    Ok(topics)
+}
+
+/// Secret key from `secret_hex` (32-byte hex), or a fresh random one.
+fn secret_key(secret_hex: Option<&str>) -> Result<SecretKey> {
+    let Some(hex) = secret_hex else {
+        let mut key = [0u8; 32];
+        rand::rng().fill_bytes(&mut key);
+        return Ok(SecretKey::from_bytes(&key));
+    };
+    let arr: [u8; 32] = hex::decode(hex)?
+        .try_into()
+        .map_err(|_| anyhow::anyhow!("secret key must be 32 bytes"))?;
+    Ok(SecretKey::from_bytes(&arr))
+}
+
+/// Establish a transport of the given kind.
+pub async fn create(kind: TransportKind, opts: TransportOpts) -> Result<Box<dyn Transport>> {
+    Ok(match kind {
+        TransportKind::Iroh if opts.force_no_join => {
+            Box::new(IrohGossip::connect_unjoined_for_test(opts).await?)
+        }
+        TransportKind::Iroh if opts.join_in_background => {
+            Box::new(IrohGossip::connect_lazy(opts).await?)
+        }
+        TransportKind::Iroh => Box::new(IrohGossip::connect(opts).await?),
+        TransportKind::Udp => Box::new(UdpTransport::connect(opts).await?),
+        TransportKind::Mem => Box::new(MemTransport::connect(opts)?),
+    })
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+
+    /// Options for a transport on `topic` (hex) with every knob at its default.
+    pub(crate) fn opts(topic: [u8; 32]) -> TransportOpts {
+        TransportOpts {
+            topic_hex: vec![hex::encode(topic)],
+            topic_name: Vec::new(),
+            topic_derivation: Default::default(),
+            send_topic: None,
+            secret_hex: None,
+            bootstrap: Vec::new(),
+            udp_bind: None,
+            discovery: Discovery::Direct,
+            run_time: 10,
+            rejoin_on_isolation_ms: None,
+            broadcast_scope: BroadcastScope::Swarm,
+            unicast: false,
+            rx_buffer: None,
+            gossip: GossipTuning::default(),
+            max_recv_bytes: None,
+            join_in_background: false,
+            force_no_join: false,
+        }
+    }
+}
@@ -0,0 +1,588 @@
+//! UDP transport: one socket, and every broadcast sent as a datagram straight
+//! to each known peer (1 hop, no relaying). Peers are the `--bootstrap`
+//! socket addresses plus whoever says hello; there is no membership
+//! protocol, loss recovery or congestion control, which makes it the
+//! baseline below the gossip overlay.
+//!
+//! A node sends `Hello` to every bootstrap address it has not heard from,
+//! every `HELLO_INTERVAL`, until it answers; both sides then count each
+//! other as neighbors until a `Bye`. A `Hello` must name a subscribed topic
+//! and a `Welcome` must come from a bootstrap address, so a stray or spoofed
+//! datagram does not make the node send broadcasts somewhere; the peer table
+//! holds at most `MAX_PEERS`.
+
+use super::{
+    BroadcastScope, ConnectReport, EVENT_CHANNEL_CAPACITY, JoinOutcome, ObservedAddrs, TopicJoin,
+    Transport, TransportError, TransportEvent, TransportKind, TransportOpts, resolve_topics,
+    secret_key, spawn_watched,
+};
+use crate::metrics::WireBytes;
+use crate::util::{TopicDerivation, diag, now_ms, process_start_ms};
+use anyhow::Result;
+use async_trait::async_trait;
+use bytes::Bytes;
+use iroh::SecretKey;
+use iroh_gossip::proto::TopicId;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::net::SocketAddr;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::{Arc, Mutex, OnceLock};
+use tokio::net::UdpSocket;
+use tokio::sync::{Notify, mpsc};
+use tokio::task::JoinHandle;
+use tokio::time::{Duration, sleep, timeout};
+
+/// Largest UDP payload over IPv4.
+const MAX_DATAGRAM: usize = 65_507;
+
+/// Largest `broadcast` payload: a datagram minus the `Datagram::Msg` framing
+/// (tag, topic, length, scope).
+const MAX_PAYLOAD: usize = MAX_DATAGRAM - 64;
+
+/// Peers beyond this many are not accepted.
+const MAX_PEERS: usize = 256;
+
+/// Interval between hellos to bootstrap peers that have not answered yet.
+const HELLO_INTERVAL: Duration = Duration::from_millis(500);
+
+/// Time `close()` gives the `Bye` datagrams.
+const BYE_TIMEOUT: Duration = Duration::from_millis(200);
+
+type EventTx = mpsc::Sender<Result<TransportEvent, TransportError>>;
+
+/// Datagram on the wire (postcard).
+#[derive(Debug, Serialize, Deserialize)]
+enum Datagram {
+    /// Introduces the sender, subscribed to `topic`; answered with `Welcome`.
+    Hello {
+        node_id: String,
+        topic: [u8; 32],
+    },
+    Welcome {
+        node_id: String,
+    },
+    /// A broadcast on `topic`; `neighbors` for `BroadcastScope::Neighbors`.
+    Msg {
+        topic: [u8; 32],
+        neighbors: bool,
+        content: Vec<u8>,
+    },
+    /// A `send_to` message.
+    Unicast {
+        content: Vec<u8>,
+    },
+    /// The sender is closing.
+    Bye,
+}
+
+impl Datagram {
+    fn encode(&self) -> Vec<u8> {
+        postcard::to_stdvec(self).expect("datagram encodes")
+    }
+}
+
+/// Known peers: node ID by address.
+type Peers = Arc<Mutex<BTreeMap<SocketAddr, String>>>;
+
+/// State shared by the transport and its receive and hello tasks.
+struct Shared {
+    socket: UdpSocket,
+    id: String,
+    peers: Peers,
+    bootstrap: Vec<SocketAddr>,
+    topics: Vec<TopicId>,
+    max_recv_bytes: Option<usize>,
+    unicast: bool,
+    wire_tx: AtomicU64,
+    wire_rx: AtomicU64,
+    /// Wall-clock time of the join: the first answer of a bootstrap peer, or
+    /// the bind without bootstrap peers.
+    joined_at_ms: OnceLock<u64>,
+    joined: Notify,
+    /// Report the join as `TransportEvent::Joined` (`join_in_background`).
+    report_join: bool,
+    /// Set once `connect` returns; peers found before are only in
+    /// `neighbors()`, without a `NeighborUp`, as after a gossip join.
+    connected: AtomicBool,
+    started_ms: u64,
+}
+
+impl Shared {
+    async fn send(&self, to: SocketAddr, datagram: &Datagram) -> Result<(), TransportError> {
+        let bytes = datagram.encode();
+        self.socket
+            .send_to(&bytes, to)
+            .await
+            .map_err(|e| TransportError::Other(format!("udp send to {to}: {e}")))?;
+        self.wire_tx
+            .fetch_add(bytes.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn hello(&self) -> Datagram {
+        Datagram::Hello {
+            node_id: self.id.clone(),
+            topic: *self.topics[0].as_bytes(),
+        }
+    }
+
+    /// Add a peer, reporting it as a neighbor (and the join) if it is new;
+    /// false if the peer table is full.
+    fn add_peer(&self, from: SocketAddr, node_id: String, ev_tx: &EventTx) -> bool {
+        let replaced = {
+            let mut peers = self.peers.lock().unwrap();
+            if peers.len() >= MAX_PEERS && !peers.contains_key(&from) {
+                return false;
+            }
+            match peers.insert(from, node_id.clone()) {
+                Some(old) if old == node_id => return true,
+                old => old,
+            }
+        };
+        if self.connected.load(Ordering::Relaxed) {
+            // A new node on a known address: the old one is gone.
+            if let Some(old) = replaced {
+                let _ = ev_tx.try_send(Ok(TransportEvent::NeighborDown(old)));
+            }
+            let _ = ev_tx.try_send(Ok(TransportEvent::NeighborUp(node_id)));
+        }
+        if self.bootstrap.contains(&from) && self.joined_at_ms.set(now_ms()).is_ok() {
+            self.joined.notify_waiters();
+            if self.report_join {
+                let _ = ev_tx.try_send(Ok(TransportEvent::Joined {
+                    wait_ms: now_ms().saturating_sub(self.started_ms),
+                }));
+            }
+        }
+        true
+    }
+
+    /// Handle datagrams until the socket fails.
+    async fn receive(self: Arc<Self>, ev_tx: EventTx) -> &'static str {
+        let mut buf = vec![0u8; MAX_DATAGRAM];
+        loop {
+            let (len, from) = match self.socket.recv_from(&mut buf).await {
+                Ok(r) => r,
+                // ICMP port unreachable from a peer that went away.
+                Err(e) if e.kind() == std::io::ErrorKind::ConnectionReset => continue,
+                Err(_) => return "socket receive failed",
+            };
+            self.wire_rx.fetch_add(len as u64, Ordering::Relaxed);
+            let Ok(datagram) = postcard::from_bytes::<Datagram>(&buf[..len]) else {
+                let _ = ev_tx.try_send(Err(TransportError::Other(format!(
+                    "undecodable datagram from {from}"
+                ))));
+                continue;
+            };
+            let known = self.peers.lock().unwrap().get(&from).cloned();
+            match datagram {
+                Datagram::Hello { node_id, topic } => {
+                    if !self.topics.contains(&TopicId::from_bytes(topic))
+                        || !self.add_peer(from, node_id, &ev_tx)
+                    {
+                        continue;
+                    }
+                    let welcome = Datagram::Welcome {
+                        node_id: self.id.clone(),
+                    };
+                    if let Err(e) = self.send(from, &welcome).await {
+                        let _ = ev_tx.try_send(Err(e));
+                    }
+                }
+                Datagram::Welcome { node_id } if self.bootstrap.contains(&from) => {
+                    self.add_peer(from, node_id, &ev_tx);
+                }
+                Datagram::Welcome { .. } => {}
+                Datagram::Bye => {
+                    if let Some(node_id) = self.peers.lock().unwrap().remove(&from) {
+                        let _ = ev_tx.try_send(Ok(TransportEvent::NeighborDown(node_id)));
+                    }
+                }
+                Datagram::Msg {
+                    topic,
+                    neighbors,
+                    content,
+                } => {
+                    let topic = TopicId::from_bytes(topic);
+                    if !self.topics.contains(&topic) {
+                        continue;
+                    }
+                    let delivered_from = known.unwrap_or_else(|| from.to_string());
+                    let event = match self.max_recv_bytes {
+                        Some(max) if content.len() > max => TransportEvent::Oversized {
+                            topic: topic.to_string(),
+                            size: content.len(),
+                            delivered_from,
+                        },
+                        _ => TransportEvent::Msg {
+                            topic: topic.to_string(),
+                            content: content.into(),
+                            ldh: (!neighbors).then_some(0),
+                            delivered_from,
+                        },
+                    };
+                    if ev_tx.try_send(Ok(event)).is_err() {
+                        let _ = ev_tx.try_send(Err(TransportError::Lagged));
+                    }
+                }
+                Datagram::Unicast { content } if self.unicast => {
+                    let from = known.unwrap_or_else(|| from.to_string());
+                    let _ = ev_tx.try_send(Ok(TransportEvent::Unicast {
+                        from,
+                        content: content.into(),
+                    }));
+                }
+                Datagram::Unicast { .. } => {}
+            }
+        }
+    }
+
+    /// Greet the bootstrap peers that have not answered yet, for as long as
+    /// the transport lives.
+    async fn greet(self: Arc<Self>) {
+        let hello = self.hello();
+        loop {
+            let pending: Vec<SocketAddr> = {
+                let peers = self.peers.lock().unwrap();
+                self.bootstrap
+                    .iter()
+                    .filter(|a| !peers.contains_key(a))
+                    .copied()
+                    .collect()
+            };
+            for addr in pending {
+                let _ = self.send(addr, &hello).await;
+            }
+            sleep(HELLO_INTERVAL).await;
+        }
+    }
+}
+
+/// Transport over a single UDP socket; see the module docs.
+pub struct UdpTransport {
+    shared: Arc<Shared>,
+    key: SecretKey,
+    topic_tags: Vec<String>,
+    topic_derivation: Option<TopicDerivation>,
+    broadcast_scope: BroadcastScope,
+    join_timeout: Duration,
+    /// `None` once closed, or from the start with `force_no_join`.
+    rx: Option<mpsc::Receiver<Result<TransportEvent, TransportError>>>,
+    force_no_join: bool,
+    tasks: Vec<JoinHandle<()>>,
+    task_exits: Arc<AtomicU64>,
+}
+
+impl UdpTransport {
+    /// Bind `opts.udp_bind` (an ephemeral port if unset) and greet the
+    /// bootstrap peers, whose entries are socket addresses. Waits until one
+    /// of them answers, at most the join timeout, unless `join_in_background`
+    /// is set; without bootstrap peers the node counts as joined at once.
+    /// With `force_no_join` nothing is sent or received.
+    pub async fn connect(opts: TransportOpts) -> Result<Self> {
+        let key = secret_key(opts.secret_hex.as_deref())?;
+        let id = key.public().to_string();
+        let topics = resolve_topics(
+            &opts.topic_hex,
+            &opts.topic_name,
+            opts.topic_derivation,
+            opts.send_topic,
+        )?;
+        let bootstrap = opts
+            .bootstrap
+            .iter()
+            .map(|b| {
+                b.parse::<SocketAddr>()
+                    .map_err(|e| anyhow::anyhow!("udp bootstrap {b} is not a socket address: {e}"))
+            })
+            .collect::<Result<Vec<_>>>()?;
+        let bind = opts
+            .udp_bind
+            .unwrap_or_else(|| SocketAddr::from(([0, 0, 0, 0], 0)));
+        let socket = UdpSocket::bind(bind).await?;
+        diag!("node_id={} udp_addr={}", id, socket.local_addr()?);
+
+        let shared = Arc::new(Shared {
+            socket,
+            id,
+            peers: Peers::default(),
+            joined_at_ms: OnceLock::new(),
+            joined: Notify::new(),
+            report_join: opts.join_in_background,
+            connected: AtomicBool::new(false),
+            started_ms: now_ms(),
+            bootstrap,
+            topics,
+            max_recv_bytes: opts.max_recv_bytes,
+            unicast: opts.unicast,
+            wire_tx: AtomicU64::new(0),
+            wire_rx: AtomicU64::new(0),
+        });
+        let mut transport = Self {
+            topic_tags: shared.topics.iter().map(|t| t.to_string()).collect(),
+            shared: shared.clone(),
+            key,
+            topic_derivation: (!opts.topic_name.is_empty()).then_some(opts.topic_derivation),
+            broadcast_scope: opts.broadcast_scope,
+            join_timeout: Duration::from_secs(opts.run_time + 10),
+            rx: None,
+            force_no_join: opts.force_no_join,
+            tasks: Vec::new(),
+            task_exits: Arc::new(AtomicU64::new(0)),
+        };
+        if opts.force_no_join {
+            return Ok(transport);
+        }
+
+        let (ev_tx, ev_rx) = mpsc::channel(opts.rx_buffer.unwrap_or(EVENT_CHANNEL_CAPACITY));
+        if shared.bootstrap.is_empty() {
+            let _ = shared.joined_at_ms.set(shared.started_ms);
+        }
+        transport.tasks.push(spawn_watched(
+            "receive",
+            shared.clone().receive(ev_tx.clone()),
+            ev_tx.downgrade(),
+            transport.task_exits.clone(),
+        ));
+        transport.tasks.push(tokio::spawn(shared.clone().greet()));
+        transport.rx = Some(ev_rx);
+
+        if !opts.join_in_background {
+            let joined = shared.joined.notified();
+            if shared.joined_at_ms.get().is_none() {
+                let _ = timeout(transport.join_timeout, joined).await;
+            }
+        }
+        shared.connected.store(true, Ordering::Relaxed);
+        Ok(transport)
+    }
+
+    /// Address of the peer with node ID (or address) `peer`.
+    fn peer_addr(&self, peer: &str) -> Result<SocketAddr, TransportError> {
+        if !self.shared.unicast {
+            return Err(TransportError::Other(
+                "unicast side-channel disabled".into(),
+            ));
+        }
+        let peers = self.shared.peers.lock().unwrap();
+        peers
+            .iter()
+            .find(|(addr, id)| *id == peer || addr.to_string() == peer)
+            .map(|(addr, _)| *addr)
+            .ok_or_else(|| TransportError::Other(format!("no such peer: {peer}")))
+    }
+
+    fn peer_addrs(&self) -> Vec<SocketAddr> {
+        self.shared.peers.lock().unwrap().keys().copied().collect()
+    }
+}
+
+#[async_trait]
+impl Transport for UdpTransport {
+    fn id(&self) -> String {
+        format!("{}:{}", TransportKind::Udp.id_prefix(), self.shared.id)
+    }
+
+    fn topics(&self) -> Vec<String> {
+        self.topic_tags.clone()
+    }
+
+    fn topic_derivation(&self) -> Option<TopicDerivation> {
+        self.topic_derivation
+    }
+
+    fn broadcast_scope(&self) -> BroadcastScope {
+        self.broadcast_scope
+    }
+
+    fn task_exits(&self) -> u64 {
+        self.task_exits.load(Ordering::Relaxed)
+    }
+
+    fn secret_key(&self) -> Option<SecretKey> {
+        Some(self.key.clone())
+    }
+
+    fn max_message_size(&self) -> Option<usize> {
+        Some(MAX_PAYLOAD)
+    }
+
+    fn max_recv_bytes(&self) -> Option<usize> {
+        self.shared.max_recv_bytes
+    }
+
+    async fn broadcast(&self, bytes: Bytes) -> Result<(), TransportError> {
+        if self.rx.is_none() {
+            return Err(TransportError::StreamClosed);
+        }
+        if bytes.len() > MAX_PAYLOAD {
+            return Err(TransportError::BroadcastFailed(format!(
+                "{} bytes exceed the {MAX_PAYLOAD}-byte datagram payload",
+                bytes.len()
+            )));
+        }
+        let datagram = Datagram::Msg {
+            topic: *self.shared.topics[0].as_bytes(),
+            neighbors: self.broadcast_scope == BroadcastScope::Neighbors,
+            content: bytes.to_vec(),
+        };
+        // One unreachable peer must not keep the others from their copy.
+        let mut failed = None;
+        for addr in self.peer_addrs() {
+            if let Err(e) = self.shared.send(addr, &datagram).await {
+                failed = Some(e);
+            }
+        }
+        match failed {
+            Some(TransportError::Other(e)) => Err(TransportError::BroadcastFailed(e)),
+            _ => Ok(()),
+        }
+    }
+
+    async fn send_to(&self, peer: &str, bytes: Bytes) -> Result<(), TransportError> {
+        let addr = self.peer_addr(peer)?;
+        let datagram = Datagram::Unicast {
+            content: bytes.to_vec(),
+        };
+        self.shared.send(addr, &datagram).await
+    }
+
+    fn send_to_background(&self, peer: &str, bytes: Bytes) -> Result<(), TransportError> {
+        let addr = self.peer_addr(peer)?;
+        let datagram = Datagram::Unicast {
+            content: bytes.to_vec(),
+        }
+        .encode();
+        // A datagram send does not wait on the peer; a full socket buffer is
+        // an error rather than a stall.
+        self.shared
+            .socket
+            .try_send_to(&datagram, addr)
+            .map_err(|e| TransportError::Other(format!("udp send to {addr}: {e}")))?;
+        self.shared
+            .wire_tx
+            .fetch_add(datagram.len() as u64, Ordering::Relaxed);
+        Ok(())
+    }
+
+    async fn next(&mut self) -> Option<Result<TransportEvent, TransportError>> {
+        self.rx.as_mut()?.recv().await
+    }
+
+    async fn rejoin(&self) -> Result<bool, TransportError> {
+        if self.rx.is_none() || self.shared.bootstrap.is_empty() {
+            return Ok(false);
+        }
+        let hello = self.shared.hello();
+        for addr in &self.shared.bootstrap {
+            self.shared.send(*addr, &hello).await?;
+        }
+        Ok(true)
+    }
+
+    fn joined(&self) -> bool {
+        self.rx.is_some() && self.shared.joined_at_ms.get().is_some()
+    }
+
+    fn join_pending(&self) -> bool {
+        self.shared.report_join
+            && self.rx.is_some()
+            && !self.joined()
+            && now_ms() < self.shared.started_ms + self.join_timeout.as_millis() as u64
+    }
+
+    fn join_wait_ms(&self) -> u64 {
+        let end = self
+            .shared
+            .joined_at_ms
+            .get()
+            .copied()
+            .unwrap_or_else(now_ms);
+        end.saturating_sub(self.shared.started_ms)
+    }
+
+    fn joined_at_ms(&self) -> Option<u64> {
+        self.shared.joined_at_ms.get().copied()
+    }
+
+    fn join_stages(&self) -> BTreeMap<String, u64> {
+        let start = process_start_ms();
+        let mut stages = BTreeMap::new();
+        stages.insert(
+            "endpoint_bound".to_string(),
+            self.shared.started_ms.saturating_sub(start),
+        );
+        if let Some(joined) = self.joined_at_ms() {
+            stages.insert("joined".to_string(), joined.saturating_sub(start));
+        }
+        stages
+    }
+
+    fn neighbors(&self) -> Vec<String> {
+        self.shared
+            .peers
+            .lock()
+            .unwrap()
+            .values()
+            .cloned()
+            .collect()
+    }
+
+    fn wire_bytes(&self) -> Option<WireBytes> {
+        Some(WireBytes {
+            tx: self.shared.wire_tx.load(Ordering::Relaxed),
+            rx: self.shared.wire_rx.load(Ordering::Relaxed),
+        })
+    }
+
+    fn observed_addrs(&self) -> Option<ObservedAddrs> {
+        let addr = self.shared.socket.local_addr().ok()?;
+        Some(ObservedAddrs {
+            direct: vec![addr.to_string()],
+            relay: None,
+        })
+    }
+
+    fn connect_report(&self) -> Option<ConnectReport> {
+        let outcome = if self.force_no_join {
+            JoinOutcome::Skipped
+        } else if self.joined() {
+            JoinOutcome::Joined
+        } else {
+            JoinOutcome::JoinTimeout
+        };
+        Some(ConnectReport {
+            node_id: self.shared.id.clone(),
+            // Entries that are not socket addresses fail `connect`.
+            bootstraps_given: self.shared.bootstrap.len(),
+            bootstraps_parsed: self.shared.bootstrap.len(),
+            join_done: !self.join_pending(),
+            topics: self
+                .topic_tags
+                .iter()
+                .map(|topic| TopicJoin {
+                    topic: topic.clone(),
+                    outcome,
+                    error: None,
+                    wait_ms: self.join_wait_ms(),
+                })
+                .collect(),
+        })
+    }
+
+    async fn close(&mut self) -> Result<(), TransportError> {
+        for task in self.tasks.drain(..) {
+            task.abort();
+        }
+        if self.rx.take().is_some() {
+            let _ = timeout(BYE_TIMEOUT, async {
+                for addr in self.peer_addrs() {
+                    let _ = self.shared.send(addr, &Datagram::Bye).await;
+                }
+            })
+            .await;
+        }
+        Ok(())
+    }
+}
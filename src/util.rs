@@ -1,6 +1,61 @@
-use std::time::{SystemTime, UNIX_EPOCH};
+use crate::metrics::{EventSink, LogEvent, LogHeader, ResourceUsage};
+use rand::RngCore;
+use serde::{Deserialize, Serialize};
+use std::io::{BufRead, BufReader};
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
 use std::{fs::File, io::Write, path::Path};
-use crate::metrics::LogEvent;
+
+/// `eprintln!` for diagnostic lines, also copied to the run's `stderr.txt`
+/// (see `set_diag_copy`).
+#[macro_export]
+macro_rules! diag {
+    ($($arg:tt)*) => {
+        $crate::util::diag_str(&format!("{}\n", format_args!($($arg)*)))
+    };
+}
+pub use diag;
+
+static DIAG_COPY: OnceLock<Mutex<File>> = OnceLock::new();
+
+/// Also write every diagnostic print (`diag!`) to `path`.
+pub fn set_diag_copy(path: &Path) -> anyhow::Result<()> {
+    let file = File::create(path)?;
+    DIAG_COPY
+        .set(Mutex::new(file))
+        .map_err(|_| anyhow::anyhow!("diagnostic copy already set"))
+}
+
+/// Print `text` to stderr as-is, and to the diagnostic copy if one is set.
+pub fn diag_str(text: &str) {
+    eprint!("{text}");
+    if let Some(file) = DIAG_COPY.get()
+        && let Ok(mut file) = file.lock()
+    {
+        let _ = file.write_all(text.as_bytes());
+    }
+}
+
+/// Create the `--out-dir` directory of this run, `<dir>/<run_id>`; an existing
+/// one is never reused, the name gets a `-1`, `-2`, ... suffix instead.
+pub fn create_run_dir(dir: &Path) -> anyhow::Result<PathBuf> {
+    std::fs::create_dir_all(dir).map_err(|e| anyhow::anyhow!("creating {}: {e}", dir.display()))?;
+    for n in 0u32.. {
+        let name = match n {
+            0 => run_id().to_string(),
+            n => format!("{}-{n}", run_id()),
+        };
+        let path = dir.join(name);
+        match std::fs::create_dir(&path) {
+            Ok(()) => return Ok(path),
+            Err(e) if e.kind() == std::io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(anyhow::anyhow!("creating {}: {e}", path.display())),
+        }
+    }
+    unreachable!("run directory suffixes exhausted")
+}
 
 pub fn now_ms() -> u64 {
     SystemTime::now()
@@ -9,37 +64,887 @@ pub fn now_ms() -> u64 {
         .as_millis() as u64
 }
 
-pub fn topic_from_name(name: &str) -> [u8; 32] {
-    *blake3::hash(name.as_bytes()).as_bytes()
+static PROCESS_START_MS: OnceLock<u64> = OnceLock::new();
+static PROCESS_START: OnceLock<Instant> = OnceLock::new();
+
+/// Wall-clock timestamp (ms) of process start.
+///
+/// The first call fixes the value (and the `elapsed_ms` origin), so `main`
+/// calls this before doing anything else.
+pub fn process_start_ms() -> u64 {
+    PROCESS_START.get_or_init(Instant::now);
+    *PROCESS_START_MS.get_or_init(now_ms)
+}
+
+/// Monotonic ms since process start; unaffected by wall-clock steps (NTP),
+/// so intra-run durations are measured with it.
+pub fn elapsed_ms() -> u64 {
+    PROCESS_START
+        .get_or_init(Instant::now)
+        .elapsed()
+        .as_millis() as u64
+}
+
+static RUN_ID: OnceLock<([u8; 8], String)> = OnceLock::new();
+
+/// Random ID of this process run (hex), stamped on every log line by `JsonWriter`.
+///
+/// Like `process_start_ms`, the first call fixes the value.
+pub fn run_id() -> &'static str {
+    &run_id_entry().1
+}
+
+/// Raw bytes of `run_id`, carried in heartbeats.
+pub fn run_id_bytes() -> [u8; 8] {
+    run_id_entry().0
 }
 
-pub fn pad_payload(mut v: Vec<u8>, target_size: usize) -> Vec<u8> {
-    if v.len() < target_size {
+fn run_id_entry() -> &'static ([u8; 8], String) {
+    RUN_ID.get_or_init(|| {
+        let mut bytes = [0u8; 8];
+        rand::rng().fill_bytes(&mut bytes);
+        (bytes, hex::encode(bytes))
+    })
+}
+
+/// Build-time versions of this binary (see `build.rs`), recorded in setup
+/// events and receiver summaries so runs from different builds can be told apart.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildInfo {
+    pub version: String,
+    pub iroh: String,
+    pub iroh_gossip: String,
+    pub git_hash: Option<String>,
+    pub target: String,
+    pub rustc: String,
+}
+
+impl BuildInfo {
+    /// Short form used in mismatch warnings, e.g. `0.1.0 (iroh-gossip 0.92.0, 1a2b3c4d5e6f)`.
+    pub fn label(&self) -> String {
+        match &self.git_hash {
+            Some(hash) => format!(
+                "{} (iroh-gossip {}, {hash})",
+                self.version, self.iroh_gossip
+            ),
+            None => format!("{} (iroh-gossip {})", self.version, self.iroh_gossip),
+        }
+    }
+}
+
+/// Versions this binary was built with.
+pub fn build_info() -> BuildInfo {
+    let git_hash = env!("BUILD_GIT_HASH");
+    BuildInfo {
+        version: env!("CARGO_PKG_VERSION").to_string(),
+        iroh: env!("BUILD_IROH_VERSION").to_string(),
+        iroh_gossip: env!("BUILD_IROH_GOSSIP_VERSION").to_string(),
+        git_hash: (!git_hash.is_empty()).then(|| git_hash.to_string()),
+        target: env!("BUILD_TARGET").to_string(),
+        rustc: env!("BUILD_RUSTC_VERSION").to_string(),
+    }
+}
+
+/// One `resource` sample of this process.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct ResourceSample {
+    pub rss_bytes: u64,
+    /// User + system CPU time over wall time since the previous sample, in
+    /// percent of one core.
+    pub cpu_pct: Option<f64>,
+    /// Tasks alive on the tokio runtime.
+    pub tokio_tasks: Option<usize>,
+}
+
+/// Self-profiling for `--resource-sample`: RSS from `/proc/self/statm`, CPU
+/// time from `/proc/self/stat`. Elsewhere `sample` always returns `None`.
+#[derive(Debug, Default)]
+pub struct ResourceSampler {
+    /// CPU time (s) at the previous sample.
+    last_cpu: Option<(Instant, f64)>,
+    samples: u64,
+    peak_rss_bytes: u64,
+    cpu_pct_sum: f64,
+    cpu_samples: u64,
+}
+
+impl ResourceSampler {
+    /// Start sampling; the first `sample` reports CPU use since this call.
+    pub fn new() -> Self {
+        Self {
+            last_cpu: read_proc_self().map(|(_, cpu_s)| (Instant::now(), cpu_s)),
+            ..Self::default()
+        }
+    }
+
+    /// Whether this system can be sampled at all.
+    pub fn supported() -> bool {
+        read_proc_self().is_some()
+    }
+
+    pub fn sample(&mut self) -> Option<ResourceSample> {
+        let (rss_bytes, cpu_s) = read_proc_self()?;
+        let now = Instant::now();
+        let cpu_pct = self.last_cpu.and_then(|(at, last_s)| {
+            let wall_s = now.duration_since(at).as_secs_f64();
+            (wall_s > 0.0).then(|| (cpu_s - last_s).max(0.0) / wall_s * 100.0)
+        });
+        self.last_cpu = Some((now, cpu_s));
+
+        self.samples += 1;
+        self.peak_rss_bytes = self.peak_rss_bytes.max(rss_bytes);
+        if let Some(pct) = cpu_pct {
+            self.cpu_pct_sum += pct;
+            self.cpu_samples += 1;
+        }
+        Some(ResourceSample {
+            rss_bytes,
+            cpu_pct,
+            tokio_tasks: tokio::runtime::Handle::try_current()
+                .ok()
+                .map(|h| h.metrics().num_alive_tasks()),
+        })
+    }
+
+    /// Peak RSS and mean CPU of the samples since the last call (None
+    /// without any); the next window starts empty.
+    pub fn take_usage(&mut self) -> Option<ResourceUsage> {
+        let usage = (self.samples > 0).then(|| ResourceUsage {
+            samples: self.samples,
+            peak_rss_bytes: self.peak_rss_bytes,
+            mean_cpu_pct: (self.cpu_samples > 0)
+                .then(|| self.cpu_pct_sum / self.cpu_samples as f64),
+        });
+        *self = Self {
+            last_cpu: self.last_cpu,
+            ..Self::default()
+        };
+        usage
+    }
+}
+
+/// Resident set size (bytes) and total CPU time (s) of this process.
+#[cfg(target_os = "linux")]
+fn read_proc_self() -> Option<(u64, f64)> {
+    // SAFETY: sysconf has no preconditions.
+    let (page_size, ticks_per_s) = unsafe {
+        (
+            libc::sysconf(libc::_SC_PAGESIZE),
+            libc::sysconf(libc::_SC_CLK_TCK),
+        )
+    };
+    if page_size <= 0 || ticks_per_s <= 0 {
+        return None;
+    }
+    let statm = std::fs::read_to_string("/proc/self/statm").ok()?;
+    let rss_pages: u64 = statm.split_whitespace().nth(1)?.parse().ok()?;
+
+    // The command name (field 2) may contain spaces, so count from its `)`:
+    // utime and stime (fields 14 and 15) are then the 12th and 13th.
+    let stat = std::fs::read_to_string("/proc/self/stat").ok()?;
+    let mut fields = stat.rsplit_once(')')?.1.split_whitespace().skip(11);
+    let utime: u64 = fields.next()?.parse().ok()?;
+    let stime: u64 = fields.next()?.parse().ok()?;
+    Some((
+        rss_pages * page_size as u64,
+        (utime + stime) as f64 / ticks_per_s as f64,
+    ))
+}
+
+#[cfg(not(target_os = "linux"))]
+fn read_proc_self() -> Option<(u64, f64)> {
+    None
+}
+
+static INTERRUPTED: AtomicBool = AtomicBool::new(false);
+static INTERRUPT_NOTIFY: tokio::sync::Notify = tokio::sync::Notify::const_new();
+
+/// Handle Ctrl-C and SIGTERM: the first one asks the run loops to stop and
+/// summarize (see `interrupted`), so the log still ends with the `summary`
+/// event; a second one exits immediately.
+pub fn install_interrupt_handler() {
+    tokio::spawn(async {
+        #[cfg(unix)]
+        let mut term =
+            tokio::signal::unix::signal(tokio::signal::unix::SignalKind::terminate()).ok();
+        loop {
+            #[cfg(unix)]
+            let terminated = async {
+                match term.as_mut() {
+                    Some(term) => term.recv().await,
+                    None => std::future::pending().await,
+                }
+            };
+            #[cfg(not(unix))]
+            let terminated = std::future::pending::<Option<()>>();
+            tokio::select! {
+                res = tokio::signal::ctrl_c() => {
+                    if res.is_err() {
+                        return;
+                    }
+                }
+                _ = terminated => {}
+            }
+            if interrupted() {
+                std::process::exit(130);
+            }
+            interrupt();
+            diag!("interrupted, finishing the run (interrupt again to exit)");
+        }
+    });
+}
+
+/// Ask the run loops to stop and summarize, as Ctrl-C does (`quit` command).
+pub fn interrupt() {
+    INTERRUPTED.store(true, Ordering::Relaxed);
+    INTERRUPT_NOTIFY.notify_waiters();
+}
+
+/// Whether Ctrl-C was pressed.
+pub fn interrupted() -> bool {
+    INTERRUPTED.load(Ordering::Relaxed)
+}
+
+/// Resolve once Ctrl-C was pressed (immediately if it already was).
+pub async fn wait_interrupted() {
+    let notified = INTERRUPT_NOTIFY.notified();
+    tokio::pin!(notified);
+    notified.as_mut().enable();
+    if interrupted() {
+        return;
+    }
+    notified.await;
+}
+
+/// Export `KEY=VALUE` lines of a `.env` file (if it exists) as environment variables,
+/// so `IGM_*` settings can live next to the experiment; variables that are already
+/// set win. Blank lines and `#` comments are skipped, values may be quoted.
+///
+/// Must run before any other thread is started.
+pub fn load_dotenv(path: impl AsRef<Path>) -> anyhow::Result<()> {
+    let path = path.as_ref();
+    let text = match std::fs::read_to_string(path) {
+        Ok(text) => text,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return Ok(()),
+        Err(e) => return Err(anyhow::anyhow!("reading {}: {e}", path.display())),
+    };
+    for (i, line) in text.lines().enumerate() {
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') {
+            continue;
+        }
+        let line = line.strip_prefix("export ").unwrap_or(line);
+        let (key, value) = line
+            .split_once('=')
+            .ok_or_else(|| anyhow::anyhow!("{}:{}: expected KEY=VALUE", path.display(), i + 1))?;
+        let key = key.trim();
+        let value = value.trim();
+        let value = value
+            .strip_prefix('"')
+            .and_then(|v| v.strip_suffix('"'))
+            .or_else(|| value.strip_prefix('\'').and_then(|v| v.strip_suffix('\'')))
+            .unwrap_or(value);
+        if std::env::var_os(key).is_none() {
+            // SAFETY: called from `main` before the runtime or any other thread exists.
+            unsafe { std::env::set_var(key, value) };
+        }
+    }
+    Ok(())
+}
+
+/// blake3 key-derivation context of `TopicDerivation::V1`.
+pub const TOPIC_CONTEXT: &str = "iroh-gossip-metrics/topic/v1";
+
+/// How `--topic-name` turns a name into a topic ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum TopicDerivation {
+    /// Domain-separated: `blake3::derive_key(TOPIC_CONTEXT, name)`.
+    #[default]
+    V1,
+    /// Plain `blake3::hash(name)`, shared with any other tool hashing the
+    /// same string; kept for interop with existing deployments.
+    Legacy,
+}
+
+impl TopicDerivation {
+    /// Parse `--topic-derivation` ("v1" or "legacy").
+    pub fn parse(s: &str) -> Result<Self, String> {
+        match s {
+            "v1" => Ok(TopicDerivation::V1),
+            "legacy" => Ok(TopicDerivation::Legacy),
+            _ => Err(format!(
+                "invalid topic derivation `{s}`, use 'v1' or 'legacy'"
+            )),
+        }
+    }
+
+    /// Name as in the setup event and summary.
+    pub fn as_str(self) -> &'static str {
+        match self {
+            TopicDerivation::V1 => "v1",
+            TopicDerivation::Legacy => "legacy",
+        }
+    }
+}
+
+pub fn topic_from_name(name: &str, derivation: TopicDerivation) -> [u8; 32] {
+    match derivation {
+        TopicDerivation::V1 => blake3::derive_key(TOPIC_CONTEXT, name.as_bytes()),
+        TopicDerivation::Legacy => *blake3::hash(name.as_bytes()).as_bytes(),
+    }
+}
+
+/// Clap value parser for `--topic-hex`, see `parse_hex32`.
+pub fn parse_topic_hex(s: &str) -> Result<String, String> {
+    parse_hex32(
+        s,
+        "copy the whole topic ID, or use --topic-name to derive one from a name",
+    )
+}
+
+/// Clap value parser for `--secret-hex`, see `parse_hex32`.
+pub fn parse_secret_hex(s: &str) -> Result<String, String> {
+    parse_hex32(
+        s,
+        "a secret key is 32 random bytes in hex, e.g. from `openssl rand -hex 32`",
+    )
+}
+
+/// Normalize a 32-byte hex value: whitespace and a `0x` prefix are dropped,
+/// then exactly 64 hex digits must remain. Errors end with `hint`.
+fn parse_hex32(s: &str, hint: &str) -> Result<String, String> {
+    let cleaned: String = s.chars().filter(|c| !c.is_whitespace()).collect();
+    let digits = cleaned
+        .strip_prefix("0x")
+        .or_else(|| cleaned.strip_prefix("0X"))
+        .unwrap_or(&cleaned);
+    if let Some((i, c)) = digits
+        .chars()
+        .enumerate()
+        .find(|(_, c)| !c.is_ascii_hexdigit())
+    {
+        return Err(format!(
+            "character {} ('{c}') is not a hex digit; {hint}",
+            i + 1
+        ));
+    }
+    if digits.len() != 64 {
+        return Err(format!(
+            "expected 64 hex characters (32 bytes), got {}; {hint}",
+            digits.len()
+        ));
+    }
+    Ok(digits.to_ascii_lowercase())
+}
+
+/// Split `--bootstrap` into node IDs. An entry that does not parse is an error
+/// naming it, unless `ignore_bad`, which skips it with a note.
+pub fn parse_bootstrap(list: &str, ignore_bad: bool) -> anyhow::Result<Vec<String>> {
+    let mut ids = Vec::new();
+    for (i, entry) in list.split(',').map(str::trim).enumerate() {
+        if entry.is_empty() {
+            continue;
+        }
+        match entry.parse::<iroh::NodeId>() {
+            Ok(_) => ids.push(entry.to_string()),
+            Err(e) if ignore_bad => {
+                diag!(
+                    "--ignore-bad-bootstrap: skipping entry {} ({entry}): {e}",
+                    i + 1
+                );
+            }
+            Err(e) => anyhow::bail!(
+                "--bootstrap entry {} is not a node ID ({} characters, '{entry}'): {e}; \
+                 use the 64-character node_id= line the peer prints at startup, \
+                 or pass --ignore-bad-bootstrap to skip bad entries",
+                i + 1,
+                entry.chars().count()
+            ),
+        }
+    }
+    Ok(ids)
+}
+
+/// Like `parse_bootstrap`, for `--transport udp`: entries are socket addresses.
+pub fn parse_bootstrap_addrs(list: &str, ignore_bad: bool) -> anyhow::Result<Vec<String>> {
+    let mut addrs = Vec::new();
+    for (i, entry) in list.split(',').map(str::trim).enumerate() {
+        if entry.is_empty() {
+            continue;
+        }
+        match entry.parse::<std::net::SocketAddr>() {
+            Ok(_) => addrs.push(entry.to_string()),
+            Err(e) if ignore_bad => {
+                diag!(
+                    "--ignore-bad-bootstrap: skipping entry {} ({entry}): {e}",
+                    i + 1
+                );
+            }
+            Err(e) => anyhow::bail!(
+                "--bootstrap entry {} is not a socket address ('{entry}'): {e}; \
+                 use the udp_addr= line the peer prints at startup, \
+                 or pass --ignore-bad-bootstrap to skip bad entries",
+                i + 1
+            ),
+        }
+    }
+    Ok(addrs)
+}
+
+/// Parse a percentile in the range 0-100 (e.g. "99.9"), used as clap value parser.
+pub fn parse_percentile(s: &str) -> Result<f64, String> {
+    let v: f64 = s
+        .trim()
+        .parse()
+        .map_err(|_| format!("'{s}' is not a number"))?;
+    if !(0.0..=100.0).contains(&v) {
+        return Err(format!("percentile {v} is outside 0-100"));
+    }
+    Ok(v)
+}
+
+/// Parse a probability in 0-1 for `--inject-dup-prob` and `--otlp-sample`.
+pub fn parse_probability(s: &str) -> Result<f64, String> {
+    let v: f64 = s
+        .trim()
+        .parse()
+        .map_err(|_| format!("'{s}' is not a number"))?;
+    if !(0.0..=1.0).contains(&v) {
+        return Err(format!("probability {v} is outside 0-1"));
+    }
+    Ok(v)
+}
+
+/// Parse a positive size in MB (fractions allowed) for `--max-stats-memory-mb`.
+pub fn parse_megabytes(s: &str) -> Result<f64, String> {
+    let v: f64 = s
+        .trim()
+        .parse()
+        .map_err(|_| format!("'{s}' is not a number"))?;
+    if !(v.is_finite() && v > 0.0) {
+        return Err(format!("size {v} MB must be positive"));
+    }
+    Ok(v)
+}
+
+/// Parse a duration flag into milliseconds: `500ms`, `3s`, `2m`, `1h`, or a
+/// bare integer taken as milliseconds (the old `--*-ms` flags).
+pub fn parse_duration(s: &str) -> Result<u64, String> {
+    let t = s.trim();
+    let split = t.find(|c: char| !c.is_ascii_digit()).unwrap_or(t.len());
+    let (digits, unit) = t.split_at(split);
+    if digits.is_empty() {
+        return Err(format!("'{s}' is not a duration (e.g. 500ms, 3s, 2m, 1h)"));
+    }
+    let factor: u64 = match unit.trim() {
+        "" | "ms" => 1,
+        "s" => 1000,
+        "m" => 60_000,
+        "h" => 3_600_000,
+        other => {
+            return Err(format!(
+                "unknown duration unit '{other}' (use ms, s, m or h)"
+            ));
+        }
+    };
+    digits
+        .parse::<u64>()
+        .ok()
+        .and_then(|n| n.checked_mul(factor))
+        .ok_or_else(|| format!("duration '{s}' is too large"))
+}
+
+/// Zero-pad `v` to `target_size`; returns the padded buffer and the number of
+/// bytes added.
+pub fn pad_payload(mut v: Vec<u8>, target_size: usize) -> (Vec<u8>, usize) {
+    let added = target_size.saturating_sub(v.len());
+    if added > 0 {
         v.resize(target_size, 0);
     }
-    v
+    (v, added)
+}
+
+/// Padding content: zeros (maximally compressible), a keyed pseudo-random
+/// stream (incompressible) or the same stream folded onto four letters (about
+/// 2 bits of entropy per byte, so compressible but not trivially).
+///
+/// The streams derive from `(test_id, seq, --seed)`, so a receiver that knows
+/// the pattern and seed can check every pad byte.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum PadPattern {
+    #[default]
+    Zero,
+    Random,
+    Compressible,
+}
+
+impl PadPattern {
+    /// Name as given to `--pad-pattern`.
+    pub fn label(self) -> &'static str {
+        match self {
+            PadPattern::Zero => "zero",
+            PadPattern::Random => "random",
+            PadPattern::Compressible => "compressible",
+        }
+    }
+
+    /// Fill `out` with the pattern for message `seq` of `test_id`.
+    fn fill(self, out: &mut [u8], test_id: &[u8; 16], seq: u64, seed: u64) {
+        if self == PadPattern::Zero {
+            out.fill(0);
+            return;
+        }
+        let mut hasher = blake3::Hasher::new_derive_key("iroh-gossip-metrics pad v1");
+        hasher.update(test_id);
+        hasher.update(&seq.to_le_bytes());
+        hasher.update(&seed.to_le_bytes());
+        hasher.finalize_xof().fill(out);
+        if self == PadPattern::Compressible {
+            for b in out.iter_mut() {
+                *b = b'a' + (*b & 3);
+            }
+        }
+    }
+}
+
+impl Serialize for PadPattern {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.label())
+    }
+}
+
+/// Parse `--pad-pattern`: zero, random or compressible.
+pub fn parse_pad_pattern(s: &str) -> Result<PadPattern, String> {
+    match s {
+        "zero" => Ok(PadPattern::Zero),
+        "random" => Ok(PadPattern::Random),
+        "compressible" => Ok(PadPattern::Compressible),
+        _ => Err(format!(
+            "invalid pad pattern '{s}' (use zero, random or compressible)"
+        )),
+    }
+}
+
+/// Like `pad_payload`, with the fill chosen by `pattern`.
+pub fn pad_payload_with(
+    v: Vec<u8>,
+    target_size: usize,
+    pattern: PadPattern,
+    test_id: &[u8; 16],
+    seq: u64,
+    seed: u64,
+) -> (Vec<u8>, usize) {
+    let start = v.len();
+    let (mut v, added) = pad_payload(v, target_size);
+    pattern.fill(&mut v[start..], test_id, seq, seed);
+    (v, added)
+}
+
+/// Whether `pad` is exactly what `pad_payload_with` would have appended.
+pub fn pad_matches(
+    pad: &[u8],
+    pattern: PadPattern,
+    test_id: &[u8; 16],
+    seq: u64,
+    seed: u64,
+) -> bool {
+    let mut expected = vec![0; pad.len()];
+    pattern.fill(&mut expected, test_id, seq, seed);
+    expected == pad
 }
 
 /// Simple JSONL writer for benchmark logs.
 ///
-/// Each call to `write` appends a single JSON object as one line.
+/// Each event appends a single JSON object as one line, stamped (by
+/// `EventSink::emit`) with this process's `run_id` and the monotonic
+/// `elapsed_ms`. Failed writes (a full disk) are counted and the first one is
+/// printed, so the run still produces its summary; `set_strict` makes them
+/// errors instead.
 pub struct JsonWriter {
-    file: File,
+    file: Box<dyn Write + Send>,
+    strict: bool,
+    write_errors: u64,
 }
 
 impl JsonWriter {
     /// Create a new JSONL writer that truncates/creates the given file path.
     pub fn new<P: AsRef<Path>>(path: P) -> anyhow::Result<Self> {
         Ok(Self {
-            file: File::create(path)?,
+            file: Box::new(File::create(path)?),
+            strict: false,
+            write_errors: 0,
         })
     }
 
+    /// Fail on write errors instead of counting them (`--strict-logging`);
+    /// fails right away if a write (the header) already did.
+    pub fn set_strict(&mut self, strict: bool) -> anyhow::Result<()> {
+        self.strict = strict;
+        if strict && self.write_errors > 0 {
+            anyhow::bail!("log is not writable");
+        }
+        Ok(())
+    }
+
+    /// Like `new`, but open the log with `header` as its first line.
+    ///
+    /// The header goes straight to the file, so even a run that crashes
+    /// before its first event leaves its configuration behind.
+    pub fn with_header<P: AsRef<Path>>(path: P, header: &LogHeader) -> anyhow::Result<Self> {
+        let mut writer = Self::new(path)?;
+        let line = serde_json::to_string(header)?;
+        writer.write_line(&line)?;
+        Ok(writer)
+    }
+
+    /// Append one line, counting a failure unless strict.
+    fn write_line(&mut self, line: &str) -> anyhow::Result<()> {
+        let Err(e) = writeln!(self.file, "{line}") else {
+            return Ok(());
+        };
+        if self.strict {
+            return Err(e.into());
+        }
+        if self.write_errors == 0 {
+            diag!("log write failed, further failures are only counted: {e}");
+        }
+        self.write_errors += 1;
+        Ok(())
+    }
+}
+
+impl EventSink for JsonWriter {
+    fn name(&self) -> &'static str {
+        "jsonl"
+    }
+
     /// Append a single event as one JSON line.
-    pub fn write(&mut self, ev: &LogEvent) -> anyhow::Result<()> {
+    fn write(&mut self, ev: &LogEvent) -> anyhow::Result<()> {
         let line = serde_json::to_string(ev)?;
-        writeln!(self.file, "{}", line)?;
-        Ok(())
+        self.write_line(&line)
+    }
+
+    fn flush(&mut self) {
+        let _ = self.file.flush();
+    }
+
+    fn strict(&self) -> bool {
+        self.strict
+    }
+
+    /// Events that could not be written to the file so far.
+    fn write_errors(&self) -> u64 {
+        self.write_errors
+    }
+}
+
+/// Stream a JSONL log line by line, calling `f` with the 1-based line number
+/// and the parsed event. Empty lines and the header line (see `LogHeader`) are
+/// skipped; only one line is held in memory.
+pub fn for_each_log_event<P: AsRef<Path>>(
+    path: P,
+    mut f: impl FnMut(usize, Result<LogEvent<'_>, serde_json::Error>) -> anyhow::Result<()>,
+) -> anyhow::Result<()> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut line = String::new();
+    let mut line_no = 0;
+    let mut first = true;
+    while reader.read_line(&mut line)? > 0 {
+        line_no += 1;
+        if !line.trim().is_empty() {
+            let header = first && LogHeader::parse(line.trim_end()).is_some();
+            first = false;
+            if !header {
+                f(line_no, serde_json::from_str(line.trim_end()))?;
+            }
+        }
+        line.clear();
+    }
+    Ok(())
+}
+
+/// The header of a JSONL log, or None for logs written without one.
+pub fn read_log_header<P: AsRef<Path>>(path: P) -> anyhow::Result<Option<LogHeader>> {
+    let mut reader = BufReader::new(File::open(path)?);
+    let mut line = String::new();
+    while reader.read_line(&mut line)? > 0 {
+        if !line.trim().is_empty() {
+            return Ok(LogHeader::parse(line.trim_end()));
+        }
+        line.clear();
     }
+    Ok(None)
 }
 
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn durations_with_units() {
+        assert_eq!(parse_duration("500ms"), Ok(500));
+        assert_eq!(parse_duration("3s"), Ok(3_000));
+        assert_eq!(parse_duration("2m"), Ok(120_000));
+        assert_eq!(parse_duration("1h"), Ok(3_600_000));
+        assert_eq!(parse_duration("0s"), Ok(0));
+    }
+
+    #[test]
+    fn bare_numbers_are_milliseconds() {
+        assert_eq!(parse_duration("250"), Ok(250));
+        assert_eq!(parse_duration(" 3s "), Ok(3_000));
+        assert_eq!(parse_duration("10 ms"), Ok(10));
+    }
+
+    #[test]
+    fn bad_durations_explain_themselves() {
+        for s in ["", "  ", "ms", "-5s", "1.5s"] {
+            let err = parse_duration(s).unwrap_err();
+            assert!(
+                err.contains("is not a duration") || err.contains("unknown duration unit"),
+                "{s}: {err}"
+            );
+        }
+        assert_eq!(
+            parse_duration("5d"),
+            Err("unknown duration unit 'd' (use ms, s, m or h)".into())
+        );
+        assert_eq!(
+            parse_duration("3sec"),
+            Err("unknown duration unit 'sec' (use ms, s, m or h)".into())
+        );
+        let too_large = format!("{}h", u64::MAX / 1000);
+        assert_eq!(
+            parse_duration(&too_large),
+            Err(format!("duration '{too_large}' is too large"))
+        );
+        assert!(parse_duration("99999999999999999999").is_err());
+    }
+    /// A log destination on a full disk.
+    struct FullDisk;
+
+    impl Write for FullDisk {
+        fn write(&mut self, _: &[u8]) -> std::io::Result<usize> {
+            Err(std::io::Error::from(std::io::ErrorKind::StorageFull))
+        }
+
+        fn flush(&mut self) -> std::io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn full_disk_writer() -> JsonWriter {
+        JsonWriter {
+            file: Box::new(FullDisk),
+            strict: false,
+            write_errors: 0,
+        }
+    }
+
+    fn event() -> LogEvent<'static> {
+        LogEvent {
+            ts_ms: 0,
+            elapsed_ms: None,
+            role: "receiver",
+            peer_id: "test",
+            run_id: None,
+            event: crate::metrics::EventKind::Recv,
+            seq: Some(0),
+            lat_ms: None,
+            ldh: None,
+            extra: serde_json::Value::Null,
+        }
+    }
+
+    #[test]
+    fn lenient_log_counts_failed_writes() {
+        let mut log = full_disk_writer();
+        for _ in 0..3 {
+            log.emit(event()).unwrap();
+        }
+        assert_eq!(log.write_errors(), 3);
+        assert!(!log.strict());
+    }
+
+    #[test]
+    fn strict_log_returns_the_first_error() {
+        let mut log = full_disk_writer();
+        log.set_strict(true).unwrap();
+        let err = log.emit(event()).unwrap_err();
+        let io = err.downcast_ref::<std::io::Error>().expect("io error");
+        assert_eq!(io.kind(), std::io::ErrorKind::StorageFull);
+        assert_eq!(log.write_errors(), 0);
+    }
+
+    #[test]
+    fn going_strict_after_a_failed_header_fails() {
+        let mut log = full_disk_writer();
+        log.write_line("{}").unwrap();
+        assert!(log.set_strict(true).is_err());
+    }
+
+    #[test]
+    fn hex32_accepts_prefix_whitespace_and_case() {
+        let hex = "ab".repeat(32);
+        assert_eq!(parse_topic_hex(&hex), Ok(hex.clone()));
+        assert_eq!(parse_topic_hex(&format!("0x{hex}")), Ok(hex.clone()));
+        assert_eq!(
+            parse_secret_hex(&format!(" 0X{} ", hex.to_uppercase())),
+            Ok(hex.clone())
+        );
+        let spaced = format!("{} {}\n", &hex[..32], &hex[32..]);
+        assert_eq!(parse_secret_hex(&spaced), Ok(hex));
+    }
+
+    #[test]
+    fn hex32_errors_name_the_problem() {
+        let err = parse_topic_hex(&"a".repeat(63)).unwrap_err();
+        assert!(
+            err.starts_with(
+                "expected 64 hex characters (32 bytes), got 63; copy the whole topic ID"
+            ),
+            "{err}"
+        );
+        let err = parse_secret_hex(&"a".repeat(66)).unwrap_err();
+        assert!(
+            err.starts_with("expected 64 hex characters (32 bytes), got 66; a secret key"),
+            "{err}"
+        );
+        let err = parse_topic_hex(&format!("0x{}g", "a".repeat(63))).unwrap_err();
+        assert!(
+            err.starts_with("character 64 ('g') is not a hex digit"),
+            "{err}"
+        );
+        assert!(parse_topic_hex("").is_err());
+    }
+
+    #[test]
+    fn bad_bootstrap_entries_fail_unless_ignored() {
+        let id = iroh::SecretKey::from_bytes(&rand::random())
+            .public()
+            .to_string();
+        let list = format!(" {id} , nope,,");
+        let err = parse_bootstrap(&list, false).unwrap_err().to_string();
+        assert!(
+            err.starts_with("--bootstrap entry 2 is not a node ID (4 characters, 'nope')"),
+            "{err}"
+        );
+        assert!(
+            err.ends_with("or pass --ignore-bad-bootstrap to skip bad entries"),
+            "{err}"
+        );
+        assert_eq!(parse_bootstrap(&list, true).unwrap(), [id]);
+        assert!(parse_bootstrap("", false).unwrap().is_empty());
+
+        let list = "127.0.0.1:4000,localhost";
+        let err = parse_bootstrap_addrs(list, false).unwrap_err().to_string();
+        assert!(
+            err.starts_with("--bootstrap entry 2 is not a socket address ('localhost')"),
+            "{err}"
+        );
+        assert_eq!(
+            parse_bootstrap_addrs(list, true).unwrap(),
+            ["127.0.0.1:4000"]
+        );
+    }
+}